@@ -3,6 +3,7 @@ use std::{fs, path::Path, time::Duration};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use duct::cmd;
 use lazy_static::lazy_static;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use tempfile::{tempdir, TempDir};
 use walkdir::{DirEntry, WalkDir};
 use writ::core::*;
@@ -31,6 +32,9 @@ fn unpack_sample_workdir() -> TempDir {
 
 lazy_static! {
     static ref SAMPLE: TempDir = unpack_sample_workdir();
+    /// Runs writ as it was before hashing and stat were parallelized, to show
+    /// the speedup.
+    static ref SINGLE_THREADED: ThreadPool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
 }
 
 fn is_workspace_file(root: &Path, entry: &DirEntry) -> bool {
@@ -113,6 +117,20 @@ pub fn bench_add(c: &mut Criterion) {
                 |(_ws, mut repo)| repo.add(vec!["."]).unwrap(),
             )
         });
+        group.bench_with_input(
+            BenchmarkId::new("writ-single-threaded", size),
+            &size,
+            |b, &size| {
+                b.iter_with_large_setup(
+                    || {
+                        let ws = setup_workspace(size);
+                        let repo = Repo::init(ws.path()).unwrap();
+                        (ws, repo)
+                    },
+                    |(_ws, mut repo)| SINGLE_THREADED.install(|| repo.add(vec!["."]).unwrap()),
+                )
+            },
+        );
         group.bench_with_input(BenchmarkId::new("git", size), &size, |b, &size| {
             b.iter_with_large_setup(
                 || {
@@ -151,6 +169,25 @@ pub fn bench_status(c: &mut Criterion) {
                 },
             )
         });
+        group.bench_with_input(
+            BenchmarkId::new("writ-single-threaded", count),
+            &count,
+            |b, &count| {
+                b.iter_with_large_setup(
+                    || {
+                        let ws = setup_workspace(WS_FILES);
+                        let mut repo = Repo::init(ws.path()).unwrap();
+                        for_first_n_files(ws.path(), count, |path| {
+                            assert_eq!(repo.add(vec![path]).unwrap().len(), 1);
+                        });
+                        (ws, repo)
+                    },
+                    |(_ws, mut repo)| {
+                        SINGLE_THREADED.install(|| repo.status().unwrap());
+                    },
+                )
+            },
+        );
         group.bench_with_input(BenchmarkId::new("git", count), &count, |b, &count| {
             b.iter_with_large_setup(
                 || {
@@ -170,6 +207,5 @@ pub fn bench_status(c: &mut Criterion) {
     group.finish();
 }
 
-// criterion_group!(core, bench_add, bench_status);
-criterion_group!(core, bench_status);
+criterion_group!(core, bench_add, bench_status);
 criterion_main!(core);
@@ -0,0 +1,694 @@
+use bstr::{BStr, BString, ByteSlice};
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+use crate::core::{locked_file, LockedFile};
+
+/// Each worktree's own config, in its git directory, read after the shared
+/// config if `extensions.worktreeConfig` is set.
+pub const WORKTREE_FILE: &str = "config.worktree";
+
+/// Git configuration, only as much of the format as we need.
+///
+/// Section and key names are case-insensitive, subsection names aren't. When a
+/// key is set more than once the last value wins, so files loaded later
+/// override earlier ones.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Config {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Entry {
+    section: String,
+    subsection: Option<BString>,
+    key: String,
+    /// `None` for a key with no `=`, which is shorthand for true
+    value: Option<BString>,
+}
+
+impl Config {
+    /// Loads the user's global config followed by the repository's, in
+    /// `common_dir`, and then that of the worktree with the git directory
+    /// `git_dir` if there is per-worktree config.
+    pub fn load(
+        common_dir: impl AsRef<Path>,
+        git_dir: impl AsRef<Path>,
+    ) -> Result<Self, LoadError> {
+        let mut config = Self::default();
+        for path in Self::global_paths() {
+            config.merge(Self::load_file(&path)?);
+        }
+        config.merge(Self::load_file(common_dir.as_ref().join("config"))?);
+        let worktree_config = config
+            .get_bool("extensions.worktreeConfig")
+            .map_err(LoadError::InvalidValue)?;
+        if worktree_config == Some(true) {
+            config.merge(Self::load_file(git_dir.as_ref().join(WORKTREE_FILE))?);
+        }
+        Ok(config)
+    }
+
+    /// A missing file is treated as empty.
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let input = match fs::read(path) {
+            Ok(input) => input,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                debug!("Config file {:?} does not exist", path);
+                return Ok(Self::default());
+            }
+            Err(err) => return Err(LoadError::Read(path.to_owned(), err)),
+        };
+        Self::parse(input.as_bstr()).map_err(|e| LoadError::Parse(path.to_owned(), e))
+    }
+
+    fn global_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Some(config_home) = xdg_config_home() {
+            paths.push(config_home.join("git/config"));
+        }
+        if let Some(home) = env::var_os("HOME") {
+            paths.push(PathBuf::from(home).join(".gitconfig"));
+        }
+        paths
+    }
+
+    pub fn merge(&mut self, other: Self) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn parse(input: &BStr) -> Result<Self, ParseError> {
+        Parser::new(input).parse()
+    }
+
+    /// Get the last value of a key such as `core.excludesFile` or
+    /// `filter.lfs.clean`. A key with no value is returned as `"true"`.
+    pub fn get(&self, key: &str) -> Option<&BStr> {
+        self.get_all(key).last()
+    }
+
+    pub fn get_all<'c>(&'c self, key: &str) -> impl Iterator<Item = &'c BStr> + 'c {
+        let key = Key::new(key);
+        self.entries
+            .iter()
+            .filter(move |e| key.matches(e))
+            .map(|e| {
+                e.value
+                    .as_ref()
+                    .map_or_else(|| b"true".as_bstr(), |v| v.as_bstr())
+            })
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, InvalidValueError> {
+        let value = if let Some(value) = self.get(key) {
+            value
+        } else {
+            return Ok(None);
+        };
+
+        match value.to_ascii_lowercase().as_slice() {
+            b"true" | b"yes" | b"on" | b"1" => Ok(Some(true)),
+            b"false" | b"no" | b"off" | b"0" | b"" => Ok(Some(false)),
+            _ => Err(InvalidValueError(key.to_owned(), value.to_owned())),
+        }
+    }
+
+    /// Like [`Self::get`], expanding a leading `~/` to the home directory.
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        let value = self.get(key)?.to_path_lossy().into_owned();
+        if let Ok(rest) = value.strip_prefix("~") {
+            if let Some(home) = env::var_os("HOME") {
+                return Some(PathBuf::from(home).join(rest));
+            }
+        }
+        Some(value)
+    }
+
+    /// The distinct subsection names of `section`, in the order they first
+    /// appear.
+    pub fn subsections(&self, section: &str) -> Vec<&BStr> {
+        let section = section.to_ascii_lowercase();
+        let mut names = Vec::new();
+        for entry in &self.entries {
+            if entry.section != section {
+                continue;
+            }
+            if let Some(sub) = &entry.subsection {
+                if !names.contains(&sub.as_bstr()) {
+                    names.push(sub.as_bstr());
+                }
+            }
+        }
+        names
+    }
+}
+
+/// A key such as `filter.lfs.clean`, to compare with [`Entry`]s.
+struct Key {
+    section: String,
+    subsection: Option<BString>,
+    name: String,
+}
+
+impl Key {
+    fn new(key: &str) -> Self {
+        let (section, subsection, name) = split_key(key);
+        Self {
+            section: section.to_ascii_lowercase(),
+            subsection: subsection.map(BString::from),
+            name: name.to_ascii_lowercase(),
+        }
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        self.is_in_section_of(entry) && entry.key == self.name
+    }
+
+    fn is_in_section_of(&self, entry: &Entry) -> bool {
+        entry.section == self.section && entry.subsection == self.subsection
+    }
+}
+
+pub(crate) fn xdg_config_home() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+/// Sets `key`, such as `submodule.lib.url`, in the config file at `path`,
+/// creating the file if it's missing. The key goes in a section of its own at
+/// the end, which overrides any earlier value as the last value wins.
+pub fn append(path: impl AsRef<Path>, key: &str, value: &str) -> Result<(), WriteError> {
+    rewrite(path.as_ref(), |contents| {
+        push_section(contents, key, value);
+        Ok(())
+    })
+}
+
+/// Like [`append`], except that if `key` is already set its last value is
+/// replaced where it is, so setting it again doesn't make the file grow, and
+/// otherwise it goes after the last key in the same section, if there is one.
+pub fn set(path: impl AsRef<Path>, key: &str, value: &str) -> Result<(), WriteError> {
+    let path = path.as_ref();
+    rewrite(path, |contents| {
+        let entries = Parser::new(contents.as_bstr())
+            .parse_entries()
+            .map_err(|e| WriteError::Parse(path.to_owned(), e))?;
+        let matching = Key::new(key);
+        let (_, _, name) = split_key(key);
+        let line = format!("{} = {}\n", name, quote(value));
+
+        let same_key = entries.iter().rfind(|(entry, _)| matching.matches(entry));
+        let same_section = entries
+            .iter()
+            .rfind(|(entry, _)| matching.is_in_section_of(entry));
+        match (same_key, same_section) {
+            (Some((_, span)), _) => {
+                contents.splice(span.clone(), line.into_bytes());
+            }
+            (None, Some((_, span))) => {
+                let mut at = span.end;
+                // The last line of the file may not end in a newline
+                if contents.get(at - 1) != Some(&b'\n') {
+                    contents.insert(at, b'\n');
+                    at += 1;
+                }
+                contents.splice(at..at, format!("\t{}", line).into_bytes());
+            }
+            (None, None) => push_section(contents, key, value),
+        }
+        Ok(())
+    })
+}
+
+/// Adds a section of its own setting `key` to `value` to the end of
+/// `contents`.
+fn push_section(contents: &mut Vec<u8>, key: &str, value: &str) {
+    let (section, subsection, name) = split_key(key);
+    let header = match subsection {
+        Some(subsection) => {
+            let escaped = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("{} \"{}\"", section, escaped)
+        }
+        None => section.to_owned(),
+    };
+    if !contents.is_empty() && !contents.ends_with(b"\n") {
+        contents.push(b'\n');
+    }
+    let section = format!("[{}]\n\t{} = {}\n", header, name, quote(value));
+    contents.extend_from_slice(section.as_bytes());
+}
+
+/// Replaces the config file at `path` with what `edit` makes of its contents,
+/// holding `config.lock` as git does so concurrent writes aren't lost.
+fn rewrite(
+    path: &Path,
+    edit: impl FnOnce(&mut Vec<u8>) -> Result<(), WriteError>,
+) -> Result<(), WriteError> {
+    let write_error = |e| WriteError::Write(path.to_owned(), e);
+    let mut lock = LockedFile::acquire(path).map_err(|e| WriteError::Lock(path.to_owned(), e))?;
+
+    let mut contents = Vec::new();
+    if let Some(mut file) = lock.protected_file() {
+        file.read_to_end(&mut contents).map_err(write_error)?;
+    }
+    edit(&mut contents)?;
+
+    lock.write_all(&contents).map_err(write_error)?;
+    lock.commit().map_err(write_error)
+}
+
+/// Escapes `value` so it reads back unchanged, quoting it only if it has
+/// surrounding whitespace or comment characters.
+fn quote(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(&[';', '#'][..]);
+    if needs_quotes {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Splits `a.b.c` into section `a`, subsection `b` and key `c`. The subsection
+/// may itself contain dots.
+fn split_key(key: &str) -> (&str, Option<&str>, &str) {
+    let (rest, name) = key.rsplit_once('.').unwrap_or(("", key));
+    match rest.split_once('.') {
+        Some((section, subsection)) => (section, Some(subsection), name),
+        None => (rest, None, name),
+    }
+}
+
+struct Parser<'i> {
+    input: &'i [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'i> Parser<'i> {
+    fn new(input: &'i BStr) -> Self {
+        Self {
+            input: input.as_bytes(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn parse(self) -> Result<Config, ParseError> {
+        let entries = self.parse_entries()?;
+        Ok(Config {
+            entries: entries.into_iter().map(|(entry, _)| entry).collect(),
+        })
+    }
+
+    /// Each entry with where it is in the input, from its key to the end of
+    /// its line.
+    fn parse_entries(mut self) -> Result<Vec<(Entry, Range<usize>)>, ParseError> {
+        let mut entries = Vec::new();
+        let mut section: Option<(String, Option<BString>)> = None;
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(b'\n') => self.bump(),
+                Some(b'#' | b';') => self.skip_line(),
+                Some(b'[') => section = Some(self.section_header()?),
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let (sec, sub) = section
+                        .clone()
+                        .ok_or(ParseError::KeyOutsideSection(self.line))?;
+                    let start = self.pos;
+                    let (key, value) = self.key_value()?;
+                    let entry = Entry {
+                        section: sec,
+                        subsection: sub,
+                        key,
+                        value,
+                    };
+                    entries.push((entry, start..self.pos));
+                }
+                Some(_) => return Err(ParseError::Invalid(self.line)),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) {
+        if self.peek() == Some(b'\n') {
+            self.line += 1;
+        }
+        self.pos += 1;
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\r')) {
+            self.bump();
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.peek() {
+            self.bump();
+            if c == b'\n' {
+                break;
+            }
+        }
+    }
+
+    fn section_header(&mut self) -> Result<(String, Option<BString>), ParseError> {
+        let line = self.line;
+        self.bump(); // [
+
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == b'-' || c == b'.' {
+                name.push(c.to_ascii_lowercase() as char);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+
+        let mut subsection = None;
+        self.skip_whitespace();
+        if self.peek() == Some(b'"') {
+            self.bump();
+            let mut sub = BString::from("");
+            loop {
+                match self.peek() {
+                    Some(b'"') => {
+                        self.bump();
+                        break;
+                    }
+                    Some(b'\\') => {
+                        self.bump();
+                        let c = self.peek().ok_or(ParseError::InvalidSection(line))?;
+                        sub.push(c);
+                        self.bump();
+                    }
+                    Some(b'\n') | None => return Err(ParseError::InvalidSection(line)),
+                    Some(c) => {
+                        sub.push(c);
+                        self.bump();
+                    }
+                }
+            }
+            subsection = Some(sub);
+        } else if let Some((section, sub)) = name.split_once('.') {
+            // The deprecated [section.subsection] syntax
+            subsection = Some(BString::from(sub));
+            name = section.to_owned();
+        }
+
+        if self.peek() != Some(b']') || name.is_empty() {
+            return Err(ParseError::InvalidSection(line));
+        }
+        self.bump();
+
+        Ok((name, subsection))
+    }
+
+    fn key_value(&mut self) -> Result<(String, Option<BString>), ParseError> {
+        let mut key = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == b'-' {
+                key.push(c.to_ascii_lowercase() as char);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'=') => {
+                self.bump();
+                Ok((key, Some(self.value()?)))
+            }
+            None | Some(b'\n' | b'#' | b';') => {
+                self.skip_line();
+                Ok((key, None))
+            }
+            Some(_) => Err(ParseError::Invalid(self.line)),
+        }
+    }
+
+    fn value(&mut self) -> Result<BString, ParseError> {
+        let line = self.line;
+        let mut value = Vec::new();
+        // Length of value without trailing unquoted whitespace
+        let mut trimmed_len = 0;
+        let mut quoted = false;
+
+        self.skip_whitespace();
+        while let Some(c) = self.peek() {
+            self.bump();
+
+            match c {
+                b'\n' if !quoted => break,
+                b'\n' => return Err(ParseError::UnterminatedQuote(line)),
+                b'#' | b';' if !quoted => {
+                    self.skip_line();
+                    break;
+                }
+                b'"' => quoted = !quoted,
+                b'\\' => {
+                    let escaped = match self.peek() {
+                        Some(b'\n') => None,
+                        Some(b'n') => Some(b'\n'),
+                        Some(b't') => Some(b'\t'),
+                        Some(b'b') => Some(8),
+                        Some(b'\\') => Some(b'\\'),
+                        Some(b'"') => Some(b'"'),
+                        _ => return Err(ParseError::InvalidEscape(self.line)),
+                    };
+                    self.bump();
+                    if let Some(escaped) = escaped {
+                        value.push(escaped);
+                    }
+                }
+                b' ' | b'\t' | b'\r' if !quoted => {
+                    value.push(c);
+                    continue;
+                }
+                c => value.push(c),
+            }
+            trimmed_len = value.len();
+        }
+
+        if quoted {
+            return Err(ParseError::UnterminatedQuote(line));
+        }
+
+        value.truncate(trimmed_len);
+        Ok(value.into())
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum LoadError {
+    /// Failed to read config file {0:?}
+    Read(PathBuf, #[source] io::Error),
+    /// Failed to parse config file {0:?}
+    Parse(PathBuf, #[source] ParseError),
+    /// Invalid extensions.worktreeConfig
+    InvalidValue(#[source] InvalidValueError),
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum ParseError {
+    /// Invalid line {0}
+    Invalid(usize),
+    /// Invalid section header on line {0}
+    InvalidSection(usize),
+    /// Key outside of any section on line {0}
+    KeyOutsideSection(usize),
+    /// Unterminated quote in value starting on line {0}
+    UnterminatedQuote(usize),
+    /// Invalid escape sequence on line {0}
+    InvalidEscape(usize),
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum WriteError {
+    /// Failed to lock config file {0:?}
+    Lock(PathBuf, #[source] locked_file::Error),
+    /// Failed to parse config file {0:?}
+    Parse(PathBuf, #[source] ParseError),
+    /// Failed to write config file {0:?}
+    Write(PathBuf, #[source] io::Error),
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Invalid value for {0}: {1}
+pub struct InvalidValueError(String, BString);
+
+impl InvalidValueError {
+    pub(crate) fn new(key: &str, value: impl Into<BString>) -> Self {
+        Self(key.to_owned(), value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_values() -> eyre::Result<()> {
+        init();
+
+        let config = Config::parse(
+            br#"
+# comment
+[core]
+	bare = false
+	excludesFile = ~/ignore ; trailing comment
+	flag
+[filter "lfs"]
+	clean = "git-lfs clean -- %f"
+	smudge = a\tb \
+c
+[Submodule.lib]
+	path = vendor/lib
+"#
+            .as_bstr(),
+        )?;
+
+        assert_eq!(Some(false), config.get_bool("core.bare")?);
+        assert_eq!(Some(true), config.get_bool("core.flag")?);
+        assert_eq!(Some(b"~/ignore".as_bstr()), config.get("CORE.EXCLUDESFILE"));
+        assert_eq!(
+            Some(b"git-lfs clean -- %f".as_bstr()),
+            config.get("filter.lfs.clean")
+        );
+        assert_eq!(Some(b"a\tb c".as_bstr()), config.get("filter.lfs.smudge"));
+        assert_eq!(None, config.get("filter.LFS.clean"));
+        assert_eq!(
+            Some(b"vendor/lib".as_bstr()),
+            config.get("submodule.lib.path")
+        );
+        assert_eq!(vec![b"lfs".as_bstr()], config.subsections("filter"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn appended_values_read_back() -> eyre::Result<()> {
+        init();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config");
+        fs::write(&path, "[core]\n\tbare = false\n")?;
+
+        append(&path, "submodule.lib/a \"b\".url", "../lib")?;
+        append(&path, "core.bare", "true")?;
+        append(&path, "alias.odd", " a;b\\c\"")?;
+
+        let config = Config::load_file(&path)?;
+        assert_eq!(
+            Some(b"../lib".as_bstr()),
+            config.get("submodule.lib/a \"b\".url")
+        );
+        assert_eq!(Some(true), config.get_bool("core.bare")?);
+        assert_eq!(Some(b" a;b\\c\"".as_bstr()), config.get("alias.odd"));
+        assert!(!dir.path().join("config.lock").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn append_fails_while_locked() -> eyre::Result<()> {
+        init();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config");
+        fs::write(&path, "[core]\n\tbare = false\n")?;
+        fs::write(dir.path().join("config.lock"), "")?;
+
+        let err = append(&path, "core.bare", "true").unwrap_err();
+        assert!(matches!(err, WriteError::Lock(..)));
+        assert_eq!("[core]\n\tbare = false\n", fs::read_to_string(&path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn set_replaces_values_in_place() -> eyre::Result<()> {
+        init();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config");
+        fs::write(
+            &path,
+            "[core]\n\tbare = false\n\tflag\n[alias]\n\tlong = a \\\n b\n[x]",
+        )?;
+
+        set(&path, "core.bare", "true")?;
+        set(&path, "core.flag", "false")?;
+        set(&path, "alias.long", "c")?;
+        set(&path, "core.new", "1")?;
+        set(&path, "x.y", "2")?;
+        set(&path, "other.z", "3")?;
+        set(&path, "other.z", "4")?;
+
+        assert_eq!(
+            "[core]\n\tbare = true\n\tflag = false\n\tnew = 1\n[alias]\n\tlong = c\n[x]\n\
+             [x]\n\ty = 2\n[other]\n\tz = 4\n",
+            fs::read_to_string(&path)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn worktree_config_needs_extension() -> eyre::Result<()> {
+        init();
+        let dir = tempfile::tempdir()?;
+        let common = dir.path();
+        let git_dir = common.join("worktrees/wt");
+        fs::create_dir_all(&git_dir)?;
+        fs::write(common.join("config"), "[core]\n\tbare = false\n")?;
+        fs::write(git_dir.join(WORKTREE_FILE), "[core]\n\tbare = true\n")?;
+
+        let config = Config::load(common, &git_dir)?;
+        assert_eq!(Some(false), config.get_bool("core.bare")?);
+
+        set(common.join("config"), "extensions.worktreeConfig", "true")?;
+        let config = Config::load(common, &git_dir)?;
+        assert_eq!(Some(true), config.get_bool("core.bare")?);
+        Ok(())
+    }
+
+    #[test]
+    fn later_values_win() -> eyre::Result<()> {
+        init();
+
+        let mut config = Config::parse(b"[core]\nbare = true\n".as_bstr())?;
+        config.merge(Config::parse(b"[core]\nbare = false\n".as_bstr())?);
+
+        assert_eq!(Some(false), config.get_bool("core.bare")?);
+
+        Ok(())
+    }
+}
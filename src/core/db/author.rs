@@ -35,6 +35,19 @@ impl Author {
         Self::new(name, email, time)
     }
 
+    pub fn name(&self) -> &BStr {
+        self.name.as_bstr()
+    }
+
+    pub fn email(&self) -> &BStr {
+        self.email.as_bstr()
+    }
+
+    /// In the timezone it was recorded in.
+    pub fn time(&self) -> DateTime<FixedOffset> {
+        self.time
+    }
+
     pub(crate) fn serialize(&self) -> BString {
         let time = self.time.format(Self::TIME_FORMAT);
         format!("{} <{}> {}", &self.name, &self.email, time).into()
@@ -19,6 +19,10 @@ impl Blob {
         bytes.extend_from_slice(file.as_bytes());
         Oid::for_serialized_bytes(&bytes)
     }
+
+    pub fn empty_oid() -> Oid<Self> {
+        Self::oid_for_file(b"".as_bstr())
+    }
 }
 
 impl Object for Blob {
@@ -37,7 +41,7 @@ impl Object for Blob {
         len: usize,
         mut data: impl BufRead,
     ) -> Result<Self, Self::DeserializeError> {
-        let mut bytes: BString = Vec::with_capacity(len).into();
+        let mut bytes: BString = vec![0; len].into();
         data.read_exact(&mut bytes)?;
         Ok(Self { bytes, oid })
     }
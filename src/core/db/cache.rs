@@ -7,7 +7,7 @@ use crate::core::{Object, Oid};
 
 use super::UntypedOid;
 
-pub(super) struct Cache(LruCache<UntypedOid, Box<dyn Any>>);
+pub(super) struct Cache(LruCache<UntypedOid, Box<dyn Any + Send + Sync>>);
 
 impl Cache {
     const CAPACITY: usize = 5000;
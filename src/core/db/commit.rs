@@ -1,9 +1,6 @@
-use std::{
-    borrow::Cow,
-    io::{self, BufRead},
-};
+use std::io::{self, BufRead};
 
-use bstr::{BString, ByteSlice};
+use bstr::{BStr, BString, ByteSlice};
 use tracing::warn;
 
 use super::{author, object::ParseOidError, Author, Tree};
@@ -12,12 +9,54 @@ use crate::core::{db, Db, Object, ObjectBuilder, Oid};
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Commit {
     pub oid: Oid<Commit>,
-    pub parent: Option<Oid<Commit>>,
+    /// More than one for a merge, in the order they were merged
+    pub parents: Vec<Oid<Commit>>,
     pub tree: Oid<Tree>,
     pub author: db::Author,
+    pub committer: db::Author,
     pub msg: BString,
 }
 
+impl Commit {
+    /// The first parent, the commit that was merged into for a merge.
+    pub fn parent(&self) -> Option<Oid<Commit>> {
+        self.parents.first().copied()
+    }
+
+    /// Like git, the first paragraph of the message joined into one line.
+    pub fn subject(&self) -> BString {
+        let lines = ByteSlice::lines(self.msg.as_bytes())
+            .skip_while(|line| line.trim().is_empty())
+            .take_while(|line| !line.trim().is_empty())
+            .map(ByteSlice::trim)
+            .collect::<Vec<_>>();
+        bstr::join(" ", lines).into()
+    }
+
+    /// The rest of the message after the subject, without the blank lines
+    /// separating them.
+    pub fn body(&self) -> &BStr {
+        let msg = self.msg.as_bytes();
+        let mut offset = 0;
+        let mut in_subject = false;
+        for line in msg.lines_with_terminator() {
+            let blank = line.trim().is_empty();
+            if in_subject && blank {
+                break;
+            }
+            in_subject |= !blank;
+            offset += line.len();
+        }
+        for line in msg[offset..].lines_with_terminator() {
+            if !line.trim().is_empty() {
+                break;
+            }
+            offset += line.len();
+        }
+        msg[offset..].as_bstr()
+    }
+}
+
 impl Object for Commit {
     const TYPE: &'static [u8] = b"commit";
 
@@ -34,9 +73,10 @@ impl Object for Commit {
         _len: usize,
         mut data: impl BufRead,
     ) -> Result<Self, Self::DeserializeError> {
-        let mut parent = None;
+        let mut parents = Vec::new();
         let mut tree = None;
         let mut author = None;
+        let mut committer = None;
 
         let mut line = BString::from(Vec::new());
         loop {
@@ -46,6 +86,9 @@ impl Object for Commit {
                 return Err(DeserializeError::UnexpectedHeadersEnd);
             } else if bytes_read == 1 {
                 break;
+            } else if line.starts_with(b" ") {
+                // Continues a header we don't use, like a signature
+                continue;
             }
 
             let i = line.find(b" ").ok_or(DeserializeError::MalformedHeader)?;
@@ -55,13 +98,14 @@ impl Object for Commit {
             match key {
                 b"parent" => {
                     let oid = Oid::parse(value).map_err(DeserializeError::ParseParent)?;
-                    parent = Some(oid);
+                    parents.push(oid);
                 }
                 b"tree" => {
                     let oid = Oid::parse(value).map_err(DeserializeError::ParseTree)?;
                     tree = Some(oid);
                 }
                 b"author" => author = Some(Author::parse(value.as_bstr())?),
+                b"committer" => committer = Some(Author::parse(value.as_bstr())?),
                 _ => warn!(
                     key = ?key.to_str_lossy(),
                     value = ?value.to_str_lossy(),
@@ -72,15 +116,17 @@ impl Object for Commit {
 
         let tree = tree.ok_or(DeserializeError::MissingTree)?;
         let author = author.ok_or(DeserializeError::MissingAuthor)?;
+        let committer = committer.ok_or(DeserializeError::MissingCommitter)?;
 
         let mut msg = BString::from(Vec::new());
         data.read_to_end(&mut msg)?;
 
         Ok(Self {
             oid,
-            parent,
+            parents,
             tree,
             author,
+            committer,
             msg,
         })
     }
@@ -88,7 +134,7 @@ impl Object for Commit {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Builder {
-    pub parent: Option<Oid<Commit>>,
+    pub parents: Vec<Oid<Commit>>,
     pub tree: Oid<Tree>,
     pub author: db::Author,
     pub msg: BString,
@@ -96,13 +142,13 @@ pub struct Builder {
 
 impl Builder {
     pub fn new(
-        parent: Option<Oid<Commit>>,
+        parents: Vec<Oid<Commit>>,
         tree: Oid<Tree>,
         author: db::Author,
         msg: impl Into<BString>,
     ) -> Self {
         Self {
-            parent,
+            parents,
             tree,
             author,
             msg: msg.into(),
@@ -116,16 +162,17 @@ impl ObjectBuilder for Builder {
     fn store(self, db: &Db) -> db::StoreResult<Commit> {
         let author = self.author.serialize();
 
-        let parent_line = if let Some(parent) = self.parent.as_ref() {
-            Cow::Owned(format!("\nparent {}", parent.to_hex()))
-        } else {
-            Cow::Borrowed("")
-        };
+        let parent_lines = self
+            .parents
+            .iter()
+            .map(|parent| format!("\nparent {}", parent.to_hex()))
+            .collect::<Vec<_>>()
+            .concat();
 
         let ser = format!(
             "tree {}{}\nauthor {}\ncommitter {}\n\n{}",
             self.tree.to_hex(),
-            &parent_line,
+            &parent_lines,
             &author,
             &author,
             &self.msg
@@ -154,4 +201,6 @@ pub enum DeserializeError {
     MissingTree,
     /// Header author not present
     MissingAuthor,
+    /// Header committer not present
+    MissingCommitter,
 }
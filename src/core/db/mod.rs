@@ -3,12 +3,14 @@ pub mod blob;
 pub mod cache;
 pub mod commit;
 pub mod object;
+pub mod tag;
 pub mod tree;
 
 pub use author::Author;
 pub use blob::Blob;
 pub use commit::Commit;
 pub use object::{Object, ObjectBuilder, Oid, UntypedOid};
+pub use tag::Tag;
 pub use tree::Tree;
 
 use bstr::{BString, ByteSlice};
@@ -16,7 +18,7 @@ use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use tempfile::NamedTempFile;
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs::{self, File},
     io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
     num::ParseIntError,
@@ -24,7 +26,16 @@ use std::{
 };
 
 use self::cache::Cache;
-use crate::core::WsPath;
+use crate::core::{Pathspec, WsPath};
+
+/// An object of any type, see [`Db::load_any`].
+#[derive(Debug, Clone)]
+pub enum AnyObject {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
 
 /// Note: Cloning doesn't keep the cache
 #[derive(Debug)]
@@ -53,6 +64,59 @@ impl Db {
         Ok(object)
     }
 
+    /// Loads `oid` as whatever type of object it is.
+    pub fn load_any(&mut self, oid: UntypedOid) -> Result<AnyObject, LoadAnyError> {
+        let o_type = self.load_type(oid)?;
+        match o_type.as_slice() {
+            Blob::TYPE => Ok(AnyObject::Blob(self.load(oid.to_typed())?)),
+            Tree::TYPE => Ok(AnyObject::Tree(self.load(oid.to_typed())?)),
+            Commit::TYPE => Ok(AnyObject::Commit(self.load(oid.to_typed())?)),
+            Tag::TYPE => Ok(AnyObject::Tag(self.load(oid.to_typed())?)),
+            _ => Err(LoadAnyError::UnknownType(oid, o_type)),
+        }
+    }
+
+    /// The type of the object `oid`, like `commit`. Doesn't cache
+    pub fn load_type(&self, oid: UntypedOid) -> Result<BString, LoadAnyError> {
+        let path = self.oid_path(&oid.to_typed::<Blob>());
+        let file = match File::open(&path) {
+            Ok(file) => Ok(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(LoadAnyError::NotFound(oid)),
+            Err(err) => Err(LoadAnyError::ReadType(oid, err)),
+        }?;
+
+        let mut o_type = BString::from(Vec::new());
+        BufReader::new(ZlibDecoder::new(file))
+            .read_until(b' ', &mut o_type)
+            .map_err(|e| LoadAnyError::ReadType(oid, e))?;
+        if o_type.pop() != Some(b' ') {
+            return Err(LoadAnyError::Corrupt(oid));
+        }
+        Ok(o_type)
+    }
+
+    /// The objects whose hex starts with `prefix`, which must be at least two
+    /// lowercase hex digits, as it names the directory to look in.
+    pub fn find_prefix(&self, prefix: &str) -> io::Result<Vec<UntypedOid>> {
+        let dir = self.path.join(&prefix[..2]);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut oids = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            let hex = format!("{}{}", &prefix[..2], name.to_string_lossy());
+            if hex.starts_with(prefix) {
+                if let Ok(oid) = UntypedOid::parse(&hex) {
+                    oids.push(oid);
+                }
+            }
+        }
+        Ok(oids)
+    }
+
     pub fn load_tree_file(
         &mut self,
         mut tree: Oid<Tree>,
@@ -107,6 +171,78 @@ impl Db {
         Ok(())
     }
 
+    /// The files that differ between the trees `old` and `new`, either of
+    /// which can be missing, as when a commit has no parent. Only paths
+    /// matching `pathspec` are compared, and subtrees with the same oid are
+    /// skipped without being loaded.
+    pub fn diff_trees(
+        &mut self,
+        old: Option<Oid<Tree>>,
+        new: Option<Oid<Tree>>,
+        pathspec: &Pathspec,
+    ) -> Result<Vec<tree::Change>, LoadError<Tree>> {
+        let mut changes = Vec::new();
+        let roots = pathspec.walk_roots();
+        self.diff_trees_into(&mut changes, &WsPath::root(), old, new, &roots, pathspec)?;
+        Ok(changes)
+    }
+
+    fn diff_trees_into(
+        &mut self,
+        changes: &mut Vec<tree::Change>,
+        root: &WsPath,
+        old: Option<Oid<Tree>>,
+        new: Option<Oid<Tree>>,
+        roots: &[WsPath],
+        pathspec: &Pathspec,
+    ) -> Result<(), LoadError<Tree>> {
+        if old == new {
+            return Ok(());
+        }
+        let old = old.map(|oid| self.load(oid)).transpose()?;
+        let new = new.map(|oid| self.load(oid)).transpose()?;
+        let names = old
+            .iter()
+            .chain(&new)
+            .flat_map(|tree| tree.direct_children().map(|node| node.name().to_owned()))
+            .collect::<BTreeSet<_>>();
+
+        for name in names {
+            let path = root.join_bytes(name.as_bstr());
+            let child = |tree: &Option<Tree>| {
+                let node = tree
+                    .as_ref()
+                    .and_then(|tree| tree.direct_child(name.as_bstr()));
+                match node {
+                    Some(tree::Node::File(file)) => (Some(file.clone()), None),
+                    Some(tree::Node::Tree { oid, .. }) => (None, Some(*oid)),
+                    None => (None, None),
+                }
+            };
+            let (old_file, old_tree) = child(&old);
+            let (new_file, new_tree) = child(&new);
+
+            let file_changed = old_file.as_ref().map(|file| (file.oid, file.mode))
+                != new_file.as_ref().map(|file| (file.oid, file.mode));
+            if file_changed && pathspec.matches(&path) {
+                changes.push(tree::Change {
+                    path: path.clone(),
+                    old: old_file,
+                    new: new_file,
+                });
+            }
+
+            let may_match = roots.iter().any(|root| {
+                root.as_path().starts_with(path.as_path())
+                    || path.as_path().starts_with(root.as_path())
+            });
+            if (old_tree.is_some() || new_tree.is_some()) && may_match {
+                self.diff_trees_into(changes, &path, old_tree, new_tree, roots, pathspec)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Doesn't cache
     fn load_bytes<O: Object>(
         &self,
@@ -177,7 +313,8 @@ impl Db {
 
             match fs::rename(temp.path(), &path) {
                 Err(err) if err.kind() == ErrorKind::NotFound => {
-                    fs::create_dir(&path.parent().expect("has parent"))?;
+                    // Another thread may be creating the same directory
+                    fs::create_dir_all(path.parent().expect("has parent"))?;
                     fs::rename(temp.path(), &path)?;
                 }
                 Err(err) => return Err(err),
@@ -221,7 +358,10 @@ impl Db {
 
 impl Clone for Db {
     fn clone(&self) -> Self {
-        Self::new(&self.path)
+        Self {
+            path: self.path.clone(),
+            cache: Cache::new(),
+        }
     }
 }
 
@@ -239,6 +379,26 @@ pub enum LoadError<O: Object + 'static> {
     Deserialize(Oid<O>, #[source] O::DeserializeError),
 }
 
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LoadAnyError {
+    /// {0:?} not found in database
+    NotFound(UntypedOid),
+    /// Failed to read the type of {0:?} from the database
+    ReadType(UntypedOid, #[source] io::Error),
+    /// Database entry for {0:?} is corrupt
+    Corrupt(UntypedOid),
+    /// {0:?} has unknown type {1}
+    UnknownType(UntypedOid, BString),
+    /// Failed to load blob
+    LoadBlob(#[from] LoadError<Blob>),
+    /// Failed to load tree
+    LoadTree(#[from] LoadError<Tree>),
+    /// Failed to load commit
+    LoadCommit(#[from] LoadError<Commit>),
+    /// Failed to load tag
+    LoadTag(#[from] LoadError<Tag>),
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum LoadBytesError<O: Object + 'static> {
     /// {0:?} not found in database
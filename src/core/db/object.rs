@@ -150,7 +150,7 @@ impl<O: Object> PartialEq for Oid<O> {
 
 impl<O: Object> Eq for Oid<O> {}
 
-pub trait Object: fmt::Debug + Clone {
+pub trait Object: fmt::Debug + Clone + Send + Sync {
     const TYPE: &'static [u8];
 
     type Builder: ObjectBuilder;
@@ -0,0 +1,151 @@
+use std::io::{self, BufRead};
+
+use bstr::{BString, ByteSlice};
+use tracing::warn;
+
+use super::{author, object::ParseOidError, Author, UntypedOid};
+use crate::core::{db, Db, Object, ObjectBuilder, Oid};
+
+/// An annotated tag, which names an object, usually a commit, with a message
+/// and who tagged it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Tag {
+    pub oid: Oid<Tag>,
+    /// The object tagged, which can be another tag
+    pub object: UntypedOid,
+    /// Its type, like `commit`
+    pub object_type: BString,
+    pub name: BString,
+    /// Missing from some tags made by old versions of git
+    pub tagger: Option<Author>,
+    pub msg: BString,
+}
+
+impl Object for Tag {
+    const TYPE: &'static [u8] = b"tag";
+
+    type DeserializeError = DeserializeError;
+
+    type Builder = Builder;
+
+    fn oid(&self) -> Oid<Tag> {
+        self.oid
+    }
+
+    fn deserialize(
+        oid: Oid<Tag>,
+        _len: usize,
+        mut data: impl BufRead,
+    ) -> Result<Self, Self::DeserializeError> {
+        let mut object = None;
+        let mut object_type = None;
+        let mut name = None;
+        let mut tagger = None;
+
+        let mut line = BString::from(Vec::new());
+        loop {
+            line.clear();
+            // Unlike a commit, a tag can end with its headers
+            if data.read_until(b'\n', &mut line)? <= 1 {
+                break;
+            }
+
+            let i = line.find(b" ").ok_or(DeserializeError::MalformedHeader)?;
+            let (key, value) = line.split_at(i);
+            let value = value[1..].strip_suffix(b"\n").unwrap_or(&value[1..]);
+
+            match key {
+                b"object" => {
+                    let oid = UntypedOid::parse(value).map_err(DeserializeError::ParseObject)?;
+                    object = Some(oid);
+                }
+                b"type" => object_type = Some(BString::from(value)),
+                b"tag" => name = Some(BString::from(value)),
+                b"tagger" => tagger = Some(Author::parse(value.as_bstr())?),
+                _ => warn!(
+                    key = ?key.to_str_lossy(),
+                    value = ?value.to_str_lossy(),
+                    "Unrecognized tag header"
+                ),
+            }
+        }
+
+        let object = object.ok_or(DeserializeError::MissingObject)?;
+        let object_type = object_type.ok_or(DeserializeError::MissingType)?;
+        let name = name.ok_or(DeserializeError::MissingName)?;
+
+        let mut msg = BString::from(Vec::new());
+        data.read_to_end(&mut msg)?;
+
+        Ok(Self {
+            oid,
+            object,
+            object_type,
+            name,
+            tagger,
+            msg,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Builder {
+    pub object: UntypedOid,
+    pub object_type: BString,
+    pub name: BString,
+    pub tagger: db::Author,
+    pub msg: BString,
+}
+
+impl Builder {
+    /// Tags `object`, of type `O`.
+    pub fn new<O: Object>(
+        object: Oid<O>,
+        name: impl Into<BString>,
+        tagger: db::Author,
+        msg: impl Into<BString>,
+    ) -> Self {
+        Self {
+            object: object.into_untyped(),
+            object_type: O::TYPE.into(),
+            name: name.into(),
+            tagger,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl ObjectBuilder for Builder {
+    type Object = Tag;
+
+    fn store(self, db: &Db) -> db::StoreResult<Tag> {
+        let mut ser = BString::from(format!(
+            "object {}\ntype {}\ntag {}\ntagger {}\n\n",
+            self.object.to_hex(),
+            self.object_type,
+            self.name,
+            self.tagger.serialize(),
+        ));
+        ser.extend_from_slice(&self.msg);
+
+        db.store_bytes::<Self>(ser.as_bstr())
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum DeserializeError {
+    /// IO error
+    Io(#[from] io::Error),
+    /// Malformed header
+    MalformedHeader,
+    /// Failed to parse oid of tagged object
+    ParseObject(#[source] ParseOidError),
+    /// Failed to parse tagger header
+    ParseTagger(#[from] author::ParseError),
+    /// Header object not present
+    MissingObject,
+    /// Header type not present
+    MissingType,
+    /// Header tag not present
+    MissingName,
+}
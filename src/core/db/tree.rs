@@ -28,6 +28,18 @@ pub struct FileNode {
     pub mode: stat::Mode,
 }
 
+/// A file that differs between two trees, see [`Db::diff_trees`].
+///
+/// [`Db::diff_trees`]: crate::core::Db::diff_trees
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Change {
+    pub path: WsPath,
+    /// `None` if it was added
+    pub old: Option<FileNode>,
+    /// `None` if it was deleted
+    pub new: Option<FileNode>,
+}
+
 impl Tree {
     const MODE: &'static [u8] = b"40000";
 
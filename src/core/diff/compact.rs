@@ -0,0 +1,362 @@
+//! Sliding runs of changes to where they read best, which git does after any
+//! algorithm: where removed or added lines could as well be some lines
+//! earlier or later, like a function added after another whose closing brace
+//! and blank line it repeats.
+
+use super::{edits_from_changes, Edit};
+
+/// Like git, at most this many lines up the indent heuristic looks.
+const MAX_SLIDING: usize = 100;
+/// Lines indented more than this count as indented this much.
+const MAX_INDENT: i32 = 200;
+/// Runs of more blank lines than this count as this many.
+const MAX_BLANKS: i32 = 20;
+
+const START_OF_FILE_PENALTY: i32 = 1;
+const END_OF_FILE_PENALTY: i32 = 21;
+const TOTAL_BLANK_WEIGHT: i32 = -30;
+const POST_BLANK_WEIGHT: i32 = 6;
+const RELATIVE_INDENT_PENALTY: i32 = -4;
+const RELATIVE_INDENT_WITH_BLANK_PENALTY: i32 = 10;
+const RELATIVE_OUTDENT_PENALTY: i32 = 24;
+const RELATIVE_OUTDENT_WITH_BLANK_PENALTY: i32 = 17;
+const RELATIVE_DEDENT_PENALTY: i32 = 23;
+const RELATIVE_DEDENT_WITH_BLANK_PENALTY: i32 = 17;
+const INDENT_WEIGHT: i32 = 60;
+
+/// The lines of text on each side.
+type Lines<'a> = (&'a [&'a [u8]], &'a [&'a [u8]]);
+
+/// Slides each run of removed lines and then of added lines, as git does:
+/// first merging it with any runs it can reach, then lining it up with
+/// changes on the other side if it can, or else with `lines` given, where
+/// git's indent heuristic says it reads best, or else as far down as it goes.
+///
+/// `old` and `new` are what's compared of each line, and `lines` the lines
+/// themselves if they're lines of text.
+pub fn compact<T: Eq>(edits: &[Edit], old: &[T], new: &[T], lines: Option<Lines>) -> Vec<Edit> {
+    let mut old = File::new(old, lines.map(|(old, _)| old));
+    let mut new = File::new(new, lines.map(|(_, new)| new));
+    for edit in edits {
+        match *edit {
+            Edit::Delete(o) => old.changed[o] = true,
+            Edit::Insert(n) => new.changed[n] = true,
+            Edit::Equal(..) => {}
+        }
+    }
+    old.compact(&new);
+    new.compact(&old);
+
+    edits_from_changes(&old.changed, &new.changed)
+}
+
+struct File<'a, T> {
+    keys: &'a [T],
+    lines: Option<&'a [&'a [u8]]>,
+    changed: Vec<bool>,
+}
+
+/// The changed lines between two unchanged ones, or the start or end, which
+/// may be none. As unchanged lines pair up, each group has one on the other
+/// side.
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    fn len(self) -> usize {
+        self.end - self.start
+    }
+}
+
+impl<'a, T: Eq> File<'a, T> {
+    fn new(keys: &'a [T], lines: Option<&'a [&'a [u8]]>) -> Self {
+        Self {
+            keys,
+            lines,
+            changed: vec![false; keys.len()],
+        }
+    }
+
+    fn is_changed(&self, i: usize) -> bool {
+        self.changed.get(i).copied().unwrap_or(false)
+    }
+
+    fn first_group(&self) -> Group {
+        let mut group = Group { start: 0, end: 0 };
+        while self.is_changed(group.end) {
+            group.end += 1;
+        }
+        group
+    }
+
+    fn next_group(&self, group: &mut Group) -> bool {
+        if group.end == self.keys.len() {
+            return false;
+        }
+        group.start = group.end + 1;
+        group.end = group.start;
+        while self.is_changed(group.end) {
+            group.end += 1;
+        }
+        true
+    }
+
+    fn previous_group(&self, group: &mut Group) -> bool {
+        if group.start == 0 {
+            return false;
+        }
+        group.end = group.start - 1;
+        group.start = group.end;
+        while group.start > 0 && self.is_changed(group.start - 1) {
+            group.start -= 1;
+        }
+        true
+    }
+
+    /// Moves a group down a line if the line after it is the same as its
+    /// first line, merging it with the group after that if they meet.
+    fn slide_down(&mut self, group: &mut Group) -> bool {
+        if group.end == self.keys.len() || self.keys[group.start] != self.keys[group.end] {
+            return false;
+        }
+        self.changed[group.start] = false;
+        self.changed[group.end] = true;
+        group.start += 1;
+        group.end += 1;
+        while self.is_changed(group.end) {
+            group.end += 1;
+        }
+        true
+    }
+
+    fn slide_up(&mut self, group: &mut Group) -> bool {
+        if group.start == 0 || self.keys[group.start - 1] != self.keys[group.end - 1] {
+            return false;
+        }
+        group.start -= 1;
+        group.end -= 1;
+        self.changed[group.start] = true;
+        self.changed[group.end] = false;
+        while group.start > 0 && self.is_changed(group.start - 1) {
+            group.start -= 1;
+        }
+        true
+    }
+
+    fn compact(&mut self, other: &File<T>) {
+        let sync = "Groups on each side pair up";
+        let mut group = self.first_group();
+        let mut other_group = other.first_group();
+        loop {
+            if !group.is_empty() {
+                self.slide_group(&mut group, other, &mut other_group);
+            }
+            if !self.next_group(&mut group) {
+                break;
+            }
+            assert!(other.next_group(&mut other_group), "{}", sync);
+        }
+    }
+
+    fn slide_group(&mut self, group: &mut Group, other: &File<T>, other_group: &mut Group) {
+        let sync = "Groups on each side pair up";
+        let up = |this: &mut Self, group: &mut Group, other_group: &mut Group| {
+            let slid = this.slide_up(group);
+            if slid {
+                assert!(other.previous_group(other_group), "{}", sync);
+            }
+            slid
+        };
+
+        // Slide as far up and then down as it goes until it stops growing
+        let (mut earliest_end, mut end_matching_other);
+        loop {
+            let len = group.len();
+            while up(self, group, other_group) {}
+            earliest_end = group.end;
+            end_matching_other = Some(group.end).filter(|_| !other_group.is_empty());
+            while self.slide_down(group) {
+                assert!(other.next_group(other_group), "{}", sync);
+                if !other_group.is_empty() {
+                    end_matching_other = Some(group.end);
+                }
+            }
+            if len == group.len() {
+                break;
+            }
+        }
+
+        if group.end == earliest_end {
+            // It can't slide
+        } else if end_matching_other.is_some() {
+            while other_group.is_empty() {
+                assert!(up(self, group, other_group), "It slid down from there");
+            }
+        } else if let Some(lines) = self.lines {
+            let len = group.len();
+            let lowest = earliest_end
+                .max(group.start.saturating_sub(1))
+                .max(group.end.saturating_sub(MAX_SLIDING));
+            let mut best: Option<(usize, Score)> = None;
+            for end in lowest..=group.end {
+                let mut score = Score::default();
+                score.add(&Split::measure(lines, end));
+                score.add(&Split::measure(lines, end - len));
+                if best.map_or(true, |(_, best)| score.cmp(best) <= 0) {
+                    best = Some((end, score));
+                }
+            }
+            let best_end = best.map_or(group.end, |(end, _)| end);
+            while group.end > best_end {
+                assert!(up(self, group, other_group), "It slid down from there");
+            }
+        }
+    }
+}
+
+/// How much a line is indented, with tabs to multiples of 8, or `None` if
+/// it's blank.
+fn indent(line: &[u8]) -> Option<i32> {
+    let mut indent = 0;
+    for &b in line {
+        if !b.is_ascii_whitespace() {
+            return Some(indent);
+        }
+        match b {
+            b' ' => indent += 1,
+            b'\t' => indent += 8 - indent % 8,
+            _ => {}
+        }
+        if indent >= MAX_INDENT {
+            return Some(MAX_INDENT);
+        }
+    }
+    None
+}
+
+/// The lines around the place between two lines that a run of changes
+/// starts or ends at.
+#[derive(Debug)]
+struct Split {
+    end_of_file: bool,
+    /// Of the line after the split
+    indent: Option<i32>,
+    /// Blank lines just before the split
+    pre_blank: i32,
+    /// Of the nearest line before the split that isn't blank
+    pre_indent: Option<i32>,
+    /// Blank lines after the one after the split
+    post_blank: i32,
+    /// Of the nearest line after the one after the split that isn't blank
+    post_indent: Option<i32>,
+}
+
+impl Split {
+    /// The split before `lines[split]`.
+    fn measure(lines: &[&[u8]], split: usize) -> Self {
+        let mut pre_blank = 0;
+        let mut pre_indent = None;
+        for line in lines[..split].iter().rev() {
+            pre_indent = indent(line);
+            if pre_indent.is_some() {
+                break;
+            }
+            pre_blank += 1;
+            if pre_blank == MAX_BLANKS {
+                pre_indent = Some(0);
+                break;
+            }
+        }
+
+        let mut post_blank = 0;
+        let mut post_indent = None;
+        for line in lines.iter().skip(split + 1) {
+            post_indent = indent(line);
+            if post_indent.is_some() {
+                break;
+            }
+            post_blank += 1;
+            if post_blank == MAX_BLANKS {
+                post_indent = Some(0);
+                break;
+            }
+        }
+
+        Self {
+            end_of_file: split >= lines.len(),
+            indent: lines.get(split).and_then(|line| indent(line)),
+            pre_blank,
+            pre_indent,
+            post_blank,
+            post_indent,
+        }
+    }
+}
+
+/// How bad splits are, the lower the better.
+#[derive(Debug, Clone, Copy, Default)]
+struct Score {
+    effective_indent: i32,
+    penalty: i32,
+}
+
+impl Score {
+    /// Like git, splits are best before a line indented less than those
+    /// around it, and next to blank lines.
+    fn add(&mut self, split: &Split) {
+        if split.pre_indent.is_none() && split.pre_blank == 0 {
+            self.penalty += START_OF_FILE_PENALTY;
+        }
+        if split.end_of_file {
+            self.penalty += END_OF_FILE_PENALTY;
+        }
+
+        // Including the line after the split
+        let post_blank = if split.indent.is_none() {
+            1 + split.post_blank
+        } else {
+            0
+        };
+        let total_blank = split.pre_blank + post_blank;
+        self.penalty += TOTAL_BLANK_WEIGHT * total_blank;
+        self.penalty += POST_BLANK_WEIGHT * post_blank;
+
+        let indent = split.indent.or(split.post_indent);
+        let any_blanks = total_blank != 0;
+        // Like git, the end of the file counts as indented -1
+        self.effective_indent += indent.unwrap_or(-1);
+
+        let (indent, pre_indent) = match (indent, split.pre_indent) {
+            (Some(indent), Some(pre_indent)) => (indent, pre_indent),
+            _ => return,
+        };
+        let (with_blanks, without) = if indent > pre_indent {
+            (RELATIVE_INDENT_WITH_BLANK_PENALTY, RELATIVE_INDENT_PENALTY)
+        } else if indent == pre_indent {
+            return;
+        } else if split.post_indent.map_or(false, |post| post > indent) {
+            // Likely the start of a block
+            (
+                RELATIVE_OUTDENT_WITH_BLANK_PENALTY,
+                RELATIVE_OUTDENT_PENALTY,
+            )
+        } else {
+            // Likely the end of a block
+            (RELATIVE_DEDENT_WITH_BLANK_PENALTY, RELATIVE_DEDENT_PENALTY)
+        };
+        self.penalty += if any_blanks { with_blanks } else { without };
+    }
+
+    /// Negative if this is better than `other`.
+    fn cmp(self, other: Self) -> i32 {
+        let indents = (self.effective_indent - other.effective_indent).signum();
+        INDENT_WEIGHT * indents + self.penalty - other.penalty
+    }
+}
@@ -0,0 +1,133 @@
+//! The ways `git diff` can show diffs, as patches or as summaries of which
+//! files changed and by how much, and the order it shows them in.
+
+use std::io::{self, Write};
+
+use super::{
+    patch::{self, PatchOptions},
+    stat, FileDiff, Side,
+};
+use crate::core::stat::Mode;
+
+/// Which of the ways to show diffs are shown.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Formats {
+    pub patch: bool,
+    /// See [`stat::write_stat`]
+    pub stat: bool,
+    /// See [`stat::write_numstat`]
+    pub numstat: bool,
+    /// See [`stat::write_shortstat`]
+    pub shortstat: bool,
+    /// Files created and deleted and modes changed
+    pub summary: bool,
+    /// Only the names of the files that changed, leaving out everything
+    /// else
+    pub names: Option<Names>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Names {
+    /// Like `git diff --name-only`
+    Only,
+    /// Like `git diff --name-status`, each name after a letter saying how
+    /// it changed
+    Status,
+}
+
+impl Formats {
+    /// Whether a log shows `---` between each commit's message and its
+    /// diff, as git does when there's a stat before the patch.
+    pub fn dashes(&self) -> bool {
+        self.names.is_none() && self.stat && self.patch
+    }
+}
+
+/// Writes `diffs` in each of `formats`, in git's order: names, stats and the
+/// summary, and then the patch after a blank line if any of those were
+/// shown.
+pub fn write<W: Write>(
+    out: &mut W,
+    diffs: &[FileDiff],
+    formats: &Formats,
+    opts: &PatchOptions,
+) -> io::Result<()> {
+    if diffs.is_empty() {
+        return Ok(());
+    }
+    match formats.names {
+        Some(Names::Only) => {
+            for diff in diffs {
+                writeln!(out, "{}", diff.path.as_bstr())?;
+            }
+            return Ok(());
+        }
+        Some(Names::Status) => {
+            for diff in diffs {
+                writeln!(out, "{}\t{}", status(diff), diff.path.as_bstr())?;
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let mut separate = false;
+    if formats.numstat || formats.stat || formats.shortstat {
+        let stats = stat::stats(diffs, opts.lines);
+        if formats.numstat {
+            stat::write_numstat(out, &stats)?;
+        }
+        if formats.stat {
+            stat::write_stat(out, &stats, opts.stat_width, opts.color)?;
+        }
+        if formats.shortstat {
+            stat::write_shortstat(out, &stats)?;
+        }
+        separate = true;
+    }
+    if formats.summary {
+        let mut summary = Vec::new();
+        write_summary(&mut summary, diffs)?;
+        if !summary.is_empty() {
+            out.write_all(&summary)?;
+            separate = true;
+        }
+    }
+    if formats.patch {
+        if separate {
+            writeln!(out)?;
+        }
+        patch::write(out, diffs, opts)?;
+    }
+    Ok(())
+}
+
+/// The letter `git diff --name-status` shows for how a file changed: added,
+/// deleted, modified, or changed type, like between a file and a submodule.
+fn status(diff: &FileDiff) -> char {
+    match (&diff.old, &diff.new) {
+        (None, _) => 'A',
+        (_, None) => 'D',
+        (Some(old), Some(new)) if (old.mode == Mode::Gitlink) != (new.mode == Mode::Gitlink) => 'T',
+        _ => 'M',
+    }
+}
+
+/// Writes the files created and deleted and whose modes changed, like `git
+/// diff --summary`.
+fn write_summary<W: Write>(out: &mut W, diffs: &[FileDiff]) -> io::Result<()> {
+    let mode = |side: &Side| side.mode.as_base8();
+    for diff in diffs {
+        let path = diff.path.as_bstr();
+        match (&diff.old, &diff.new) {
+            (None, Some(new)) => writeln!(out, " create mode {} {}", mode(new), path)?,
+            (Some(old), None) => writeln!(out, " delete mode {} {}", mode(old), path)?,
+            (Some(old), Some(new)) if old.mode != new.mode => {
+                writeln!(out, " mode change {} => {} {}", mode(old), mode(new), path)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,196 @@
+//! Histogram diff, git's extension of patience diff that lines up the run of
+//! equal lines whose lines are found the fewest times in `old`, so that it
+//! still finds anchors when no line is unique.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+    ops::Range,
+};
+
+use super::{myers, Edit};
+
+/// Like git, lines found more often than this are only lined up by falling
+/// back to Myers' algorithm.
+const MAX_COUNT: usize = 64;
+
+/// The edits turning `old` into `new`, like git's histogram diff.
+pub fn diff<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    histogram(old, 0..old.len(), new, 0..new.len(), &mut edits);
+    edits
+}
+
+fn histogram<T: Eq + Hash>(
+    old: &[T],
+    mut olds: Range<usize>,
+    new: &[T],
+    mut news: Range<usize>,
+    edits: &mut Vec<Edit>,
+) {
+    while !olds.is_empty() && !news.is_empty() {
+        match Index::new(old, olds.clone()).find_lcs(new, news.clone()) {
+            Lcs::Found(found) => {
+                histogram(
+                    old,
+                    olds.start..found.old.start,
+                    new,
+                    news.start..found.new.start,
+                    edits,
+                );
+                edits.extend(
+                    found
+                        .old
+                        .clone()
+                        .zip(found.new.clone())
+                        .map(|(o, n)| Edit::Equal(o, n)),
+                );
+                olds.start = found.old.end;
+                news.start = found.new.end;
+            }
+            Lcs::TooCommon => {
+                myers::diff_ranges(old, olds, new, news, edits);
+                return;
+            }
+            Lcs::None => break,
+        }
+    }
+    edits.extend(olds.map(Edit::Delete));
+    edits.extend(news.map(Edit::Insert));
+}
+
+/// Where each line of `olds` is found in it.
+struct Index<'a, T> {
+    old: &'a [T],
+    olds: Range<usize>,
+    records: HashMap<&'a T, Record>,
+    /// The next line after each with the same contents
+    next: Vec<Option<usize>>,
+}
+
+struct Record {
+    first: usize,
+    count: usize,
+}
+
+/// Equal lines at `old` and `new`.
+#[derive(Debug)]
+struct Region {
+    old: Range<usize>,
+    new: Range<usize>,
+}
+
+enum Lcs {
+    Found(Region),
+    /// The lines in common are all found more than [`MAX_COUNT`] times
+    TooCommon,
+    None,
+}
+
+impl<'a, T: Eq + Hash> Index<'a, T> {
+    fn new(old: &'a [T], olds: Range<usize>) -> Self {
+        let mut records = HashMap::new();
+        let mut next = vec![None; olds.len()];
+        for o in olds.clone().rev() {
+            match records.entry(&old[o]) {
+                Entry::Occupied(mut entry) => {
+                    let record: &mut Record = entry.get_mut();
+                    next[o - olds.start] = Some(record.first);
+                    record.first = o;
+                    record.count += 1;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Record { first: o, count: 1 });
+                }
+            }
+        }
+        Self {
+            old,
+            olds,
+            records,
+            next,
+        }
+    }
+
+    fn count(&self, o: usize) -> usize {
+        self.records[&self.old[o]].count
+    }
+
+    /// Like git, of the longest runs of equal lines with the fewest times
+    /// any of their lines is found in `olds`, the first found in `news`.
+    fn find_lcs(&self, new: &[T], news: Range<usize>) -> Lcs {
+        let mut lcs: Option<Region> = None;
+        let mut lcs_count = MAX_COUNT + 1;
+        let mut has_common = false;
+
+        let mut n = news.start;
+        while n < news.end {
+            let mut next_n = n + 1;
+            let record = if let Some(record) = self.records.get(&new[n]) {
+                record
+            } else {
+                n = next_n;
+                continue;
+            };
+            has_common = true;
+            if record.count > lcs_count {
+                n = next_n;
+                continue;
+            }
+
+            let mut o = record.first;
+            loop {
+                let (mut old_start, mut new_start) = (o, n);
+                let (mut old_end, mut new_end) = (o + 1, n + 1);
+                let mut count = record.count;
+                while old_start > self.olds.start
+                    && new_start > news.start
+                    && self.old[old_start - 1] == new[new_start - 1]
+                {
+                    old_start -= 1;
+                    new_start -= 1;
+                    if count > 1 {
+                        count = count.min(self.count(old_start));
+                    }
+                }
+                while old_end < self.olds.end
+                    && new_end < news.end
+                    && self.old[old_end] == new[new_end]
+                {
+                    if count > 1 {
+                        count = count.min(self.count(old_end));
+                    }
+                    old_end += 1;
+                    new_end += 1;
+                }
+
+                next_n = next_n.max(new_end);
+                let lcs_len = lcs.as_ref().map_or(0, |lcs| lcs.old.len() - 1);
+                if lcs_len < old_end - old_start - 1 || count < lcs_count {
+                    lcs = Some(Region {
+                        old: old_start..old_end,
+                        new: new_start..new_end,
+                    });
+                    lcs_count = count;
+                }
+
+                // The next line like this one after the run
+                let mut next_o = self.next[o - self.olds.start];
+                while let Some(later) = next_o.filter(|&later| later < old_end) {
+                    next_o = self.next[later - self.olds.start];
+                }
+                match next_o {
+                    Some(next_o) => o = next_o,
+                    None => break,
+                }
+            }
+            n = next_n;
+        }
+
+        match lcs {
+            Some(lcs) => Lcs::Found(lcs),
+            None if has_common => Lcs::TooCommon,
+            None => Lcs::None,
+        }
+    }
+}
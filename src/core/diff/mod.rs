@@ -0,0 +1,408 @@
+//! Showing how files differ, like `git diff`: which files differ between
+//! two versions of the tree, and how their lines do.
+
+mod compact;
+pub mod format;
+pub mod histogram;
+pub mod myers;
+pub mod patch;
+pub mod patience;
+pub mod stat;
+pub mod words;
+
+use bstr::{BString, ByteSlice};
+use std::{borrow::Cow, hash::Hash, str::FromStr};
+
+use crate::core::{
+    config::{Config, InvalidValueError},
+    db::Blob,
+    stat::Mode,
+    Oid, WsPath,
+};
+
+/// The colours git shows diffs in by default.
+mod colors {
+    pub const BOLD: &str = "\x1b[1m";
+    pub const CYAN: &str = "\x1b[36m";
+    pub const RED: &str = "\x1b[31m";
+    pub const GREEN: &str = "\x1b[32m";
+    pub const RED_BACKGROUND: &str = "\x1b[41m";
+    pub const RESET: &str = "\x1b[m";
+}
+
+/// How many bytes at the start of a file are checked for NUL to decide if
+/// it's binary, as git does.
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// A file that differs between two versions, with its contents in each.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FileDiff {
+    pub path: WsPath,
+    /// `None` if it was added
+    pub old: Option<Side>,
+    /// `None` if it was deleted
+    pub new: Option<Side>,
+    /// Whether the contents are shown as lines, which is decided by the
+    /// `diff` attribute or else by whether either side looks binary
+    pub binary: bool,
+}
+
+/// One version of a file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Side {
+    pub mode: Mode,
+    pub oid: Oid<Blob>,
+    /// For a submodule, the line `Subproject commit <oid>` like git shows
+    pub data: BString,
+}
+
+impl Side {
+    pub fn new(mode: Mode, oid: Oid<Blob>, data: BString) -> Self {
+        Self { mode, oid, data }
+    }
+
+    /// A submodule whose gitlink is `oid`, shown as a line naming the
+    /// commit.
+    pub fn gitlink(oid: Oid<Blob>) -> Self {
+        let data = format!("Subproject commit {}\n", oid.to_hex());
+        Self::new(Mode::Gitlink, oid, data.into())
+    }
+}
+
+impl FileDiff {
+    /// Decides whether it's binary from the contents.
+    pub fn new(path: WsPath, old: Option<Side>, new: Option<Side>) -> Self {
+        let binary = old.iter().chain(&new).any(|side| looks_binary(&side.data));
+        Self {
+            path,
+            old,
+            new,
+            binary,
+        }
+    }
+
+    /// Whether the contents or mode differ, rather than only the stat of a
+    /// file in the workspace.
+    pub fn is_changed(&self) -> bool {
+        let version = |side: &Option<Side>| side.as_ref().map(|side| (side.mode, side.oid));
+        version(&self.old) != version(&self.new)
+    }
+
+    /// The edits between the lines of each side, see [`lines`]. Like git,
+    /// runs of changes that could as well be a few lines up or down are put
+    /// where they read best.
+    pub fn line_edits(&self, opts: LineOptions) -> Vec<Edit> {
+        let old = self
+            .old
+            .as_ref()
+            .map_or_else(Vec::new, |side| lines(&side.data));
+        let new = self
+            .new
+            .as_ref()
+            .map_or_else(Vec::new, |side| lines(&side.data));
+        let (old_keys, new_keys) = (opts.whitespace.keys(&old), opts.whitespace.keys(&new));
+        let edits = opts.algorithm.diff(&old_keys, &new_keys);
+        compact::compact(&edits, &old_keys, &new_keys, Some((&old, &new)))
+    }
+}
+
+/// The edits between sides with lines `old_changed` and `new_changed`
+/// removed and added, with those removed before those added where they
+/// meet.
+fn edits_from_changes(old_changed: &[bool], new_changed: &[bool]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old_changed.len().max(new_changed.len()));
+    let (mut o, mut n) = (0, 0);
+    while o < old_changed.len() || n < new_changed.len() {
+        if old_changed.get(o) == Some(&true) {
+            edits.push(Edit::Delete(o));
+            o += 1;
+        } else if new_changed.get(n) == Some(&true) {
+            edits.push(Edit::Insert(n));
+            n += 1;
+        } else {
+            edits.push(Edit::Equal(o, n));
+            o += 1;
+            n += 1;
+        }
+    }
+    edits
+}
+
+/// How lines are compared.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct LineOptions {
+    pub algorithm: Algorithm,
+    pub whitespace: Whitespace,
+}
+
+/// Which algorithm finds the edits between lines, like `--diff-algorithm`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Algorithm {
+    /// See [`myers`]
+    Myers,
+    /// See [`patience`]
+    Patience,
+    /// See [`histogram`]
+    Histogram,
+}
+
+impl Default for Algorithm {
+    fn default() -> Self {
+        Self::Myers
+    }
+}
+
+impl Algorithm {
+    /// Reads `diff.algorithm`.
+    pub fn from_config(config: &Config) -> Result<Self, InvalidValueError> {
+        let key = "diff.algorithm";
+        match config.get(key) {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| InvalidValueError::new(key, value)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The edits turning `old` into `new`, before they're slid like git
+    /// does, see [`FileDiff::line_edits`].
+    pub fn diff<T: Eq + Hash>(self, old: &[T], new: &[T]) -> Vec<Edit> {
+        match self {
+            Self::Myers => myers::diff(old, new),
+            Self::Patience => patience::diff(old, new),
+            Self::Histogram => histogram::diff(old, new),
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = ParseAlgorithmError;
+
+    /// Like git, `default` is Myers' algorithm, and so is `minimal` as git's
+    /// shortcuts for huge diffs it turns off aren't taken here.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "myers" | "default" | "minimal" => Ok(Self::Myers),
+            "patience" => Ok(Self::Patience),
+            "histogram" => Ok(Self::Histogram),
+            _ => Err(ParseAlgorithmError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Unknown diff algorithm {0:?}, expected myers, minimal, patience or
+/// histogram
+pub struct ParseAlgorithmError(String);
+
+/// Which changes to whitespace make lines differ.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Whitespace {
+    All,
+    /// Like `--ignore-space-change`, only whitespace being added where there
+    /// was none or removed entirely, other than at the end of the line
+    IgnoreChange,
+    /// Like `--ignore-all-space`, none
+    IgnoreAll,
+}
+
+impl Default for Whitespace {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl Whitespace {
+    /// What's compared of `line`, which is the same for lines that differ
+    /// only in ignored whitespace. That includes the line ending, so like git
+    /// a missing one at the end of the file is ignored too.
+    pub fn key(self, line: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            Self::All => Cow::Borrowed(line),
+            Self::IgnoreChange => {
+                let mut key = Vec::with_capacity(line.len());
+                for word in line.fields() {
+                    if !key.is_empty() {
+                        key.push(b' ');
+                    }
+                    key.extend_from_slice(word);
+                }
+                // Whitespace at the start still counts
+                if line.first().map_or(false, u8::is_ascii_whitespace) && !key.is_empty() {
+                    key.insert(0, b' ');
+                }
+                Cow::Owned(key)
+            }
+            Self::IgnoreAll => Cow::Owned(
+                line.iter()
+                    .copied()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect(),
+            ),
+        }
+    }
+
+    fn keys<'l>(self, lines: &[&'l [u8]]) -> Vec<Cow<'l, [u8]>> {
+        lines.iter().map(|line| self.key(line)).collect()
+    }
+}
+
+/// Like git, a file is binary if there's a NUL near its start.
+pub fn looks_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_CHECK_LEN)].contains(&b'\0')
+}
+
+/// The lines of `data`, each with its line ending so that a last line
+/// without one differs from the same line with one.
+pub fn lines(data: &[u8]) -> Vec<&[u8]> {
+    data.lines_with_terminator().collect()
+}
+
+/// How one line changes, by its index in the old or new lines.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Edit {
+    /// The line at the old index is kept as the one at the new index
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+impl Edit {
+    pub fn is_equal(self) -> bool {
+        matches!(self, Self::Equal(..))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    /// Applies `edits` to `old`, checking they only keep equal lines.
+    fn apply<'a>(old: &[&'a str], new: &[&'a str], edits: &[Edit]) -> Vec<&'a str> {
+        let mut result = Vec::new();
+        let (mut next_old, mut next_new) = (0, 0);
+        for &edit in edits {
+            match edit {
+                Edit::Equal(o, n) => {
+                    assert_eq!((next_old, next_new), (o, n));
+                    assert_eq!(old[o], new[n]);
+                    result.push(new[n]);
+                    next_old += 1;
+                    next_new += 1;
+                }
+                Edit::Delete(o) => {
+                    assert_eq!(next_old, o);
+                    next_old += 1;
+                }
+                Edit::Insert(n) => {
+                    assert_eq!(next_new, n);
+                    result.push(new[n]);
+                    next_new += 1;
+                }
+            }
+        }
+        assert_eq!((old.len(), new.len()), (next_old, next_new));
+        result
+    }
+
+    #[test]
+    fn myers_finds_shortest_edits() {
+        init();
+        let cases: &[(&str, &str, usize)] = &[
+            ("abcabba", "cbabac", 5),
+            ("", "abc", 3),
+            ("abc", "", 3),
+            ("abc", "abc", 0),
+            ("abcdef", "azcdxf", 4),
+            ("aaaa", "aa", 2),
+            ("abcd", "dcba", 6),
+        ];
+        for &(old, new, distance) in cases {
+            let old = old.split("").filter(|s| !s.is_empty()).collect::<Vec<_>>();
+            let new = new.split("").filter(|s| !s.is_empty()).collect::<Vec<_>>();
+            let edits = myers::diff(&old, &new);
+
+            assert_eq!(new, apply(&old, &new, &edits));
+            let changes = edits.iter().filter(|edit| !edit.is_equal()).count();
+            assert_eq!(distance, changes, "{:?} {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn algorithms_keep_unique_lines_together() {
+        init();
+        let old = ["a", "{", "b", "}", "c", "{", "d", "}"];
+        let new = ["c", "{", "d", "}", "a", "{", "b", "}"];
+        let kept = |algorithm: Algorithm| {
+            let edits = algorithm.diff(&old, &new);
+            assert_eq!(new.to_vec(), apply(&old, &new, &edits), "{:?}", algorithm);
+            edits
+                .iter()
+                .filter_map(|edit| match *edit {
+                    Edit::Equal(o, _) => Some(old[o]),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Myers' algorithm matches braces as readily as anything else
+        assert_eq!(4, kept(Algorithm::Myers).len());
+        assert_eq!(vec!["c", "{", "d", "}"], kept(Algorithm::Patience));
+        assert_eq!(vec!["c", "{", "d", "}"], kept(Algorithm::Histogram));
+    }
+
+    #[test]
+    fn ignores_whitespace() {
+        init();
+        let same = |whitespace: Whitespace, old: &str, new: &str| {
+            whitespace.key(old.as_bytes()) == whitespace.key(new.as_bytes())
+        };
+
+        assert!(!same(Whitespace::All, "a b\n", "a  b\n"));
+        assert!(same(Whitespace::IgnoreChange, "a b\n", "a \t b \n"));
+        assert!(same(Whitespace::IgnoreChange, "  a\n", "\ta"));
+        assert!(!same(Whitespace::IgnoreChange, "ab\n", "a b\n"));
+        assert!(!same(Whitespace::IgnoreChange, "a\n", " a\n"));
+        assert!(same(Whitespace::IgnoreAll, "ab\n", " a b\n"));
+    }
+
+    #[test]
+    fn slides_changes_like_git() {
+        init();
+        let lines = |text: &'static str| {
+            text.split_inclusive('\n')
+                .map(str::as_bytes)
+                .collect::<Vec<_>>()
+        };
+        let old = lines("x\nfn a() {\n}\n");
+        let new = lines("y\nfn a() {\n}\n\nfn b() {\n}\n");
+        // Myers' algorithm adds "}\n\nfn b() {\n" before the last brace
+        let edits = compact::compact(&myers::diff(&old, &new), &old, &new, Some((&old, &new)));
+
+        let added = edits
+            .iter()
+            .filter_map(|edit| match *edit {
+                Edit::Insert(n) => Some(new[n]),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines("y\n\nfn b() {\n}\n"), added);
+    }
+
+    #[test]
+    fn detects_binary_and_changes() {
+        init();
+        let side =
+            |data: &str| Side::new(Mode::Regular, Blob::oid_for_file(data.into()), data.into());
+        let path = WsPath::new_unchecked("f");
+
+        assert!(!FileDiff::new(path.clone(), Some(side("a\n")), Some(side("b"))).binary);
+        let diff = FileDiff::new(path.clone(), None, Some(side("a\0b")));
+        assert!(diff.binary && diff.is_changed());
+        assert!(!FileDiff::new(path, Some(side("a")), Some(side("a"))).is_changed());
+    }
+}
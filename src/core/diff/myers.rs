@@ -0,0 +1,344 @@
+//! Myers' O(ND) difference algorithm, in linear space by recursing on the
+//! middle snake, see "An O(ND) Difference Algorithm and Its Variations".
+//!
+//! This follows git's version, so that of the edit scripts as short as each
+//! other it finds the same one: lines found nowhere on the other side are
+//! set aside as changed first, and so are runs of lines found too often
+//! there among them.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::{Index, IndexMut, Range},
+};
+
+use super::{edits_from_changes, Edit};
+
+/// Like git, lines found on the other side more often than the square root
+/// of how many lines there are, up to this, are found too often.
+const MAX_EQ_LIMIT: usize = 1024;
+/// How far around a line found too often is looked at to decide whether to
+/// set it aside.
+const SIMSCAN_WINDOW: usize = 100;
+/// Lines found too often are set aside among lines found nowhere if there
+/// are more than this many times as many lines.
+const KPDIS_RUN: usize = 4;
+
+/// The edits turning `old` into `new`, with as few insertions and deletions
+/// as there can be but for lines set aside.
+pub fn diff<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Edit> {
+    // Equal lines share a class, which are cheaper to compare
+    let mut classes = HashMap::new();
+    let mut classify = |line| {
+        let class = classes.len();
+        *classes.entry(line).or_insert(class)
+    };
+    let old = old.iter().map(&mut classify).collect::<Vec<_>>();
+    let new = new.iter().map(&mut classify).collect::<Vec<_>>();
+    let mut counts = vec![(0, 0); classes.len()];
+    for &class in &old {
+        counts[class].0 += 1;
+    }
+    for &class in &new {
+        counts[class].1 += 1;
+    }
+
+    let prefix = old.iter().zip(&new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let mut old = Lines::new(&old, prefix..old.len() - suffix, |class| counts[class].1);
+    let mut new = Lines::new(&new, prefix..new.len() - suffix, |class| counts[class].0);
+
+    let mut forward = V::new(old.classes.len(), new.classes.len());
+    let mut backward = V::new(old.classes.len(), new.classes.len());
+    let (olds, news) = (0..old.classes.len(), 0..new.classes.len());
+    conquer(&mut old, olds, &mut new, news, &mut forward, &mut backward);
+    edits_from_changes(&old.changed, &new.changed)
+}
+
+/// Adds the edits turning `olds` of `old` into `news` of `new` to `edits`,
+/// for the other algorithms to fall back on.
+pub(super) fn diff_ranges<T: Eq + Hash>(
+    old: &[T],
+    olds: Range<usize>,
+    new: &[T],
+    news: Range<usize>,
+    edits: &mut Vec<Edit>,
+) {
+    let (o, n) = (olds.start, news.start);
+    edits.extend(
+        diff(&old[olds], &new[news])
+            .into_iter()
+            .map(|edit| match edit {
+                Edit::Equal(old, new) => Edit::Equal(o + old, n + new),
+                Edit::Delete(old) => Edit::Delete(o + old),
+                Edit::Insert(new) => Edit::Insert(n + new),
+            }),
+    );
+}
+
+/// The lines of one side left to compare once those that can't be lined up
+/// are set aside.
+struct Lines {
+    classes: Vec<usize>,
+    /// Where each of `classes` is on the side
+    index: Vec<usize>,
+    changed: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Matches {
+    None,
+    Some,
+    TooMany,
+}
+
+impl Lines {
+    /// Sets aside lines of `range` that aren't found on the other side, which
+    /// `matches` counts, or which are found too often among those that
+    /// aren't.
+    fn new(classes: &[usize], range: Range<usize>, matches: impl Fn(usize) -> usize) -> Self {
+        let limit = sqrt(classes.len()).min(MAX_EQ_LIMIT);
+        let found = classes[range.clone()]
+            .iter()
+            .map(|&class| match matches(class) {
+                0 => Matches::None,
+                n if n >= limit => Matches::TooMany,
+                _ => Matches::Some,
+            })
+            .collect::<Vec<_>>();
+
+        let mut lines = Self {
+            classes: Vec::with_capacity(range.len()),
+            index: Vec::with_capacity(range.len()),
+            changed: vec![false; classes.len()],
+        };
+        for (i, line) in range.enumerate() {
+            let kept = match found[i] {
+                Matches::None => false,
+                Matches::Some => true,
+                Matches::TooMany => !among_unmatched(&found, i),
+            };
+            if kept {
+                lines.classes.push(classes[line]);
+                lines.index.push(line);
+            } else {
+                lines.changed[line] = true;
+            }
+        }
+        lines
+    }
+}
+
+/// Like git's rough square root, a power of two.
+fn sqrt(mut n: usize) -> usize {
+    let mut root = 1;
+    while n > 0 {
+        root <<= 1;
+        n >>= 2;
+    }
+    root
+}
+
+/// Whether the line at `i`, found too often, is in a run of lines found
+/// too often or not at all, with some of the latter both before and after it
+/// and those being enough of the run.
+fn among_unmatched(found: &[Matches], i: usize) -> bool {
+    let start = i.saturating_sub(SIMSCAN_WINDOW);
+    let end = (i + SIMSCAN_WINDOW).min(found.len() - 1);
+    let run = |lines: &mut dyn Iterator<Item = usize>| {
+        let (mut unmatched, mut too_many) = (0, 1);
+        for j in lines {
+            match found[j] {
+                Matches::None => unmatched += 1,
+                Matches::TooMany => too_many += 1,
+                Matches::Some => break,
+            }
+        }
+        (unmatched, too_many)
+    };
+
+    let (unmatched_before, too_many_before) = run(&mut (start..i).rev());
+    if unmatched_before == 0 {
+        return false;
+    }
+    let (unmatched_after, too_many_after) = run(&mut (i + 1..=end));
+    if unmatched_after == 0 {
+        return false;
+    }
+    let too_many = too_many_before + too_many_after;
+    too_many * KPDIS_RUN < too_many + unmatched_before + unmatched_after
+}
+
+/// The furthest line of `old` reached on each diagonal, which is where in
+/// `old` less where in `new` and can be negative.
+///
+/// Lengths are far below `isize::MAX`, so casting between them and
+/// diagonals is lossless.
+struct V {
+    offset: isize,
+    v: Vec<isize>,
+}
+
+#[allow(clippy::cast_possible_wrap)]
+impl V {
+    fn new(old_len: usize, new_len: usize) -> Self {
+        Self {
+            offset: new_len as isize + 1,
+            v: vec![0; old_len + new_len + 3],
+        }
+    }
+}
+
+#[allow(clippy::cast_sign_loss)]
+impl Index<isize> for V {
+    type Output = isize;
+
+    fn index(&self, d: isize) -> &isize {
+        &self.v[(d + self.offset) as usize]
+    }
+}
+
+#[allow(clippy::cast_sign_loss)]
+impl IndexMut<isize> for V {
+    fn index_mut(&mut self, d: isize) -> &mut isize {
+        &mut self.v[(d + self.offset) as usize]
+    }
+}
+
+fn conquer(
+    old: &mut Lines,
+    mut olds: Range<usize>,
+    new: &mut Lines,
+    mut news: Range<usize>,
+    forward: &mut V,
+    backward: &mut V,
+) {
+    while !olds.is_empty() && !news.is_empty() && old.classes[olds.start] == new.classes[news.start]
+    {
+        olds.start += 1;
+        news.start += 1;
+    }
+    while !olds.is_empty()
+        && !news.is_empty()
+        && old.classes[olds.end - 1] == new.classes[news.end - 1]
+    {
+        olds.end -= 1;
+        news.end -= 1;
+    }
+
+    if olds.is_empty() {
+        for n in news {
+            new.changed[new.index[n]] = true;
+        }
+    } else if news.is_empty() {
+        for o in olds {
+            old.changed[old.index[o]] = true;
+        }
+    } else {
+        let (o, n) = middle_snake(
+            &old.classes,
+            olds.clone(),
+            &new.classes,
+            news.clone(),
+            forward,
+            backward,
+        );
+        conquer(old, olds.start..o, new, news.start..n, forward, backward);
+        conquer(old, o..olds.end, new, n..news.end, forward, backward);
+    }
+}
+
+/// Where a shortest edit script crosses the middle, found like git by
+/// searching from both ends at once along the diagonals within the box.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn middle_snake(
+    old: &[usize],
+    olds: Range<usize>,
+    new: &[usize],
+    news: Range<usize>,
+    forward: &mut V,
+    backward: &mut V,
+) -> (usize, usize) {
+    let (old_start, old_end) = (olds.start as isize, olds.end as isize);
+    let (new_start, new_end) = (news.start as isize, news.end as isize);
+    let (d_min, d_max) = (old_start - new_end, old_end - new_start);
+    let (start_d, end_d) = (old_start - new_start, old_end - new_end);
+    let odd = (start_d - end_d) & 1 == 1;
+    let (mut forward_min, mut forward_max) = (start_d, start_d);
+    let (mut backward_min, mut backward_max) = (end_d, end_d);
+    forward[start_d] = old_start;
+    backward[end_d] = old_end;
+
+    loop {
+        // Widen the diagonals searched by one each way, or where that leaves
+        // the box, narrow them so they keep to every other diagonal
+        if forward_min > d_min {
+            forward_min -= 1;
+            forward[forward_min - 1] = -1;
+        } else {
+            forward_min += 1;
+        }
+        if forward_max < d_max {
+            forward_max += 1;
+            forward[forward_max + 1] = -1;
+        } else {
+            forward_max -= 1;
+        }
+
+        let mut d = forward_max;
+        while d >= forward_min {
+            let mut o = if forward[d - 1] >= forward[d + 1] {
+                forward[d - 1] + 1
+            } else {
+                forward[d + 1]
+            };
+            let mut n = o - d;
+            while o < old_end && n < new_end && old[o as usize] == new[n as usize] {
+                o += 1;
+                n += 1;
+            }
+            forward[d] = o;
+            if odd && backward_min <= d && d <= backward_max && backward[d] <= o {
+                return (o as usize, n as usize);
+            }
+            d -= 2;
+        }
+
+        if backward_min > d_min {
+            backward_min -= 1;
+            backward[backward_min - 1] = isize::MAX;
+        } else {
+            backward_min += 1;
+        }
+        if backward_max < d_max {
+            backward_max += 1;
+            backward[backward_max + 1] = isize::MAX;
+        } else {
+            backward_max -= 1;
+        }
+
+        let mut d = backward_max;
+        while d >= backward_min {
+            let mut o = if backward[d - 1] < backward[d + 1] {
+                backward[d - 1]
+            } else {
+                backward[d + 1] - 1
+            };
+            let mut n = o - d;
+            while o > old_start && n > new_start && old[o as usize - 1] == new[n as usize - 1] {
+                o -= 1;
+                n -= 1;
+            }
+            backward[d] = o;
+            if !odd && forward_min <= d && d <= forward_max && o <= forward[d] {
+                return (o as usize, n as usize);
+            }
+            d -= 2;
+        }
+    }
+}
@@ -0,0 +1,517 @@
+//! Unified diffs, the patches `git diff` shows by default.
+
+use bstr::{BStr, ByteSlice};
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use super::{
+    colors::{BOLD, CYAN, GREEN, RED, RED_BACKGROUND, RESET},
+    lines,
+    words::{self, WordDiff},
+    Edit, FileDiff, LineOptions, Side,
+};
+
+const ABBREV: usize = 7;
+/// Like git, at most this much of the line a hunk is in is shown after its
+/// header.
+const FUNC_LEN: usize = 80;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PatchOptions {
+    /// How many unchanged lines to show around changes, also joining hunks
+    /// up to twice as far apart
+    pub context: usize,
+    /// Colour headers, hunk headers and changed lines as git does
+    pub color: bool,
+    pub lines: LineOptions,
+    /// Show the words that changed within changed lines, rather than the
+    /// lines removed and added
+    pub word_diff: Option<WordDiff>,
+    /// How many columns a stat is cut short to fit in
+    pub stat_width: usize,
+}
+
+impl Default for PatchOptions {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            color: false,
+            lines: LineOptions::default(),
+            word_diff: None,
+            stat_width: 80,
+        }
+    }
+}
+
+/// A run of lines with changes and the context around them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Hunk {
+    /// Where the hunk starts in the old and new lines, counting from zero
+    pub old_start: usize,
+    pub new_start: usize,
+    pub old_len: usize,
+    pub new_len: usize,
+    /// The edits in the hunk
+    pub edits: Range<usize>,
+}
+
+impl Hunk {
+    /// The `@@ -1,3 +1,4 @@` header, where like git a length of one is left
+    /// out and an empty range starts at the line before it.
+    pub fn header(&self) -> String {
+        let range = |start: usize, len: usize| match len {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", start + 1, len),
+        };
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+    }
+}
+
+/// Groups `edits` into hunks with `context` unchanged lines around the
+/// changes, joining those with at most twice that between them.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let changes = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !edit.is_equal())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    let mut ranges = Vec::new();
+    let mut changes = changes.into_iter().peekable();
+    while let Some(first) = changes.next() {
+        let mut last = first;
+        while let Some(&next) = changes.peek() {
+            if next - last - 1 > 2 * context {
+                break;
+            }
+            last = next;
+            changes.next();
+        }
+        ranges.push(first.saturating_sub(context)..(last + 1 + context).min(edits.len()));
+    }
+
+    // Where each edit is in the old and new lines
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut old, mut new) = (0, 0);
+    for edit in edits {
+        positions.push((old, new));
+        match edit {
+            Edit::Equal(..) => {
+                old += 1;
+                new += 1;
+            }
+            Edit::Delete(_) => old += 1,
+            Edit::Insert(_) => new += 1,
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|range| {
+            let (old_start, new_start) = positions[range.start];
+            let in_hunk = &edits[range.clone()];
+            let old_len = in_hunk
+                .iter()
+                .filter(|edit| !matches!(edit, Edit::Insert(_)))
+                .count();
+            let new_len = in_hunk
+                .iter()
+                .filter(|edit| !matches!(edit, Edit::Delete(_)))
+                .count();
+            Hunk {
+                old_start,
+                new_start,
+                old_len,
+                new_len,
+                edits: range,
+            }
+        })
+        .collect()
+}
+
+/// Writes a patch for each of `diffs`.
+pub fn write<W: Write>(out: &mut W, diffs: &[FileDiff], opts: &PatchOptions) -> io::Result<()> {
+    let opts = PatchOptions {
+        color: opts.color || opts.word_diff == Some(WordDiff::Color),
+        ..*opts
+    };
+    for diff in diffs {
+        write_file(out, diff, &opts)?;
+    }
+    Ok(())
+}
+
+fn write_file<W: Write>(out: &mut W, diff: &FileDiff, opts: &PatchOptions) -> io::Result<()> {
+    let edits = if diff.binary {
+        Vec::new()
+    } else {
+        diff.line_edits(opts.lines)
+    };
+    let hunks = hunks(&edits, opts.context);
+    // Like git, a file whose only changes are to ignored whitespace isn't
+    // shown at all
+    if let (Some(old), Some(new)) = (&diff.old, &diff.new) {
+        if hunks.is_empty() && !diff.binary && old.mode == new.mode {
+            return Ok(());
+        }
+    }
+
+    let meta = |out: &mut W, line: String| {
+        if opts.color {
+            writeln!(out, "{}{}{}", BOLD, line, RESET)
+        } else {
+            writeln!(out, "{}", line)
+        }
+    };
+    let path = diff.path.as_bstr();
+    meta(out, format!("diff --git a/{} b/{}", path, path))?;
+    match (&diff.old, &diff.new) {
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            meta(out, format!("old mode {}", old.mode.as_base8()))?;
+            meta(out, format!("new mode {}", new.mode.as_base8()))?;
+        }
+        (None, Some(new)) => meta(out, format!("new file mode {}", new.mode.as_base8()))?,
+        (Some(old), None) => meta(out, format!("deleted file mode {}", old.mode.as_base8()))?,
+        _ => {}
+    }
+
+    let oid = |side: &Option<Side>| side.as_ref().map(|side| side.oid);
+    if oid(&diff.old) == oid(&diff.new) {
+        return Ok(());
+    }
+    let abbrev = |side: &Option<Side>| match side {
+        Some(side) => side.oid.to_hex()[..ABBREV].to_owned(),
+        None => "0".repeat(ABBREV),
+    };
+    let mut index = format!("index {}..{}", abbrev(&diff.old), abbrev(&diff.new));
+    if let (Some(old), Some(new)) = (&diff.old, &diff.new) {
+        if old.mode == new.mode {
+            index = format!("{} {}", index, old.mode.as_base8());
+        }
+    }
+    meta(out, index)?;
+
+    let (old_name, new_name) = (
+        diff.old.as_ref().map(|_| format!("a/{}", path)),
+        diff.new.as_ref().map(|_| format!("b/{}", path)),
+    );
+    let (old_name, new_name) = (
+        old_name.unwrap_or_else(|| "/dev/null".to_owned()),
+        new_name.unwrap_or_else(|| "/dev/null".to_owned()),
+    );
+    if diff.binary {
+        return writeln!(out, "Binary files {} and {} differ", old_name, new_name);
+    }
+
+    // Like an empty file being added
+    if hunks.is_empty() {
+        return Ok(());
+    }
+    meta(out, format!("--- {}", old_name))?;
+    meta(out, format!("+++ {}", new_name))?;
+
+    let old = diff
+        .old
+        .as_ref()
+        .map_or_else(Vec::new, |side| lines(&side.data));
+    let new = diff
+        .new
+        .as_ref()
+        .map_or_else(Vec::new, |side| lines(&side.data));
+    for hunk in hunks {
+        let header = hunk.header();
+        let func = func_line(&old, hunk.old_start);
+        if opts.color {
+            // Like git, the line it's in is in no colour of its own
+            let func = func
+                .map(|func| format!(" {}{}{}", RESET, func, RESET))
+                .unwrap_or_default();
+            writeln!(out, "{}{}{}{}", CYAN, header, RESET, func)?;
+        } else {
+            let func = func.map(|func| format!(" {}", func)).unwrap_or_default();
+            writeln!(out, "{}{}", header, func)?;
+        }
+        write_hunk_lines(out, &edits[hunk.edits], &old, &new, opts)?;
+    }
+    Ok(())
+}
+
+/// Like git, within each run of changes the removed lines come before the
+/// added ones, and unchanged lines are shown as they are in `new` in case
+/// only ignored whitespace changed.
+fn write_hunk_lines<W: Write>(
+    out: &mut W,
+    edits: &[Edit],
+    old: &[&[u8]],
+    new: &[&[u8]],
+    opts: &PatchOptions,
+) -> io::Result<()> {
+    let mut i = 0;
+    while i < edits.len() {
+        if let Edit::Equal(_, n) = edits[i] {
+            match opts.word_diff {
+                Some(_) => write_word_context(out, new[n], opts)?,
+                None => write_line(out, Line::Context, new[n], opts)?,
+            }
+            i += 1;
+            continue;
+        }
+        let run_end = edits[i..]
+            .iter()
+            .position(|edit| edit.is_equal())
+            .map_or(edits.len(), |len| i + len);
+        if let Some(mode) = opts.word_diff {
+            write_word_run(out, &edits[i..run_end], old, new, mode, opts)?;
+            i = run_end;
+            continue;
+        }
+        for edit in &edits[i..run_end] {
+            if let Edit::Delete(o) = *edit {
+                write_line(out, Line::Deleted, old[o], opts)?;
+            }
+        }
+        for edit in &edits[i..run_end] {
+            if let Edit::Insert(n) = *edit {
+                write_line(out, Line::Added, new[n], opts)?;
+            }
+        }
+        i = run_end;
+    }
+    Ok(())
+}
+
+/// Like git, the lines a run removed and added are each joined up, ending
+/// each in a newline, and their words compared.
+fn write_word_run<W: Write>(
+    out: &mut W,
+    run: &[Edit],
+    old: &[&[u8]],
+    new: &[&[u8]],
+    mode: WordDiff,
+    opts: &PatchOptions,
+) -> io::Result<()> {
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    for edit in run {
+        let (text, line) = match *edit {
+            Edit::Delete(o) => (&mut removed, old[o]),
+            Edit::Insert(n) => (&mut added, new[n]),
+            Edit::Equal(..) => continue,
+        };
+        text.extend_from_slice(line);
+        if !line.ends_with(b"\n") {
+            text.push(b'\n');
+        }
+    }
+    words::write(
+        out,
+        &removed,
+        &added,
+        mode,
+        opts.color,
+        opts.lines.algorithm,
+    )
+}
+
+/// Unchanged lines in a word diff have no sign, and there's no marker for
+/// a missing newline at the end.
+fn write_word_context<W: Write>(out: &mut W, line: &[u8], opts: &PatchOptions) -> io::Result<()> {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    out.write_all(content)?;
+    if opts.color && !content.is_empty() {
+        write!(out, "{}", RESET)?;
+    }
+    writeln!(out)
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Line {
+    Context,
+    Deleted,
+    Added,
+}
+
+/// Like git, in colour the sign of an added line is coloured on its own, and
+/// whitespace at its end is highlighted as an error.
+fn write_line<W: Write>(
+    out: &mut W,
+    kind: Line,
+    line: &[u8],
+    opts: &PatchOptions,
+) -> io::Result<()> {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    let colored = |out: &mut W, color: &str, data: &[u8]| -> io::Result<()> {
+        write!(out, "{}", color)?;
+        out.write_all(data)?;
+        write!(out, "{}", RESET)
+    };
+    match kind {
+        _ if !opts.color => {
+            let sign = match kind {
+                Line::Context => " ",
+                Line::Deleted => "-",
+                Line::Added => "+",
+            };
+            write!(out, "{}", sign)?;
+            out.write_all(content)?;
+        }
+        Line::Context => colored(out, " ", content)?,
+        Line::Deleted => colored(out, &format!("{}-", RED), content)?,
+        Line::Added => {
+            colored(out, GREEN, b"+")?;
+            let body_len = content
+                .iter()
+                .rposition(|b| !matches!(b, b' ' | b'\t' | b'\r'))
+                .map_or(0, |i| i + 1);
+            let (body, trailing) = content.split_at(body_len);
+            if !body.is_empty() {
+                colored(out, GREEN, body)?;
+            }
+            if !trailing.is_empty() {
+                colored(out, RED_BACKGROUND, trailing)?;
+            }
+        }
+    }
+    writeln!(out)?;
+
+    if !line.ends_with(b"\n") {
+        write!(out, "\\ No newline at end of file")?;
+        if opts.color {
+            write!(out, "{}", RESET)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Like git's default, the last line before `start` that starts with a
+/// letter, `_` or `$`, as it's likely to name the function a hunk is in.
+fn func_line<'l>(lines: &[&'l [u8]], start: usize) -> Option<&'l BStr> {
+    lines[..start.min(lines.len())]
+        .iter()
+        .rev()
+        .find(|line| {
+            line.first().map_or(false, |&b| {
+                b.is_ascii_alphabetic() || b == b'_' || b == b'$'
+            })
+        })
+        .map(|line| line[..line.len().min(FUNC_LEN)].trim_end().as_bstr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{db::Blob, stat::Mode, WsPath};
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    fn side(data: &str) -> Side {
+        Side::new(Mode::Regular, Blob::oid_for_file(data.into()), data.into())
+    }
+
+    fn patch(diff: &FileDiff, opts: &PatchOptions) -> String {
+        let mut out = Vec::new();
+        write(&mut out, std::slice::from_ref(diff), opts).unwrap();
+        out.to_str_lossy().into_owned()
+    }
+
+    #[test]
+    fn groups_hunks_with_context() {
+        init();
+        let old = (1..=20).map(|i| i.to_string() + "\n").collect::<String>();
+        let new = old
+            .replacen("2\n", "two\n", 1)
+            .replacen("\n8\n", "\n", 1)
+            .replacen("19\n", "19\n19.5\n", 1);
+        let diff = FileDiff::new(
+            WsPath::new_unchecked("f"),
+            Some(side(&old)),
+            Some(side(&new)),
+        );
+        let edits = diff.line_edits(LineOptions::default());
+
+        let headers = |context| {
+            hunks(&edits, context)
+                .iter()
+                .map(Hunk::header)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["@@ -1,11 +1,10 @@", "@@ -17,4 +16,5 @@"], headers(3));
+        assert_eq!(
+            vec!["@@ -2 +2 @@", "@@ -8 +7,0 @@", "@@ -19,0 +19 @@"],
+            headers(0)
+        );
+    }
+
+    #[test]
+    fn writes_patches() {
+        init();
+        let old = "fn main() {\n    a();\n    b();\n}\n\nfn other() {\n    c();\n    d();\n    e();\n    f();\n}";
+        let new = old.replace("e();", "x();").replace("f();\n}", "f();\n}\n");
+        let diff = FileDiff::new(
+            WsPath::new_unchecked("src/main.rs"),
+            Some(side(old)),
+            Some(Side::new(
+                Mode::Executable,
+                Blob::oid_for_file(new.as_str().into()),
+                new.clone().into(),
+            )),
+        );
+
+        assert_eq!(
+            format!(
+                "diff --git a/src/main.rs b/src/main.rs
+old mode 100644
+new mode 100755
+index {}..{}
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -6,6 +6,6 @@ fn main() {{
+ fn other() {{
+     c();
+     d();
+-    e();
++    x();
+     f();
+-}}
+\\ No newline at end of file
++}}
+",
+                &side(old).oid.to_hex()[..7],
+                &side(&new).oid.to_hex()[..7]
+            ),
+            patch(&diff, &PatchOptions::default())
+        );
+    }
+
+    #[test]
+    fn writes_added_binary_and_colored_files() {
+        init();
+        let path = WsPath::new_unchecked("f");
+        let added = FileDiff::new(path.clone(), None, Some(side("")));
+        assert_eq!(
+            "diff --git a/f b/f\nnew file mode 100644\nindex 0000000..e69de29\n",
+            patch(&added, &PatchOptions::default())
+        );
+
+        let binary = FileDiff::new(path.clone(), Some(side("a\0")), None);
+        assert!(patch(&binary, &PatchOptions::default())
+            .ends_with("index 90802fe..0000000\nBinary files a/f and /dev/null differ\n"));
+
+        let colored = FileDiff::new(path, Some(side("a\n")), Some(side("b \n")));
+        let opts = PatchOptions {
+            color: true,
+            ..PatchOptions::default()
+        };
+        assert!(patch(&colored, &opts).ends_with(
+            "\x1b[1m+++ b/f\x1b[m\n\x1b[36m@@ -1 +1 @@\x1b[m\n\x1b[31m-a\x1b[m\n\x1b[32m+\x1b[m\x1b[32mb\x1b[m\x1b[41m \x1b[m\n"
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+//! Patience diff, which lines up the lines found once on both sides before
+//! anything else, so that changes keep to the blocks around them rather than
+//! matching up braces and blank lines.
+
+use std::{collections::HashMap, hash::Hash, ops::Range};
+
+use super::{myers, Edit};
+
+/// The edits turning `old` into `new`, like git's patience diff.
+pub fn diff<T: Eq + Hash>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    patience(old, 0..old.len(), new, 0..new.len(), &mut edits);
+    edits
+}
+
+fn patience<T: Eq + Hash>(
+    old: &[T],
+    olds: Range<usize>,
+    new: &[T],
+    news: Range<usize>,
+    edits: &mut Vec<Edit>,
+) {
+    if olds.is_empty() || news.is_empty() {
+        edits.extend(olds.map(Edit::Delete));
+        edits.extend(news.map(Edit::Insert));
+        return;
+    }
+    let anchors = unique_common_lines(old, olds.clone(), new, news.clone());
+    if anchors.is_empty() {
+        myers::diff_ranges(old, olds, new, news, edits);
+        return;
+    }
+
+    let (mut o, mut n) = (olds.start, news.start);
+    let mut anchors = anchors.into_iter().peekable();
+    loop {
+        // Equal lines next to the anchor go with it, rather than into the
+        // lines between anchors
+        let (next_o, next_n) = match anchors.peek() {
+            Some(&(mut a_o, mut a_n)) => {
+                while a_o > o && a_n > n && old[a_o - 1] == new[a_n - 1] {
+                    a_o -= 1;
+                    a_n -= 1;
+                }
+                (a_o, a_n)
+            }
+            None => (olds.end, news.end),
+        };
+        while o < next_o && n < next_n && old[o] == new[n] {
+            edits.push(Edit::Equal(o, n));
+            o += 1;
+            n += 1;
+        }
+        if next_o > o || next_n > n {
+            patience(old, o..next_o, new, n..next_n, edits);
+        }
+
+        let (a_o, a_n) = match anchors.next() {
+            Some(anchor) => anchor,
+            None => return,
+        };
+        edits.extend(
+            (next_o..a_o)
+                .zip(next_n..a_n)
+                .map(|(o, n)| Edit::Equal(o, n)),
+        );
+        edits.push(Edit::Equal(a_o, a_n));
+        o = a_o + 1;
+        n = a_n + 1;
+    }
+}
+
+/// The longest run of lines found once in each of `olds` and `news` that are
+/// in the same order in both, by where they are on each side.
+fn unique_common_lines<T: Eq + Hash>(
+    old: &[T],
+    olds: Range<usize>,
+    new: &[T],
+    news: Range<usize>,
+) -> Vec<(usize, usize)> {
+    #[derive(Clone, Copy)]
+    struct Line {
+        old: usize,
+        old_count: usize,
+        new: usize,
+        new_count: usize,
+    }
+
+    // In the order they're first found in `old`
+    let mut lines = Vec::<Line>::new();
+    let mut by_content = HashMap::new();
+    for o in olds {
+        let i = *by_content.entry(&old[o]).or_insert_with(|| {
+            lines.push(Line {
+                old: o,
+                old_count: 0,
+                new: 0,
+                new_count: 0,
+            });
+            lines.len() - 1
+        });
+        lines[i].old_count += 1;
+    }
+    for n in news {
+        if let Some(&i) = by_content.get(&new[n]) {
+            lines[i].new = n;
+            lines[i].new_count += 1;
+        }
+    }
+
+    // Patience sorting: each pile's top is where the longest run found so
+    // far of that pile's length can end
+    let unique = lines
+        .into_iter()
+        .filter(|line| line.old_count == 1 && line.new_count == 1)
+        .collect::<Vec<_>>();
+    let mut piles = Vec::<usize>::new();
+    let mut previous = vec![None; unique.len()];
+    for (i, line) in unique.iter().enumerate() {
+        let pile = piles.partition_point(|&top| unique[top].new < line.new);
+        previous[i] = pile.checked_sub(1).map(|pile| piles[pile]);
+        if pile == piles.len() {
+            piles.push(i);
+        } else {
+            piles[pile] = i;
+        }
+    }
+
+    let mut anchors = Vec::with_capacity(piles.len());
+    let mut next = piles.last().copied();
+    while let Some(i) = next {
+        anchors.push((unique[i].old, unique[i].new));
+        next = previous[i];
+    }
+    anchors.reverse();
+    anchors
+}
@@ -0,0 +1,433 @@
+//! How many lines changed in each file rather than how, like `git diff
+//! --stat`, `--numstat` and `--shortstat`.
+
+use bstr::{BStr, ByteSlice};
+use std::io::{self, Write};
+
+use super::{
+    colors::{GREEN, RED, RESET},
+    Edit, FileDiff, LineOptions, Side,
+};
+
+/// Like git, the name and the graph of changes share at least this much of
+/// the width.
+const MIN_WIDTH: usize = 16;
+
+/// How much of a file changed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FileStat<'d> {
+    pub path: &'d BStr,
+    pub changes: Changes,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Changes {
+    Lines {
+        added: usize,
+        deleted: usize,
+    },
+    /// Binary files have no lines, so like git the size of each side is
+    /// shown instead
+    Binary {
+        old_size: usize,
+        new_size: usize,
+    },
+}
+
+impl Changes {
+    /// How many lines were added and deleted, with none for binary files.
+    fn lines(self) -> (usize, usize) {
+        match self {
+            Self::Lines { added, deleted } => (added, deleted),
+            Self::Binary { .. } => (0, 0),
+        }
+    }
+}
+
+/// The changes to each of `diffs`. Like git, files whose only changes are to
+/// whitespace `opts` ignores are left out.
+pub fn stats(diffs: &[FileDiff], opts: LineOptions) -> Vec<FileStat<'_>> {
+    let size = |side: &Option<Side>| side.as_ref().map_or(0, |side| side.data.len());
+    let oid = |side: &Option<Side>| side.as_ref().map(|side| side.oid);
+    let mut stats = Vec::with_capacity(diffs.len());
+    for diff in diffs {
+        let changes = if oid(&diff.old) == oid(&diff.new) {
+            Changes::Lines {
+                added: 0,
+                deleted: 0,
+            }
+        } else if diff.binary {
+            Changes::Binary {
+                old_size: size(&diff.old),
+                new_size: size(&diff.new),
+            }
+        } else {
+            let edits = diff.line_edits(opts);
+            let count = |f: fn(&Edit) -> bool| edits.iter().filter(|edit| f(edit)).count();
+            let added = count(|edit| matches!(edit, Edit::Insert(_)));
+            let deleted = count(|edit| matches!(edit, Edit::Delete(_)));
+            if let (Some(old), Some(new)) = (&diff.old, &diff.new) {
+                if added == 0 && deleted == 0 && old.mode == new.mode {
+                    continue;
+                }
+            }
+            Changes::Lines { added, deleted }
+        };
+        stats.push(FileStat {
+            path: diff.path.as_bstr(),
+            changes,
+        });
+    }
+    stats
+}
+
+/// Writes a line for each file with how many lines changed and a graph of
+/// them, and then the totals, like `git diff --stat`. Like git, the names
+/// and graphs are cut short to fit in `width` columns, with names losing
+/// their start.
+pub fn write_stat<W: Write>(
+    out: &mut W,
+    stats: &[FileStat],
+    width: usize,
+    color: bool,
+) -> io::Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+    let (added_color, deleted_color, reset) = if color {
+        (GREEN, RED, RESET)
+    } else {
+        ("", "", "")
+    };
+    let Widths {
+        name: name_width,
+        number: number_width,
+        graph: graph_width,
+        max_change,
+    } = Widths::new(stats, width);
+
+    for stat in stats {
+        // Cut the name short from the start, at a slash if there is one
+        let chars = stat.path.chars().count();
+        let (prefix, name, name_len) = if chars > name_width {
+            let len = name_width.saturating_sub(3);
+            let skip = stat
+                .path
+                .char_indices()
+                .nth(chars - len)
+                .map_or(stat.path.len(), |(start, _, _)| start);
+            let name = stat.path[skip..].as_bstr();
+            let name = name
+                .find_byte(b'/')
+                .map_or(name, |slash| name[slash..].as_bstr());
+            ("...", name, len)
+        } else {
+            ("", stat.path, name_width)
+        };
+        let padding = name_len.saturating_sub(name.chars().count());
+        write!(
+            out,
+            " {}{}{:padding$} | ",
+            prefix,
+            name,
+            "",
+            padding = padding
+        )?;
+
+        let (added, deleted) = match stat.changes {
+            Changes::Binary { old_size, new_size } => {
+                write!(out, "{:>width$}", "Bin", width = number_width)?;
+                if old_size != 0 || new_size != 0 {
+                    write!(
+                        out,
+                        " {}{}{} -> {}{}{} bytes",
+                        deleted_color, old_size, reset, added_color, new_size, reset
+                    )?;
+                }
+                writeln!(out)?;
+                continue;
+            }
+            Changes::Lines { added, deleted } => (added, deleted),
+        };
+        write!(out, "{:>width$}", added + deleted, width = number_width)?;
+        if added + deleted > 0 {
+            write!(out, " ")?;
+        }
+        let (pluses, minuses) = scale(added, deleted, graph_width, max_change);
+        if pluses > 0 {
+            write!(out, "{}{}{}", added_color, "+".repeat(pluses), reset)?;
+        }
+        if minuses > 0 {
+            write!(out, "{}{}{}", deleted_color, "-".repeat(minuses), reset)?;
+        }
+        writeln!(out)?;
+    }
+    write_shortstat(out, stats)
+}
+
+/// How wide each part of a stat's lines is.
+struct Widths {
+    name: usize,
+    /// Of how many lines changed
+    number: usize,
+    graph: usize,
+    /// The most lines changed in any file
+    max_change: usize,
+}
+
+impl Widths {
+    /// Like git, the name and graph are cut short to fit in `width` if they
+    /// have to be, the graph to about 3/8 of it.
+    fn new(stats: &[FileStat], width: usize) -> Self {
+        let digits = |n: usize| n.to_string().len();
+        let max_name = stats
+            .iter()
+            .map(|stat| stat.path.chars().count())
+            .max()
+            .unwrap_or(0);
+        let max_change = stats
+            .iter()
+            .map(|stat| {
+                let (added, deleted) = stat.changes.lines();
+                added + deleted
+            })
+            .max()
+            .unwrap_or(0);
+        // Like `Bin 12 -> 345 bytes`
+        let bin_width = stats
+            .iter()
+            .filter_map(|stat| match stat.changes {
+                Changes::Binary { old_size, new_size } => {
+                    Some(14 + digits(old_size) + digits(new_size))
+                }
+                Changes::Lines { .. } => None,
+            })
+            .max();
+        // Counts line up with `Bin`
+        let number = digits(max_change).max(if bin_width.is_some() { 3 } else { 0 });
+        let width = width.max(MIN_WIDTH + 6 + number);
+
+        // As wide as they'd like to be, and then narrower if they don't fit
+        let mut graph = match bin_width {
+            Some(bin_width) if bin_width > max_change + 4 => bin_width - 4,
+            _ => max_change,
+        };
+        let mut name = max_name;
+        if name + number + 6 + graph > width {
+            let graph_share = (width * 3 / 8).saturating_sub(number + 6);
+            if graph > graph_share {
+                graph = graph_share.max(6);
+            }
+            if name > width - number - 6 - graph {
+                name = width - number - 6 - graph;
+            } else {
+                graph = width - number - 6 - name;
+            }
+        }
+        Self {
+            name,
+            number,
+            graph,
+            max_change,
+        }
+    }
+}
+
+/// How many `+` and `-` show `added` and `deleted` in a graph `width` wide
+/// for at most `max_change` changes. Like git, any change gets at least one.
+fn scale(added: usize, deleted: usize, width: usize, max_change: usize) -> (usize, usize) {
+    if width > max_change {
+        return (added, deleted);
+    }
+    let scale = |n: usize| {
+        if n == 0 {
+            0
+        } else {
+            1 + n * (width - 1) / max_change
+        }
+    };
+    let mut total = scale(added + deleted);
+    if total < 2 && added > 0 && deleted > 0 {
+        total = 2;
+    }
+    if added < deleted {
+        let added = scale(added);
+        (added, total - added)
+    } else {
+        let deleted = scale(deleted);
+        (total - deleted, deleted)
+    }
+}
+
+/// Writes how many lines were added and deleted in each file, or `-` for
+/// binary files, like `git diff --numstat`.
+pub fn write_numstat<W: Write>(out: &mut W, stats: &[FileStat]) -> io::Result<()> {
+    for stat in stats {
+        match stat.changes {
+            Changes::Lines { added, deleted } => {
+                writeln!(out, "{}\t{}\t{}", added, deleted, stat.path)?;
+            }
+            Changes::Binary { .. } => writeln!(out, "-\t-\t{}", stat.path)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes how many files changed and how many lines were added and deleted
+/// in all, like `git diff --shortstat`.
+pub fn write_shortstat<W: Write>(out: &mut W, stats: &[FileStat]) -> io::Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+    let (added, deleted) = stats.iter().fold((0, 0), |(added, deleted), stat| {
+        let lines = stat.changes.lines();
+        (added + lines.0, deleted + lines.1)
+    });
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+
+    write!(
+        out,
+        " {}",
+        plural(stats.len(), "file changed", "files changed")
+    )?;
+    // Like git, say there were none rather than nothing at all
+    if added > 0 || deleted == 0 {
+        write!(out, ", {}", plural(added, "insertion(+)", "insertions(+)"))?;
+    }
+    if deleted > 0 || added == 0 {
+        write!(out, ", {}", plural(deleted, "deletion(-)", "deletions(-)"))?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{db::Blob, diff::Whitespace, stat::Mode, WsPath};
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    fn side(mode: Mode, data: &str) -> Side {
+        Side::new(mode, Blob::oid_for_file(data.into()), data.into())
+    }
+
+    fn stat(path: &str, added: usize, deleted: usize) -> FileStat<'_> {
+        FileStat {
+            path: path.into(),
+            changes: Changes::Lines { added, deleted },
+        }
+    }
+
+    fn write(stats: &[FileStat], width: usize) -> String {
+        let mut out = Vec::new();
+        write_stat(&mut out, stats, width, false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn counts_changed_lines() {
+        init();
+        let regular = |data| Some(side(Mode::Regular, data));
+        let diffs = [
+            FileDiff::new(
+                WsPath::new_unchecked("text"),
+                regular("a b\nb\n"),
+                regular("a  b\nb\nc\n"),
+            ),
+            FileDiff::new(
+                WsPath::new_unchecked("spaces"),
+                regular("a b\n"),
+                regular("a  b\n"),
+            ),
+            FileDiff::new(
+                WsPath::new_unchecked("mode"),
+                regular("a\n"),
+                Some(side(Mode::Executable, "a\n")),
+            ),
+            FileDiff::new(WsPath::new_unchecked("bin"), None, regular("\0\0")),
+        ];
+        let lines = |added, deleted| Changes::Lines { added, deleted };
+        let changes = |whitespace| {
+            let opts = LineOptions {
+                whitespace,
+                ..LineOptions::default()
+            };
+            stats(&diffs, opts)
+                .into_iter()
+                .map(|stat| (stat.path.to_string(), stat.changes))
+                .collect::<Vec<_>>()
+        };
+        let binary = Changes::Binary {
+            old_size: 0,
+            new_size: 2,
+        };
+
+        assert_eq!(
+            vec![
+                ("text".to_owned(), lines(2, 1)),
+                ("spaces".to_owned(), lines(1, 1)),
+                ("mode".to_owned(), lines(0, 0)),
+                ("bin".to_owned(), binary),
+            ],
+            changes(Whitespace::All)
+        );
+        // Like git, files with only ignored changes are left out
+        assert_eq!(
+            vec![
+                ("text".to_owned(), lines(1, 0)),
+                ("mode".to_owned(), lines(0, 0)),
+                ("bin".to_owned(), binary),
+            ],
+            changes(Whitespace::IgnoreChange)
+        );
+    }
+
+    #[test]
+    fn scales_graphs_to_fit() {
+        init();
+        let stats = [stat("big", 150, 50), stat("small", 1, 1), stat("one", 0, 1)];
+        assert_eq!(
+            format!(
+                " big   | 200 {}{}\n small |   2 +-\n one   |   1 -\n 3 files changed, 151 insertions(+), 52 deletions(-)\n",
+                "+".repeat(49),
+                "-".repeat(17)
+            ),
+            write(&stats, 80)
+        );
+    }
+
+    #[test]
+    fn cuts_names_short() {
+        init();
+        let long = format!("{}/file", "dir".repeat(30));
+        let stats = [stat(&long, 1, 0), stat("short", 0, 0)];
+        let out = write(&stats, 40);
+
+        let lines = out.lines().collect::<Vec<_>>();
+        // Padded like the name it's short for
+        assert!(lines[0].starts_with(" .../file "), "{}", lines[0]);
+        assert!(lines[0].ends_with(" | 1 +"), "{}", lines[0]);
+        assert_eq!(lines[0].find('|'), lines[1].find('|'));
+        assert_eq!(" 2 files changed, 1 insertion(+)", lines[2]);
+    }
+
+    #[test]
+    fn writes_binary_sizes() {
+        init();
+        let stats = [
+            FileStat {
+                path: "bin".into(),
+                changes: Changes::Binary {
+                    old_size: 3,
+                    new_size: 1024,
+                },
+            },
+            stat("text", 1, 0),
+        ];
+        assert_eq!(
+            " bin  | Bin 3 -> 1024 bytes\n text |   1 +\n 2 files changed, 1 insertion(+)\n",
+            write(&stats, 80)
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Word diffs, which show the words that changed within changed lines, like
+//! `git diff --word-diff`.
+
+use bstr::ByteSlice;
+use std::{
+    io::{self, Write},
+    ops::Range,
+    str::FromStr,
+};
+
+use super::{colors, compact, Algorithm, Edit};
+
+/// How changed words are marked.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WordDiff {
+    /// Like `[-removed-]{+added+}`, coloured as well if colour is on
+    Plain,
+    /// Only by colour, which is always on
+    Color,
+}
+
+impl FromStr for WordDiff {
+    type Err = ParseWordDiffError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "color" => Ok(Self::Color),
+            _ => Err(ParseWordDiffError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Invalid word diff mode {0:?}, expected plain or color
+pub struct ParseWordDiffError(String);
+
+struct Style {
+    color: &'static str,
+    prefix: &'static str,
+    suffix: &'static str,
+}
+
+impl WordDiff {
+    fn styles(self) -> (Style, Style) {
+        let (old, new) = match self {
+            Self::Plain => (("[-", "-]"), ("{+", "+}")),
+            Self::Color => (("", ""), ("", "")),
+        };
+        (
+            Style {
+                color: colors::RED,
+                prefix: old.0,
+                suffix: old.1,
+            },
+            Style {
+                color: colors::GREEN,
+                prefix: new.0,
+                suffix: new.1,
+            },
+        )
+    }
+}
+
+/// Writes the lines `new` replaced `old` with, each ending in a newline,
+/// marking the words removed and added. Like git, the words are runs of
+/// anything but whitespace, and the whitespace between them is shown as it
+/// is in `new`.
+pub fn write<W: Write>(
+    out: &mut W,
+    old: &[u8],
+    new: &[u8],
+    mode: WordDiff,
+    color: bool,
+    algorithm: Algorithm,
+) -> io::Result<()> {
+    let (removed, added) = mode.styles();
+    if new.is_empty() {
+        return write_styled(out, old, Some(&removed), color);
+    }
+
+    let old_words = words(old);
+    let new_words = words(new);
+    let old_keys = old_words
+        .iter()
+        .map(|w| &old[w.clone()])
+        .collect::<Vec<_>>();
+    let new_keys = new_words
+        .iter()
+        .map(|w| &new[w.clone()])
+        .collect::<Vec<_>>();
+    let edits = algorithm.diff(&old_keys, &new_keys);
+    let edits = compact::compact(&edits, &old_keys, &new_keys, None);
+
+    // How much of `new` has been written
+    let mut written = 0;
+    let (mut o, mut n) = (0, 0);
+    let mut i = 0;
+    while i < edits.len() {
+        if let Edit::Equal(..) = edits[i] {
+            o += 1;
+            n += 1;
+            i += 1;
+            continue;
+        }
+        let (old_start, new_start) = (o, n);
+        while let Some(edit) = edits.get(i).filter(|edit| !edit.is_equal()) {
+            if let Edit::Delete(_) = edit {
+                o += 1;
+            } else {
+                n += 1;
+            }
+            i += 1;
+        }
+
+        // Where nothing was added, the words removed are shown after the
+        // word before them
+        let new_span = if n > new_start {
+            new_words[new_start].start..new_words[n - 1].end
+        } else {
+            let end = new_start.checked_sub(1).map_or(0, |w| new_words[w].end);
+            end..end
+        };
+        write_styled(out, &new[written..new_span.start], None, color)?;
+        if o > old_start {
+            let old_span = old_words[old_start].start..old_words[o - 1].end;
+            write_styled(out, &old[old_span], Some(&removed), color)?;
+        }
+        write_styled(out, &new[new_span.clone()], Some(&added), color)?;
+        written = new_span.end;
+    }
+    write_styled(out, &new[written..], None, color)
+}
+
+/// Where each run of anything but whitespace is.
+fn words(text: &[u8]) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, b) in text.iter().enumerate() {
+        match (start, b.is_ascii_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                words.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push(s..text.len());
+    }
+    words
+}
+
+/// Like git, each line is marked on its own.
+fn write_styled<W: Write>(
+    out: &mut W,
+    text: &[u8],
+    style: Option<&Style>,
+    color: bool,
+) -> io::Result<()> {
+    for (i, line) in text.split_str("\n").enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        match style {
+            Some(style) => {
+                if color {
+                    write!(out, "{}", style.color)?;
+                }
+                write!(out, "{}", style.prefix)?;
+                out.write_all(line)?;
+                write!(out, "{}", style.suffix)?;
+                if color {
+                    write!(out, "{}", colors::RESET)?;
+                }
+            }
+            None => out.write_all(line)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,341 @@
+//! Finding the repository a directory belongs to, like git does. See
+//! `git(1)` for the environment variables involved.
+
+use bstr::ByteSlice;
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Where a repository's git directory and workspace are.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Location {
+    pub git_dir: PathBuf,
+    /// `None` for a bare repository, found as a git directory itself
+    pub workspace: Option<PathBuf>,
+}
+
+/// The environment variables that affect discovery, read once so discovery
+/// can be tested without changing the process environment.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    /// `GIT_DIR`
+    pub git_dir: Option<PathBuf>,
+    /// `GIT_WORK_TREE`
+    pub work_tree: Option<PathBuf>,
+    /// `GIT_CEILING_DIRECTORIES`
+    pub ceiling_dirs: Vec<PathBuf>,
+    /// `GIT_DISCOVERY_ACROSS_FILESYSTEM`
+    pub across_filesystem: bool,
+}
+
+impl Env {
+    pub fn from_process() -> Self {
+        let path = |name| {
+            env::var_os(name)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+
+        let ceiling_dirs = env::var_os("GIT_CEILING_DIRECTORIES")
+            .map(|dirs| {
+                env::split_paths(&dirs)
+                    // Relative entries are ignored
+                    .filter(|dir| dir.is_absolute())
+                    .map(|dir| dir.canonicalize().unwrap_or(dir))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let across_filesystem =
+            env::var_os("GIT_DISCOVERY_ACROSS_FILESYSTEM").map_or(false, |v| is_truthy(&v));
+
+        Self {
+            git_dir: path("GIT_DIR"),
+            work_tree: path("GIT_WORK_TREE"),
+            ceiling_dirs,
+            across_filesystem,
+        }
+    }
+}
+
+fn is_truthy(value: &OsString) -> bool {
+    matches!(
+        value.to_string_lossy().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// Finds the repository containing `start`, walking up through its parents
+/// unless `GIT_DIR` is set. Relative paths in `env` are relative to `start`.
+pub fn discover(start: &Path, env: &Env) -> Result<Location, Error> {
+    let start = start
+        .canonicalize()
+        .map_err(|e| Error::Io(start.to_owned(), e))?;
+
+    let mut location = if let Some(git_dir) = &env.git_dir {
+        // Without a work tree, the start is taken to be the top of it
+        Location {
+            git_dir: start.join(git_dir),
+            workspace: Some(start.clone()),
+        }
+    } else {
+        walk_up(&start, env)?
+    };
+
+    if let Some(work_tree) = &env.work_tree {
+        location.workspace = Some(start.join(work_tree));
+    }
+
+    if let Some(workspace) = &location.workspace {
+        let workspace = workspace
+            .canonicalize()
+            .map_err(|e| Error::Io(workspace.clone(), e))?;
+        location.workspace = Some(workspace);
+    }
+
+    debug!("Discovered {:?} from {:?}", location, start);
+    Ok(location)
+}
+
+fn walk_up(start: &Path, env: &Env) -> Result<Location, Error> {
+    let device = |dir: &Path| {
+        dir.metadata()
+            .map(|m| m.dev())
+            .map_err(|e| Error::Io(dir.to_owned(), e))
+    };
+    let start_device = device(start)?;
+
+    let mut dir = start;
+    loop {
+        if let Some(git_dir) = git_dir_in(dir)? {
+            return Ok(Location {
+                git_dir,
+                workspace: Some(dir.to_owned()),
+            });
+        }
+        if is_git_dir(dir) {
+            return Ok(Location {
+                git_dir: dir.to_owned(),
+                workspace: None,
+            });
+        }
+
+        let parent = match dir.parent() {
+            Some(parent) => parent,
+            None => return Err(Error::NotFound(start.to_owned())),
+        };
+        if env.ceiling_dirs.iter().any(|ceiling| ceiling == parent) {
+            debug!("Stopping discovery at ceiling {:?}", parent);
+            return Err(Error::NotFound(start.to_owned()));
+        }
+        if !env.across_filesystem && device(parent)? != start_device {
+            return Err(Error::FilesystemBoundary(
+                start.to_owned(),
+                parent.to_owned(),
+            ));
+        }
+
+        dir = parent;
+    }
+}
+
+/// The git directory of a workspace at `dir`: either the directory `.git`, or
+/// the directory named by a `.git` file containing `gitdir: <path>`.
+pub fn git_dir_in(dir: &Path) -> Result<Option<PathBuf>, Error> {
+    let dot_git = dir.join(".git");
+    let meta = match dot_git.metadata() {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::Io(dot_git, err)),
+    };
+
+    if meta.is_dir() {
+        return Ok(Some(dot_git));
+    }
+
+    let contents = fs::read(&dot_git).map_err(|e| Error::Io(dot_git.clone(), e))?;
+    let target = contents
+        .trim_end()
+        .strip_prefix(b"gitdir: ")
+        .ok_or_else(|| Error::InvalidGitFile(dot_git.clone()))?;
+    let git_dir = dir.join(target.to_path_lossy());
+
+    if !git_dir.is_dir() {
+        return Err(Error::InvalidGitFile(dot_git));
+    }
+    Ok(Some(git_dir))
+}
+
+/// Whether `dir` is a git directory itself, as a bare repository is. Git
+/// looks for `HEAD`, but a new repository has none until its first commit,
+/// so its config will do too.
+pub fn is_git_dir(dir: &Path) -> bool {
+    dir.join("objects").is_dir()
+        && dir.join("refs").is_dir()
+        && (dir.join("HEAD").is_file() || dir.join("config").is_file())
+}
+
+/// The directory holding what's shared between all the worktrees of the
+/// repository with git directory `git_dir`: objects, refs other than `HEAD`,
+/// and config. A linked worktree's git directory names it in its `commondir`
+/// file, otherwise it is the git directory itself.
+pub fn common_dir(git_dir: &Path) -> Result<PathBuf, Error> {
+    let path = git_dir.join("commondir");
+    match fs::read(&path) {
+        Ok(contents) => {
+            let dir = git_dir.join(contents.trim_end().to_path_lossy());
+            dir.canonicalize().map_err(|e| Error::Io(dir, e))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(git_dir.to_owned()),
+        Err(err) => Err(Error::Io(path, err)),
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+#[allow(clippy::doc_markdown)]
+pub enum Error {
+    /// Not a git repository (or any of the parent directories): {0:?}
+    NotFound(PathBuf),
+    /// Not a git repository (or any parent up to mount point {1:?}): {0:?}. Stopping at filesystem boundary (GIT_DISCOVERY_ACROSS_FILESYSTEM not set).
+    FilesystemBoundary(PathBuf, PathBuf),
+    /// Invalid gitfile format: {0:?}
+    InvalidGitFile(PathBuf),
+    /// IO error while looking for a git repository at {0:?}
+    Io(PathBuf, #[source] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[test]
+    fn walks_up_to_nearest_repo() -> eyre::Result<()> {
+        init();
+
+        let dir = tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        fs::create_dir_all(dir.join(".git"))?;
+        fs::create_dir_all(dir.join("a/b"))?;
+
+        let location = discover(&dir.join("a/b"), &Env::default())?;
+        assert_eq!(
+            Location {
+                git_dir: dir.join(".git"),
+                workspace: Some(dir.clone())
+            },
+            location
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_at_ceiling() -> eyre::Result<()> {
+        init();
+
+        let dir = tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        fs::create_dir_all(dir.join(".git"))?;
+        fs::create_dir_all(dir.join("a/b"))?;
+
+        let env = Env {
+            ceiling_dirs: vec![dir.join("a")],
+            ..Env::default()
+        };
+        let err = discover(&dir.join("a/b"), &env).unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn follows_git_file() -> eyre::Result<()> {
+        init();
+
+        let dir = tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        fs::create_dir_all(dir.join("elsewhere/repo.git"))?;
+        fs::create_dir_all(dir.join("ws"))?;
+        fs::write(dir.join("ws/.git"), "gitdir: ../elsewhere/repo.git\n")?;
+
+        let location = discover(&dir.join("ws"), &Env::default())?;
+        assert_eq!(dir.join("ws/../elsewhere/repo.git"), location.git_dir);
+        assert_eq!(Some(dir.join("ws")), location.workspace);
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_bare_repo() -> eyre::Result<()> {
+        init();
+
+        let dir = tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        fs::create_dir_all(dir.join("repo.git/objects"))?;
+        fs::create_dir_all(dir.join("repo.git/refs/heads"))?;
+        fs::write(dir.join("repo.git/config"), "")?;
+
+        let location = discover(&dir.join("repo.git/refs/heads"), &Env::default())?;
+        assert_eq!(
+            Location {
+                git_dir: dir.join("repo.git"),
+                workspace: None
+            },
+            location
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_common_dir() -> eyre::Result<()> {
+        init();
+
+        let dir = tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        fs::create_dir_all(dir.join(".git/worktrees/wt"))?;
+        fs::write(dir.join(".git/worktrees/wt/commondir"), "../..\n")?;
+
+        assert_eq!(
+            dir.join(".git"),
+            common_dir(&dir.join(".git/worktrees/wt"))?
+        );
+        assert_eq!(dir.join(".git"), common_dir(&dir.join(".git"))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn honours_git_dir_and_work_tree() -> eyre::Result<()> {
+        init();
+
+        let dir = tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        fs::create_dir_all(dir.join("repo.git"))?;
+        fs::create_dir_all(dir.join("ws"))?;
+
+        let env = Env {
+            git_dir: Some("repo.git".into()),
+            work_tree: Some("ws".into()),
+            ..Env::default()
+        };
+        let location = discover(&dir, &env)?;
+        assert_eq!(
+            Location {
+                git_dir: dir.join("repo.git"),
+                workspace: Some(dir.join("ws"))
+            },
+            location
+        );
+
+        Ok(())
+    }
+}
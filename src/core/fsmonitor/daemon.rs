@@ -0,0 +1,453 @@
+//! Watches every directory of the workspace with inotify, recording the
+//! sequence number of the last change to each path.
+//!
+//! A change can be made just before a query but reach the daemon after it.
+//! So before answering, the daemon creates a cookie file in the git
+//! directory and waits to see it, as inotify delivers events in order.
+
+use bstr::{BString, ByteSlice};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::{ffi::OsStrExt, net::UnixListener},
+    path::{Component, Path, PathBuf},
+    process,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+
+use super::{cookie_dir, socket_path, Token, EVERYTHING};
+
+/// How long to wait to see a cookie before assuming events were lost.
+const COOKIE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A running daemon, see [`super`].
+#[derive(Debug)]
+pub struct Daemon {
+    git_dir: PathBuf,
+    server: JoinHandle<io::Result<()>>,
+    watcher: JoinHandle<()>,
+}
+
+impl Daemon {
+    /// Starts watching the workspace and answering queries on background
+    /// threads.
+    pub fn start(git_dir: &Path, workspace: &Path) -> Result<Self, StartError> {
+        let socket = socket_path(git_dir);
+        if super::is_running(git_dir) {
+            return Err(StartError::AlreadyRunning);
+        }
+        // Left behind by a daemon that didn't stop cleanly
+        match fs::remove_file(&socket) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(StartError::Io(err));
+            }
+            _ => {}
+        }
+
+        let cookies = cookie_dir(git_dir);
+        fs::create_dir_all(&cookies)?;
+
+        let mut inotify_watches = Watches {
+            inotify: Inotify::init()?,
+            root: workspace.to_owned(),
+            dirs: HashMap::new(),
+            cookies: None,
+        };
+        let wd = inotify_watches
+            .inotify
+            .add_watch(&cookies, WatchMask::CREATE | WatchMask::ONLYDIR)?;
+        inotify_watches.cookies = Some(wd);
+        inotify_watches.watch_tree(Path::new(""))?;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::new()),
+            changed: Condvar::new(),
+        });
+
+        let listener = UnixListener::bind(&socket)?;
+        debug!("fsmonitor daemon listening on {socket:?}");
+
+        let watcher = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || inotify_watches.run(&shared))
+        };
+        let server = {
+            let cookies = cookies.clone();
+            thread::spawn(move || serve(&listener, &shared, &cookies))
+        };
+
+        Ok(Self {
+            git_dir: git_dir.to_owned(),
+            server,
+            watcher,
+        })
+    }
+
+    /// Blocks until a client asks the daemon to stop, see [`super::stop`].
+    pub fn wait(self) -> io::Result<()> {
+        let result = self.server.join().expect("fsmonitor server panicked");
+        self.watcher.join().expect("fsmonitor watcher panicked");
+        if let Err(err) = fs::remove_file(socket_path(&self.git_dir)) {
+            warn!("Failed to remove fsmonitor socket: {err}");
+        }
+        result
+    }
+
+    pub fn stop(self) -> Result<(), super::Error> {
+        super::stop(&self.git_dir)?;
+        self.wait()?;
+        Ok(())
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Notified whenever the watcher has handled events
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("fsmonitor thread panicked")
+    }
+}
+
+struct State {
+    /// Tells apart the tokens of different daemons, as they number changes
+    /// from the start
+    id: String,
+    seq: u64,
+    /// Tokens from before this can't be answered, as events were lost
+    valid_since: u64,
+    /// The sequence number of the last change to each path
+    changed: BTreeMap<PathBuf, u64>,
+    next_cookie: u64,
+    seen_cookies: HashSet<u64>,
+    stopping: bool,
+}
+
+impl State {
+    fn new() -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Self {
+            id: format!("{}.{}", process::id(), started),
+            seq: 0,
+            valid_since: 0,
+            changed: BTreeMap::new(),
+            next_cookie: 0,
+            seen_cookies: HashSet::new(),
+            stopping: false,
+        }
+    }
+
+    fn record(&mut self, path: PathBuf) {
+        self.seq += 1;
+        debug!("fsmonitor change {}: {path:?}", self.seq);
+        self.changed.insert(path, self.seq);
+    }
+
+    /// Every earlier token is now unanswerable.
+    fn lose_events(&mut self) {
+        self.seq += 1;
+        self.valid_since = self.seq;
+        self.changed.clear();
+    }
+
+    fn token(&self) -> Token {
+        Token {
+            daemon: self.id.clone(),
+            seq: self.seq,
+        }
+    }
+
+    /// The new token followed by the changed paths, all NUL terminated.
+    fn respond(&self, token: Option<&str>) -> Vec<u8> {
+        let mut response = self.token().to_string().into_bytes();
+        response.push(0);
+
+        let since = token
+            .and_then(|token| token.parse::<Token>().ok())
+            .filter(|token| token.daemon == self.id && token.seq >= self.valid_since);
+        if let Some(since) = since {
+            for (path, _) in self.changed.iter().filter(|(_, &seq)| seq > since.seq) {
+                response.extend_from_slice(path.as_os_str().as_bytes());
+                response.push(0);
+            }
+        } else {
+            response.extend_from_slice(EVERYTHING);
+            response.push(0);
+        }
+        response
+    }
+}
+
+struct Watches {
+    inotify: Inotify,
+    root: PathBuf,
+    /// Each watched directory, relative to the workspace
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    cookies: Option<WatchDescriptor>,
+}
+
+impl Watches {
+    fn mask() -> WatchMask {
+        WatchMask::MODIFY
+            | WatchMask::ATTRIB
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MOVE
+            | WatchMask::ONLYDIR
+            | WatchMask::DONT_FOLLOW
+            | WatchMask::EXCL_UNLINK
+    }
+
+    /// Watches `dir` and every directory under it, except the git directory.
+    fn watch_tree(&mut self, dir: &Path) -> io::Result<()> {
+        let wd = match self.inotify.add_watch(self.root.join(dir), Self::mask()) {
+            Ok(wd) => wd,
+            // Gone already, the parent's watch reports that
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        self.dirs.insert(wd, dir.to_owned());
+
+        let entries = match fs::read_dir(self.root.join(dir)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() && !is_git_dir(&path) {
+                self.watch_tree(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops watching a directory that was moved away, along with the
+    /// directories under it.
+    fn unwatch_tree(&mut self, dir: &Path) {
+        let under = self
+            .dirs
+            .iter()
+            .filter(|(_, path)| path.starts_with(dir))
+            .map(|(wd, _)| wd.clone())
+            .collect::<Vec<_>>();
+        for wd in under {
+            self.dirs.remove(&wd);
+            if let Err(err) = self.inotify.rm_watch(wd) {
+                debug!("Failed to remove watch under {dir:?}: {err}");
+            }
+        }
+    }
+
+    fn run(mut self, shared: &Shared) {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match self.inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events
+                    .map(|event| (event.wd, event.mask, event.name.map(OsStr::to_owned)))
+                    .collect::<Vec<_>>(),
+                Err(err) => {
+                    warn!("Failed to read filesystem events, stopping: {err}");
+                    shared.lock().lose_events();
+                    return;
+                }
+            };
+
+            let mut state = shared.lock();
+            for (wd, mask, name) in events {
+                self.handle(&mut state, &wd, mask, name.as_deref());
+            }
+            let stopping = state.stopping;
+            drop(state);
+            shared.changed.notify_all();
+            if stopping {
+                return;
+            }
+        }
+    }
+
+    fn handle(
+        &mut self,
+        state: &mut State,
+        wd: &WatchDescriptor,
+        mask: EventMask,
+        name: Option<&OsStr>,
+    ) {
+        if mask.contains(EventMask::Q_OVERFLOW) {
+            warn!("Filesystem events were lost, the next status checks everything");
+            state.lose_events();
+            return;
+        }
+
+        if Some(wd) == self.cookies.as_ref() {
+            if let Some(cookie) = name.and_then(|n| n.to_str()?.parse().ok()) {
+                state.seen_cookies.insert(cookie);
+            }
+            return;
+        }
+
+        let dir = if let Some(dir) = self.dirs.get(wd) {
+            dir.clone()
+        } else {
+            return;
+        };
+        if mask.contains(EventMask::IGNORED) {
+            self.dirs.remove(wd);
+            return;
+        }
+        let path = name.map_or_else(|| dir.clone(), |name| dir.join(name));
+        if is_git_dir(&path) {
+            return;
+        }
+
+        if mask.contains(EventMask::ISDIR) {
+            if mask.contains(EventMask::MOVED_FROM) {
+                self.unwatch_tree(&path);
+            } else if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                // Anything created inside before it was watched is covered by
+                // the change to the directory itself
+                if let Err(err) = self.watch_tree(&path) {
+                    warn!("Failed to watch {path:?}, the next status checks everything: {err}");
+                    state.lose_events();
+                    return;
+                }
+            }
+        }
+        state.record(path);
+    }
+}
+
+fn is_git_dir(path: &Path) -> bool {
+    path.components().next() == Some(Component::Normal(OsStr::new(".git")))
+}
+
+fn serve(listener: &UnixListener, shared: &Shared, cookies: &Path) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let mut request = Vec::new();
+        if let Err(err) = stream.read_to_end(&mut request) {
+            warn!("Failed to read fsmonitor request: {err}");
+            continue;
+        }
+        let request = BString::from(request);
+        debug!("fsmonitor request {request:?}");
+
+        let (command, arg) = match request.find_byte(b' ') {
+            Some(i) => (&request[..i], Some(request[i + 1..].to_str_lossy())),
+            None => (&request[..], None),
+        };
+        let result = match command {
+            b"query" => sync(shared, cookies)
+                .and_then(|state| stream.write_all(&state.respond(arg.as_deref()))),
+            b"stop" => {
+                shared.lock().stopping = true;
+                // Wakes the watcher so it sees it's stopping
+                drop(sync(shared, cookies)?);
+                stream.shutdown(Shutdown::Both)?;
+                return Ok(());
+            }
+            _ => {
+                warn!("Unknown fsmonitor request {request:?}");
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            warn!("Failed to answer fsmonitor request: {err}");
+        }
+    }
+    Ok(())
+}
+
+/// Waits until the watcher has handled every event from before now. If the
+/// cookie isn't seen in time, every earlier token is taken to be invalid.
+fn sync<'s>(shared: &'s Shared, cookies: &Path) -> io::Result<MutexGuard<'s, State>> {
+    let cookie = {
+        let mut state = shared.lock();
+        state.next_cookie += 1;
+        state.next_cookie
+    };
+    let path = cookies.join(cookie.to_string());
+    fs::File::create(&path)?.flush()?;
+
+    let (mut state, timeout) = shared
+        .changed
+        .wait_timeout_while(shared.lock(), COOKIE_TIMEOUT, |state| {
+            !state.seen_cookies.contains(&cookie)
+        })
+        .expect("fsmonitor thread panicked");
+    if timeout.timed_out() {
+        warn!("Timed out waiting for filesystem events");
+        state.lose_events();
+    }
+    state.seen_cookies.remove(&cookie);
+    fs::remove_file(&path)?;
+    Ok(state)
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum StartError {
+    /// An fsmonitor daemon is already running for this repository
+    AlreadyRunning,
+    /// Failed to start watching workspace
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    fn response(state: &State, token: Option<&str>) -> Vec<String> {
+        state
+            .respond(token)
+            .split_str(b"\0")
+            .skip(1)
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_str_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn reports_changes_since_token() {
+        init();
+        let mut state = State::new();
+        state.record("a".into());
+        let token = state.token().to_string();
+        state.record("b".into());
+        state.record("dir/c".into());
+
+        assert_eq!(vec!["b", "dir/c"], response(&state, Some(&token)));
+        assert_eq!(vec!["/"], response(&state, None));
+        assert_eq!(vec!["/"], response(&state, Some("writ:other:0")));
+    }
+
+    #[test]
+    fn lost_events_invalidate_tokens() {
+        init();
+        let mut state = State::new();
+        let before = state.token().to_string();
+        state.record("a".into());
+        state.lose_events();
+        let after = state.token().to_string();
+        state.record("b".into());
+
+        assert_eq!(vec!["/"], response(&state, Some(&before)));
+        assert_eq!(vec!["b"], response(&state, Some(&after)));
+    }
+}
@@ -0,0 +1,155 @@
+//! A filesystem monitor, so status only checks files that changed since it
+//! last ran rather than statting the whole workspace. Like git's builtin
+//! `fsmonitor--daemon`, a [`Daemon`] watches the workspace and answers
+//! queries over a socket in the git directory, enabled with
+//! `core.fsmonitor`.
+//!
+//! Every change the daemon sees gets the next sequence number, and a token
+//! names the daemon and a sequence number. Asking for the changes since a
+//! token gives the paths changed after it and a new token for next time.
+//! A token from another daemon, as after a restart, can't be answered, so
+//! everything must be assumed to have changed.
+
+pub mod daemon;
+pub use daemon::Daemon;
+
+use bstr::{BStr, BString, ByteSlice};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::core::WsPath;
+
+const TOKEN_PREFIX: &str = "writ";
+/// Sent in place of paths when everything may have changed, as git's hooks
+/// do
+const EVERYTHING: &[u8] = b"/";
+
+/// Where the daemon for the repository at `git_dir` listens.
+pub fn socket_path(git_dir: &Path) -> PathBuf {
+    git_dir.join("fsmonitor--daemon.ipc")
+}
+
+/// Where the daemon creates files to find out it has seen every earlier
+/// change, see [`daemon`].
+fn cookie_dir(git_dir: &Path) -> PathBuf {
+    git_dir.join("fsmonitor--daemon").join("cookies")
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Changes {
+    /// To ask for the changes after these next time
+    pub token: BString,
+    /// Files and directories that changed, or `None` if anything may have
+    /// changed. A changed directory may have had anything under it change.
+    pub paths: Option<Vec<WsPath>>,
+}
+
+/// The changes since `token`, or everything if there's no token yet.
+pub fn query(git_dir: &Path, token: Option<&BStr>) -> Result<Changes, Error> {
+    let mut request = BString::from("query");
+    if let Some(token) = token {
+        request.push(b' ');
+        request.extend_from_slice(token);
+    }
+    let response = send(git_dir, &request)?;
+
+    let mut fields = response.split_str(b"\0");
+    let token = fields
+        .next()
+        .filter(|token| !token.is_empty())
+        .ok_or(Error::InvalidResponse)?;
+    let rest = fields.filter(|f| !f.is_empty()).collect::<Vec<_>>();
+    let paths = if rest.contains(&EVERYTHING) {
+        None
+    } else {
+        Some(rest.into_iter().map(WsPath::new_unchecked_bytes).collect())
+    };
+    Ok(Changes {
+        token: token.into(),
+        paths,
+    })
+}
+
+/// Asks the daemon to stop, returning once it has stopped watching.
+pub fn stop(git_dir: &Path) -> Result<(), Error> {
+    send(git_dir, b"stop")?;
+    Ok(())
+}
+
+pub fn is_running(git_dir: &Path) -> bool {
+    UnixStream::connect(socket_path(git_dir)).is_ok()
+}
+
+fn send(git_dir: &Path, request: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut stream = UnixStream::connect(socket_path(git_dir)).map_err(Error::Connect)?;
+    stream.write_all(request)?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(response)
+}
+
+/// Names the daemon that gave it out, and the last change it had seen.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Token {
+    daemon: String,
+    seq: u64,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", TOKEN_PREFIX, self.daemon, self.seq)
+    }
+}
+
+impl FromStr for Token {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(TOKEN_PREFIX), Some(daemon), Some(seq)) => Ok(Self {
+                daemon: daemon.to_owned(),
+                seq: seq.parse().map_err(|_| ())?,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum Error {
+    /// Failed to connect to fsmonitor daemon, is it running?
+    Connect(#[source] io::Error),
+    /// Failed to talk to fsmonitor daemon
+    Io(#[from] io::Error),
+    /// Invalid response from fsmonitor daemon
+    InvalidResponse,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_tokens() {
+        init();
+        let token = Token {
+            daemon: "123.456".to_owned(),
+            seq: 7,
+        };
+        assert_eq!("writ:123.456:7", token.to_string());
+        assert_eq!(Ok(token), "writ:123.456:7".parse());
+        assert_eq!(Err(()), "writ:123.456".parse::<Token>());
+        assert_eq!(Err(()), "builtin:123.456:7".parse::<Token>());
+        assert_eq!(Err(()), "writ:123.456:x".parse::<Token>());
+    }
+}
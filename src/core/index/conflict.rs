@@ -0,0 +1,76 @@
+use crate::core::WsPath;
+
+use super::{entry::Stage, Entry};
+
+/// The entries recorded for a path that has not been merged. At least one
+/// side is always present.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Conflict {
+    pub path: WsPath,
+    pub base: Option<Entry>,
+    pub ours: Option<Entry>,
+    pub theirs: Option<Entry>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[allow(clippy::module_name_repetitions)]
+pub enum ConflictKind {
+    BothDeleted,
+    AddedByUs,
+    DeletedByThem,
+    AddedByThem,
+    DeletedByUs,
+    BothAdded,
+    BothModified,
+}
+
+impl Conflict {
+    pub(super) fn new(path: WsPath) -> Self {
+        Self {
+            path,
+            base: None,
+            ours: None,
+            theirs: None,
+        }
+    }
+
+    pub(super) fn insert(&mut self, entry: Entry) {
+        match entry.stage() {
+            Stage::Base => self.base = Some(entry),
+            Stage::Ours => self.ours = Some(entry),
+            Stage::Theirs => self.theirs = Some(entry),
+            Stage::Merged => panic!("Merged entry {} isn't part of a conflict", entry),
+        }
+    }
+
+    pub fn kind(&self) -> ConflictKind {
+        match (
+            self.base.is_some(),
+            self.ours.is_some(),
+            self.theirs.is_some(),
+        ) {
+            (true, false, false) => ConflictKind::BothDeleted,
+            (false, true, false) => ConflictKind::AddedByUs,
+            (true, true, false) => ConflictKind::DeletedByThem,
+            (false, false, true) => ConflictKind::AddedByThem,
+            (true, false, true) => ConflictKind::DeletedByUs,
+            (false, true, true) => ConflictKind::BothAdded,
+            (true, true, true) => ConflictKind::BothModified,
+            (false, false, false) => unreachable!("Conflict has at least one side"),
+        }
+    }
+}
+
+impl ConflictKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BothDeleted => "both deleted",
+            Self::AddedByUs => "added by us",
+            Self::DeletedByThem => "deleted by them",
+            Self::AddedByThem => "added by them",
+            Self::DeletedByUs => "deleted by us",
+            Self::BothAdded => "both added",
+            Self::BothModified => "both modified",
+        }
+    }
+}
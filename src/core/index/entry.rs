@@ -1,12 +1,12 @@
 use bstr::{BStr, BString, ByteSlice};
 use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
-use std::{convert::TryInto, fmt, io};
-use tracing::{debug, instrument};
+use std::{convert::TryInto, fmt, io, time::SystemTime};
+use tracing::{debug, instrument, warn};
 
 use crate::core::{
     db::{object::OID_SIZE, Blob},
     stat::{self, Mode},
-    ws::{ReadFileError, StatFileError},
+    ws::{self, ReadFileError, StatFileError},
     Oid, Stat, Workspace, WsPath,
 };
 
@@ -21,6 +21,36 @@ pub struct Entry {
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Flags {
     path_len: PathLen,
+    stage: Stage,
+    /// Set by `update-index --assume-unchanged`. The workspace file is assumed
+    /// to match the entry without checking.
+    assume_valid: bool,
+    /// Set by `update-index --skip-worktree` and sparse checkout. The
+    /// workspace file is ignored, even if missing.
+    skip_worktree: bool,
+    /// Set by `add -N`. The path is tracked, but its contents aren't staged.
+    intent_to_add: bool,
+    /// Extended flags we don't know, kept to be written back as they were.
+    unknown_extended: u16,
+}
+
+/// The merge stage of an entry. Entries for a path are either a single
+/// [`Stage::Merged`] entry, or up to one of each of the other stages if the
+/// path has a conflict.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Stage {
+    Merged,
+    Base,
+    Ours,
+    Theirs,
+}
+
+/// What entries are keyed by in the index. Sorts by path and then stage, which
+/// is the order entries are stored in the index file.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Key {
+    pub path: BString,
+    pub stage: Stage,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -32,6 +62,7 @@ pub enum PathLen {
 impl Entry {
     const BLOCK_SIZE: usize = 8;
     const PATH_OFFSET: usize = 62;
+    const EXTENDED_PATH_OFFSET: usize = 64;
 
     pub fn new(path: impl Into<WsPath>, oid: Oid<Blob>, stat: Stat) -> Self {
         let path = path.into();
@@ -43,8 +74,73 @@ impl Entry {
         }
     }
 
-    pub fn key(&self) -> &BStr {
-        self.path.as_ref()
+    /// Set the stage, as when recording one side of a conflict.
+    pub fn with_stage(mut self, stage: Stage) -> Self {
+        self.flags.stage = stage;
+        self
+    }
+
+    /// Move the entry to `path`, keeping its flags, as when renaming it.
+    pub fn with_path(mut self, path: impl Into<WsPath>) -> Self {
+        self.path = path.into();
+        self.flags.path_len = PathLen::from(&self.path);
+        self
+    }
+
+    /// An entry recording only that `path` will be added later, as with
+    /// `git add -N`. Like git, it has the oid of the empty blob and no stat
+    /// besides the mode.
+    pub fn new_intent_to_add(path: impl Into<WsPath>, mode: Mode) -> Self {
+        let stat = Stat {
+            mode,
+            ..Stat::zeroed()
+        };
+        let mut entry = Self::new(path, Blob::empty_oid(), stat);
+        entry.flags.intent_to_add = true;
+        entry
+    }
+
+    pub fn key(&self) -> Key {
+        Key::new(&self.path, self.stage())
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.flags.stage
+    }
+
+    pub fn is_conflicted(&self) -> bool {
+        self.stage() != Stage::Merged
+    }
+
+    pub fn assume_unchanged(&self) -> bool {
+        self.flags.assume_valid
+    }
+
+    pub fn set_assume_unchanged(&mut self, value: bool) {
+        self.flags.assume_valid = value;
+    }
+
+    pub fn skip_worktree(&self) -> bool {
+        self.flags.skip_worktree
+    }
+
+    pub fn set_skip_worktree(&mut self, value: bool) {
+        self.flags.skip_worktree = value;
+    }
+
+    pub fn intent_to_add(&self) -> bool {
+        self.flags.intent_to_add
+    }
+
+    /// Whether the workspace file should be compared against this entry at
+    /// all.
+    pub fn is_checked_in_workspace(&self) -> bool {
+        !(self.assume_unchanged() || self.skip_worktree())
+    }
+
+    /// Entries with extended flags can only be written to a version 3 index.
+    pub fn needs_extended_flags(&self) -> bool {
+        self.flags.is_extended()
     }
 
     pub fn filename(&self) -> &BStr {
@@ -61,10 +157,30 @@ impl Entry {
         old
     }
 
+    /// An entry is racy if its file was modified no earlier than the index was
+    /// written, so a later change within the same timestamp granularity
+    /// wouldn't show up in its stat.
+    pub(crate) fn is_racy(&self, index_mtime: Option<SystemTime>) -> bool {
+        index_mtime.map_or(false, |index_mtime| self.stat.mtime >= index_mtime)
+    }
+
+    /// A smudged entry has had its size zeroed so that its stat can't be
+    /// trusted and its contents must be rehashed.
+    pub(crate) fn is_smudged(&self) -> bool {
+        self.stat.size == 0 && self.oid != Blob::empty_oid()
+    }
+
+    pub(crate) fn smudge(&mut self) {
+        self.stat.size = 0;
+    }
+
+    /// `index_mtime` is the modification time of the index file the entry was
+    /// loaded from, used to detect racily clean entries.
     #[instrument(err)]
     pub(crate) fn index_status_chatty(
         &self,
         workspace: &Workspace,
+        index_mtime: Option<SystemTime>,
     ) -> Result<StatusChatty, IsUnchangedError> {
         let new_stat = match workspace.stat(&self.path) {
             Ok(stat) => stat,
@@ -75,7 +191,8 @@ impl Entry {
             Err(err) => return Err(err.into()),
         };
 
-        if self.stat.size != new_stat.size || self.stat.mode != new_stat.mode {
+        let smudged = self.is_smudged();
+        if (self.stat.size != new_stat.size && !smudged) || self.stat.mode != new_stat.mode {
             debug!(
                 "Determined changed based on size or mode. other: {:?}",
                 new_stat
@@ -83,7 +200,9 @@ impl Entry {
             return Ok(StatusChatty::Modified);
         }
 
-        if self.times_match(&new_stat) {
+        if smudged || self.is_racy(index_mtime) {
+            debug!("Can't trust stat of racy or smudged entry, rehashing");
+        } else if self.times_match(&new_stat) {
             debug!(
                 "Determined unchanged based on timestamps. other: {:?}",
                 new_stat
@@ -92,6 +211,7 @@ impl Entry {
         }
 
         let new_data = workspace.read_file(&self.path)?;
+        let new_data = workspace.convert_to_git(&self.path, new_data)?;
         let new_oid = Blob::oid_for_file(new_data.as_bstr());
 
         if self.oid == new_oid {
@@ -130,9 +250,14 @@ impl Entry {
         writer.write_all(self.oid.as_bytes())?; // offset 60
         writer.write_u16::<NetworkEndian>(self.flags.as_u16())?; // offset 62
 
+        let extended = self.flags.is_extended();
+        if extended {
+            writer.write_u16::<NetworkEndian>(self.flags.extended_as_u16())?; // offset 64
+        }
+
         let path = self.path.as_bstr();
         writer.write_all(path)?;
-        for _ in 0..Self::padding_size(path) {
+        for _ in 0..Self::padding_size(path, extended) {
             writer.write_all(b"\0")?;
         }
 
@@ -175,7 +300,13 @@ impl Entry {
         let oid = Oid::new(oid);
 
         let flags = reader.read_u16::<NetworkEndian>()?; // offset 62
-        let flags = Flags::from_u16(flags);
+        let extended = flags & Flags::EXTENDED != 0;
+        let mut flags = Flags::from_u16(flags);
+
+        if extended {
+            let extended_flags = reader.read_u16::<NetworkEndian>()?; // offset 64
+            flags.set_extended_from_u16(extended_flags);
+        }
 
         let mut path = BString::from(Vec::new());
         loop {
@@ -185,7 +316,7 @@ impl Entry {
             }
             path.push(byte);
         }
-        let padding_size = Self::padding_size(&path);
+        let padding_size = Self::padding_size(&path, extended);
         // we already read one null byte
         if padding_size > 1 {
             for _ in 0..padding_size - 1 {
@@ -202,8 +333,13 @@ impl Entry {
         })
     }
 
-    fn padding_size(path: &[u8]) -> usize {
-        let len = Self::PATH_OFFSET + path.len();
+    fn padding_size(path: &[u8], extended: bool) -> usize {
+        let offset = if extended {
+            Self::EXTENDED_PATH_OFFSET
+        } else {
+            Self::PATH_OFFSET
+        };
+        let len = offset + path.len();
         // See <https://stackoverflow.com/a/11642218>
         let mut padding = (Self::BLOCK_SIZE - (len % Self::BLOCK_SIZE)) % Self::BLOCK_SIZE;
         if padding == 0 {
@@ -219,28 +355,121 @@ impl fmt::Display for Entry {
     }
 }
 
+impl Key {
+    pub fn new(path: impl AsRef<BStr>, stage: Stage) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            stage,
+        }
+    }
+}
+
 impl Flags {
+    const ASSUME_VALID: u16 = 0x8000;
+    const EXTENDED: u16 = 0x4000;
+    const STAGE_MASK: u16 = 0x3000;
+    const STAGE_SHIFT: u16 = 12;
+
+    const SKIP_WORKTREE: u16 = 0x4000;
+    const INTENT_TO_ADD: u16 = 0x2000;
+    const KNOWN_EXTENDED: u16 = Self::SKIP_WORKTREE | Self::INTENT_TO_ADD;
+
     fn from_path(path: &WsPath) -> Self {
         Self {
             path_len: PathLen::from(path),
+            stage: Stage::Merged,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            unknown_extended: 0,
         }
     }
 
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add || self.unknown_extended != 0
+    }
+
+    /// Only the flags stored in the first two bytes. If [`Self::is_extended`]
+    /// the rest must be set with [`Self::set_extended_from_u16`].
     fn from_u16(val: u16) -> Self {
-        let val = val as usize;
-        let path_len = if val <= PathLen::MAX {
-            PathLen::Exactly(val)
+        let stage = Stage::from_bits((val & Self::STAGE_MASK) >> Self::STAGE_SHIFT);
+        let assume_valid = val & Self::ASSUME_VALID != 0;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let len = (val & PathLen::MAX as u16) as usize;
+        let path_len = if len < PathLen::MAX {
+            PathLen::Exactly(len)
         } else {
             PathLen::MaxOrGreater
         };
-        Self { path_len }
+
+        Self {
+            path_len,
+            stage,
+            assume_valid,
+            skip_worktree: false,
+            intent_to_add: false,
+            unknown_extended: 0,
+        }
+    }
+
+    fn set_extended_from_u16(&mut self, val: u16) {
+        self.unknown_extended = val & !Self::KNOWN_EXTENDED;
+        if self.unknown_extended != 0 {
+            warn!(
+                "Keeping unknown extended index flags {:#06x}",
+                self.unknown_extended
+            );
+        }
+        self.skip_worktree = val & Self::SKIP_WORKTREE != 0;
+        self.intent_to_add = val & Self::INTENT_TO_ADD != 0;
     }
 
     fn as_u16(&self) -> u16 {
-        match self.path_len {
+        let mut val = match self.path_len {
             PathLen::Exactly(len) => len.try_into().expect("len < MAX"),
             #[allow(clippy::cast_possible_truncation)]
             PathLen::MaxOrGreater => PathLen::MAX as u16,
+        };
+        val |= self.stage.as_bits() << Self::STAGE_SHIFT;
+        if self.assume_valid {
+            val |= Self::ASSUME_VALID;
+        }
+        if self.is_extended() {
+            val |= Self::EXTENDED;
+        }
+        val
+    }
+
+    fn extended_as_u16(&self) -> u16 {
+        let mut val = self.unknown_extended;
+        if self.skip_worktree {
+            val |= Self::SKIP_WORKTREE;
+        }
+        if self.intent_to_add {
+            val |= Self::INTENT_TO_ADD;
+        }
+        val
+    }
+}
+
+impl Stage {
+    fn from_bits(bits: u16) -> Self {
+        match bits {
+            0 => Self::Merged,
+            1 => Self::Base,
+            2 => Self::Ours,
+            3 => Self::Theirs,
+            _ => unreachable!("Stage is two bits"),
+        }
+    }
+
+    fn as_bits(self) -> u16 {
+        match self {
+            Self::Merged => 0,
+            Self::Base => 1,
+            Self::Ours => 2,
+            Self::Theirs => 3,
         }
     }
 }
@@ -250,7 +479,7 @@ impl PathLen {
 
     fn from(path: &WsPath) -> Self {
         let path = path.as_bstr();
-        if path.len() <= Self::MAX {
+        if path.len() < Self::MAX {
             Self::Exactly(path.len())
         } else {
             Self::MaxOrGreater
@@ -264,6 +493,8 @@ pub enum IsUnchangedError {
     Stat(#[from] StatFileError),
     /// Failed to read file
     Read(#[from] ReadFileError),
+    /// Failed to convert file
+    Convert(#[from] ws::ConvertError),
 }
 
 pub(crate) enum StatusChatty {
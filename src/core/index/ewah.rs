@@ -0,0 +1,157 @@
+//! The compressed bitmaps git stores in some index extensions, see
+//! `ewah/ewah_io.c` in git.
+//!
+//! Bits are packed into 64-bit words, least significant first. The words
+//! are stored as a sequence of marker words, each followed by its literal
+//! words. A marker gives a run of words that are all zero or all one, and
+//! how many literal words follow it.
+
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use std::{convert::TryInto, io};
+
+const WORD_BITS: usize = 64;
+const RUNNING_LEN_BITS: u32 = 32;
+const MAX_RUNNING_LEN: u64 = (1 << RUNNING_LEN_BITS) - 1;
+const MAX_LITERAL_WORDS: usize = (1 << 31) - 1;
+
+/// Returns the bitmap with `len` bits, set where `is_set` is true.
+pub fn encode(len: usize, is_set: impl Fn(usize) -> bool) -> Vec<u8> {
+    let words = (0..(len + WORD_BITS - 1) / WORD_BITS)
+        .map(|w| {
+            (0..WORD_BITS)
+                .filter(|&b| w * WORD_BITS + b < len && is_set(w * WORD_BITS + b))
+                .fold(0_u64, |word, b| word | 1 << b)
+        })
+        .collect::<Vec<_>>();
+
+    let mut encoded = Vec::new();
+    let mut last_marker = 0;
+    let mut i = 0;
+    while i < words.len() || encoded.is_empty() {
+        let fill = words.get(i).copied().unwrap_or(0);
+        let running_bit = fill == u64::MAX;
+        let mut running_len = 0;
+        while i < words.len() && words[i] == fill && (fill == 0 || running_bit) {
+            if running_len == MAX_RUNNING_LEN {
+                break;
+            }
+            running_len += 1;
+            i += 1;
+        }
+        let literals_start = i;
+        while i < words.len()
+            && words[i] != 0
+            && words[i] != u64::MAX
+            && i - literals_start < MAX_LITERAL_WORDS
+        {
+            i += 1;
+        }
+
+        last_marker = encoded.len();
+        let literals = (i - literals_start) as u64;
+        encoded.push(u64::from(running_bit) | running_len << 1 | literals << 33);
+        encoded.extend_from_slice(&words[literals_start..i]);
+    }
+
+    let mut out = Vec::with_capacity(12 + encoded.len() * 8);
+    let as_u32 = |n: usize| -> u32 { n.try_into().expect("Bitmap too large") };
+    out.write_u32::<NetworkEndian>(as_u32(len)).unwrap();
+    out.write_u32::<NetworkEndian>(as_u32(encoded.len()))
+        .unwrap();
+    for word in encoded {
+        out.write_u64::<NetworkEndian>(word).unwrap();
+    }
+    out.write_u32::<NetworkEndian>(as_u32(last_marker)).unwrap();
+    out
+}
+
+/// The positions of the set bits, in order.
+pub fn decode(mut input: &[u8]) -> io::Result<Vec<usize>> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let len = input.read_u32::<NetworkEndian>()? as usize;
+    let word_count = input.read_u32::<NetworkEndian>()? as usize;
+    if input.len() < word_count * 8 + 4 {
+        return Err(invalid("bitmap shorter than its size"));
+    }
+    let mut words = Vec::with_capacity(word_count);
+    for _ in 0..word_count {
+        words.push(input.read_u64::<NetworkEndian>()?);
+    }
+    let _last_marker = input.read_u32::<NetworkEndian>()?;
+
+    let mut set = Vec::new();
+    let mut pos = 0;
+    let mut i = 0;
+    while i < words.len() {
+        let marker = words[i];
+        i += 1;
+        let running_len = ((marker >> 1) & MAX_RUNNING_LEN) as usize;
+        let literals = (marker >> 33) as usize;
+
+        if marker & 1 == 1 {
+            set.extend(pos..(pos + running_len * WORD_BITS).min(len));
+        }
+        pos += running_len * WORD_BITS;
+
+        let literals = words
+            .get(i..i + literals)
+            .ok_or_else(|| invalid("bitmap literals past its end"))?;
+        for word in literals {
+            set.extend(
+                (0..WORD_BITS)
+                    .filter(|b| word & 1 << b != 0)
+                    .map(|b| pos + b)
+                    .filter(|&bit| bit < len),
+            );
+            pos += WORD_BITS;
+        }
+        i += literals.len();
+    }
+
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    fn round_trip(len: usize, set: &[usize]) -> eyre::Result<()> {
+        let encoded = encode(len, |bit| set.contains(&bit));
+        assert_eq!(set.to_vec(), decode(&encoded)?, "{} bits", len);
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips() -> eyre::Result<()> {
+        init();
+        round_trip(0, &[])?;
+        round_trip(5, &[])?;
+        round_trip(5, &[0, 4])?;
+        round_trip(200, &[1, 63, 64, 199])?;
+        round_trip(1000, &(128..576).collect::<Vec<_>>())?;
+        round_trip(1000, &[3, 900])?;
+        Ok(())
+    }
+
+    #[test]
+    fn compresses_runs() {
+        init();
+        // Header, a marker for the run of empty words and the literal, then
+        // the position of the marker
+        let encoded = encode(64 * 100, |bit| bit == 64 * 99 + 1);
+        assert_eq!(4 + 4 + 2 * 8 + 4, encoded.len());
+    }
+
+    #[test]
+    fn matches_gits_layout() -> eyre::Result<()> {
+        init();
+        // Size, word count, a marker for no run and one literal, the literal
+        // and the marker's position
+        let expected = hex::decode("00000003000000020000000200000000000000000000000600000000")?;
+        assert_eq!(expected, encode(3, |bit| bit > 0));
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
+pub mod conflict;
 pub mod entry;
+pub mod ewah;
+pub use conflict::{Conflict, ConflictKind};
 pub use entry::Entry;
 
 use std::{
@@ -9,58 +12,103 @@ use std::{
     io::{self, Read, Write},
     ops::Deref,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+use self::entry::{Key, Stage};
 use crate::core::{locked_file, LockedFile, Stat, WithDigest, WsPath};
-use bstr::BString;
+use bstr::{BStr, BString, ByteSlice};
 use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 use ring::digest::SHA1_FOR_LEGACY_USE_ONLY as SHA1;
 use tracing::debug;
 
-type EntriesMap = BTreeMap<BString, Entry>;
+type EntriesMap = BTreeMap<Key, Entry>;
 
 #[derive(Clone)]
 pub struct Index {
     entries: EntriesMap,
     path: PathBuf,
+    /// When the index file was last written, if it exists
+    mtime: Option<SystemTime>,
+    fsmonitor: Option<FsmonitorState>,
+}
+
+/// What the index records for a filesystem monitor, see
+/// [`crate::core::fsmonitor`]: the token of the last query, and the entries
+/// that were known to match the workspace then.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct FsmonitorState {
+    token: BString,
+    valid: BTreeSet<Key>,
+}
+
+/// Everything stored in the index file.
+struct Contents {
+    entries: EntriesMap,
+    fsmonitor: Option<FsmonitorState>,
 }
 
 impl Index {
     const SIG: &'static [u8] = b"DIRC";
     const VERSION: u32 = 2;
+    /// Version 3 is only needed for entries with extended flags
+    const EXTENDED_VERSION: u32 = 3;
     const CHECKSUM_LEN: usize = 20;
+    const FSMONITOR_SIG: &'static [u8] = b"FSMN";
+    const FSMONITOR_VERSION: u32 = 2;
 
     pub fn load<P: AsRef<Path>>(git_dir: P) -> Result<Self, LoadError> {
         let path = Self::file_path(git_dir);
-        let entries = Self::load_entries(&path)?;
+        let (contents, mtime) = Self::load_entries(&path)?;
 
-        Ok(Self { entries, path })
+        Ok(Self {
+            entries: contents.entries,
+            path,
+            mtime,
+            fsmonitor: contents.fsmonitor,
+        })
     }
 
     /// Reload the index from disk. You don't need to do this after using
     /// [`Self::modify`] on this instance, this is for getting changes made by
     /// external programs.
     pub fn reload(&mut self) -> Result<(), LoadError> {
-        let entries = Self::load_entries(&self.path)?;
-        self.entries = entries;
+        let (contents, mtime) = Self::load_entries(&self.path)?;
+        self.entries = contents.entries;
+        self.fsmonitor = contents.fsmonitor;
+        self.mtime = mtime;
         Ok(())
     }
 
-    fn load_entries(path: &Path) -> Result<EntriesMap, LoadError> {
+    fn load_entries(path: &Path) -> Result<(Contents, Option<SystemTime>), LoadError> {
         let file = match File::open(&path) {
             Ok(file) => file,
             Err(err) if err.kind() == io::ErrorKind::NotFound => {
                 debug!("Index does not exist");
-                return Ok(BTreeMap::new());
+                let contents = Contents {
+                    entries: BTreeMap::new(),
+                    fsmonitor: None,
+                };
+                return Ok((contents, None));
             }
             Err(err) => return Err(err.into()),
         };
+        let mtime = file.metadata()?.modified()?;
 
-        Self::load_entries_from(file)
+        Ok((Self::load_entries_from(file)?, Some(mtime)))
     }
 
-    fn load_entries_from(mut reader: impl Read) -> Result<EntriesMap, LoadError> {
-        let mut input = WithDigest::new(&SHA1, &mut reader);
+    fn load_entries_from(mut reader: impl Read) -> Result<Contents, LoadError> {
+        // Extensions run up to the checksum, so the whole file is read to
+        // find where they end
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let body_len = data
+            .len()
+            .checked_sub(Self::CHECKSUM_LEN)
+            .ok_or(CorruptError::IncorrectChecksum)?;
+        let (body, actual_checksum) = data.split_at(body_len);
+        let mut input = WithDigest::new(&SHA1, body);
 
         let mut sig = [0; 4];
         input.read_exact(&mut sig)?; // offset 0
@@ -69,7 +117,7 @@ impl Index {
         }
 
         let version = input.read_u32::<NetworkEndian>()?; // offset 4
-        if version != Self::VERSION {
+        if version != Self::VERSION && version != Self::EXTENDED_VERSION {
             return Err(LoadError::UnsupportedVersion(version));
         }
 
@@ -77,39 +125,180 @@ impl Index {
 
         // offset 12
         let mut entries = BTreeMap::new();
+        let mut order = Vec::new();
         for _ in 0..count {
             let entry = Entry::parse_from_index(&mut input)?;
-            entries.insert(entry.key().to_owned(), entry);
+            order.push(entry.key());
+            entries.insert(entry.key(), entry);
         }
 
-        let expected_checksum = input.finish();
-        let expected_checksum: [u8; Self::CHECKSUM_LEN] =
-            expected_checksum.as_ref().try_into().unwrap();
-
-        let mut actual_checksum = [0; Self::CHECKSUM_LEN];
-        reader.read_exact(&mut actual_checksum)?;
+        let mut fsmonitor = None;
+        let mut sig = [0; 4];
+        while input.read(&mut sig[..1])? == 1 {
+            input.read_exact(&mut sig[1..])?;
+            let size = input.read_u32::<NetworkEndian>()?;
+            let mut ext = vec![0; size as usize];
+            input.read_exact(&mut ext)?;
+
+            if sig == Self::FSMONITOR_SIG {
+                fsmonitor = Self::parse_fsmonitor(&ext, &order)?;
+            } else if sig[0].is_ascii_uppercase() {
+                debug!("Ignoring optional index extension {:?}", sig.as_bstr());
+            } else {
+                return Err(LoadError::UnsupportedExtension(sig.as_bstr().to_owned()));
+            }
+        }
 
-        if actual_checksum != expected_checksum {
+        let expected_checksum = input.finish();
+        if actual_checksum != expected_checksum.as_ref() {
             return Err(CorruptError::IncorrectChecksum.into());
         }
 
-        Ok(entries)
+        Ok(Contents { entries, fsmonitor })
+    }
+
+    /// `order` is the keys of the entries in the order they were stored, as
+    /// the bitmap of entries that may have changed refers to them by position.
+    fn parse_fsmonitor(mut ext: &[u8], order: &[Key]) -> Result<Option<FsmonitorState>, LoadError> {
+        let version = ext.read_u32::<NetworkEndian>()?;
+        if version != Self::FSMONITOR_VERSION {
+            // Version 1 stores a timestamp for hooks, not a token. Without
+            // it, every entry is checked again.
+            debug!("Ignoring fsmonitor extension version {version}");
+            return Ok(None);
+        }
+        let token_len = ext
+            .find_byte(0)
+            .ok_or(CorruptError::InvalidExtension("FSMN"))?;
+        let token = ext[..token_len].into();
+        ext = &ext[token_len + 1..];
+        let bitmap_len = ext.read_u32::<NetworkEndian>()? as usize;
+        let bitmap = ext
+            .get(..bitmap_len)
+            .ok_or(CorruptError::InvalidExtension("FSMN"))?;
+
+        let mut valid = order.iter().cloned().collect::<BTreeSet<_>>();
+        for bit in ewah::decode(bitmap).map_err(|_| CorruptError::InvalidExtension("FSMN"))? {
+            let key = order
+                .get(bit)
+                .ok_or(CorruptError::InvalidExtension("FSMN"))?;
+            valid.remove(key);
+        }
+        Ok(Some(FsmonitorState { token, valid }))
     }
 
     pub fn entries(&self) -> impl Iterator<Item = &Entry> {
         self.entries.values()
     }
 
+    /// When the index file was last written, `None` if it doesn't exist yet.
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.mtime
+    }
+
+    /// The filesystem monitor token the index was last refreshed with, if
+    /// a monitor is in use.
+    pub fn fsmonitor_token(&self) -> Option<&BStr> {
+        self.fsmonitor.as_ref().map(|state| state.token.as_bstr())
+    }
+
+    /// Whether the filesystem monitor has reported no change to the merged
+    /// entry for `path` since it was last found to match the workspace, so
+    /// its file needn't be checked.
+    pub fn is_fsmonitor_valid(&self, path: &WsPath) -> bool {
+        self.fsmonitor.as_ref().map_or(false, |state| {
+            state.valid.contains(&Key::new(path, Stage::Merged))
+        })
+    }
+
     pub fn modify(&mut self) -> Result<IndexMut, OpenForModificationsError> {
         IndexMut::new(self)
     }
 
+    /// Includes paths that are tracked only as part of a conflict.
     pub fn is_tracked_file(&self, path: &WsPath) -> bool {
-        self.entries.contains_key(path.as_bstr())
+        self.stages_of(path).next().is_some()
     }
 
+    /// The merged entry for `path`. Conflicted paths have no merged entry,
+    /// see [`Self::conflict`].
     pub fn entry(&self, path: &WsPath) -> Option<&Entry> {
-        self.entries.get(path.as_bstr())
+        self.entry_at(path, Stage::Merged)
+    }
+
+    pub fn entry_at(&self, path: &WsPath, stage: Stage) -> Option<&Entry> {
+        self.entries.get(&Key::new(path, stage))
+    }
+
+    /// Every entry for `path`, in order of stage.
+    pub fn stages_of(&self, path: &WsPath) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .range(Key::new(path, Stage::Merged)..=Key::new(path, Stage::Theirs))
+            .map(|(_, entry)| entry)
+    }
+
+    /// Entries for files inside the directory `dir`, or every entry if `dir`
+    /// is the root.
+    pub fn entries_under<'a>(&'a self, dir: &'a WsPath) -> impl Iterator<Item = &'a Entry> + 'a {
+        self.entries()
+            .filter(move |entry| entry.path != *dir && entry.path.as_path().starts_with(dir))
+    }
+
+    /// Whether any file inside the directory `dir` is tracked. Unlike
+    /// [`Self::entries_under`], this doesn't scan the whole index.
+    pub fn has_entries_under(&self, dir: &WsPath) -> bool {
+        let mut prefix = dir.to_bstring();
+        if prefix.is_empty() {
+            return !self.entries.is_empty();
+        }
+        prefix.push(b'/');
+
+        let start = Key {
+            path: prefix.clone(),
+            stage: Stage::Merged,
+        };
+        self.entries
+            .range(start..)
+            .next()
+            .map_or(false, |(key, _)| key.path.starts_with(&prefix))
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries().any(Entry::is_conflicted)
+    }
+
+    pub fn is_conflicted(&self, path: &WsPath) -> bool {
+        self.stages_of(path).any(Entry::is_conflicted)
+    }
+
+    pub fn conflict(&self, path: &WsPath) -> Option<Conflict> {
+        let mut conflict = None;
+        for entry in self.stages_of(path).filter(|e| e.is_conflicted()) {
+            conflict
+                .get_or_insert_with(|| Conflict::new(path.clone()))
+                .insert(entry.clone());
+        }
+        conflict
+    }
+
+    /// Paths with unmerged entries, in order.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts: Vec<Conflict> = Vec::new();
+        for entry in self.entries().filter(|e| e.is_conflicted()) {
+            match conflicts.last_mut() {
+                Some(conflict) if conflict.path == entry.path => conflict.insert(entry.clone()),
+                _ => {
+                    let mut conflict = Conflict::new(entry.path.clone());
+                    conflict.insert(entry.clone());
+                    conflicts.push(conflict);
+                }
+            }
+        }
+        conflicts
+    }
+
+    pub fn unmerged_paths(&self) -> Vec<WsPath> {
+        self.conflicts().into_iter().map(|c| c.path).collect()
     }
 
     fn file_path(git_dir: impl AsRef<Path>) -> PathBuf {
@@ -133,6 +322,9 @@ pub struct IndexMut<'i> {
     index: &'i mut Index,
     parents: ParentsMap,
     lock: Option<LockedFile>,
+    /// Entries whose stat was taken from the workspace while the index was
+    /// open, so they can't be racily clean.
+    verified: BTreeSet<Key>,
 }
 
 impl<'i> IndexMut<'i> {
@@ -148,13 +340,32 @@ impl<'i> IndexMut<'i> {
             index,
             parents,
             lock: Some(lock),
+            verified: BTreeSet::new(),
         })
     }
 
+    /// Adding a merged entry resolves any conflict for the path, and adding
+    /// one side of a conflict replaces the merged entry.
     pub fn add(&mut self, entry: Entry) {
         Self::populate_parents_for(&mut self.parents, &entry);
         self.discard_conflicts_with(&entry.path);
-        self.index.entries.insert(entry.key().to_owned(), entry);
+        self.discard_other_stages_of(&entry);
+        self.verified.insert(entry.key());
+        if let Some(state) = &mut self.index.fsmonitor {
+            state.valid.remove(&entry.key());
+        }
+        self.index.entries.insert(entry.key(), entry);
+    }
+
+    fn discard_other_stages_of(&mut self, entry: &Entry) {
+        let stages: &[Stage] = if entry.is_conflicted() {
+            &[Stage::Merged]
+        } else {
+            &[Stage::Base, Stage::Ours, Stage::Theirs]
+        };
+        for stage in stages {
+            self.index.entries.remove(&Key::new(&entry.path, *stage));
+        }
     }
 
     fn populate_parents_for(parents: &mut ParentsMap, entry: &Entry) {
@@ -169,7 +380,7 @@ impl<'i> IndexMut<'i> {
     fn discard_conflicts_with(&mut self, path: &WsPath) {
         // If the new entry is lib/index/foo, remove lib and index.
         for parent in path.parents() {
-            self.index.entries.remove(parent.as_bstr());
+            self.remove_stages(&parent);
         }
 
         // If the new entry is lib, remove lib/index/foo and lib/index
@@ -182,28 +393,114 @@ impl<'i> IndexMut<'i> {
             }
 
             for path in to_remove {
-                self.remove(&WsPath::new_unchecked_bytes(path))
-                    .expect("Parents out of sync");
+                let removed = self.remove(&WsPath::new_unchecked_bytes(path));
+                assert!(!removed.is_empty(), "Parents out of sync");
             }
         }
     }
 
+    pub fn set_assume_unchanged(
+        &mut self,
+        path: &WsPath,
+        value: bool,
+    ) -> Result<(), NonexistentEntryError> {
+        self.merged_entry_mut(path)?.set_assume_unchanged(value);
+        self.invalidate_fsmonitor(Some(path));
+        Ok(())
+    }
+
+    pub fn set_skip_worktree(
+        &mut self,
+        path: &WsPath,
+        value: bool,
+    ) -> Result<(), NonexistentEntryError> {
+        self.merged_entry_mut(path)?.set_skip_worktree(value);
+        self.invalidate_fsmonitor(Some(path));
+        Ok(())
+    }
+
+    fn merged_entry_mut(&mut self, path: &WsPath) -> Result<&mut Entry, NonexistentEntryError> {
+        self.index
+            .entries
+            .get_mut(&Key::new(path, Stage::Merged))
+            .ok_or(NonexistentEntryError)
+    }
+
     pub fn update_stat(
         &mut self,
         path: &WsPath,
         stat: Stat,
     ) -> Result<Stat, NonexistentEntryError> {
-        let entry = self
-            .index
-            .entries
-            .get_mut(path.as_bstr())
-            .ok_or(NonexistentEntryError)?;
-        let old = entry.update_stat(stat);
+        let old = self.merged_entry_mut(path)?.update_stat(stat);
+        self.verified.insert(Key::new(path, Stage::Merged));
         Ok(old)
     }
 
-    pub fn remove(&mut self, path: &WsPath) -> Option<Entry> {
-        if let Some(entry) = self.index.entries.remove(path.as_bstr()) {
+    /// Records the token of a filesystem monitor query, or with `None` stops
+    /// recording it. Entries valid for the previous token stay valid, so
+    /// the changes reported since must be passed to
+    /// [`Self::invalidate_fsmonitor`].
+    pub fn set_fsmonitor_token(&mut self, token: Option<BString>) {
+        match (token, &mut self.index.fsmonitor) {
+            (Some(token), Some(state)) => state.token = token,
+            (Some(token), fsmonitor) => {
+                *fsmonitor = Some(FsmonitorState {
+                    token,
+                    valid: BTreeSet::new(),
+                });
+            }
+            (None, fsmonitor) => *fsmonitor = None,
+        }
+    }
+
+    /// Marks the entries for `path` and any files under it as possibly
+    /// changed, or every entry if `path` is `None`.
+    pub fn invalidate_fsmonitor(&mut self, path: Option<&WsPath>) {
+        let state = if let Some(state) = &mut self.index.fsmonitor {
+            state
+        } else {
+            return;
+        };
+        let path = match path {
+            Some(path) if !path.as_bstr().is_empty() => path.to_bstring(),
+            _ => {
+                state.valid.clear();
+                return;
+            }
+        };
+
+        let start = Key {
+            path: path.clone(),
+            stage: Stage::Merged,
+        };
+        let under = state
+            .valid
+            .range(start..)
+            .take_while(|key| key.path.starts_with(&path))
+            .filter(|key| key.path.len() == path.len() || key.path[path.len()] == b'/')
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in under {
+            state.valid.remove(&key);
+        }
+    }
+
+    /// Records that the merged entry for `path` matches the workspace.
+    pub fn set_fsmonitor_valid(&mut self, path: &WsPath) {
+        let key = Key::new(path, Stage::Merged);
+        if let Some(state) = &mut self.index.fsmonitor {
+            if self.index.entries.contains_key(&key) {
+                state.valid.insert(key);
+            }
+        }
+    }
+
+    /// Removes every stage of `path`, returning the removed entries in order
+    /// of stage.
+    pub fn remove(&mut self, path: &WsPath) -> Vec<Entry> {
+        let removed = self.remove_stages(path);
+
+        if !removed.is_empty() {
             for parent in path.parents() {
                 if let Some(children) = self.parents.get_mut(parent.as_bstr()) {
                     children.remove(path.as_bstr());
@@ -212,20 +509,39 @@ impl<'i> IndexMut<'i> {
                     }
                 }
             }
+        }
 
-            Some(entry)
-        } else {
-            None
+        removed
+    }
+
+    fn remove_stages(&mut self, path: &WsPath) -> Vec<Entry> {
+        let keys = self.stages_of(path).map(Entry::key).collect::<Vec<_>>();
+        if let Some(state) = &mut self.index.fsmonitor {
+            for key in &keys {
+                state.valid.remove(key);
+            }
         }
+        keys.iter()
+            .filter_map(|key| self.index.entries.remove(key))
+            .collect()
     }
 
     pub fn commit(mut self) -> Result<(), CommitError> {
         let mut lock = self.lock.take().expect("Has a lock");
 
+        let written_at = lock.lock_file().metadata()?.modified()?;
+        self.smudge_racy_entries(written_at);
+
         let mut out = WithDigest::new(&SHA1, &mut lock);
 
+        let version = if self.entries().any(Entry::needs_extended_flags) {
+            Index::EXTENDED_VERSION
+        } else {
+            Index::VERSION
+        };
+
         out.write_all(Index::SIG)?; // offset 0
-        out.write_u32::<NetworkEndian>(Index::VERSION)?; // offset 4
+        out.write_u32::<NetworkEndian>(version)?; // offset 4
 
         let size = self.index.entries.len().try_into().expect("Len overflowed");
         out.write_u32::<NetworkEndian>(size)?; // offset 8
@@ -234,13 +550,47 @@ impl<'i> IndexMut<'i> {
             entry.write_to_index(&mut out)?;
         }
 
+        if let Some(state) = &self.index.fsmonitor {
+            let mut ext = Vec::new();
+            ext.write_u32::<NetworkEndian>(Index::FSMONITOR_VERSION)?;
+            ext.extend_from_slice(&state.token);
+            ext.push(0);
+            // Bits are set for the entries that may have changed
+            let keys = self.index.entries.keys().collect::<Vec<_>>();
+            let bitmap = ewah::encode(keys.len(), |i| !state.valid.contains(keys[i]));
+            ext.write_u32::<NetworkEndian>(bitmap.len().try_into().expect("Len overflowed"))?;
+            ext.extend_from_slice(&bitmap);
+
+            out.write_all(Index::FSMONITOR_SIG)?;
+            out.write_u32::<NetworkEndian>(ext.len().try_into().expect("Len overflowed"))?;
+            out.write_all(&ext)?;
+        }
+
         let hash = out.finish();
         lock.write_all(hash.as_ref())?; // offset
 
         lock.commit()?;
+        self.index.mtime = Some(self.index.path.metadata()?.modified()?);
 
         Ok(())
     }
+
+    /// Any entry that was racy when the index was loaded, or is racy compared
+    /// to the index we're about to write, would look clean once the index is
+    /// rewritten, so unless we've checked its stat it must be smudged.
+    fn smudge_racy_entries(&mut self, written_at: SystemTime) {
+        let racy_since = self
+            .index
+            .mtime
+            .map_or(written_at, |loaded| loaded.min(written_at));
+
+        for (key, entry) in &mut self.index.entries {
+            if !self.verified.contains(key) && entry.is_racy(Some(racy_since)) {
+                debug!("Smudging racy entry {}", entry);
+                entry.smudge();
+            }
+        }
+    }
 }
 
 impl Deref for IndexMut<'_> {
@@ -264,8 +614,10 @@ impl PartialEq for IndexMut<'_> {
 pub enum LoadError {
     /// Failed to read index file, corrupt
     Corrupt(#[from] CorruptError),
-    /// Only version 2 of the index file is supported, but index is version {0}
+    /// Only versions 2 and 3 of the index file are supported, but index is version {0}
     UnsupportedVersion(u32),
+    /// Index has required extension {0} which is not supported
+    UnsupportedExtension(BString),
     /// Performing IO
     Io(#[from] io::Error),
 }
@@ -301,6 +653,8 @@ pub enum CorruptError {
     MissingSignature,
     /// Failed checksum validation
     IncorrectChecksum,
+    /// Invalid {0} extension
+    InvalidExtension(&'static str),
 }
 
 #[cfg(test)]
@@ -320,7 +674,7 @@ mod tests {
         init();
 
         let sample = hex::decode(SAMPLE_INDEX)?;
-        let actual = Index::load_entries_from(&*sample)?;
+        let actual = Index::load_entries_from(&*sample)?.entries;
 
         assert_debug_snapshot!(actual);
 
@@ -330,8 +684,10 @@ mod tests {
     fn index_fixture() -> eyre::Result<(tempfile::NamedTempFile, Index)> {
         let file = tempfile::NamedTempFile::new()?;
         let index = Index {
-            entries: BTreeMap::<BString, Entry>::new(),
+            entries: EntriesMap::new(),
             path: file.path().to_owned(),
+            mtime: None,
+            fsmonitor: None,
         };
 
         Ok((file, index))
@@ -428,6 +784,147 @@ mod tests {
         Ok(())
     }
 
+    fn conflicted_fixture(path: &str, stages: &[Stage]) -> Vec<Entry> {
+        stages
+            .iter()
+            .map(|stage| entry_fixture(path).with_stage(*stage))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_conflict_stages() -> eyre::Result<()> {
+        init();
+
+        let (file, mut index) = index_fixture()?;
+        let mut index_mut = index.modify()?;
+        index_mut.add(entry_fixture("alice.txt"));
+        for entry in conflicted_fixture("bob.txt", &[Stage::Base, Stage::Ours, Stage::Theirs]) {
+            index_mut.add(entry);
+        }
+        index_mut.commit()?;
+
+        let (actual, _) = Index::load_entries(file.path())?;
+        assert_eq!(index.entries, actual.entries);
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_unknown_extended_flags() -> eyre::Result<()> {
+        init();
+
+        let mut entry = entry_fixture("alice.txt");
+        entry.set_skip_worktree(true);
+        let mut bytes = Vec::new();
+        entry.write_to_index(&mut bytes)?;
+        // The extended flags follow the stat, oid and flags
+        bytes[62] |= 0x10;
+
+        let parsed = Entry::parse_from_index(&mut &*bytes)?;
+        assert!(parsed.skip_worktree());
+        let mut written = Vec::new();
+        parsed.write_to_index(&mut written)?;
+        assert_eq!(bytes, written);
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_fsmonitor_state() -> eyre::Result<()> {
+        init();
+
+        let (file, mut index) = index_fixture()?;
+        let mut index_mut = index.modify()?;
+        for path in &["a.txt", "dir/b.txt", "dir/c.txt", "dir.txt"] {
+            index_mut.add(entry_fixture(path));
+        }
+        index_mut.set_fsmonitor_token(Some("token".into()));
+        for path in &["a.txt", "dir/b.txt", "dir/c.txt", "dir.txt"] {
+            index_mut.set_fsmonitor_valid(&WsPath::new_unchecked(path));
+        }
+        index_mut.invalidate_fsmonitor(Some(&WsPath::new_unchecked("dir")));
+        index_mut.commit()?;
+
+        let (actual, _) = Index::load_entries(file.path())?;
+        assert_eq!(index.fsmonitor, actual.fsmonitor);
+        let state = actual.fsmonitor.unwrap();
+        assert_eq!("token", state.token);
+        let valid = state.valid.iter().map(|key| &key.path).collect::<Vec<_>>();
+        assert_eq!(vec!["a.txt", "dir.txt"], valid);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lists_conflicts_by_kind() -> eyre::Result<()> {
+        init();
+
+        let (_file, mut index) = index_fixture()?;
+        let mut index = index.modify()?;
+        index.add(entry_fixture("merged.txt"));
+        for entry in conflicted_fixture("both_added.txt", &[Stage::Ours, Stage::Theirs])
+            .into_iter()
+            .chain(conflicted_fixture(
+                "deleted_by_us.txt",
+                &[Stage::Base, Stage::Theirs],
+            ))
+            .chain(conflicted_fixture(
+                "both_modified.txt",
+                &[Stage::Base, Stage::Ours, Stage::Theirs],
+            ))
+        {
+            index.add(entry);
+        }
+
+        assert!(index.has_conflicts());
+        assert!(!index.is_conflicted(&WsPath::new_unchecked("merged.txt")));
+        assert!(index
+            .entry(&WsPath::new_unchecked("both_added.txt"))
+            .is_none());
+
+        let actual = index
+            .conflicts()
+            .into_iter()
+            .map(|c| (c.path.clone(), c.kind()))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            (
+                WsPath::new_unchecked("both_added.txt"),
+                ConflictKind::BothAdded,
+            ),
+            (
+                WsPath::new_unchecked("both_modified.txt"),
+                ConflictKind::BothModified,
+            ),
+            (
+                WsPath::new_unchecked("deleted_by_us.txt"),
+                ConflictKind::DeletedByUs,
+            ),
+        ];
+        assert_eq!(expected, actual);
+
+        Ok(())
+    }
+
+    #[test]
+    fn adding_merged_entry_resolves_conflict() -> eyre::Result<()> {
+        init();
+
+        let (_file, mut index) = index_fixture()?;
+        let mut index = index.modify()?;
+        for entry in conflicted_fixture("alice.txt", &[Stage::Base, Stage::Ours, Stage::Theirs]) {
+            index.add(entry);
+        }
+
+        index.add(entry_fixture("alice.txt"));
+
+        assert!(!index.has_conflicts());
+        let actual = index.entries().map(Entry::stage).collect::<Vec<_>>();
+        assert_eq!(vec![Stage::Merged], actual);
+
+        Ok(())
+    }
+
     const SAMPLE_INDEX: &str = "\
 4449524300000002000000036084db442e8f6d7c6084db442e8f6d7c0000\
 fd0100a421bd000081a4000003e8000003e800000000e69de29bb2d1d643\
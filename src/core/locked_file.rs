@@ -28,7 +28,10 @@ pub struct LockedFile {
 impl LockedFile {
     pub fn acquire<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
         let path = path.into();
-        let lock_path = path.with_extension("lock");
+        // Like git, `config.worktree` is locked by `config.worktree.lock`
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
 
         let lock = match fs::File::with_options()
             .write(true)
@@ -68,6 +71,14 @@ impl LockedFile {
         self.protected.as_ref()
     }
 
+    /// The lock file being written, which replaces the protected file on
+    /// commit.
+    pub fn lock_file(&self) -> &fs::File {
+        self.lock
+            .as_ref()
+            .expect("Lock exists until cancelled/committed")
+    }
+
     pub fn commit(mut self) -> io::Result<()> {
         let mut lock = self.lock.take().unwrap();
         lock.flush()?;
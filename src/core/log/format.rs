@@ -0,0 +1,373 @@
+//! How `log` shows each commit, see the "Pretty formats" section of
+//! `git-log(1)`.
+
+use bstr::{BStr, ByteSlice};
+use chrono::{DateTime, FixedOffset};
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use crate::core::db::{Author, Commit};
+
+pub(super) const YELLOW: &str = "\x1b[33m";
+pub(super) const RESET: &str = "\x1b[m";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Format {
+    /// The commit, its author and date and its indented message, git's
+    /// default
+    Medium,
+    /// The abbreviated commit and the subject on one line
+    Oneline,
+    /// Placeholders like `%h` and `%s` replaced, see [`expand`]
+    Custom {
+        template: String,
+        /// Each commit's output ends in a newline, rather than there being
+        /// one between them
+        terminated: bool,
+    },
+}
+
+/// Parses what's given to `--format` or `--pretty`: the name of a format,
+/// or a template after `format:` or `tformat:`. Like git, anything else with
+/// a `%` in it, or nothing, is taken to be a template after `tformat:`.
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let custom = |template: &str, terminated| Self::Custom {
+            template: template.to_owned(),
+            terminated,
+        };
+        if let Some(template) = s.strip_prefix("format:") {
+            Ok(custom(template, false))
+        } else if let Some(template) = s.strip_prefix("tformat:") {
+            Ok(custom(template, true))
+        } else {
+            match s {
+                "medium" => Ok(Self::Medium),
+                "oneline" => Ok(Self::Oneline),
+                _ if s.is_empty() || s.contains('%') => Ok(custom(s, true)),
+                _ => Err(ParseFormatError(s.to_owned())),
+            }
+        }
+    }
+}
+
+impl Format {
+    /// Whether each commit's output ends in a newline, rather than there
+    /// being one between them.
+    pub fn terminated(&self) -> bool {
+        match self {
+            Self::Medium => false,
+            Self::Oneline => true,
+            Self::Custom { terminated, .. } => *terminated,
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Invalid pretty format: {0}
+pub struct ParseFormatError(String);
+
+/// Writes `commits` in `format`, with the commit line coloured in
+/// [`Format::Medium`] if `color` is set.
+pub fn write<'c, W: Write>(
+    out: &mut W,
+    commits: impl IntoIterator<Item = &'c Commit>,
+    format: &Format,
+    color: bool,
+) -> io::Result<()> {
+    write_with_diffs(out, commits, format, color, false, |_| {
+        Ok::<_, io::Error>(Vec::new())
+    })
+}
+
+/// Like [`write`], with what `diff` gives for each commit after it. Like
+/// git, a blank line comes between the commit and its diff unless it's on
+/// one line, or `---` and then a newline if `dashes` is set.
+pub fn write_with_diffs<'c, W, E>(
+    out: &mut W,
+    commits: impl IntoIterator<Item = &'c Commit>,
+    format: &Format,
+    color: bool,
+    dashes: bool,
+    mut diff: impl FnMut(&Commit) -> Result<Vec<u8>, E>,
+) -> Result<(), E>
+where
+    W: Write,
+    E: From<io::Error>,
+{
+    let separated = match format {
+        Format::Medium => true,
+        Format::Oneline => false,
+        Format::Custom { template, .. } => !template.is_empty(),
+    };
+    for (i, commit) in commits.into_iter().enumerate() {
+        if i > 0 && !format.terminated() {
+            writeln!(out)?;
+        }
+        match format {
+            Format::Medium => write_medium(out, commit, color)?,
+            Format::Oneline => {
+                writeln!(out, "{} {}", abbrev(commit), commit.subject())?;
+            }
+            Format::Custom {
+                template,
+                terminated,
+            } => {
+                out.write_all(&expand(template, commit))?;
+                // Like git, an empty template shows nothing at all, for
+                // showing only diffs
+                if *terminated && !template.is_empty() {
+                    writeln!(out)?;
+                }
+            }
+        }
+
+        let diff = diff(commit)?;
+        if !diff.is_empty() {
+            if separated {
+                if dashes {
+                    write!(out, "---")?;
+                }
+                writeln!(out)?;
+            }
+            out.write_all(&diff)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_medium<W: Write>(out: &mut W, commit: &Commit, color: bool) -> io::Result<()> {
+    let (start, end) = if color { (YELLOW, RESET) } else { ("", "") };
+    writeln!(out, "{}commit {}{}", start, commit.oid.to_hex(), end)?;
+    if commit.parents.len() > 1 {
+        let parents = commit
+            .parents
+            .iter()
+            .map(|parent| parent.to_hex()[..ABBREV].to_owned())
+            .collect::<Vec<_>>();
+        writeln!(out, "Merge: {}", parents.join(" "))?;
+    }
+    writeln!(out, "Author: {}", ident(&commit.author))?;
+    writeln!(out, "Date:   {}", commit.author.time().format(DEFAULT_DATE))?;
+    writeln!(out)?;
+    for line in message_lines(commit.msg.as_bstr()) {
+        writeln!(out, "    {}", line.as_bstr())?;
+    }
+    Ok(())
+}
+
+/// The lines of `msg` without the blank ones at either end.
+fn message_lines(msg: &BStr) -> Vec<&[u8]> {
+    let mut lines = msg.lines().collect::<Vec<_>>();
+    while lines.last().map_or(false, |line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let first = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(lines.len());
+    lines.split_off(first)
+}
+
+const ABBREV: usize = 7;
+pub(super) const DEFAULT_DATE: &str = "%a %b %-d %H:%M:%S %Y %z";
+const ISO_DATE: &str = "%Y-%m-%d %H:%M:%S %z";
+const STRICT_ISO_DATE: &str = "%Y-%m-%dT%H:%M:%S%:z";
+const RFC2822_DATE: &str = "%a, %-d %b %Y %H:%M:%S %z";
+
+fn abbrev(commit: &Commit) -> String {
+    commit.oid.to_hex()[..ABBREV].to_owned()
+}
+
+pub(super) fn ident(author: &Author) -> String {
+    format!("{} <{}>", author.name(), author.email())
+}
+
+/// Replaces the placeholders in `template` with what they stand for in
+/// `commit`:
+///
+/// - `%H`, `%h`: the commit, abbreviated with `%h`
+/// - `%T`, `%t`: its tree
+/// - `%P`, `%p`: its parents, separated by spaces
+/// - `%an`, `%ae`: the author's name and email, and `%cn`, `%ce` the
+///   committer's
+/// - `%ad`, `%aD`, `%ai`, `%aI`, `%at`: the author date in the default, RFC
+///   2822, ISO 8601-like, strict ISO 8601 or Unix timestamp format, and
+///   `%c` followed by the same letters for the committer date
+/// - `%s`, `%b`, `%B`: the subject, body and raw message
+/// - `%n`: a newline, `%%`: a `%` and `%x` followed by two hex digits: that
+///   byte
+/// - `%Cred`, `%Cgreen`, `%Cblue`, `%Creset`: change colour
+///
+/// Like git, anything else is left as it is.
+pub fn expand(template: &str, commit: &Commit) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut rest = template;
+    while let Some(i) = rest.find('%') {
+        out.extend_from_slice(rest[..i].as_bytes());
+        rest = &rest[i..];
+        if let Some((expanded, len)) = placeholder(&rest[1..], commit) {
+            out.extend_from_slice(&expanded);
+            rest = &rest[1 + len..];
+        } else {
+            out.push(b'%');
+            rest = &rest[1..];
+        }
+    }
+    out.extend_from_slice(rest.as_bytes());
+    out
+}
+
+/// What the placeholder at the start of `spec`, after its `%`, expands to
+/// and how long it is.
+fn placeholder(spec: &str, commit: &Commit) -> Option<(Vec<u8>, usize)> {
+    let hexes = |oids: Vec<String>, len: usize| {
+        oids.iter()
+            .map(|oid| oid[..len].to_owned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let parents = || commit.parents.iter().map(|p| p.to_hex()).collect();
+
+    for (name, color) in &[
+        ("Cred", "\x1b[31m"),
+        ("Cgreen", "\x1b[32m"),
+        ("Cblue", "\x1b[34m"),
+        ("Creset", RESET),
+    ] {
+        if spec.starts_with(name) {
+            return Some((color.as_bytes().to_vec(), name.len()));
+        }
+    }
+
+    let mut chars = spec.chars();
+    let first = chars.next()?;
+    let one = |expanded: String| Some((expanded.into_bytes(), 1));
+    match first {
+        'H' => return one(commit.oid.to_hex()),
+        'h' => return one(abbrev(commit)),
+        'T' => return one(commit.tree.to_hex()),
+        't' => return one(commit.tree.to_hex()[..ABBREV].to_owned()),
+        'P' => return one(hexes(parents(), 40)),
+        'p' => return one(hexes(parents(), ABBREV)),
+        's' => return Some((commit.subject().into(), 1)),
+        'b' => return Some((commit.body().to_vec(), 1)),
+        'B' => return Some((commit.msg.to_vec(), 1)),
+        'n' => return one("\n".to_owned()),
+        '%' => return one("%".to_owned()),
+        'x' => {
+            let hex = spec.get(1..3)?;
+            let byte = u8::from_str_radix(hex, 16).ok()?;
+            return Some((vec![byte], 3));
+        }
+        _ => {}
+    }
+
+    let who = match first {
+        'a' => &commit.author,
+        'c' => &commit.committer,
+        _ => return None,
+    };
+    let time = who.time();
+    let expanded = match chars.next()? {
+        'n' => who.name().to_str_lossy().into_owned(),
+        'e' => who.email().to_str_lossy().into_owned(),
+        'd' => format_date(time, DEFAULT_DATE),
+        'D' => format_date(time, RFC2822_DATE),
+        'i' => format_date(time, ISO_DATE),
+        'I' => format_date(time, STRICT_ISO_DATE),
+        't' => time.timestamp().to_string(),
+        _ => return None,
+    };
+    Some((expanded.into_bytes(), 2))
+}
+
+fn format_date(time: DateTime<FixedOffset>, format: &str) -> String {
+    time.format(format).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Oid;
+    use crate::test_support::init;
+    use bstr::BString;
+    use pretty_assertions::assert_eq;
+
+    fn commit(msg: &str) -> Commit {
+        let time = DateTime::parse_from_rfc3339("2021-05-06T07:08:09+02:00").unwrap();
+        let author = Author::new("Ann", "ann@example.com", time);
+        Commit {
+            oid: Oid::new([0xab; 20]),
+            parents: vec![Oid::new([0x01; 20]), Oid::new([0x02; 20])],
+            tree: Oid::new([0xcd; 20]),
+            author: author.clone(),
+            committer: author,
+            msg: BString::from(msg),
+        }
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        init();
+        let commit = commit("Subject\nwrapped\n\nBody\n");
+        let expand = |template| expand(template, &commit).to_str_lossy().into_owned();
+
+        assert_eq!("abababa cdcdcdc 0101010 0202020", expand("%h %t %p"));
+        assert_eq!("Ann <ann@example.com>", expand("%an <%ae>"));
+        assert_eq!("Subject wrapped|Body\n", expand("%s|%b"));
+        assert_eq!("2021-05-06 07:08:09 +0200", expand("%ai"));
+        assert_eq!("2021-05-06T07:08:09+02:00", expand("%cI"));
+        assert_eq!("Thu May 6 07:08:09 2021 +0200", expand("%ad"));
+        assert_eq!("1620277689", expand("%at"));
+        assert_eq!("a\nb%c\t%z %ax", expand("a%nb%%c%x09%z %ax"));
+    }
+
+    #[test]
+    fn writes_medium_format() -> eyre::Result<()> {
+        init();
+        let mut commit = commit("\nSubject\n\nBody\n\n");
+        commit.parents.truncate(1);
+        let mut out = Vec::new();
+        write(&mut out, vec![&commit, &commit], &Format::Medium, false)?;
+
+        let entry = format!(
+            "commit {}\nAuthor: Ann <ann@example.com>\nDate:   Thu May 6 07:08:09 2021 +0200\n\n    Subject\n    \n    Body\n",
+            "ab".repeat(20)
+        );
+        assert_eq!(format!("{}\n{}", entry, entry), out.to_str_lossy());
+        Ok(())
+    }
+
+    #[test]
+    fn parses_formats() {
+        init();
+        assert_eq!(Ok(Format::Oneline), "oneline".parse().map_err(drop));
+        assert_eq!(
+            Ok(Format::Custom {
+                template: "%h".to_owned(),
+                terminated: false
+            }),
+            "format:%h".parse().map_err(drop)
+        );
+        assert_eq!(
+            Ok(Format::Custom {
+                template: "%h %s".to_owned(),
+                terminated: true
+            }),
+            "%h %s".parse().map_err(drop)
+        );
+        assert_eq!(
+            Ok(Format::Custom {
+                template: String::new(),
+                terminated: true
+            }),
+            "".parse().map_err(drop)
+        );
+        assert!("fuller".parse::<Format>().is_err());
+    }
+}
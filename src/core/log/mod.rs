@@ -0,0 +1,169 @@
+//! Showing history, like `git log`.
+
+pub mod format;
+pub mod show;
+
+pub use format::Format;
+
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use regex::bytes::Regex;
+
+use crate::core::{db::Commit, rev_walk::Order, Pathspec, Revision};
+
+/// Which commits `log` shows, and in what order. By default, all of those
+/// reachable from `HEAD`, newest first.
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// The commits to show with their ancestors or to hide, `HEAD` if there
+    /// are none
+    pub revisions: Vec<Revision>,
+    pub order: Order,
+    /// Show the commits picked in the opposite order
+    pub reverse: bool,
+    /// Only follow the first parent of merges
+    pub first_parent: bool,
+    /// Stop after this many commits have been shown
+    pub max_count: Option<usize>,
+    /// Only commits whose author, as `name <email>`, matches
+    pub author: Option<Regex>,
+    /// Only commits whose message matches
+    pub grep: Option<Regex>,
+    /// Only commits committed at or after this
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Only commits committed at or before this
+    pub until: Option<DateTime<FixedOffset>>,
+    /// Only commits changing a path matching this. Like git, a merge is only
+    /// shown if it differs from each of its parents.
+    pub pathspec: Pathspec,
+}
+
+impl LogOptions {
+    /// Whether `commit` passes the filters other than [`Self::pathspec`],
+    /// which needs its parents' trees.
+    pub fn matches(&self, commit: &Commit) -> bool {
+        let time = commit.committer.time();
+        if self.since.map_or(false, |since| time < since)
+            || self.until.map_or(false, |until| time > until)
+        {
+            return false;
+        }
+        if let Some(author) = &self.author {
+            let ident = format!("{} <{}>", commit.author.name(), commit.author.email());
+            if !author.is_match(ident.as_bytes()) {
+                return false;
+            }
+        }
+        self.grep
+            .as_ref()
+            .map_or(true, |grep| grep.is_match(&commit.msg))
+    }
+}
+
+/// Parses a date given to `--since` or `--until`, as `git log` does for
+/// the common cases: an ISO 8601 or RFC 2822 date, a Unix timestamp after
+/// `@`, or a relative date like `2 weeks ago` or `yesterday`. A date without
+/// a time is the start of that day, in the local timezone unless one is
+/// given.
+pub fn parse_date(
+    date: &str,
+    now: DateTime<Local>,
+) -> Result<DateTime<FixedOffset>, ParseDateError> {
+    let err = || ParseDateError(date.to_owned());
+    let trimmed = date.trim();
+    let local = |naive: NaiveDateTime| {
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|time| time.with_timezone(time.offset()))
+    };
+
+    if let Some(secs) = trimmed.strip_prefix('@') {
+        let secs = secs.parse::<i64>().map_err(|_| err())?;
+        let time = chrono::Utc
+            .timestamp_opt(secs, 0)
+            .single()
+            .ok_or_else(err)?;
+        return Ok(time.with_timezone(&FixedOffset::east_opt(0).expect("UTC is valid")));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(trimmed) {
+        return Ok(time);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc2822(trimmed) {
+        return Ok(time);
+    }
+    if let Ok(time) = DateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M:%S %z") {
+        return Ok(time);
+    }
+    for format in &["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(trimmed, format) {
+            return local(naive).ok_or_else(err);
+        }
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(trimmed, "%Y-%m-%d") {
+        return local(day.and_hms_opt(0, 0, 0).expect("Midnight is valid")).ok_or_else(err);
+    }
+
+    let now = now.with_timezone(now.offset());
+    match trimmed {
+        "now" => return Ok(now),
+        "yesterday" => return Ok(now - Duration::days(1)),
+        _ => {}
+    }
+    let mut words = trimmed.split_whitespace().collect::<Vec<_>>();
+    if words.last() == Some(&"ago") {
+        words.pop();
+    }
+    let (count, unit) = match words.as_slice() {
+        [count, unit] => (count, unit),
+        _ => return Err(err()),
+    };
+    let count = count.parse::<i64>().map_err(|_| err())?;
+    let duration = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => Duration::seconds(count),
+        "minute" => Duration::minutes(count),
+        "hour" => Duration::hours(count),
+        "day" => Duration::days(count),
+        "week" => Duration::weeks(count),
+        // Like git's approximation
+        "month" => Duration::days(count * 30),
+        "year" => Duration::days(count * 365),
+        _ => return Err(err()),
+    };
+    Ok(now - duration)
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Invalid date {0:?}
+pub struct ParseDateError(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_dates() -> eyre::Result<()> {
+        init();
+        let now = Local.timestamp_opt(1_600_000_000, 0).unwrap();
+        let parse = |date| parse_date(date, now).map(|time| time.timestamp());
+
+        assert_eq!(1_500_000_000, parse("@1500000000")?);
+        assert_eq!(1_500_000_000, parse("2017-07-14T02:40:00Z")?);
+        assert_eq!(1_500_000_000, parse("2017-07-14 04:40:00 +0200")?);
+        assert_eq!(1_500_000_000, parse("Fri, 14 Jul 2017 02:40:00 +0000")?);
+        assert_eq!(1_600_000_000 - 2 * 7 * 24 * 3600, parse("2 weeks ago")?);
+        assert_eq!(1_600_000_000 - 3600, parse("1 hour ago")?);
+        assert_eq!(1_600_000_000 - 24 * 3600, parse("yesterday")?);
+        let midnight = NaiveDate::from_ymd_opt(2017, 7, 14)
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .unwrap();
+        let day = Local.from_local_datetime(&midnight).unwrap().timestamp();
+        assert_eq!(day, parse("2017-07-14")?);
+        assert_eq!(
+            "Invalid date \"last tuesday\"",
+            parse("last tuesday").unwrap_err().to_string()
+        );
+        Ok(())
+    }
+}
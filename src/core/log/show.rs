@@ -0,0 +1,125 @@
+//! Showing objects of any type, like `git show`.
+
+use bstr::{BString, ByteSlice};
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    iter,
+};
+
+use super::format::{self, Format, DEFAULT_DATE, RESET, YELLOW};
+use crate::core::{
+    db::{tree::Node, AnyObject, Commit, LoadAnyError, Tag, Tree, UntypedOid},
+    Db,
+};
+
+/// The object `oid` and, if it's a tag, the object it tags, and so on, which
+/// is what `git show` shows for it.
+pub fn objects(db: &mut Db, oid: UntypedOid) -> Result<Vec<AnyObject>, LoadAnyError> {
+    let mut objects = vec![db.load_any(oid)?];
+    while let Some(AnyObject::Tag(tag)) = objects.last() {
+        let object = tag.object;
+        objects.push(db.load_any(object)?);
+    }
+    Ok(objects)
+}
+
+/// Writes each of `objects` with the name it was given as: commits in
+/// `format` followed by what `diff` gives, like [`format::write_with_diffs`],
+/// trees as the names of their entries, blobs as they are and tags with who
+/// made them and their messages. Like git, each commit is only shown once.
+pub fn write<'o, W, E>(
+    out: &mut W,
+    objects: impl IntoIterator<Item = (&'o str, &'o AnyObject)>,
+    format: &Format,
+    color: bool,
+    dashes: bool,
+    mut diff: impl FnMut(&Commit) -> Result<Vec<u8>, E>,
+) -> Result<(), E>
+where
+    W: Write,
+    E: From<io::Error>,
+{
+    // Like git, blobs aren't separated from what comes after them
+    let mut shown = false;
+    let mut shown_commits = HashSet::new();
+    for (name, object) in objects {
+        match object {
+            AnyObject::Blob(blob) => out.write_all(&blob.bytes)?,
+            AnyObject::Tree(tree) => {
+                if shown {
+                    writeln!(out)?;
+                }
+                write_tree(out, name, tree, color)?;
+                shown = true;
+            }
+            AnyObject::Tag(tag) => {
+                if shown {
+                    writeln!(out)?;
+                }
+                write_tag(out, tag, format, color)?;
+                shown = true;
+            }
+            AnyObject::Commit(commit) => {
+                if !shown_commits.insert(commit.oid) {
+                    continue;
+                }
+                if shown && !format.terminated() {
+                    writeln!(out)?;
+                }
+                format::write_with_diffs(
+                    out,
+                    iter::once(commit),
+                    format,
+                    color,
+                    dashes,
+                    &mut diff,
+                )?;
+                shown = true;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the names of the entries of `tree`, directories ending in `/`.
+fn write_tree<W: Write>(out: &mut W, name: &str, tree: &Tree, color: bool) -> io::Result<()> {
+    let (start, end) = if color { (YELLOW, RESET) } else { ("", "") };
+    writeln!(out, "{}tree {}{}\n", start, name, end)?;
+
+    // Git sorts directories as though their names ended in `/`
+    let mut entries = tree
+        .direct_children()
+        .map(|node| {
+            let mut entry = BString::from(node.name().as_bytes());
+            if let Node::Tree { .. } = node {
+                entry.push(b'/');
+            }
+            entry
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    for entry in entries {
+        writeln!(out, "{}", entry)?;
+    }
+    Ok(())
+}
+
+/// Writes the tag's name, who made it unless `format` is
+/// [`Format::Oneline`], and its message.
+fn write_tag<W: Write>(out: &mut W, tag: &Tag, format: &Format, color: bool) -> io::Result<()> {
+    let (start, end) = if color { (YELLOW, RESET) } else { ("", "") };
+    writeln!(out, "{}tag {}{}", start, tag.name, end)?;
+    if let Some(tagger) = &tag.tagger {
+        match format {
+            Format::Medium => {
+                writeln!(out, "Tagger: {}", format::ident(tagger))?;
+                writeln!(out, "Date:   {}", tagger.time().format(DEFAULT_DATE))?;
+            }
+            Format::Oneline => {}
+            Format::Custom { .. } => writeln!(out, "Tagger: {}", format::ident(tagger))?,
+        }
+    }
+    writeln!(out)?;
+    out.write_all(&tag.msg)
+}
@@ -1,20 +1,35 @@
+pub mod config;
 pub mod db;
+pub mod diff;
+pub mod discover;
+pub mod fsmonitor;
 pub mod index;
 pub mod locked_file;
+pub mod log;
+pub mod pathspec;
 pub mod refs;
 pub mod repo;
+pub mod rev_walk;
+pub mod revision;
 pub mod stat;
 pub mod status;
+pub mod submodule;
 pub mod with_digest;
+pub mod worktree;
 pub mod ws;
 
+pub use config::Config;
 pub use db::{Db, Object, ObjectBuilder, Oid};
 pub use index::{Index, IndexMut};
 pub use locked_file::LockedFile;
+pub use pathspec::Pathspec;
 pub use refs::Refs;
 pub use repo::Repo;
+pub use rev_walk::RevWalk;
+pub use revision::Revision;
 pub use stat::Stat;
-pub use status::{FileStatus, Status};
+pub use status::{FileStatus, Renames, Status, StatusOptions, UntrackedFiles};
 pub use with_digest::WithDigest;
+pub use worktree::Worktree;
 pub use ws::Workspace;
 pub use ws::WsPath;
@@ -0,0 +1,493 @@
+//! Pathspecs select the paths a command acts on. See the "pathspec" entry in
+//! `gitglossary(7)`.
+
+use bstr::{BStr, BString, ByteSlice};
+use std::{os::unix::prelude::OsStrExt, path::Path};
+
+use crate::core::{
+    ws::{
+        ignore::{fnmatch, wildmatch},
+        path::NewCanonicalizeError,
+    },
+    Workspace, WsPath,
+};
+
+/// A path matches if any item matches it and no item with `exclude` magic
+/// does. Without items that aren't excludes, every path is included.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Pathspec {
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Item {
+    /// The pathspec as written, for messages
+    pub original: String,
+    /// Relative to the workspace, with magic removed
+    pattern: BString,
+    pub magic: Magic,
+    /// The length of the leading part of `pattern` without wildcards
+    nowildcard_len: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Magic {
+    /// Relative to the workspace rather than the current directory
+    pub top: bool,
+    /// Wildcards are ordinary characters
+    pub literal: bool,
+    /// Wildcards don't match `/`, except `**`, as in `.gitignore`
+    pub glob: bool,
+    pub icase: bool,
+    pub exclude: bool,
+}
+
+/// How an item matched a path.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Match {
+    /// The item names the path itself
+    Exact,
+    /// The item names a directory containing the path
+    Leading,
+    /// The item's wildcards match the path
+    Wildcard,
+}
+
+/// Something a pathspec can be made from. Plain paths are relative to the
+/// workspace.
+pub trait IntoPathspec {
+    fn into_pathspec(self, workspace: &Workspace) -> Result<Pathspec, ParseError>;
+}
+
+impl IntoPathspec for Pathspec {
+    fn into_pathspec(self, _workspace: &Workspace) -> Result<Pathspec, ParseError> {
+        Ok(self)
+    }
+}
+
+impl<I, P> IntoPathspec for I
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    fn into_pathspec(self, workspace: &Workspace) -> Result<Pathspec, ParseError> {
+        Pathspec::parse(self, &WsPath::root(), workspace)
+    }
+}
+
+impl Pathspec {
+    /// Matches every path.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Items without `top` magic are relative to `prefix`, the directory the
+    /// command was run from.
+    pub fn parse<I, P>(specs: I, prefix: &WsPath, workspace: &Workspace) -> Result<Self, ParseError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let items = specs
+            .into_iter()
+            .map(|spec| Item::parse(spec.as_ref(), prefix, workspace))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { items })
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    fn includes(&self) -> impl Iterator<Item = (usize, &Item)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.magic.exclude)
+    }
+
+    fn is_excluded(&self, path: &BStr) -> bool {
+        self.items
+            .iter()
+            .any(|item| item.magic.exclude && item.matches(path).is_some())
+    }
+
+    pub fn matches(&self, path: &WsPath) -> bool {
+        let path = path.as_bstr();
+        if self.is_excluded(path) {
+            return false;
+        }
+
+        let mut includes = self.includes().peekable();
+        includes.peek().is_none() || includes.any(|(_, item)| item.matches(path).is_some())
+    }
+
+    /// The indices of the items that match `path` and how they matched.
+    /// Empty if an exclude matches it.
+    pub fn matching_items(&self, path: &WsPath) -> Vec<(usize, Match)> {
+        let path = path.as_bstr();
+        if self.is_excluded(path) {
+            return Vec::new();
+        }
+
+        self.includes()
+            .filter_map(|(i, item)| item.matches(path).map(|m| (i, m)))
+            .collect()
+    }
+
+    /// The first item, other than excludes, that matches none of `paths`.
+    pub fn first_unmatched<'p>(
+        &self,
+        paths: impl IntoIterator<Item = &'p WsPath>,
+    ) -> Option<&Item> {
+        let mut seen = vec![false; self.items.len()];
+        for path in paths {
+            for (i, _) in self.matching_items(path) {
+                seen[i] = true;
+            }
+        }
+
+        self.includes()
+            .find(|(i, _)| !seen[*i])
+            .map(|(_, item)| item)
+    }
+
+    /// The directories and files that contain every path that can match, so
+    /// the rest of the workspace needn't be searched. None contains another.
+    pub fn walk_roots(&self) -> Vec<WsPath> {
+        let mut roots = self
+            .includes()
+            .map(|(_, item)| item.walk_root())
+            .collect::<Vec<_>>();
+        if roots.is_empty() {
+            return vec![WsPath::root()];
+        }
+
+        roots.sort();
+        roots.dedup();
+        let mut outermost: Vec<WsPath> = Vec::new();
+        for root in roots {
+            if !outermost
+                .iter()
+                .any(|outer| root.as_path().starts_with(outer))
+            {
+                outermost.push(root);
+            }
+        }
+        outermost
+    }
+}
+
+impl Item {
+    fn parse(spec: &Path, prefix: &WsPath, workspace: &Workspace) -> Result<Self, ParseError> {
+        let original = spec.to_string_lossy().into_owned();
+        let bytes = spec.as_os_str().as_bytes();
+        let (magic, rest) = Self::parse_magic(bytes, &original)?;
+
+        if magic.literal && magic.glob {
+            return Err(ParseError::IncompatibleMagic(original));
+        }
+
+        let base = if magic.top { &WsPath::root() } else { prefix };
+        let path = WsPath::new_normalized(base.join(rest.to_path_lossy()), workspace)
+            .map_err(|e| ParseError::OutsideWorkspace(original.clone(), e))?;
+
+        let mut pattern = path.to_bstring();
+        if magic.icase {
+            pattern.make_ascii_lowercase();
+        }
+        let nowildcard_len = if magic.literal {
+            pattern.len()
+        } else {
+            pattern
+                .find_byteset(b"*?[\\")
+                .unwrap_or_else(|| pattern.len())
+        };
+
+        Ok(Self {
+            original,
+            pattern,
+            magic,
+            nowildcard_len,
+        })
+    }
+
+    /// Splits off the long form `:(top,icase)` or the short form `:/!` of
+    /// magic.
+    fn parse_magic<'s>(spec: &'s [u8], original: &str) -> Result<(Magic, &'s [u8]), ParseError> {
+        let mut magic = Magic::default();
+        let rest = match spec.strip_prefix(b":") {
+            None => return Ok((magic, spec)),
+            Some(rest) => rest,
+        };
+
+        if let Some(long) = rest.strip_prefix(b"(") {
+            let end = long
+                .find_byte(b')')
+                .ok_or_else(|| ParseError::Unterminated(original.to_owned()))?;
+            for word in long[..end].split_str(",") {
+                match word {
+                    b"top" => magic.top = true,
+                    b"literal" => magic.literal = true,
+                    b"glob" => magic.glob = true,
+                    b"icase" => magic.icase = true,
+                    b"exclude" => magic.exclude = true,
+                    b"" => {}
+                    _ => {
+                        return Err(ParseError::UnknownMagic(
+                            original.to_owned(),
+                            word.to_str_lossy().into_owned(),
+                        ))
+                    }
+                }
+            }
+            return Ok((magic, &long[end + 1..]));
+        }
+
+        let mut rest = rest;
+        loop {
+            match rest.first() {
+                Some(b'/') => magic.top = true,
+                Some(b'!' | b'^') => magic.exclude = true,
+                Some(b':') => {
+                    rest = &rest[1..];
+                    break;
+                }
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+        Ok((magic, rest))
+    }
+
+    /// `path` is relative to the workspace.
+    pub fn matches(&self, path: &BStr) -> Option<Match> {
+        let folded;
+        let path = if self.magic.icase {
+            folded = path.to_ascii_lowercase();
+            folded.as_slice()
+        } else {
+            path.as_bytes()
+        };
+        let pattern = self.pattern.as_slice();
+
+        // A file can be named literally even if its name has wildcards
+        if path == pattern {
+            return Some(Match::Exact);
+        }
+
+        let literal = &pattern[..self.nowildcard_len];
+        if !path.starts_with(literal) {
+            return None;
+        }
+
+        if self.nowildcard_len == pattern.len() {
+            let is_leading = literal.is_empty() || path.get(literal.len()) == Some(&b'/');
+            return if is_leading {
+                Some(Match::Leading)
+            } else {
+                None
+            };
+        }
+
+        let matched = if self.magic.glob {
+            wildmatch(pattern, path)
+        } else {
+            fnmatch(pattern, path)
+        };
+        if matched {
+            Some(Match::Wildcard)
+        } else {
+            None
+        }
+    }
+
+    /// The path the item names, if it has no wildcards.
+    pub fn literal_path(&self) -> Option<WsPath> {
+        if self.nowildcard_len == self.pattern.len() && !self.magic.icase {
+            Some(WsPath::new_unchecked_bytes(self.pattern.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// The directory containing the item's matches, or the file it names.
+    fn walk_root(&self) -> WsPath {
+        if self.magic.icase {
+            return WsPath::root();
+        }
+
+        let literal = &self.pattern[..self.nowildcard_len];
+        if self.nowildcard_len == self.pattern.len() {
+            return WsPath::new_unchecked_bytes(literal);
+        }
+        match literal.rfind_byte(b'/') {
+            Some(slash) => WsPath::new_unchecked_bytes(&literal[..slash]),
+            None => WsPath::root(),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum ParseError {
+    /// Invalid pathspec magic {1:?} in {0:?}
+    UnknownMagic(String, String),
+    /// Missing ')' at the end of pathspec magic in {0:?}
+    Unterminated(String),
+    /// {0:?}: 'literal' and 'glob' are incompatible
+    IncompatibleMagic(String),
+    /// {0:?} is outside the repository
+    OutsideWorkspace(String, #[source] NewCanonicalizeError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    fn parse(specs: &[&str], prefix: &str) -> Pathspec {
+        let workspace = Workspace::new("/ws");
+        Pathspec::parse(specs, &WsPath::new_unchecked(prefix), &workspace).unwrap()
+    }
+
+    fn matching<'p>(pathspec: &Pathspec, paths: &[&'p str]) -> Vec<&'p str> {
+        paths
+            .iter()
+            .copied()
+            .filter(|path| pathspec.matches(&WsPath::new_unchecked(path)))
+            .collect()
+    }
+
+    const PATHS: &[&str] = &[
+        "Cargo.toml",
+        "README.md",
+        "src/lib.rs",
+        "src/main.rs",
+        "src/ui/mod.rs",
+        "tests/core.rs",
+    ];
+
+    #[test]
+    fn matches_files_directories_and_wildcards() {
+        init();
+
+        assert_eq!(PATHS.to_vec(), matching(&parse(&["."], ""), PATHS));
+        assert_eq!(
+            vec!["src/lib.rs", "src/main.rs", "src/ui/mod.rs"],
+            matching(&parse(&["src/"], ""), PATHS)
+        );
+        assert_eq!(
+            vec!["README.md"],
+            matching(&parse(&["README.md"], ""), PATHS)
+        );
+        // Without glob magic, "*" matches "/" too
+        assert_eq!(
+            vec![
+                "src/lib.rs",
+                "src/main.rs",
+                "src/ui/mod.rs",
+                "tests/core.rs"
+            ],
+            matching(&parse(&["*.rs"], ""), PATHS)
+        );
+        assert_eq!(
+            vec!["src/lib.rs", "src/main.rs"],
+            matching(&parse(&[":(glob)src/*.rs"], ""), PATHS)
+        );
+        assert_eq!(
+            vec!["src/ui/mod.rs"],
+            matching(&parse(&[":(glob)**/mod.rs"], ""), PATHS)
+        );
+        assert_eq!(Vec::<&str>::new(), matching(&parse(&["src/m"], ""), PATHS));
+    }
+
+    #[test]
+    fn applies_magic() {
+        init();
+
+        assert_eq!(
+            vec!["Cargo.toml", "README.md", "tests/core.rs"],
+            matching(&parse(&[":!src"], ""), PATHS)
+        );
+        assert_eq!(
+            vec!["src/lib.rs", "src/ui/mod.rs"],
+            matching(&parse(&["src", ":(exclude)*main*"], ""), PATHS)
+        );
+        assert_eq!(
+            vec!["README.md"],
+            matching(&parse(&[":(icase)readme.MD"], ""), PATHS)
+        );
+        assert_eq!(
+            Vec::<&str>::new(),
+            matching(&parse(&[":(literal)*.rs"], ""), PATHS)
+        );
+        assert_eq!(
+            vec!["Cargo.toml"],
+            matching(&parse(&[":/Cargo.toml"], "src"), PATHS)
+        );
+        assert_eq!(
+            vec!["Cargo.toml", "src/lib.rs"],
+            matching(&parse(&["lib.rs", "../Cargo.toml"], "src"), PATHS)
+        );
+    }
+
+    #[test]
+    fn reports_how_items_matched() {
+        init();
+
+        let pathspec = parse(&["src", "*.toml", "nothing"], "");
+        let path = |path| WsPath::new_unchecked(path);
+        assert_eq!(
+            vec![(0, Match::Leading)],
+            pathspec.matching_items(&path("src/lib.rs"))
+        );
+        assert_eq!(
+            vec![(1, Match::Wildcard)],
+            pathspec.matching_items(&path("Cargo.toml"))
+        );
+        assert_eq!(
+            Some("nothing"),
+            pathspec
+                .first_unmatched(&[path("src/lib.rs"), path("Cargo.toml")])
+                .map(|item| item.original.as_str())
+        );
+    }
+
+    #[test]
+    fn walks_only_what_can_match() {
+        init();
+
+        let pathspec = parse(&["src/ui", "src/*.rs", "tests/core.rs", ":!src/ui"], "");
+        assert_eq!(
+            vec![
+                WsPath::new_unchecked("src"),
+                WsPath::new_unchecked("tests/core.rs")
+            ],
+            pathspec.walk_roots()
+        );
+        assert_eq!(vec![WsPath::root()], parse(&["*.rs"], "").walk_roots());
+    }
+
+    #[test]
+    fn rejects_invalid_magic() {
+        init();
+
+        let workspace = Workspace::new("/ws");
+        let parse = |spec| Pathspec::parse([spec], &WsPath::root(), &workspace);
+        assert!(matches!(
+            parse(":(nope)a"),
+            Err(ParseError::UnknownMagic(..))
+        ));
+        assert!(matches!(parse(":(top"), Err(ParseError::Unterminated(_))));
+        assert!(matches!(
+            parse(":(literal,glob)a"),
+            Err(ParseError::IncompatibleMagic(_))
+        ));
+        assert!(matches!(
+            parse("../outside"),
+            Err(ParseError::OutsideWorkspace(..))
+        ));
+    }
+}
@@ -12,16 +12,35 @@ use std::{
     path::PathBuf,
 };
 
+/// Refs are stored as files under the git directory. In a linked worktree
+/// `HEAD` is its own, while the other refs are shared with the rest of the
+/// repository from the common directory, see [`discover::common_dir`].
+///
+/// [`discover::common_dir`]: crate::core::discover::common_dir
 #[derive(Debug, Clone)]
 pub struct Refs {
     path: PathBuf,
+    common_path: PathBuf,
 }
 
 impl Refs {
     const HEAD: &'static [u8] = b"HEAD";
 
     pub fn new<P: Into<PathBuf>>(path: P) -> Self {
-        Self { path: path.into() }
+        let path = path.into();
+        Self {
+            common_path: path.clone(),
+            path,
+        }
+    }
+
+    /// Refs for the worktree with git directory `path`, sharing the refs in
+    /// `common_path`.
+    pub fn for_worktree<P: Into<PathBuf>, C: Into<PathBuf>>(path: P, common_path: C) -> Self {
+        Self {
+            path: path.into(),
+            common_path: common_path.into(),
+        }
     }
 
     pub fn update_ref(&self, ref_name: &BStr, oid: &Oid<Commit>) -> Result<(), UpdateError> {
@@ -60,7 +79,20 @@ impl Refs {
     }
 
     fn ref_path(&self, ref_name: &BStr) -> PathBuf {
-        self.path.join(OsStr::from_bytes(ref_name.as_bytes()))
+        let dir = if Self::is_per_worktree(ref_name) {
+            &self.path
+        } else {
+            &self.common_path
+        };
+        dir.join(OsStr::from_bytes(ref_name.as_bytes()))
+    }
+
+    /// Like git, refs outside `refs/` such as `HEAD`, and those under
+    /// `refs/worktree/` and `refs/bisect/`, belong to a single worktree.
+    fn is_per_worktree(ref_name: &BStr) -> bool {
+        !ref_name.starts_with(b"refs/")
+            || ref_name.starts_with(b"refs/worktree/")
+            || ref_name.starts_with(b"refs/bisect/")
     }
 }
 
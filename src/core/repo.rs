@@ -1,56 +1,107 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     env, fmt, fs,
     io::{self},
     path::{Path, PathBuf},
 };
 
 use crate::core::{
-    db::{self, object, tree, Blob, Commit, Tree},
+    config,
+    db::{self, object, tree, Blob, Commit, Tree, UntypedOid},
+    diff::{FileDiff, Side},
+    discover, fsmonitor,
     index::{
         self,
-        entry::{self, Entry, StatusChatty},
+        entry::{self, Entry, Stage, StatusChatty},
     },
+    log::LogOptions,
+    pathspec::{self, IntoPathspec, Match},
     refs,
-    ws::{self, ListFilesError, ReadFileError, StatFileError},
-    Db, FileStatus, Index, IndexMut, ObjectBuilder, Refs, Status, Workspace, WsPath,
+    revision::{self, ResolveError, Revision},
+    stat::Mode,
+    status::{renames, Version, Versions},
+    submodule::{self, Submodule, SubmoduleStatus},
+    worktree,
+    ws::{
+        self, attributes, ignore, sparse, Attributes, Excludes, Filters, Found, ListFilesError,
+        Pattern, ReadFileError, Sparse, StatFileError,
+    },
+    Config, Db, FileStatus, Index, IndexMut, ObjectBuilder, Oid, Pathspec, Refs, Renames, RevWalk,
+    Stat, Status, StatusOptions, UntrackedFiles, Workspace, WsPath,
 };
+use bstr::{BString, ByteSlice};
 use chrono::Local;
-use tracing::{debug, instrument};
+use rayon::prelude::*;
+use tracing::{debug, instrument, warn};
 
 #[derive(Debug, Clone)]
 pub struct Repo {
     git_dir: PathBuf,
-    pub workspace: Workspace,
+    common_dir: PathBuf,
+    pub config: Config,
+    /// `None` in a bare repository
+    workspace: Option<Workspace>,
     pub db: Db,
     pub refs: Refs,
-    pub index: Index,
+    /// `None` in a bare repository
+    index: Option<Index>,
 }
 
 impl Repo {
+    /// Opens the repository with its workspace at `dir`, or the bare
+    /// repository at `dir`.
     #[instrument(err)]
-    pub fn new(workspace: impl Into<PathBuf> + fmt::Debug) -> Result<Self, ReadError> {
-        let workspace_dir = workspace.into();
-        let workspace_dir = workspace_dir
-            .canonicalize()
-            .map_err(|e| ReadError::Io(workspace_dir, e))?;
+    pub fn new(dir: impl Into<PathBuf> + fmt::Debug) -> Result<Self, ReadError> {
+        let dir = dir.into();
+        let dir = dir.canonicalize().map_err(|e| ReadError::Io(dir, e))?;
 
-        let git_dir = workspace_dir.join(".git");
-
-        if !git_dir
-            .try_exists()
-            .map_err(|e| ReadError::Io(git_dir.clone(), e))?
-        {
-            return Err(ReadError::NotRepo(workspace_dir));
+        match discover::git_dir_in(&dir)? {
+            Some(git_dir) => Self::open(git_dir, Some(dir)),
+            None if discover::is_git_dir(&dir) => Self::open(dir, None),
+            None => Err(ReadError::NotRepo(dir)),
         }
+    }
+
+    /// Finds the repository containing `dir`, see [`discover::discover`].
+    #[instrument(err)]
+    pub fn discover(dir: impl AsRef<Path> + fmt::Debug) -> Result<Self, ReadError> {
+        let location = discover::discover(dir.as_ref(), &discover::Env::from_process())?;
+        Self::open(location.git_dir, location.workspace)
+    }
+
+    /// Everything but `HEAD` and the index is shared between worktrees, so
+    /// comes from the common directory.
+    ///
+    /// The repository is bare without a workspace, or if `core.bare` is set.
+    /// Like git, that setting only applies to the main worktree.
+    fn open(git_dir: PathBuf, workspace_dir: Option<PathBuf>) -> Result<Self, ReadError> {
+        let common_dir = discover::common_dir(&git_dir)?;
+        let config = Config::load(&common_dir, &git_dir)?;
+        let bare = git_dir == common_dir && config.get_bool("core.bare")?.unwrap_or(false);
 
-        let workspace = Workspace::new(workspace_dir);
-        let db = Db::new(&git_dir);
-        let refs = Refs::new(&git_dir);
-        let index = Index::load(&git_dir)?;
+        let (workspace, index) = match workspace_dir.filter(|_| !bare) {
+            Some(workspace_dir) => {
+                let excludes = Excludes::load(&common_dir, &config)?;
+                let attributes = Attributes::load(&common_dir, &config)?;
+                let filters = Filters::load(&config)?;
+                // Each worktree has its own sparse checkout
+                let sparse = Sparse::load(&git_dir, &config)?;
+                let workspace = Workspace::new(workspace_dir)
+                    .with_excludes(excludes)
+                    .with_attributes(attributes)
+                    .with_filters(filters)
+                    .with_sparse(sparse);
+                (Some(workspace), Some(Index::load(&git_dir)?))
+            }
+            None => (None, None),
+        };
+        let db = Db::new(&common_dir);
+        let refs = Refs::for_worktree(&git_dir, &common_dir);
 
         Ok(Self {
             git_dir,
+            common_dir,
+            config,
             workspace,
             db,
             refs,
@@ -58,74 +109,484 @@ impl Repo {
         })
     }
 
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    /// The git directory of the main worktree, see [`discover::common_dir`].
+    pub fn common_dir(&self) -> &Path {
+        &self.common_dir
+    }
+
+    /// A bare repository has no workspace or index, just what's in its git
+    /// directory.
+    pub fn is_bare(&self) -> bool {
+        self.workspace.is_none()
+    }
+
+    pub fn workspace(&self) -> Result<&Workspace, BareError> {
+        self.workspace.as_ref().ok_or(BareError)
+    }
+
+    pub fn index(&self) -> Result<&Index, BareError> {
+        self.index.as_ref().ok_or(BareError)
+    }
+
+    pub fn index_mut(&mut self) -> Result<&mut Index, BareError> {
+        self.index.as_mut().ok_or(BareError)
+    }
+
     pub fn for_current_dir() -> Result<Self, ForCurrentDirError> {
         let dir = env::current_dir()?;
-        Ok(Self::new(dir)?)
+        Ok(Self::discover(dir)?)
     }
 
-    #[instrument(err)]
     pub fn init(workspace: impl Into<PathBuf> + fmt::Debug) -> Result<Self, InitError> {
-        let workspace_dir = workspace.into();
+        Self::init_with_options(workspace, InitOptions::default())
+    }
 
-        fs::create_dir_all(&workspace_dir)
-            .map_err(|e| InitError::CreateWorkspace(workspace_dir.clone(), e))?;
+    /// Creates a repository with its workspace at `dir`, or with
+    /// [`InitOptions::bare`] a bare repository at `dir`.
+    #[instrument(err)]
+    pub fn init_with_options(
+        dir: impl Into<PathBuf> + fmt::Debug,
+        opts: InitOptions,
+    ) -> Result<Self, InitError> {
+        let dir = dir.into();
 
-        let git_dir = workspace_dir.join(".git");
-        if git_dir
-            .try_exists()
-            .map_err(|e| InitError::Open(git_dir.clone(), e))?
-        {
-            return Err(InitError::Exists(git_dir));
-        }
+        fs::create_dir_all(&dir).map_err(|e| InitError::CreateWorkspace(dir.clone(), e))?;
+
+        let (git_dir, workspace_dir) = if opts.bare {
+            if discover::is_git_dir(&dir) {
+                return Err(InitError::Exists(dir));
+            }
+            (dir, None)
+        } else {
+            let git_dir = dir.join(".git");
+            if git_dir
+                .try_exists()
+                .map_err(|e| InitError::Open(git_dir.clone(), e))?
+            {
+                return Err(InitError::Exists(git_dir));
+            }
+            (git_dir, Some(dir))
+        };
 
         for child in &["objects", "refs"] {
             let child = git_dir.join(child);
             fs::create_dir_all(&child).map_err(|e| InitError::Write(child, e))?;
         }
+        if opts.bare {
+            let config = git_dir.join("config");
+            fs::write(&config, "[core]\n\tbare = true\n")
+                .map_err(|e| InitError::Write(config, e))?;
+        }
 
-        let workspace = Workspace::new(workspace_dir);
-        let db = Db::new(&git_dir);
-        let refs = Refs::new(&git_dir);
-        let index = Index::load(&git_dir)?;
+        Ok(Self::open(git_dir, workspace_dir)?)
+    }
 
-        Ok(Self {
-            git_dir,
-            workspace,
-            db,
-            refs,
-            index,
-        })
+    pub fn add(
+        &mut self,
+        pathspec: impl IntoPathspec + fmt::Debug,
+    ) -> Result<Vec<WsPath>, AddError> {
+        self.add_with_options(pathspec, AddOptions::default())
     }
 
+    /// Stages the files matching `pathspec`. Tracked files that match but
+    /// have been deleted are removed from the index. Returns the paths of the
+    /// entries added or removed.
     #[instrument(err)]
-    pub fn add<I, P>(&mut self, files: I) -> Result<Vec<WsPath>, AddError>
-    where
-        I: IntoIterator<Item = P> + fmt::Debug,
-        P: AsRef<Path>,
-    {
-        let workspace = &self.workspace;
+    pub fn add_with_options(
+        &mut self,
+        pathspec: impl IntoPathspec + fmt::Debug,
+        opts: AddOptions,
+    ) -> Result<Vec<WsPath>, AddError> {
+        let workspace = self.workspace.as_ref().ok_or(BareError)?;
         let db = &self.db;
-        self.index.reload()?;
-        let mut index = self.index.modify()?;
+        let pathspec = pathspec.into_pathspec(workspace)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+        let mut index = index.modify()?;
 
-        let mut added = Vec::new();
-        for file in workspace.find_files(files)? {
-            let data = workspace.read_file(&file)?;
-            let stat = workspace.stat(&file)?;
+        let mut files = workspace.find_files(&pathspec)?;
+        let mut deleted = Vec::new();
+        if opts.intent_to_add {
+            files.retain(|file| {
+                let tracked = index.is_tracked_file(file);
+                if tracked {
+                    debug!("Not recording intent to add {file}, already tracked");
+                }
+                !tracked
+            });
+        } else {
+            // Tracked files are added even if they're ignored, but those left
+            // out of a sparse checkout aren't missing
+            let found = files.iter().cloned().collect::<BTreeSet<_>>();
+            let mut tracked = index
+                .entries()
+                .filter(|e| !e.skip_worktree())
+                .map(|e| e.path.clone())
+                .filter(|path| !found.contains(path) && pathspec.matches(path))
+                .collect::<Vec<_>>();
+            tracked.dedup();
+            for path in tracked {
+                if !workspace.exists(&path) {
+                    deleted.push(path);
+                } else if !workspace.is_dir(&path) {
+                    files.push(path);
+                }
+            }
+        }
 
-            let oid = db::blob::Builder::new(data).store(&db)?;
-            let entry = Entry::new(file.clone(), oid, stat);
+        if let Some(item) = pathspec.first_unmatched(files.iter().chain(&deleted)) {
+            return Err(match item.literal_path() {
+                Some(path)
+                    if workspace.exists(&path) && workspace.ignore_match(&path)?.is_some() =>
+                {
+                    AddError::Ignored(path)
+                }
+                _ => AddError::DidNotMatch(item.original.clone()),
+            });
+        }
 
+        // Reading, hashing and compressing dominate, so we do them in
+        // parallel. Collecting before checking for errors keeps the order, and
+        // so which error is reported, independent of scheduling.
+        let entries = files
+            .into_par_iter()
+            .map(|file| {
+                let stat = workspace.stat(&file)?;
+                if opts.intent_to_add {
+                    return Ok(Entry::new_intent_to_add(file, stat.mode));
+                }
+                if stat.mode == Mode::Gitlink {
+                    // A submodule is recorded by the commit checked out in it
+                    let head = submodule::head(&file.to_absolute(workspace))?
+                        .ok_or_else(|| AddError::NoSubmoduleCommit(file.clone()))?;
+                    return Ok(Entry::new(file, submodule::gitlink_of(head), stat));
+                }
+
+                let data = workspace.read_file(&file)?;
+                let data = workspace.convert_to_git(&file, data)?;
+                let oid = db::blob::Builder::new(data).store(db)?;
+                Ok(Entry::new(file, oid, stat))
+            })
+            .collect::<Vec<Result<_, AddError>>>();
+
+        let mut added = Vec::new();
+        for entry in entries {
+            let entry = entry?;
             debug!("Adding {:?}", entry);
-            added.push(file);
+            added.push(entry.path.clone());
             index.add(entry);
         }
+        for path in deleted {
+            debug!("Removing deleted {path}");
+            index.remove(&path);
+            added.push(path);
+        }
 
         index.commit()?;
 
         Ok(added)
     }
 
+    /// Stop tracking `paths`, deleting them from the workspace unless
+    /// [`RmOptions::cached`] is set.
+    ///
+    /// Like git, this refuses to remove files whose changes would be lost
+    /// unless [`RmOptions::force`] is set.
+    #[instrument(err)]
+    pub fn rm(
+        &mut self,
+        pathspec: impl IntoPathspec + fmt::Debug,
+        opts: RmOptions,
+    ) -> Result<Vec<WsPath>, RmError> {
+        let head = self.head_files().map_err(RmError::LoadHead)?;
+
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let pathspec = pathspec.into_pathspec(work)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+        let mut index = index.modify()?;
+
+        let mut to_remove = Vec::new();
+        for entry in index.entries() {
+            let matches = pathspec.matching_items(&entry.path);
+            if matches.is_empty() {
+                continue;
+            }
+            // Naming a directory only removes its contents with recursive
+            if !opts.recursive && matches.iter().all(|(_, m)| *m == Match::Leading) {
+                let item = &pathspec.items()[matches[0].0];
+                return Err(RmError::NotRecursive(item.original.clone()));
+            }
+            to_remove.push(entry.path.clone());
+        }
+        // Conflicted paths have an entry for each stage
+        to_remove.sort();
+        to_remove.dedup();
+
+        if let Some(item) = pathspec.first_unmatched(&to_remove) {
+            return Err(RmError::DidNotMatch(item.original.clone()));
+        }
+
+        if !opts.force {
+            for path in &to_remove {
+                Self::check_rm_safe(work, &index, &head, path, opts.cached)?;
+            }
+        }
+
+        for path in &to_remove {
+            debug!("Removing {path}");
+            index.remove(path);
+            if !opts.cached {
+                work.remove_file(path)?;
+            }
+        }
+
+        index.commit()?;
+
+        Ok(to_remove)
+    }
+
+    fn check_rm_safe(
+        work: &Workspace,
+        index: &Index,
+        head: &BTreeMap<WsPath, tree::FileNode>,
+        path: &WsPath,
+        cached: bool,
+    ) -> Result<(), RmError> {
+        let entry = if let Some(entry) = index.entry(path) {
+            entry
+        } else {
+            // Removing a conflicted path discards the conflict, as in git
+            return Ok(());
+        };
+        if cached && entry.intent_to_add() {
+            // Nothing was staged to be lost
+            return Ok(());
+        }
+
+        let staged = head.get(path).map_or(true, |file| {
+            file.oid != entry.oid || file.mode != entry.mode()
+        });
+
+        let local = match work.stat(path) {
+            Ok(stat) => {
+                let data = work.read_file(path)?;
+                let data = work.convert_to_git(path, data)?;
+                stat.mode != entry.mode() || Blob::oid_for_file(data.as_bstr()) != entry.oid
+            }
+            Err(err) if err.is_not_found() => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        if staged && local {
+            Err(RmError::StagedAndLocalChanges(path.clone()))
+        } else if cached {
+            Ok(())
+        } else if staged {
+            Err(RmError::StagedChanges(path.clone()))
+        } else if local {
+            Err(RmError::LocalChanges(path.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Rename `sources` to `dest`, or move them inside `dest` if it is a
+    /// directory or there are multiple sources. Index entries keep their
+    /// oids, stats and flags.
+    ///
+    /// Returns the old and new path of every index entry moved.
+    #[instrument(err)]
+    pub fn mv<I, P>(
+        &mut self,
+        sources: I,
+        dest: impl AsRef<Path> + fmt::Debug,
+    ) -> Result<Vec<(WsPath, WsPath)>, MvError>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+        let mut index = index.modify()?;
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+
+        let sources = sources
+            .into_iter()
+            .map(|src| WsPath::new_normalized(src, work))
+            .collect::<Result<Vec<_>, _>>()?;
+        let dest = WsPath::new_normalized(dest, work)?;
+
+        let into_dir = work.is_dir(&dest);
+        if sources.len() > 1 && !into_dir {
+            return Err(MvError::DestinationNotDir(dest));
+        }
+
+        let mut planned = Vec::new();
+        for src in sources {
+            if src == WsPath::root() {
+                return Err(MvError::MoveRoot);
+            }
+
+            let target = if into_dir {
+                dest.join_bytes(src.file_name())
+            } else {
+                dest.clone()
+            };
+
+            if !work.exists(&src) {
+                return Err(MvError::SourceMissing(src));
+            }
+            if target.as_path().starts_with(&src) {
+                return Err(MvError::IntoItself(src, target));
+            }
+
+            let entries = if work.is_dir(&src) {
+                index.entries_under(&src).cloned().collect::<Vec<_>>()
+            } else if index.is_conflicted(&src) {
+                return Err(MvError::Conflicted(src));
+            } else {
+                index.entry(&src).cloned().into_iter().collect()
+            };
+            if entries.is_empty() {
+                return Err(MvError::NotTracked(src));
+            }
+            if let Some(conflicted) = entries.iter().find(|e| e.is_conflicted()) {
+                return Err(MvError::Conflicted(conflicted.path.clone()));
+            }
+
+            let claimed = planned.iter().any(|(_, other, _)| *other == target);
+            let tracked =
+                index.is_tracked_file(&target) || index.entries_under(&target).next().is_some();
+            if claimed || tracked || work.exists(&target) {
+                return Err(MvError::DestinationExists(target));
+            }
+            if !work.is_dir(&target.parent()) {
+                return Err(MvError::DestinationDirMissing(target));
+            }
+
+            planned.push((src, target, entries));
+        }
+
+        // Everything was checked above, but renames can still fail, and the
+        // workspace shouldn't be left out of step with the index
+        let mut renamed = Vec::new();
+        for (src, target, _) in &planned {
+            debug!("Moving {src} to {target}");
+            if let Err(err) = work.rename(src, target) {
+                Self::undo_renames(work, &renamed);
+                return Err(err.into());
+            }
+            renamed.push((src, target));
+        }
+
+        let mut moved = Vec::new();
+        for (src, target, entries) in &planned {
+            for entry in entries.iter().cloned() {
+                let rel = entry.path.strip_prefix(src).expect("Entry under source");
+                let path = if rel == WsPath::root() {
+                    target.clone()
+                } else {
+                    target.join(rel)
+                };
+
+                index.remove(&entry.path);
+                moved.push((entry.path.clone(), path.clone()));
+                index.add(entry.with_path(path));
+            }
+        }
+
+        if let Err(err) = index.commit() {
+            Self::undo_renames(work, &renamed);
+            return Err(err.into());
+        }
+
+        Ok(moved)
+    }
+
+    /// Moves files back after a failed `mv`, latest first.
+    fn undo_renames(work: &Workspace, renamed: &[(&WsPath, &WsPath)]) {
+        for (src, target) in renamed.iter().rev() {
+            if let Err(err) = work.rename(target, src) {
+                warn!("Failed to move {target} back to {src}: {err}");
+            }
+        }
+    }
+
+    /// Set or clear per-entry flags of tracked files, as with `git
+    /// update-index`. Flags left as `None` in `opts` are unchanged.
+    #[instrument(err)]
+    pub fn update_index<I, P>(
+        &mut self,
+        paths: I,
+        opts: UpdateIndexOptions,
+    ) -> Result<Vec<WsPath>, UpdateIndexError>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+        let mut index = index.modify()?;
+
+        let mut updated = Vec::new();
+        for path in paths {
+            let path = WsPath::new_normalized(path, work)?;
+            if index.entry(&path).is_none() {
+                return Err(UpdateIndexError::NotTracked(path));
+            }
+
+            if let Some(value) = opts.assume_unchanged {
+                index
+                    .set_assume_unchanged(&path, value)
+                    .expect("Entry exists");
+            }
+            if let Some(value) = opts.skip_worktree {
+                index.set_skip_worktree(&path, value).expect("Entry exists");
+            }
+            updated.push(path);
+        }
+
+        index.commit()?;
+
+        Ok(updated)
+    }
+
+    /// The pattern deciding whether each of `paths` is ignored, which may be a
+    /// negated pattern that un-ignores it. Like git, tracked files are never
+    /// ignored.
+    #[instrument(err)]
+    pub fn check_ignore<I, P>(
+        &mut self,
+        paths: I,
+    ) -> Result<Vec<(WsPath, Option<Pattern>)>, CheckIgnoreError>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+
+        let mut matches = Vec::new();
+        for path in paths {
+            let path = WsPath::new_normalized(path, work)?;
+            let pattern = if index.is_tracked_file(&path) {
+                None
+            } else {
+                work.ignore_match(&path)?
+            };
+            matches.push((path, pattern));
+        }
+
+        Ok(matches)
+    }
+
     #[instrument(err)]
     pub fn commit(
         &mut self,
@@ -146,65 +607,840 @@ impl Repo {
 
         let db = &self.db;
         let refs = &self.refs;
-        let index = &self.index;
+        let index = self.index.as_ref().ok_or(BareError)?;
 
-        let entries = index.entries().map(|entry| db::tree::EntryBuilder {
-            oid: entry.oid,
-            path: entry.path.clone(),
-            mode: entry.mode(),
-        });
+        if index.has_conflicts() {
+            return Err(CommitError::Unmerged(index.unmerged_paths()));
+        }
+
+        // Like git, intent-to-add entries are left out of the commit
+        let entries =
+            index
+                .entries()
+                .filter(|e| !e.intent_to_add())
+                .map(|entry| db::tree::EntryBuilder {
+                    oid: entry.oid,
+                    path: entry.path.clone(),
+                    mode: entry.mode(),
+                });
 
         let root = db::tree::Builder::new().entries(entries).store(&db)?;
 
         let parent = refs.head()?;
         let author = db::Author::new_local(name, email, Local::now());
-        let commit = db::commit::Builder::new(parent, root, author, msg).store(db)?;
+        let parents = parent.into_iter().collect();
+        let commit = db::commit::Builder::new(parents, root, author, msg).store(db)?;
         refs.update_head(&commit)?;
 
         Ok(())
     }
 
-    /// Unlike git, this lists files only. Children of untracked directories are
-    /// reported instead of reporting the directory itself.
+    /// The commit `name` refers to, see [`revision`] for what it can be.
+    pub fn resolve(&mut self, name: &str) -> Result<Oid<Commit>, ResolveError> {
+        revision::resolve(&self.refs, &mut self.db, name)
+    }
+
+    /// Like [`Self::resolve`] but for objects of any type, see
+    /// [`revision::resolve_object`].
+    pub fn resolve_object(&mut self, name: &str) -> Result<UntypedOid, ResolveError> {
+        revision::resolve_object(&self.refs, &mut self.db, name)
+    }
+
+    /// A walk through the commits `revisions` include, which can be
+    /// configured further before it starts.
+    pub fn rev_walk(&mut self, revisions: &[Revision]) -> Result<RevWalk, ResolveError> {
+        let mut walk = RevWalk::new(self.db.clone());
+        revision::push_all(&mut walk, &self.refs, &mut self.db, revisions)?;
+        Ok(walk)
+    }
+
+    /// The commits that `opts` selects, newest first unless
+    /// [`LogOptions::order`] or [`LogOptions::reverse`] say otherwise.
     #[instrument(err)]
-    pub fn status(&mut self) -> Result<BTreeMap<WsPath, FileStatus>, StatusError> {
-        let head = if let Some(head) = self.refs.head()? {
-            let tree = self.db.load(head)?.tree;
-            self.db.load_tree_files(&WsPath::root(), tree)?
+    pub fn log(&mut self, opts: &LogOptions) -> Result<Vec<Commit>, LogError> {
+        let walk = if opts.revisions.is_empty() {
+            let head = self.refs.head()?.ok_or(LogError::NoCommits)?;
+            let mut walk = RevWalk::new(self.db.clone());
+            walk.push(head)?;
+            walk
         } else {
-            BTreeMap::new()
+            self.rev_walk(&opts.revisions)?
         };
+        let walk = walk
+            .with_order(opts.order)
+            .with_first_parent(opts.first_parent);
 
-        self.index.reload()?;
-        let mut index = self
-            .index
+        let mut commits = Vec::new();
+        for commit in walk {
+            if opts.max_count.map_or(false, |max| commits.len() >= max) {
+                break;
+            }
+            let commit = commit?;
+            if opts.matches(&commit) && self.changes_paths(&commit, opts)? {
+                commits.push(commit);
+            }
+        }
+        // Like git, after the commits shown are picked
+        if opts.reverse {
+            commits.reverse();
+        }
+        Ok(commits)
+    }
+
+    /// Whether `commit` changes any paths matching [`LogOptions::pathspec`].
+    /// Like git, a merge only does if it differs from each of its parents, or
+    /// from its first parent with [`LogOptions::first_parent`].
+    fn changes_paths(&mut self, commit: &Commit, opts: &LogOptions) -> Result<bool, LogError> {
+        let pathspec = &opts.pathspec;
+        if pathspec.items().is_empty() {
+            return Ok(true);
+        }
+        if commit.parents.is_empty() {
+            return Ok(!self
+                .db
+                .diff_trees(None, Some(commit.tree), pathspec)?
+                .is_empty());
+        }
+        let parents = if opts.first_parent {
+            &commit.parents[..1]
+        } else {
+            &commit.parents[..]
+        };
+        for &parent in parents {
+            let parent = self.db.load(parent)?.tree;
+            if self
+                .db
+                .diff_trees(Some(parent), Some(commit.tree), pathspec)?
+                .is_empty()
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// How the files in the workspace differ from the index, like `git
+    /// diff`. Files added with intent to add are new, and unmerged paths are
+    /// left out.
+    #[instrument(err)]
+    pub fn diff_workspace(
+        &mut self,
+        pathspec: impl IntoPathspec + fmt::Debug,
+    ) -> Result<Vec<FileDiff>, DiffError> {
+        let statuses = self.status_with_options(pathspec, Self::diff_status_options())?;
+        let mut diffs = Vec::new();
+        for status in statuses.values() {
+            if !matches!(
+                status.workspace,
+                Status::Modified | Status::Deleted | Status::Added
+            ) {
+                continue;
+            }
+            let old = status
+                .versions
+                .merged
+                .map(|version| self.side_in_db(version))
+                .transpose()?;
+            let new = match status.versions.workspace_mode {
+                Some(mode) if status.workspace != Status::Deleted => {
+                    self.side_in_workspace(&status.path, mode)?
+                }
+                _ => None,
+            };
+            let diff = self.file_diff(status.path.clone(), old, new)?;
+            // The file may only have a new stat
+            if diff.is_changed() {
+                diffs.push(diff);
+            }
+        }
+        Ok(diffs)
+    }
+
+    /// How the files in the index differ from `HEAD`, like `git diff
+    /// --cached`.
+    #[instrument(err)]
+    pub fn diff_cached(
+        &mut self,
+        pathspec: impl IntoPathspec + fmt::Debug,
+    ) -> Result<Vec<FileDiff>, DiffError> {
+        let statuses = self.status_with_options(pathspec, Self::diff_status_options())?;
+        let mut diffs = Vec::new();
+        for status in statuses.values() {
+            if !matches!(
+                status.index,
+                Status::Modified | Status::Deleted | Status::Added
+            ) {
+                continue;
+            }
+            let mut side =
+                |version: Option<Version>| version.map(|v| self.side_in_db(v)).transpose();
+            let old = side(status.versions.head)?;
+            let new = side(status.versions.merged)?;
+            diffs.push(self.file_diff(status.path.clone(), old, new)?);
+        }
+        Ok(diffs)
+    }
+
+    /// How the files in commit `new` differ from those in `old`, or from
+    /// nothing if it's `None`.
+    #[instrument(err)]
+    pub fn diff_commits(
+        &mut self,
+        old: Option<Oid<Commit>>,
+        new: Oid<Commit>,
+        pathspec: &Pathspec,
+    ) -> Result<Vec<FileDiff>, DiffError> {
+        let old = old.map(|old| self.db.load(old)).transpose()?;
+        let new = self.db.load(new)?;
+        let changes = self
+            .db
+            .diff_trees(old.map(|old| old.tree), Some(new.tree), pathspec)?;
+
+        let mut diffs = Vec::new();
+        for change in changes {
+            let mut side = |file: Option<tree::FileNode>| {
+                file.map(|file| {
+                    self.side_in_db(Version {
+                        mode: file.mode,
+                        oid: file.oid,
+                    })
+                })
+                .transpose()
+            };
+            let old = side(change.old)?;
+            let new = side(change.new)?;
+            diffs.push(self.file_diff(change.path, old, new)?);
+        }
+        // Trees are walked in order of their entries' names, but like git,
+        // `d-e` should come before `d/b`
+        diffs.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(diffs)
+    }
+
+    /// How `commit` changed the files, as log shows it: from its first
+    /// parent, or from nothing if it has none. Like git, merges are shown as
+    /// changing nothing.
+    #[instrument(err)]
+    pub fn diff_commit(
+        &mut self,
+        commit: &Commit,
+        pathspec: &Pathspec,
+    ) -> Result<Vec<FileDiff>, DiffError> {
+        if commit.parents.len() > 1 {
+            return Ok(Vec::new());
+        }
+        self.diff_commits(commit.parent(), commit.oid, pathspec)
+    }
+
+    /// How `commit` changed the files from its first parent, or from nothing
+    /// if it has none, as show shows it, merges included.
+    #[instrument(err)]
+    pub fn diff_first_parent(
+        &mut self,
+        commit: &Commit,
+        pathspec: &Pathspec,
+    ) -> Result<Vec<FileDiff>, DiffError> {
+        self.diff_commits(commit.parent(), commit.oid, pathspec)
+    }
+
+    /// Diffs compare contents themselves, so renames aren't paired up.
+    fn diff_status_options() -> StatusOptions {
+        StatusOptions {
+            untracked: UntrackedFiles::No,
+            renames: Renames::No,
+            ..StatusOptions::default()
+        }
+    }
+
+    fn side_in_db(&mut self, version: Version) -> Result<Side, DiffError> {
+        if version.mode == Mode::Gitlink {
+            return Ok(Side::gitlink(version.oid));
+        }
+        let blob = self.db.load(version.oid)?;
+        Ok(Side::new(version.mode, version.oid, blob.bytes))
+    }
+
+    /// The file at `path` as it would be added, or for a submodule the commit
+    /// checked out in it, `None` if there isn't one.
+    fn side_in_workspace(&self, path: &WsPath, mode: Mode) -> Result<Option<Side>, DiffError> {
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        if mode == Mode::Gitlink {
+            let head = submodule::head(&path.to_absolute(work))?;
+            return Ok(head.map(|commit| Side::gitlink(submodule::gitlink_of(commit))));
+        }
+        let data = work.read_file(path)?;
+        let data = work.convert_to_git(path, data)?;
+        let oid = Blob::oid_for_file(data.as_bstr());
+        Ok(Some(Side::new(mode, oid, data)))
+    }
+
+    /// Like git, the `diff` attribute decides if a file is binary, or else
+    /// its contents do.
+    fn file_diff(
+        &self,
+        path: WsPath,
+        old: Option<Side>,
+        new: Option<Side>,
+    ) -> Result<FileDiff, DiffError> {
+        let mut diff = FileDiff::new(path, old, new);
+        if let Some(work) = &self.workspace {
+            let attrs = work.attributes().for_path(work.path(), &diff.path)?;
+            match attrs.state("diff") {
+                attributes::State::Unset => diff.binary = true,
+                attributes::State::Set => diff.binary = false,
+                _ => {}
+            }
+        }
+        Ok(diff)
+    }
+
+    /// Adds a linked worktree at `path`, which mustn't exist or must be an
+    /// empty directory, with `HEAD` detached at [`WorktreeAddOptions::commit`]
+    /// or else this worktree's `HEAD`, and checks it out. Returns the
+    /// repository opened in the new worktree.
+    #[instrument(err)]
+    pub fn worktree_add(
+        &mut self,
+        path: impl AsRef<Path> + fmt::Debug,
+        opts: WorktreeAddOptions,
+    ) -> Result<Self, WorktreeAddError> {
+        let commit = match opts.commit {
+            Some(commit) => commit,
+            None => self.refs.head()?.ok_or(WorktreeAddError::NoCommit)?,
+        };
+        // Checked before anything is created
+        self.db.load(commit)?;
+
+        let path = path.as_ref();
+        let is_empty_dir = path
+            .read_dir()
+            .map_or(false, |mut dir| dir.next().is_none());
+        if path.exists() && !is_empty_dir {
+            return Err(WorktreeAddError::Exists(path.to_owned()));
+        }
+        fs::create_dir_all(path).map_err(|e| WorktreeAddError::Create(path.to_owned(), e))?;
+        let path = path
+            .canonicalize()
+            .map_err(|e| WorktreeAddError::Create(path.to_owned(), e))?;
+
+        let git_dir = worktree::create(&self.common_dir, &path, &commit)?;
+        // Like git, the new worktree is as sparse as this one
+        let sparse = self
+            .workspace
+            .as_ref()
+            .map(|work| work.sparse().clone())
+            .filter(Sparse::is_sparse);
+        let added = sparse
+            .map_or(Ok(()), |sparse| {
+                sparse.write(&git_dir)?;
+                Self::set_sparse_config(&self.common_dir, &git_dir, true)?;
+                Ok(())
+            })
+            .and_then(|()| Ok(Self::open(git_dir.clone(), Some(path.clone()))?))
+            .and_then(|mut repo| {
+                repo.check_out_head()?;
+                Ok(repo)
+            });
+        if added.is_err() {
+            // Like git, don't leave a half made worktree behind. Failing to
+            // clean up is less important than why it failed.
+            fs::remove_dir_all(&path).ok();
+            fs::remove_dir_all(&git_dir).ok();
+        }
+        added
+    }
+
+    /// Removes the linked worktree at `path`, both its workspace and its git
+    /// directory. Unless `force` is set, refuses if it's locked or has
+    /// changes or untracked files that would be lost.
+    #[instrument(err)]
+    pub fn worktree_remove(
+        &self,
+        path: impl AsRef<Path> + fmt::Debug,
+        force: bool,
+    ) -> Result<(), WorktreeRemoveError> {
+        let path = path.as_ref();
+        let not_worktree = || WorktreeRemoveError::NotWorktree(path.to_owned());
+        let abs = path.canonicalize().map_err(|_| not_worktree())?;
+        let worktree = worktree::list(&self.common_dir)?
+            .into_iter()
+            .find(|worktree| worktree.path == abs)
+            .ok_or_else(not_worktree)?;
+        if worktree.is_main() {
+            return Err(WorktreeRemoveError::Main);
+        }
+
+        if !force {
+            if let Some(reason) = worktree.locked {
+                return Err(WorktreeRemoveError::Locked(worktree.path, reason));
+            }
+            let mut repo = Self::open(worktree.git_dir.clone(), Some(worktree.path.clone()))?;
+            let dirty = repo.status()?.values().any(|status| {
+                status.index != Status::Unmodified || status.workspace != Status::Unmodified
+            });
+            if dirty {
+                return Err(WorktreeRemoveError::Dirty(worktree.path));
+            }
+        }
+
+        fs::remove_dir_all(&worktree.path)
+            .map_err(|e| WorktreeRemoveError::Remove(worktree.path.clone(), e))?;
+        worktree::remove(&worktree)?;
+        Ok(())
+    }
+
+    /// The submodules recorded in the index, in path order, with the commit
+    /// checked out in each.
+    #[instrument(err)]
+    pub fn submodule_status(&mut self) -> Result<Vec<SubmoduleStatus>, SubmoduleError> {
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+
+        Self::gitlinks(work, index)
+    }
+
+    /// Copies the URL of every submodule recorded in the index from
+    /// `.gitmodules` to the repository's config, unless it's there already,
+    /// so [`Self::submodule_update`] will check it out. Returns those newly
+    /// registered, with the URL as written to the config.
+    #[instrument(err)]
+    pub fn submodule_init(&mut self) -> Result<Vec<Submodule>, SubmoduleError> {
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+
+        let mut registered = Vec::new();
+        for SubmoduleStatus { mut submodule, .. } in Self::gitlinks(work, index)? {
+            let key = format!("submodule.{}.url", submodule.name);
+            if self.config.get(&key).is_some() {
+                continue;
+            }
+            let url = submodule
+                .url
+                .as_deref()
+                .ok_or_else(|| SubmoduleError::NoUrl(submodule.path.clone()))?;
+            let url = submodule::resolve_url(work, url);
+            config::append(self.common_dir.join("config"), &key, &url)?;
+            submodule.url = Some(url);
+            registered.push(submodule);
+        }
+
+        self.config = Config::load(&self.common_dir, &self.git_dir)?;
+        Ok(registered)
+    }
+
+    /// Checks out the recorded commit in every submodule registered by
+    /// [`Self::submodule_init`], cloning it from its URL, a local path, if
+    /// it hasn't been yet. Like git, `HEAD` is detached at the commit and
+    /// uninitialized submodules are skipped. Returns the submodules checked
+    /// out, with their commits.
+    #[instrument(err)]
+    pub fn submodule_update(&mut self) -> Result<Vec<(Submodule, Oid<Commit>)>, SubmoduleError> {
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+
+        let mut updated = Vec::new();
+        for status in Self::gitlinks(work, index)? {
+            let submodule = status.submodule;
+            let recorded = status
+                .recorded
+                .ok_or_else(|| SubmoduleError::Unmerged(submodule.path.clone()))?;
+            let key = format!("submodule.{}.url", submodule.name);
+            let url = if let Some(url) = self.config.get(&key) {
+                url.to_str_lossy().into_owned()
+            } else {
+                debug!("Skipping uninitialized submodule {}", submodule.name);
+                continue;
+            };
+            if status.checked_out == Some(recorded) {
+                continue;
+            }
+            let dir = submodule.path.to_absolute(work);
+            Self::update_submodule(&self.common_dir, &submodule, &dir, &url, recorded)?;
+            updated.push((submodule, recorded));
+        }
+        Ok(updated)
+    }
+
+    fn update_submodule(
+        common_dir: &Path,
+        submodule: &Submodule,
+        dir: &Path,
+        url: &str,
+        commit: Oid<Commit>,
+    ) -> Result<(), SubmoduleError> {
+        let cloned = discover::git_dir_in(dir)?.is_some();
+        if !cloned {
+            submodule::create(&submodule::git_dir(common_dir, &submodule.name), dir, url)?;
+        }
+        let mut repo = Self::new(dir)?;
+
+        if repo.db.load(commit).is_err() {
+            let source = Self::new(url).map_err(|e| SubmoduleError::Clone(url.to_owned(), e))?;
+            submodule::copy_objects(source.common_dir(), repo.common_dir())?;
+            repo.db
+                .load(commit)
+                .map_err(|_| SubmoduleError::MissingCommit(submodule.path.clone(), commit))?;
+        }
+
+        if cloned {
+            let changed = repo.status()?.values().any(|status| {
+                !matches!(status.workspace, Status::Untracked | Status::Ignored)
+                    && (status.index != Status::Unmodified
+                        || status.workspace != Status::Unmodified)
+            });
+            if changed {
+                return Err(SubmoduleError::LocalChanges(submodule.path.clone()));
+            }
+        }
+        repo.check_out(commit)?;
+        Ok(())
+    }
+
+    /// The submodules recorded in the index, in path order. Each must be
+    /// declared in `.gitmodules`.
+    fn gitlinks(work: &Workspace, index: &Index) -> Result<Vec<SubmoduleStatus>, SubmoduleError> {
+        let declared = submodule::load(work)?;
+        let mut gitlinks: Vec<SubmoduleStatus> = Vec::new();
+        for entry in index.entries().filter(|e| e.mode() == Mode::Gitlink) {
+            if gitlinks
+                .last()
+                .map_or(false, |s| s.submodule.path == entry.path)
+            {
+                // Another stage of a conflicted submodule
+                continue;
+            }
+            let submodule = declared
+                .iter()
+                .find(|s| s.path == entry.path)
+                .cloned()
+                .ok_or_else(|| SubmoduleError::NoMapping(entry.path.clone()))?;
+            let recorded = if entry.is_conflicted() {
+                None
+            } else {
+                Some(submodule::commit_of(entry.oid))
+            };
+            let checked_out = submodule::head(&entry.path.to_absolute(work))?;
+            gitlinks.push(SubmoduleStatus {
+                submodule,
+                recorded,
+                checked_out,
+            });
+        }
+        Ok(gitlinks)
+    }
+
+    /// Checks out only `dirs` and the files in their parents, like `git
+    /// sparse-checkout set`, see [`Sparse`]. Returns the files that should be
+    /// left out but have changes, which like git are kept.
+    #[instrument(err)]
+    pub fn sparse_checkout_set(
+        &mut self,
+        dirs: Vec<WsPath>,
+    ) -> Result<Vec<WsPath>, SparseCheckoutError> {
+        let sparse = Sparse::cone(dirs);
+        sparse.write(&self.git_dir)?;
+        if self.config.get_bool("core.sparseCheckout")? != Some(true) {
+            Self::set_sparse_config(&self.common_dir, &self.git_dir, true)?;
+            self.config = Config::load(&self.common_dir, &self.git_dir)?;
+        }
+        self.apply_sparse(sparse)
+    }
+
+    /// Checks out everything again, like `git sparse-checkout disable`. The
+    /// patterns are kept for when it's enabled again.
+    #[instrument(err)]
+    pub fn sparse_checkout_disable(&mut self) -> Result<Vec<WsPath>, SparseCheckoutError> {
+        if self.config.get_bool("core.sparseCheckout")? == Some(true) {
+            Self::set_sparse_config(&self.common_dir, &self.git_dir, false)?;
+            self.config = Config::load(&self.common_dir, &self.git_dir)?;
+        }
+        self.apply_sparse(Sparse::all())
+    }
+
+    /// Turns sparse checkout, in cone mode, on or off for the worktree with
+    /// the git directory `git_dir` alone. Like git, that needs per-worktree
+    /// config, which is turned on for the repository if it isn't already.
+    fn set_sparse_config(
+        common_dir: &Path,
+        git_dir: &Path,
+        enabled: bool,
+    ) -> Result<(), config::WriteError> {
+        let common = common_dir.join("config");
+        // Git ignores extensions in repositories without a version
+        config::set(&common, "core.repositoryFormatVersion", "1")?;
+        config::set(&common, "extensions.worktreeConfig", "true")?;
+        let path = git_dir.join(config::WORKTREE_FILE);
+        if enabled {
+            config::set(&path, "core.sparseCheckout", "true")?;
+            config::set(&path, "core.sparseCheckoutCone", "true")
+        } else {
+            config::set(&path, "core.sparseCheckout", "false")
+        }
+    }
+
+    /// Writes the files `sparse` includes that were left out, and removes
+    /// unchanged files it leaves out, setting their skip-worktree bits.
+    fn apply_sparse(&mut self, sparse: Sparse) -> Result<Vec<WsPath>, SparseCheckoutError> {
+        let work = self.workspace.as_mut().ok_or(BareError)?;
+        *work = work.clone().with_sparse(sparse);
+        let work = &*work;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+        let mut index = index.modify()?;
+
+        let entries = index
+            .entries()
+            .filter(|entry| !entry.is_conflicted())
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut kept = Vec::new();
+        for entry in entries {
+            let path = &entry.path;
+            let included = work.sparse().includes_file(path);
+            if included && entry.skip_worktree() {
+                let data = if entry.mode() == Mode::Gitlink {
+                    BString::default()
+                } else {
+                    let data = self.db.load(entry.oid)?.bytes;
+                    work.convert_to_workspace(path, data)?
+                };
+                work.write_file(path, &data, entry.mode())?;
+                index.set_skip_worktree(path, false).expect("Entry exists");
+                index
+                    .update_stat(path, work.stat(path)?)
+                    .expect("Entry exists");
+            } else if !included && !entry.skip_worktree() {
+                let (status, _) = Self::workspace_status_of(work, &index, path)?;
+                // A submodule's directory also holds its repository
+                let checked_out = entry.mode() == Mode::Gitlink
+                    && fs::read_dir(path.to_absolute(work))
+                        .map_or(false, |mut d| d.next().is_some());
+                if !matches!(status, Status::Unmodified | Status::Deleted) || checked_out {
+                    debug!("Keeping {path} with changes outside sparse checkout");
+                    kept.push(path.clone());
+                    continue;
+                }
+                if entry.mode() == Mode::Gitlink {
+                    // Empty, so it's removed like a file's parent would be
+                    let _ = fs::remove_dir(path.to_absolute(work));
+                }
+                work.remove_file(path)?;
+                index.set_skip_worktree(path, true).expect("Entry exists");
+            }
+        }
+
+        index.commit()?;
+        Ok(kept)
+    }
+
+    /// Moves `HEAD` to `commit`, replacing the files checked out with its.
+    /// Untracked files are left alone unless they're in the way.
+    fn check_out(&mut self, commit: Oid<Commit>) -> Result<(), CheckoutError> {
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+        let mut index = index.modify()?;
+
+        let tracked = index
+            .entries()
+            .map(|entry| entry.path.clone())
+            .collect::<BTreeSet<_>>();
+        for path in tracked {
+            work.remove_file(&path)?;
+            index.remove(&path);
+        }
+        index.commit()?;
+
+        self.refs.update_head(&commit)?;
+        self.check_out_head()
+    }
+
+    /// Writes the files of `HEAD` to the workspace and the index, which are
+    /// taken to be empty. Files left out of a sparse checkout only go in the
+    /// index, marked skip-worktree.
+    fn check_out_head(&mut self) -> Result<(), CheckoutError> {
+        let files = self.head_files()?;
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let db = &mut self.db;
+        let mut index = self.index.as_mut().ok_or(BareError)?.modify()?;
+
+        // Attributes can change how any file is written, so they go first
+        let (attributes, others): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|(path, _)| path.file_name() == Attributes::FILE_NAME);
+        for (path, file) in attributes.into_iter().chain(others) {
+            if !work.sparse().includes_file(&path) {
+                let stat = Stat {
+                    mode: file.mode,
+                    ..Stat::zeroed()
+                };
+                let mut entry = Entry::new(path, file.oid, stat);
+                entry.set_skip_worktree(true);
+                index.add(entry);
+                continue;
+            }
+            // A submodule is left empty until it's updated
+            let data = if file.mode == Mode::Gitlink {
+                BString::default()
+            } else {
+                let data = db.load(file.oid)?.bytes;
+                work.convert_to_workspace(&path, data)?
+            };
+            work.write_file(&path, &data, file.mode)?;
+            let stat = work.stat(&path)?;
+            index.add(Entry::new(path, file.oid, stat));
+        }
+
+        index.commit()?;
+        Ok(())
+    }
+
+    fn head_files(&mut self) -> Result<BTreeMap<WsPath, tree::FileNode>, LoadHeadError> {
+        if let Some(head) = self.refs.head()? {
+            let tree = self.db.load(head)?.tree;
+            Ok(self.db.load_tree_files(&WsPath::root(), tree)?)
+        } else {
+            Ok(BTreeMap::new())
+        }
+    }
+
+    /// Like git, a directory containing no tracked files is reported once
+    /// as a whole rather than file by file, see [`StatusOptions`].
+    pub fn status(&mut self) -> Result<BTreeMap<WsPath, FileStatus>, StatusError> {
+        self.status_with_options(Pathspec::all(), StatusOptions::default())
+    }
+
+    /// Like [`Self::status`], but only for the paths matching `pathspec`.
+    #[instrument(err)]
+    #[allow(clippy::too_many_lines)]
+    pub fn status_with_options(
+        &mut self,
+        pathspec: impl IntoPathspec + fmt::Debug,
+        opts: StatusOptions,
+    ) -> Result<BTreeMap<WsPath, FileStatus>, StatusError> {
+        let head = self.head_files()?;
+        let work = self.workspace.as_ref().ok_or(BareError)?;
+        let pathspec = pathspec.into_pathspec(work)?;
+
+        let use_fsmonitor = self.config.get_bool("core.fsmonitor")?.unwrap_or(false);
+
+        let index = self.index.as_mut().ok_or(BareError)?;
+        index.reload()?;
+        let mut index = index
             .modify()
             .map_err(|e| StatusError::UpdateIndex(e.into()))?;
 
-        let work = &self.workspace;
+        if use_fsmonitor {
+            Self::refresh_fsmonitor(&self.git_dir, &mut index);
+        } else {
+            index.set_fsmonitor_token(None);
+        }
 
         let mut ws_statuses = BTreeMap::new();
         let mut index_statuses = BTreeMap::new();
+        // Untracked and ignored directories, and ignored files
+        let mut others = Vec::new();
 
-        for path in self.workspace.list_files()? {
-            let ws_status = Self::workspace_status_of(work, &mut index, &path)?;
-            let index_status = Self::index_status_of(&index, &head, &path)?;
+        let mut files = Vec::new();
+        for found in work.walk(&pathspec, opts, |dir| index.has_entries_under(dir))? {
+            let (path, status, is_dir) = match found {
+                Found::File(path) => {
+                    files.push(path);
+                    continue;
+                }
+                Found::UntrackedDir(path) => (path, Status::Untracked, true),
+                // Tracked files are compared below even if they're ignored
+                Found::IgnoredFile(path) if index.is_tracked_file(&path) => continue,
+                Found::IgnoredFile(path) => (path, Status::Ignored, false),
+                Found::IgnoredDir(path) => (path, Status::Ignored, true),
+            };
+            others.push(FileStatus {
+                path,
+                index: status.clone(),
+                workspace: status,
+                is_dir,
+                versions: Versions::default(),
+            });
+        }
+
+        // Stat comparisons and rehashing run in parallel, then stats that
+        // turned out to be stale are updated in order.
+        let results = files
+            .into_par_iter()
+            .map(|path| {
+                let (ws_status, new_stat) = Self::workspace_status_of(work, &index, &path)?;
+                let index_status = Self::index_status_of(&index, &head, &path)?;
+                Ok((path, ws_status, new_stat, index_status))
+            })
+            .collect::<Vec<Result<_, StatusError>>>();
+
+        for result in results {
+            let (path, ws_status, new_stat, index_status) = result?;
+            if let Some(new_stat) = new_stat {
+                index.update_stat(&path, new_stat).expect("Entry exists");
+            }
             debug!("{path} in workspace, so ws: {ws_status:?}, idx: {index_status:?}");
+            if ws_status == Status::Untracked && opts.untracked == UntrackedFiles::No {
+                continue;
+            }
             ws_statuses.insert(path.clone(), ws_status);
             index_statuses.insert(path, index_status);
         }
 
+        let mut new_stats = Vec::new();
         for entry in index.entries() {
-            if !ws_statuses.contains_key(&entry.path) {
-                debug!("{} in idx but not ws, so ws: Status::Deleted", entry.path);
-                ws_statuses.insert(entry.path.clone(), Status::Deleted);
+            if ws_statuses.contains_key(&entry.path) || !pathspec.matches(&entry.path) {
+                continue;
+            }
+
+            if entry.is_conflicted() {
+                let status = Self::index_status_of(&index, &head, &entry.path)?;
+                debug!("{} unmerged and not in ws, so {status:?}", entry.path);
+                ws_statuses.insert(entry.path.clone(), status.clone());
+                index_statuses.insert(entry.path.clone(), status);
+            } else {
+                let ws_status = if !entry.is_checked_in_workspace() {
+                    Status::Unmodified
+                } else if work.exists(&entry.path) {
+                    // Tracked files are still compared if they're ignored
+                    let (status, new_stat) = Self::workspace_status_of(work, &index, &entry.path)?;
+                    if let Some(new_stat) = new_stat {
+                        new_stats.push((entry.path.clone(), new_stat));
+                    }
+                    status
+                } else {
+                    Status::Deleted
+                };
+                let index_status = Self::index_status_of(&index, &head, &entry.path)?;
+                debug!(
+                    "{} in idx but not ws, so ws: {ws_status:?}, idx: {index_status:?}",
+                    entry.path
+                );
+                ws_statuses.insert(entry.path.clone(), ws_status);
+                index_statuses.insert(entry.path.clone(), index_status);
             }
         }
 
-        for (path, _file) in head {
-            if !index.is_tracked_file(&path) {
+        for (path, new_stat) in new_stats {
+            index.update_stat(&path, new_stat).expect("Entry exists");
+        }
+
+        for (path, status) in &ws_statuses {
+            let checked = index
+                .entry(path)
+                .map_or(false, Entry::is_checked_in_workspace);
+            if use_fsmonitor && *status == Status::Unmodified && checked {
+                index.set_fsmonitor_valid(path);
+            }
+        }
+
+        for path in head.keys() {
+            if !index.is_tracked_file(path) && pathspec.matches(path) {
                 debug!("{path} in head but not idx, so idx: Status::Deleted",);
-                index_statuses.insert(path, Status::Deleted);
+                index_statuses.insert(path.clone(), Status::Deleted);
             }
         }
 
@@ -222,6 +1458,8 @@ impl Repo {
                     path,
                     workspace: ws_status,
                     index: index_status,
+                    is_dir: false,
+                    versions: Versions::default(),
                 },
             );
         }
@@ -233,46 +1471,166 @@ impl Repo {
                     path,
                     workspace: Status::Deleted,
                     index: index_status,
+                    is_dir: false,
+                    versions: Versions::default(),
                 },
             );
         }
 
+        let index = self.index.as_ref().ok_or(BareError)?;
+        for status in statuses.values_mut() {
+            status.versions = Self::versions_of(work, index, &head, status)?;
+        }
+
+        renames::detect(&mut self.db, &mut statuses, opts.renames)?;
+
+        for status in others {
+            statuses.insert(status.path.clone(), status);
+        }
+
         Ok(statuses)
     }
 
+    /// Marks the entries the filesystem monitor reports may have changed
+    /// since the index was last refreshed as needing to be checked. If the
+    /// monitor can't be asked, every entry is checked.
+    fn refresh_fsmonitor(git_dir: &Path, index: &mut IndexMut) {
+        let changes = match fsmonitor::query(git_dir, index.fsmonitor_token()) {
+            Ok(changes) => changes,
+            Err(err) => {
+                warn!("{err}, checking every file");
+                index.set_fsmonitor_token(None);
+                return;
+            }
+        };
+        debug!("fsmonitor reported changes to {:?}", changes.paths);
+        match &changes.paths {
+            Some(paths) => {
+                for path in paths {
+                    index.invalidate_fsmonitor(Some(path));
+                }
+            }
+            None => index.invalidate_fsmonitor(None),
+        }
+        index.set_fsmonitor_token(Some(changes.token));
+    }
+
+    /// Also returns the new stat of the file if the index entry's stat is
+    /// stale but the contents are unchanged, so the caller can update it.
     pub fn workspace_status_of(
         work: &Workspace,
-        index: &mut IndexMut,
+        index: &Index,
         path: &WsPath,
-    ) -> Result<Status, StatusError> {
+    ) -> Result<(Status, Option<Stat>), StatusError> {
+        if let Some(conflict) = index.conflict(path) {
+            return Ok((Status::Unmerged(conflict.kind()), None));
+        }
+
         let status = if let Some(entry) = index.entry(path) {
-            match entry.index_status_chatty(work)? {
-                StatusChatty::Unmodified => Status::Unmodified,
+            if entry.intent_to_add() {
+                return Ok((Status::Added, None));
+            } else if !entry.is_checked_in_workspace() {
+                return Ok((Status::Unmodified, None));
+            } else if entry.mode() == Mode::Gitlink {
+                return Ok((Self::submodule_status_of(work, entry)?, None));
+            } else if index.is_fsmonitor_valid(path) {
+                return Ok((Status::Unmodified, None));
+            }
+
+            match entry.index_status_chatty(work, index.mtime())? {
+                StatusChatty::Unmodified => (Status::Unmodified, None),
                 StatusChatty::UnmodifiedButNewStat(new_stat) => {
-                    index.update_stat(path, new_stat).expect("Entry exists");
-                    Status::Unmodified
+                    (Status::Unmodified, Some(new_stat))
                 }
-                StatusChatty::Modified => Status::Modified,
-                StatusChatty::Deleted => Status::Deleted,
+                StatusChatty::Modified => (Status::Modified, None),
+                StatusChatty::Deleted => (Status::Deleted, None),
             }
         } else {
-            Status::Untracked
+            (Status::Untracked, None)
+        };
+        Ok(status)
+    }
+
+    /// A submodule is modified if another commit than the recorded one is
+    /// checked out in it, but like git one that isn't checked out at all is
+    /// unmodified.
+    fn submodule_status_of(work: &Workspace, entry: &Entry) -> Result<Status, StatusError> {
+        let dir = entry.path.to_absolute(work);
+        if !dir.is_dir() {
+            return Ok(Status::Deleted);
+        }
+        let status = match submodule::head(&dir)? {
+            Some(head) if head != submodule::commit_of(entry.oid) => Status::Modified,
+            _ => Status::Unmodified,
         };
         Ok(status)
     }
 
+    fn versions_of(
+        work: &Workspace,
+        index: &Index,
+        head: &BTreeMap<WsPath, tree::FileNode>,
+        status: &FileStatus,
+    ) -> Result<Versions, StatusError> {
+        let path = &status.path;
+        let mut versions = Versions {
+            head: head.get(path).map(|file| Version {
+                mode: file.mode,
+                oid: file.oid,
+            }),
+            ..Versions::default()
+        };
+
+        for entry in index.stages_of(path) {
+            if entry.intent_to_add() {
+                continue;
+            }
+            let version = Some(Version {
+                mode: entry.mode(),
+                oid: entry.oid,
+            });
+            match entry.stage() {
+                Stage::Merged => versions.merged = version,
+                Stage::Base => versions.base = version,
+                Stage::Ours => versions.ours = version,
+                Stage::Theirs => versions.theirs = version,
+            }
+        }
+
+        versions.workspace_mode = match status.workspace {
+            Status::Deleted => None,
+            Status::Unmodified if versions.merged.is_some() => versions.merged.map(|v| v.mode),
+            _ => match work.stat(path) {
+                Ok(stat) => Some(stat.mode),
+                Err(err) if err.is_not_found() => None,
+                Err(err) => return Err(err.into()),
+            },
+        };
+
+        Ok(versions)
+    }
+
     #[allow(clippy::option_if_let_else)]
     pub fn index_status_of(
         index: &Index,
         head: &BTreeMap<WsPath, tree::FileNode>,
         path: &WsPath,
     ) -> Result<Status, StatusError> {
+        if let Some(conflict) = index.conflict(path) {
+            return Ok(Status::Unmerged(conflict.kind()));
+        }
+
         let index_entry = if let Some(index_entry) = index.entry(path) {
             index_entry
         } else {
             return Ok(Status::Untracked);
         };
 
+        if index_entry.intent_to_add() {
+            // Nothing is staged yet, the addition shows in the workspace status
+            return Ok(Status::Unmodified);
+        }
+
         let status = if let Some(head_file) = head.get(path) {
             if head_file.mode == index_entry.mode() && head_file.oid == index_entry.oid {
                 Status::Unmodified
@@ -287,30 +1645,52 @@ impl Repo {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InitOptions {
+    /// Create a repository without a workspace, as with `git init --bare`
+    pub bare: bool,
+}
+
 #[derive(Debug, displaydoc::Display, thiserror::Error)]
 // Failed in initialize repository
 pub enum InitError {
     /// Directory {0:?} already exists
     Exists(PathBuf),
-    /// Failed to create workspace directory {0:?}
+    /// Failed to create directory {0:?}
     CreateWorkspace(PathBuf, #[source] io::Error),
     /// Failed to open directory {0:?} to initialize
     Open(PathBuf, #[source] io::Error),
     /// Failed to populate {0:?}
     Write(PathBuf, #[source] io::Error),
-    /// Failed to open index
-    OpenIndex(#[from] index::LoadError),
+    /// Failed to open new repository
+    Read(#[from] ReadError),
 }
 
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// This operation must be run in a work tree, not a bare repository
+pub struct BareError;
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 /// Failed to read a directory as a git repository.
 pub enum ReadError {
     /// The directory {0:?} is not a git repository
     NotRepo(PathBuf),
+    /// Failed to find repository
+    Discover(#[from] discover::Error),
     /// IO error while checking if directory {0:?} is a git repository
     Io(PathBuf, #[source] io::Error),
     /// Failed to open index
     OpenIndex(#[from] index::LoadError),
+    /// Failed to load config
+    LoadConfig(#[from] config::LoadError),
+    /// Failed to load ignore patterns
+    LoadExcludes(#[from] ignore::LoadError),
+    /// Failed to load attributes
+    LoadAttributes(#[from] attributes::LoadError),
+    /// Failed to load sparse-checkout patterns
+    LoadSparse(#[from] sparse::LoadError),
+    /// Invalid config
+    InvalidConfig(#[from] config::InvalidValueError),
 }
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -324,26 +1704,164 @@ pub enum ForCurrentDirError {
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum AddError {
+    /// {0}
+    Bare(#[from] BareError),
     /// Failed to reload index
     ReloadIndex(#[from] index::LoadError),
     /// Failed to open index of modifications
     OpenIndex(#[from] index::OpenForModificationsError),
+    /// Invalid pathspec
+    Pathspec(#[from] pathspec::ParseError),
+    /// Pathspec {0:?} did not match any files
+    DidNotMatch(String),
+    /// The path {0} is ignored by one of your .gitignore files
+    Ignored(WsPath),
+    /// Failed to load ignore patterns
+    LoadExcludes(#[from] ignore::LoadError),
     /// Failed to find files provided in repository
     FindFiles(#[from] ws::ListFilesError),
     /// Failed to stat file
     Stat(#[from] StatFileError),
+    /// '{0}' does not have a commit checked out
+    NoSubmoduleCommit(WsPath),
+    /// Failed to read submodule
+    Submodule(#[from] submodule::Error),
     /// Failed to read file
     Read(#[from] ReadFileError),
+    /// Failed to convert file
+    Convert(#[from] ws::ConvertError),
     /// Failed to store file
     StoreBlob(#[from] db::StoreError<Blob>),
     /// Failed to commit changes to index
     CommitIndex(#[from] index::CommitError),
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddOptions {
+    /// Record only that the files will be added later, as with `git add -N`
+    pub intent_to_add: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateIndexOptions {
+    pub assume_unchanged: Option<bool>,
+    pub skip_worktree: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RmOptions {
+    /// Only remove from the index, leaving the workspace untouched
+    pub cached: bool,
+    /// Allow removing the contents of directories
+    pub recursive: bool,
+    /// Remove even if changes would be lost
+    pub force: bool,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum RmError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Failed to load HEAD
+    LoadHead(#[source] LoadHeadError),
+    /// Failed to reload index
+    ReloadIndex(#[from] index::LoadError),
+    /// Failed to open index of modifications
+    OpenIndex(#[from] index::OpenForModificationsError),
+    /// Invalid pathspec
+    Pathspec(#[from] pathspec::ParseError),
+    /// Pathspec {0:?} did not match any files
+    DidNotMatch(String),
+    /// Not removing {0:?} recursively without -r
+    NotRecursive(String),
+    /// {0} has staged content different from both the file and HEAD
+    StagedAndLocalChanges(WsPath),
+    /// {0} has changes staged in the index
+    StagedChanges(WsPath),
+    /// {0} has local modifications
+    LocalChanges(WsPath),
+    /// Failed to stat file
+    Stat(#[from] StatFileError),
+    /// Failed to read file
+    Read(#[from] ReadFileError),
+    /// Failed to convert file
+    Convert(#[from] ws::ConvertError),
+    /// Failed to remove file
+    Remove(#[from] ws::RemoveFileError),
+    /// Failed to commit changes to index
+    CommitIndex(#[from] index::CommitError),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum MvError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Failed to reload index
+    ReloadIndex(#[from] index::LoadError),
+    /// Failed to open index of modifications
+    OpenIndex(#[from] index::OpenForModificationsError),
+    /// Path is not in the workspace
+    Normalize(#[from] ws::path::NewCanonicalizeError),
+    /// Cannot move the root of the workspace
+    MoveRoot,
+    /// Destination {0} is not a directory
+    DestinationNotDir(WsPath),
+    /// Source {0} does not exist
+    SourceMissing(WsPath),
+    /// Cannot move {0} into itself ({1})
+    IntoItself(WsPath, WsPath),
+    /// {0} is not under version control
+    NotTracked(WsPath),
+    /// {0} is conflicted
+    Conflicted(WsPath),
+    /// Destination {0} already exists
+    DestinationExists(WsPath),
+    /// The directory {0} would be moved to does not exist
+    DestinationDirMissing(WsPath),
+    /// Failed to rename file
+    Rename(#[from] ws::RenameFileError),
+    /// Failed to commit changes to index
+    CommitIndex(#[from] index::CommitError),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CheckIgnoreError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Failed to reload index
+    ReloadIndex(#[from] index::LoadError),
+    /// Path is not in the workspace
+    Normalize(#[from] ws::path::NewCanonicalizeError),
+    /// Failed to load ignore patterns
+    LoadExcludes(#[from] ignore::LoadError),
+    /// Failed to load attributes
+    LoadAttributes(#[from] attributes::LoadError),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum UpdateIndexError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Failed to reload index
+    ReloadIndex(#[from] index::LoadError),
+    /// Failed to open index of modifications
+    OpenIndex(#[from] index::OpenForModificationsError),
+    /// Path is not in the workspace
+    Normalize(#[from] ws::path::NewCanonicalizeError),
+    /// {0} is not tracked
+    NotTracked(WsPath),
+    /// Failed to commit changes to index
+    CommitIndex(#[from] index::CommitError),
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum CommitError {
+    /// {0}
+    Bare(#[from] BareError),
     /// Empty commit message
     EmptyMessage,
+    /// Cannot commit because you have unmerged paths: {0:?}
+    Unmerged(Vec<WsPath>),
     /// Failed to load index
     LoadIndex(#[from] index::OpenForModificationsError),
     /// Failed to store tree
@@ -359,19 +1877,215 @@ pub enum CommitError {
 }
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
-pub enum StatusError {
+pub enum LogError {
+    /// Your current HEAD does not have any commits yet
+    NoCommits,
+    /// Failed to read HEAD
+    ReadHead(#[from] refs::ReadError),
+    /// Failed to resolve revision
+    Resolve(#[from] ResolveError),
+    /// Failed to load commit
+    LoadCommit(#[from] db::LoadError<Commit>),
+    /// Failed to load tree
+    LoadTree(#[from] db::LoadError<Tree>),
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum DiffError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Failed to compare the workspace and index
+    Status(#[from] StatusError),
+    /// Failed to load commit
+    LoadCommit(#[from] db::LoadError<Commit>),
+    /// Failed to load tree
+    LoadTree(#[from] db::LoadError<Tree>),
+    /// Failed to load blob
+    LoadBlob(#[from] db::LoadError<Blob>),
+    /// Failed to read file
+    Read(#[from] ReadFileError),
+    /// Failed to convert file
+    Convert(#[from] ws::ConvertError),
+    /// Failed to load attributes
+    Attributes(#[from] attributes::LoadError),
+    /// Failed to read submodule
+    Submodule(#[from] submodule::Error),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WorktreeAddOptions {
+    /// What to check out instead of `HEAD`
+    pub commit: Option<Oid<Commit>>,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum WorktreeAddError {
+    /// Failed to read HEAD
+    ReadHead(#[from] refs::ReadError),
+    /// Not a valid commit: HEAD
+    NoCommit,
+    /// Failed to load commit to check out
+    LoadCommit(#[from] db::LoadError<Commit>),
+    /// {0:?} already exists
+    Exists(PathBuf),
+    /// Failed to create worktree directory {0:?}
+    Create(PathBuf, #[source] io::Error),
+    /// Failed to set up worktree
+    Worktree(#[from] worktree::Error),
+    /// Failed to copy sparse-checkout patterns
+    Sparse(#[from] sparse::WriteError),
+    /// Failed to turn on sparse checkout
+    SparseConfig(#[from] config::WriteError),
+    /// Failed to open new worktree
+    Open(#[from] ReadError),
+    /// Failed to check out new worktree
+    Checkout(#[from] CheckoutError),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum WorktreeRemoveError {
+    /// {0:?} is not a working tree
+    NotWorktree(PathBuf),
+    /// The main working tree cannot be removed
+    Main,
+    /// Cannot remove a locked working tree {0:?}, lock reason: {1}
+    Locked(PathBuf, String),
+    /// {0:?} contains modified or untracked files, use force to delete it
+    Dirty(PathBuf),
+    /// Failed to list worktrees
+    Worktree(#[from] worktree::Error),
+    /// Failed to open worktree
+    Open(#[from] ReadError),
+    /// Failed to check worktree for changes
+    Status(#[from] StatusError),
+    /// Failed to remove {0:?}
+    Remove(PathBuf, #[source] io::Error),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CheckoutError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Failed to load HEAD
+    LoadHead(#[from] LoadHeadError),
+    /// Failed to open index of modifications
+    OpenIndex(#[from] index::OpenForModificationsError),
+    /// Failed to load blob
+    LoadBlob(#[from] db::LoadError<Blob>),
+    /// Failed to convert file
+    Convert(#[from] ws::ConvertError),
+    /// Failed to write file
+    Write(#[from] ws::WriteFileError),
+    /// Failed to stat file
+    Stat(#[from] StatFileError),
+    /// Failed to commit changes to index
+    CommitIndex(#[from] index::CommitError),
+    /// Failed to reload index
+    ReloadIndex(#[from] index::LoadError),
+    /// Failed to remove file
+    Remove(#[from] ws::RemoveFileError),
+    /// Failed to update HEAD
+    UpdateHead(#[from] refs::UpdateError),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SubmoduleError {
+    /// {0}
+    Bare(#[from] BareError),
     /// Failed to reload index
     ReloadIndex(#[from] index::LoadError),
+    /// Failed to read submodule
+    Submodule(#[from] submodule::Error),
+    /// No submodule mapping found in .gitmodules for path '{0}'
+    NoMapping(WsPath),
+    /// No url found for submodule path '{0}' in .gitmodules
+    NoUrl(WsPath),
+    /// Submodule path '{0}' is unmerged
+    Unmerged(WsPath),
+    /// Failed to write config
+    WriteConfig(#[from] config::WriteError),
+    /// Failed to reload config
+    LoadConfig(#[from] config::LoadError),
+    /// Failed to find submodule's git directory
+    Discover(#[from] discover::Error),
+    /// Failed to open submodule
+    Open(#[from] ReadError),
+    /// Failed to clone '{0}'
+    Clone(String, #[source] ReadError),
+    /// Fetched in submodule path '{0}', but it did not contain {1}
+    MissingCommit(WsPath, Oid<Commit>),
+    /// Failed to check submodule for changes
+    Status(#[from] StatusError),
+    /// Your local changes to submodule path '{0}' would be overwritten by checkout
+    LocalChanges(WsPath),
+    /// Failed to check out submodule
+    Checkout(#[from] CheckoutError),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SparseCheckoutError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Failed to write sparse-checkout patterns
+    Write(#[from] sparse::WriteError),
+    /// Invalid config
+    InvalidConfig(#[from] config::InvalidValueError),
+    /// Failed to write config
+    WriteConfig(#[from] config::WriteError),
+    /// Failed to reload config
+    LoadConfig(#[from] config::LoadError),
+    /// Failed to reload index
+    ReloadIndex(#[from] index::LoadError),
+    /// Failed to open index of modifications
+    OpenIndex(#[from] index::OpenForModificationsError),
+    /// Failed to check file for changes
+    Status(#[from] StatusError),
+    /// Failed to load blob
+    LoadBlob(#[from] db::LoadError<Blob>),
+    /// Failed to convert file
+    Convert(#[from] ws::ConvertError),
+    /// Failed to write file
+    WriteFile(#[from] ws::WriteFileError),
+    /// Failed to stat file
+    Stat(#[from] StatFileError),
+    /// Failed to remove file
+    Remove(#[from] ws::RemoveFileError),
+    /// Failed to commit changes to index
+    CommitIndex(#[from] index::CommitError),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LoadHeadError {
     /// Failed to get head oid
     GetHeadOid(#[from] refs::ReadError),
     /// Failed to load head commit
     LoadHeadCommit(#[from] db::LoadError<db::Commit>),
     /// Failed to load of tree from head
     LoadHeadTree(#[from] db::LoadError<db::Tree>),
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum StatusError {
+    /// {0}
+    Bare(#[from] BareError),
+    /// Invalid pathspec
+    Pathspec(#[from] pathspec::ParseError),
+    /// Failed to reload index
+    ReloadIndex(#[from] index::LoadError),
+    /// Failed to load HEAD
+    LoadHead(#[from] LoadHeadError),
     /// Failed to list files
     ListFiles(#[from] ListFilesError),
     /// Failed to check if file unchanged
     IsUnchanged(#[from] entry::IsUnchangedError),
+    /// Failed to stat file
+    Stat(#[from] StatFileError),
+    /// Failed to load blob to detect renames
+    LoadBlob(#[from] db::LoadError<Blob>),
+    /// Invalid config
+    Config(#[from] config::InvalidValueError),
     /// Failed to update index with new stat
     UpdateIndex(#[from] index::ModifyError),
+    /// Failed to check submodule
+    Submodule(#[from] submodule::Error),
 }
@@ -0,0 +1,409 @@
+//! Walking history back from some commits through their parents, newest
+//! first, like `git rev-list`.
+
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::core::{
+    db::{self, Commit},
+    Db, Oid,
+};
+
+/// How long to keep walking once only hidden commits are left, in case
+/// skewed dates put an ancestor of a hidden commit after them.
+const SLOP: usize = 5;
+
+/// Yields each commit reachable from the ones pushed once, except those
+/// reachable from the ones hidden, in [`Order`].
+///
+/// With nothing hidden, the default order and not reversed, commits are
+/// loaded as they're yielded. Otherwise the walk goes on until only hidden
+/// commits are left before yielding any, as a commit can only be known not to
+/// be hidden then. Like git, that stops a few commits after, rather than
+/// walking all of their history.
+#[derive(Debug)]
+pub struct RevWalk {
+    db: Db,
+    queue: BinaryHeap<Queued>,
+    nodes: HashMap<Oid<Commit>, Node>,
+    queued: usize,
+    order: Order,
+    reverse: bool,
+    first_parent: bool,
+    /// Whether any commits are hidden
+    limited: bool,
+    /// The commits left to yield, once the whole walk is done
+    sorted: Option<std::vec::IntoIter<Commit>>,
+}
+
+/// The order [`RevWalk`] yields commits in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Order {
+    /// By committer date, newest first, as they're reached. Like git's
+    /// default, a commit comes before its parents unless its date is skewed
+    /// to be older than theirs.
+    Walk,
+    /// No commit before any of its children, otherwise by committer date,
+    /// like `--date-order`
+    Date,
+    /// No commit before any of its children, with the commits of each line
+    /// of history together rather than interleaved by date, like
+    /// `--topo-order`
+    Topo,
+}
+
+impl Default for Order {
+    fn default() -> Self {
+        Self::Walk
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    hidden: bool,
+    /// Set once the commit has been taken from the queue
+    parents: Option<Vec<Oid<Commit>>>,
+}
+
+#[derive(Debug)]
+struct Queued {
+    time: i64,
+    /// Commits with the same date come out in the order they went in
+    order: usize,
+    commit: Commit,
+}
+
+impl RevWalk {
+    /// Commits are loaded from `db`, which can be a clone of another so it
+    /// can still be used during the walk.
+    pub fn new(db: Db) -> Self {
+        Self {
+            db,
+            queue: BinaryHeap::new(),
+            nodes: HashMap::new(),
+            queued: 0,
+            order: Order::default(),
+            reverse: false,
+            first_parent: false,
+            limited: false,
+            sorted: None,
+        }
+    }
+
+    pub fn with_order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Yields the commits in the opposite order, oldest first by default.
+    pub fn with_reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Only follows the first parent of each commit, like `--first-parent`,
+    /// which leaves out the commits merged in.
+    pub fn with_first_parent(mut self, first_parent: bool) -> Self {
+        self.first_parent = first_parent;
+        self
+    }
+
+    /// Adds `oid` and its ancestors to the walk.
+    pub fn push(&mut self, oid: Oid<Commit>) -> Result<(), db::LoadError<Commit>> {
+        if self.nodes.contains_key(&oid) {
+            return Ok(());
+        }
+        self.nodes.insert(oid, Node::default());
+        self.enqueue(oid)
+    }
+
+    /// Leaves `oid` and its ancestors out of the walk, even those reachable
+    /// from commits pushed, like `^oid`.
+    pub fn hide(&mut self, oid: Oid<Commit>) -> Result<(), db::LoadError<Commit>> {
+        self.limited = true;
+        let is_new = !self.nodes.contains_key(&oid);
+        self.mark_hidden(oid);
+        if is_new {
+            self.enqueue(oid)?;
+        }
+        Ok(())
+    }
+
+    /// The commit's parents that the walk follows.
+    fn parents<'c>(&self, commit: &'c Commit) -> &'c [Oid<Commit>] {
+        if self.first_parent {
+            &commit.parents[..commit.parents.len().min(1)]
+        } else {
+            &commit.parents
+        }
+    }
+
+    fn enqueue(&mut self, oid: Oid<Commit>) -> Result<(), db::LoadError<Commit>> {
+        let commit = self.db.load(oid)?;
+        self.queue.push(Queued::new(commit, self.queued));
+        self.queued += 1;
+        Ok(())
+    }
+
+    /// Hides `oid`, and the ancestors of it already taken from the queue.
+    fn mark_hidden(&mut self, oid: Oid<Commit>) {
+        let mut stack = vec![oid];
+        while let Some(oid) = stack.pop() {
+            let node = self.nodes.entry(oid).or_default();
+            if node.hidden {
+                continue;
+            }
+            node.hidden = true;
+            stack.extend(node.parents.iter().flatten());
+        }
+    }
+
+    /// Takes the next commit from the queue and queues its parents, hiding
+    /// them if it's hidden. Returns it and whether it's hidden.
+    fn next_queued(&mut self) -> Result<Option<(Commit, bool)>, db::LoadError<Commit>> {
+        let commit = match self.queue.pop() {
+            Some(queued) => queued.commit,
+            None => return Ok(None),
+        };
+        let parents = self.parents(&commit).to_vec();
+        let node = self.nodes.entry(commit.oid).or_default();
+        node.parents = Some(parents.clone());
+        let hidden = node.hidden;
+
+        for parent in parents {
+            if hidden {
+                let is_new = !self.nodes.contains_key(&parent);
+                self.mark_hidden(parent);
+                if is_new {
+                    self.enqueue(parent)?;
+                }
+            } else {
+                self.push(parent)?;
+            }
+        }
+        Ok(Some((commit, hidden)))
+    }
+
+    fn is_hidden(&self, oid: Oid<Commit>) -> bool {
+        self.nodes.get(&oid).map_or(false, |node| node.hidden)
+    }
+
+    /// Walks until only hidden commits are left, and returns the rest in the
+    /// order to yield them.
+    fn walk_all(&mut self) -> Result<Vec<Commit>, db::LoadError<Commit>> {
+        let mut commits = Vec::new();
+        let mut slop = SLOP;
+        while let Some((commit, hidden)) = self.next_queued()? {
+            if !hidden {
+                commits.push(commit);
+            }
+            if self.limited && self.queue.iter().all(|q| self.is_hidden(q.commit.oid)) {
+                slop -= 1;
+                if slop == 0 {
+                    break;
+                }
+            } else {
+                slop = SLOP;
+            }
+        }
+        // Commits found to be hidden after being reached
+        commits.retain(|commit| !self.is_hidden(commit.oid));
+
+        let mut commits = match self.order {
+            Order::Walk => commits,
+            Order::Date | Order::Topo => self.sort_topologically(commits),
+        };
+        if self.reverse {
+            commits.reverse();
+        }
+        Ok(commits)
+    }
+
+    /// Like git, the commits without children among `commits` start off in
+    /// the order they were reached, and a commit is ready once all of its
+    /// children have been taken. [`Order::Date`] takes the newest ready
+    /// commit, while [`Order::Topo`] takes the one that became ready last,
+    /// following a line of history until it joins another. Like git, that's
+    /// so for all parents, even with [`Self::with_first_parent`].
+    fn sort_topologically(&self, commits: Vec<Commit>) -> Vec<Commit> {
+        let mut children = HashMap::new();
+        for commit in &commits {
+            children.entry(commit.oid).or_insert(0);
+        }
+        for commit in &commits {
+            for parent in &commit.parents {
+                if let Some(count) = children.get_mut(parent) {
+                    *count += 1;
+                }
+            }
+        }
+
+        let mut by_oid = HashMap::new();
+        let mut ready = Ready::new(self.order);
+        for (order, commit) in commits.into_iter().enumerate() {
+            if children[&commit.oid] == 0 {
+                ready.push(commit, order);
+            } else {
+                by_oid.insert(commit.oid, commit);
+            }
+        }
+        ready.start();
+
+        let mut sorted = Vec::with_capacity(children.len());
+        let mut order = children.len();
+        while let Some(commit) = ready.pop() {
+            for parent in &commit.parents {
+                let count = match children.get_mut(parent) {
+                    Some(count) => count,
+                    None => continue,
+                };
+                *count -= 1;
+                if *count == 0 {
+                    let parent = by_oid.remove(parent).expect("Each commit is ready once");
+                    ready.push(parent, order);
+                    order += 1;
+                }
+            }
+            sorted.push(commit);
+        }
+        sorted
+    }
+
+    fn next_commit(&mut self) -> Result<Option<Commit>, db::LoadError<Commit>> {
+        let streams = !self.limited && self.order == Order::Walk && !self.reverse;
+        if streams {
+            return Ok(self.next_queued()?.map(|(commit, _)| commit));
+        }
+        if self.sorted.is_none() {
+            // Nothing more is yielded after an error
+            self.sorted = Some(Vec::new().into_iter());
+            self.sorted = Some(self.walk_all()?.into_iter());
+        }
+        Ok(self.sorted.as_mut().and_then(Iterator::next))
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = Result<Commit, db::LoadError<Commit>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_commit().transpose()
+    }
+}
+
+/// The commits ready to be taken while sorting topologically.
+enum Ready {
+    Date(BinaryHeap<Queued>),
+    Topo(Vec<Commit>),
+}
+
+impl Ready {
+    fn new(order: Order) -> Self {
+        match order {
+            Order::Topo => Self::Topo(Vec::new()),
+            Order::Walk | Order::Date => Self::Date(BinaryHeap::new()),
+        }
+    }
+
+    fn push(&mut self, commit: Commit, order: usize) {
+        match self {
+            Self::Date(queue) => queue.push(Queued::new(commit, order)),
+            Self::Topo(stack) => stack.push(commit),
+        }
+    }
+
+    /// Called once the commits without children are pushed, so the first of
+    /// them is taken first.
+    fn start(&mut self) {
+        if let Self::Topo(stack) = self {
+            stack.reverse();
+        }
+    }
+
+    fn pop(&mut self) -> Option<Commit> {
+        match self {
+            Self::Date(queue) => queue.pop().map(|queued| queued.commit),
+            Self::Topo(stack) => stack.pop(),
+        }
+    }
+}
+
+/// The best common ancestors of `a` and `b`, which aren't ancestors of
+/// other common ancestors, like `git merge-base --all`. Skewed dates can
+/// leave in one that is.
+pub fn merge_bases(
+    db: &mut Db,
+    a: Oid<Commit>,
+    b: Oid<Commit>,
+) -> Result<Vec<Oid<Commit>>, db::LoadError<Commit>> {
+    const A: u8 = 1;
+    const B: u8 = 2;
+    const BOTH: u8 = A | B;
+    const STALE: u8 = 4;
+
+    if a == b {
+        return Ok(vec![a]);
+    }
+    let mut flags = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    flags.insert(a, A);
+    flags.insert(b, B);
+    queue.push(Queued::new(db.load(a)?, 0));
+    queue.push(Queued::new(db.load(b)?, 1));
+    let mut queued = 2;
+
+    let mut bases = Vec::new();
+    // Until everything left reaches a common ancestor already found
+    while queue.iter().any(|q| flags[&q.commit.oid] & STALE == 0) {
+        let commit = queue.pop().expect("Not empty").commit;
+        let mut flag = flags[&commit.oid];
+        if flag & BOTH == BOTH {
+            if flag & STALE == 0 {
+                bases.push(commit.oid);
+            }
+            flag |= STALE;
+            flags.insert(commit.oid, flag);
+        }
+        for &parent in &commit.parents {
+            let parent_flag = flags.entry(parent).or_insert(0);
+            if *parent_flag & flag == flag {
+                continue;
+            }
+            *parent_flag |= flag;
+            queue.push(Queued::new(db.load(parent)?, queued));
+            queued += 1;
+        }
+    }
+    Ok(bases)
+}
+
+impl Queued {
+    fn new(commit: Commit, order: usize) -> Self {
+        Self {
+            time: commit.committer.time().timestamp(),
+            order,
+            commit,
+        }
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.time
+            .cmp(&other.time)
+            .then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
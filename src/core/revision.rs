@@ -0,0 +1,272 @@
+//! Naming commits, like `git rev-parse` and the ranges `git rev-list` takes,
+//! see `gitrevisions(7)`.
+//!
+//! A name is `HEAD` or `@`, a ref like `main`, `refs/heads/main` or
+//! `tags/v1`, or a full or abbreviated object id, followed by any number of
+//! `^` or `^<n>` for the first or `n`th parent and `~<n>` for the `n`th
+//! first parent back. Tags name the commits they tag.
+//!
+//! Objects of other types can be named too, see [`resolve_object`].
+
+use bstr::ByteSlice;
+use std::io;
+
+use crate::core::{
+    db::{self, tree::Node, Commit, Object, Tag, Tree, UntypedOid},
+    refs, Db, Oid, Refs, RevWalk,
+};
+
+/// The fewest hex digits an abbreviated object id can have.
+const MIN_ABBREV: usize = 4;
+
+/// Where refs are looked for, in order, with the name in place of `{}`.
+const REF_RULES: &[&str] = &[
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+];
+
+/// Which commits a revision given to `log` or `rev-list` includes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Revision {
+    /// `name`: the commit and its ancestors
+    Include(String),
+    /// `^name`: none of the commit's ancestors, even if others include them
+    Exclude(String),
+    /// `from..to`: the ancestors of `to` that aren't ancestors of `from`
+    Range { from: String, to: String },
+    /// `left...right`: the ancestors of either that aren't ancestors of both
+    Symmetric { left: String, right: String },
+}
+
+impl Revision {
+    /// Like git, a missing side of a range is `HEAD`.
+    pub fn parse(revision: &str) -> Self {
+        let or_head = |name: &str| {
+            if name.is_empty() {
+                "HEAD".to_owned()
+            } else {
+                name.to_owned()
+            }
+        };
+        if let Some(name) = revision.strip_prefix('^') {
+            Self::Exclude(name.to_owned())
+        } else if let Some(i) = revision.find("...") {
+            Self::Symmetric {
+                left: or_head(&revision[..i]),
+                right: or_head(&revision[i + 3..]),
+            }
+        } else if let Some(i) = revision.find("..") {
+            Self::Range {
+                from: or_head(&revision[..i]),
+                to: or_head(&revision[i + 2..]),
+            }
+        } else {
+            Self::Include(revision.to_owned())
+        }
+    }
+
+    /// The names of commits in this.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Self::Include(name) | Self::Exclude(name) => vec![name],
+            Self::Range { from, to } => vec![from, to],
+            Self::Symmetric { left, right } => vec![left, right],
+        }
+    }
+}
+
+/// Adds the commits `revisions` include to `walk`, and hides those they
+/// exclude.
+pub fn push_all(
+    walk: &mut RevWalk,
+    refs: &Refs,
+    db: &mut Db,
+    revisions: &[Revision],
+) -> Result<(), ResolveError> {
+    for revision in revisions {
+        match revision {
+            Revision::Include(name) => walk.push(resolve(refs, db, name)?)?,
+            Revision::Exclude(name) => walk.hide(resolve(refs, db, name)?)?,
+            Revision::Range { from, to } => {
+                let (from, to) = (resolve(refs, db, from)?, resolve(refs, db, to)?);
+                walk.hide(from)?;
+                walk.push(to)?;
+            }
+            Revision::Symmetric { left, right } => {
+                let (left, right) = (resolve(refs, db, left)?, resolve(refs, db, right)?);
+                for base in crate::core::rev_walk::merge_bases(db, left, right)? {
+                    walk.hide(base)?;
+                }
+                walk.push(left)?;
+                walk.push(right)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The commit `name` refers to.
+pub fn resolve(refs: &Refs, db: &mut Db, name: &str) -> Result<Oid<Commit>, ResolveError> {
+    let unknown = || ResolveError::Unknown(name.to_owned());
+    let base_len = name.find(&['^', '~'][..]).unwrap_or(name.len());
+    let (base, mut suffix) = name.split_at(base_len);
+    let base = resolve_base(refs, db, base)?.ok_or_else(unknown)?;
+    let mut oid = peel(db, name, base)?;
+
+    while let Some(op) = suffix.chars().next() {
+        suffix = &suffix[1..];
+        let digits = suffix
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(suffix.len());
+        let count = if digits == 0 {
+            1
+        } else {
+            suffix[..digits].parse().map_err(|_| unknown())?
+        };
+        suffix = &suffix[digits..];
+
+        let commit = db.load(oid)?;
+        oid = match op {
+            // `^0` is the commit itself
+            '^' if count == 0 => oid,
+            '^' => *commit.parents.get(count - 1).ok_or_else(unknown)?,
+            _ => {
+                let mut commit = commit;
+                for _ in 0..count {
+                    let parent = commit.parent().ok_or_else(unknown)?;
+                    commit = db.load(parent)?;
+                }
+                commit.oid
+            }
+        };
+    }
+    Ok(oid)
+}
+
+/// The object `name` refers to, which unlike with [`resolve`] can be of any
+/// type: tags aren't followed, and `<revision>:<path>` is the file or
+/// directory at the path in the commit's tree, or the tree itself if the
+/// path is empty.
+pub fn resolve_object(refs: &Refs, db: &mut Db, name: &str) -> Result<UntypedOid, ResolveError> {
+    if let Some((revision, path)) = name.split_once(':') {
+        let commit = resolve(refs, db, revision)?;
+        let mut oid = db.load(commit)?.tree.into_untyped();
+        let not_in_tree = || ResolveError::NotInTree {
+            revision: revision.to_owned(),
+            path: path.to_owned(),
+        };
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            let tree = db.load(oid.to_typed::<Tree>()).map_err(|_| not_in_tree())?;
+            let node = tree.direct_child(component.as_bytes().as_bstr());
+            oid = node.map(Node::untyped_oid).ok_or_else(not_in_tree)?;
+        }
+        return Ok(oid);
+    }
+    if name.contains(&['^', '~'][..]) {
+        return Ok(resolve(refs, db, name)?.into_untyped());
+    }
+    resolve_base(refs, db, name)?.ok_or_else(|| ResolveError::Unknown(name.to_owned()))
+}
+
+/// Follows tags from `oid` to the commit they tag.
+fn peel(db: &mut Db, name: &str, mut oid: UntypedOid) -> Result<Oid<Commit>, ResolveError> {
+    loop {
+        let o_type = db.load_type(oid)?;
+        match o_type.as_slice() {
+            Commit::TYPE => return Ok(oid.to_typed()),
+            Tag::TYPE => oid = db.load(oid.to_typed::<Tag>())?.object,
+            _ => return Err(ResolveError::NotACommit(name.to_owned())),
+        }
+    }
+}
+
+fn resolve_base(refs: &Refs, db: &Db, name: &str) -> Result<Option<UntypedOid>, ResolveError> {
+    if name == "HEAD" || name == "@" {
+        return Ok(refs.head()?.map(Oid::into_untyped));
+    }
+    // Like git, only names like `HEAD` are looked for outside `refs/`
+    let is_special = !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+    if is_special || name.starts_with("refs/") {
+        if let Some(oid) = refs.read_ref(name.as_bytes().as_bstr())? {
+            return Ok(Some(oid.into_untyped()));
+        }
+    }
+    for rule in REF_RULES {
+        let ref_name = rule.replace("{}", name);
+        if let Some(oid) = refs.read_ref(ref_name.as_bytes().as_bstr())? {
+            return Ok(Some(oid.into_untyped()));
+        }
+    }
+
+    let is_hex = name
+        .chars()
+        .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c));
+    if !is_hex || name.len() < MIN_ABBREV {
+        return Ok(None);
+    }
+    if let Ok(oid) = UntypedOid::parse(name) {
+        return Ok(Some(oid));
+    }
+    let found = db
+        .find_prefix(name)
+        .map_err(|e| ResolveError::FindObjects(name.to_owned(), e))?;
+    match found.as_slice() {
+        [] => Ok(None),
+        [oid] => Ok(Some(*oid)),
+        _ => Err(ResolveError::Ambiguous(name.to_owned())),
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum ResolveError {
+    /// Unknown revision {0:?}
+    Unknown(String),
+    /// Short object id {0} is ambiguous
+    Ambiguous(String),
+    /// {0:?} is not a commit
+    NotACommit(String),
+    /// Path {path:?} does not exist in {revision:?}
+    NotInTree { revision: String, path: String },
+    /// Failed to look for objects starting with {0}
+    FindObjects(String, #[source] io::Error),
+    /// Failed to read ref
+    ReadRef(#[from] refs::ReadError),
+    /// Failed to load commit
+    LoadCommit(#[from] db::LoadError<Commit>),
+    /// Failed to load tag
+    LoadTag(#[from] db::LoadError<Tag>),
+    /// Failed to load object
+    LoadObject(#[from] db::LoadAnyError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_revisions() {
+        init();
+        let name = |name: &str| name.to_owned();
+
+        assert_eq!(Revision::Include(name("main~2")), Revision::parse("main~2"));
+        assert_eq!(Revision::Exclude(name("v1")), Revision::parse("^v1"));
+        assert_eq!(
+            Revision::Range {
+                from: name("a"),
+                to: name("HEAD")
+            },
+            Revision::parse("a..")
+        );
+        assert_eq!(
+            Revision::Symmetric {
+                left: name("HEAD^"),
+                right: name("b")
+            },
+            Revision::parse("HEAD^...b")
+        );
+    }
+}
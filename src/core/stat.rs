@@ -24,6 +24,8 @@ pub struct Stat {
 pub enum Mode {
     Regular,
     Executable,
+    /// A submodule, whose oid is that of a commit in another repository
+    Gitlink,
 }
 
 impl Stat {
@@ -84,14 +86,20 @@ impl Stat {
 impl Mode {
     const EXECUTABLE: u32 = 0o10_07_55;
     const REGULAR: u32 = 0o10_06_44;
+    const GITLINK: u32 = 0o16_00_00;
+
+    const TYPE_MASK: u32 = 0o17_00_00;
+    const DIRECTORY: u32 = 0o04_00_00;
 
     const REGULAR_S: &'static [u8] = b"100644";
     const EXECUTABLE_S: &'static [u8] = b"100755";
+    const GITLINK_S: &'static [u8] = b"160000";
 
     pub fn as_base8(self) -> &'static BStr {
         match self {
             Self::Regular => Self::REGULAR_S.as_bstr(),
             Self::Executable => Self::EXECUTABLE_S.as_bstr(),
+            Self::Gitlink => Self::GITLINK_S.as_bstr(),
         }
     }
 
@@ -99,10 +107,17 @@ impl Mode {
         match self {
             Self::Regular => Self::REGULAR,
             Self::Executable => Self::EXECUTABLE,
+            Self::Gitlink => Self::GITLINK,
         }
     }
 
+    /// Like git, a directory is taken to be a submodule, as that's the only
+    /// kind of directory that gets an entry of its own.
     pub fn from_u32(val: u32) -> Self {
+        match val & Self::TYPE_MASK {
+            Self::GITLINK | Self::DIRECTORY => return Self::Gitlink,
+            _ => {}
+        }
         let is_executable = val & 0o111 != 0;
         if is_executable {
             Self::Executable
@@ -116,6 +131,7 @@ impl Mode {
         match bytes.as_bytes() {
             Self::REGULAR_S => Self::Regular,
             Self::EXECUTABLE_S => Self::Executable,
+            Self::GITLINK_S => Self::Gitlink,
             _ => {
                 warn!("Assuming unrecognized mode {} to be regular", bytes);
                 Self::Regular
@@ -0,0 +1,467 @@
+//! Status formats meant for scripts and editors rather than people, see the
+//! "Output" section of `git-status(1)`.
+
+use bstr::{BString, ByteSlice};
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use super::{FileStatus, Status, Version};
+use crate::core::{db::Commit, index::ConflictKind, stat::Mode, Oid, WsPath};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    /// Like [`Self::PorcelainV1`], but paths are relative to the current
+    /// directory
+    Short,
+    PorcelainV1,
+    /// Also shows the modes and object ids of each version
+    PorcelainV2,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FormatOptions {
+    pub format: Format,
+    /// Start with a header describing HEAD
+    pub branch: bool,
+    /// End entries with NUL rather than a newline, and don't quote paths
+    pub nul_terminated: bool,
+}
+
+/// Parses the version given to `--porcelain`.
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" | "1" => Ok(Self::PorcelainV1),
+            "v2" | "2" => Ok(Self::PorcelainV2),
+            _ => Err(ParseFormatError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Unsupported porcelain version {0:?}, expected v1 or v2
+pub struct ParseFormatError(String);
+
+/// Writes changed paths in path order, then untracked and then ignored ones,
+/// as git does. `display_path` gives the path to show for short output.
+pub fn write<'s, W, F>(
+    out: &mut W,
+    statuses: impl IntoIterator<Item = &'s FileStatus>,
+    head: Option<Oid<Commit>>,
+    opts: FormatOptions,
+    display_path: F,
+) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(&WsPath) -> BString,
+{
+    let end = if opts.nul_terminated { b'\0' } else { b'\n' };
+
+    if opts.branch {
+        write_branch_header(out, head, opts.format, end)?;
+    }
+
+    let mut changed = Vec::new();
+    let mut untracked = Vec::new();
+    let mut ignored = Vec::new();
+    for status in statuses {
+        match (&status.index, &status.workspace) {
+            (Status::Unmodified, Status::Unmodified) => {}
+            (_, Status::Untracked) => untracked.push(status),
+            (_, Status::Ignored) => ignored.push(status),
+            _ => changed.push(status),
+        }
+    }
+
+    let format_path = |path: &WsPath, is_dir: bool| {
+        let mut path = match opts.format {
+            Format::Short => display_path(path),
+            Format::PorcelainV1 | Format::PorcelainV2 => path.to_bstring(),
+        };
+        if is_dir {
+            path.push(b'/');
+        }
+        if opts.nul_terminated {
+            path
+        } else {
+            // Spaces would be ambiguous with the arrow between renamed paths
+            let quote_space = opts.format != Format::PorcelainV2;
+            BString::from(quote_path(&path, quote_space).into_owned())
+        }
+    };
+
+    for status in changed.into_iter().chain(untracked).chain(ignored) {
+        let path = format_path(&status.path, status.is_dir);
+        let from = match &status.index {
+            Status::Renamed { from, .. } | Status::Copied { from, .. } => {
+                Some(format_path(from, false))
+            }
+            _ => None,
+        };
+
+        match opts.format {
+            Format::Short | Format::PorcelainV1 => {
+                out.write_all(&xy(status, b' '))?;
+                out.write_all(b" ")?;
+                match from {
+                    // Without NUL termination the original path comes first,
+                    // like an arrow from it to the new path
+                    Some(from) if !opts.nul_terminated => {
+                        out.write_all(&from)?;
+                        out.write_all(b" -> ")?;
+                        out.write_all(&path)?;
+                    }
+                    Some(from) => {
+                        out.write_all(&path)?;
+                        out.write_all(&[end])?;
+                        out.write_all(&from)?;
+                    }
+                    None => out.write_all(&path)?,
+                }
+            }
+            Format::PorcelainV2 => {
+                write_v2_fields(out, status)?;
+                out.write_all(&path)?;
+                if let Some(from) = from {
+                    out.write_all(if opts.nul_terminated { b"\0" } else { b"\t" })?;
+                    out.write_all(&from)?;
+                }
+            }
+        }
+        out.write_all(&[end])?;
+    }
+
+    Ok(())
+}
+
+fn write_branch_header<W: Write>(
+    out: &mut W,
+    head: Option<Oid<Commit>>,
+    format: Format,
+    end: u8,
+) -> io::Result<()> {
+    // HEAD always refers directly to a commit, there are no branches yet
+    match format {
+        Format::Short | Format::PorcelainV1 => {
+            if head.is_some() {
+                out.write_all(b"## HEAD (no branch)")?;
+            } else {
+                out.write_all(b"## No commits yet on HEAD")?;
+            }
+            out.write_all(&[end])?;
+        }
+        Format::PorcelainV2 => {
+            let oid = head.map_or_else(|| "(initial)".to_owned(), |oid| oid.to_hex());
+            write!(out, "# branch.oid {}", oid)?;
+            out.write_all(&[end])?;
+            out.write_all(b"# branch.head (detached)")?;
+            out.write_all(&[end])?;
+        }
+    }
+    Ok(())
+}
+
+fn write_v2_fields<W: Write>(out: &mut W, status: &FileStatus) -> io::Result<()> {
+    match status.workspace {
+        Status::Untracked => return out.write_all(b"? "),
+        Status::Ignored => return out.write_all(b"! "),
+        _ => {}
+    }
+
+    let mode =
+        |mode: Option<Mode>| mode.map_or_else(|| "000000".to_owned(), |m| m.as_base8().to_string());
+    let version_mode = |version: Option<Version>| mode(version.map(|v| v.mode));
+    let oid = |version: Option<Version>| version.map_or_else(|| "0".repeat(40), |v| v.oid.to_hex());
+
+    let versions = &status.versions;
+    let xy = xy(status, b'.');
+    let xy = xy.as_bstr();
+    // Only whether a submodule's commit changed is known, not what's
+    // changed inside it
+    let is_gitlink = [versions.head, versions.merged, versions.ours]
+        .iter()
+        .any(|v| v.map_or(false, |v| v.mode == Mode::Gitlink));
+    let sub = match (is_gitlink, &status.workspace) {
+        (false, _) => "N...",
+        (true, Status::Modified) => "SC..",
+        (true, _) => "S...",
+    };
+    match &status.index {
+        Status::Unmerged(_) => write!(
+            out,
+            "u {} {} {} {} {} {} {} {} {} ",
+            xy,
+            sub,
+            version_mode(versions.base),
+            version_mode(versions.ours),
+            version_mode(versions.theirs),
+            mode(versions.workspace_mode),
+            oid(versions.base),
+            oid(versions.ours),
+            oid(versions.theirs),
+        ),
+        Status::Renamed { score, .. } | Status::Copied { score, .. } => write!(
+            out,
+            "2 {} {} {} {} {} {} {} {}{} ",
+            xy,
+            sub,
+            version_mode(versions.head),
+            version_mode(versions.merged),
+            mode(versions.workspace_mode),
+            oid(versions.head),
+            oid(versions.merged),
+            xy[..1].as_bstr(),
+            score,
+        ),
+        _ => write!(
+            out,
+            "1 {} {} {} {} {} {} {} ",
+            xy,
+            sub,
+            version_mode(versions.head),
+            version_mode(versions.merged),
+            mode(versions.workspace_mode),
+            oid(versions.head),
+            oid(versions.merged),
+        ),
+    }
+}
+
+/// The two letter code for the index and workspace status. `unmodified` is
+/// the letter for no change, which differs between formats.
+fn xy(status: &FileStatus, unmodified: u8) -> [u8; 2] {
+    if let Status::Unmerged(kind) = status.index {
+        return match kind {
+            ConflictKind::BothDeleted => *b"DD",
+            ConflictKind::AddedByUs => *b"AU",
+            ConflictKind::DeletedByThem => *b"UD",
+            ConflictKind::AddedByThem => *b"UA",
+            ConflictKind::DeletedByUs => *b"DU",
+            ConflictKind::BothAdded => *b"AA",
+            ConflictKind::BothModified => *b"UU",
+        };
+    }
+
+    let letter = |status: &Status| match status {
+        Status::Unmodified => unmodified,
+        Status::Modified => b'M',
+        Status::Added => b'A',
+        Status::Deleted => b'D',
+        Status::Untracked => b'?',
+        Status::Ignored => b'!',
+        Status::Unmerged(_) => b'U',
+        Status::Renamed { .. } => b'R',
+        Status::Copied { .. } => b'C',
+    };
+
+    // A path deleted from the index has nothing left in the workspace to
+    // compare against
+    let workspace = if status.index == Status::Deleted {
+        unmodified
+    } else {
+        letter(&status.workspace)
+    };
+    [letter(&status.index), workspace]
+}
+
+/// Quotes paths with unusual characters like git does by default, see
+/// `core.quotePath` in `git-config(1)`. With `quote_space`, paths containing
+/// spaces are quoted too, without escaping the spaces.
+fn quote_path(path: &[u8], quote_space: bool) -> Cow<[u8]> {
+    let needs_quoting = |b: u8| b < 0x20 || b == b'"' || b == b'\\' || b >= 0x7f;
+    if !path
+        .iter()
+        .any(|&b| needs_quoting(b) || (quote_space && b == b' '))
+    {
+        return Cow::Borrowed(path);
+    }
+
+    let mut quoted = vec![b'"'];
+    for &b in path {
+        match b {
+            0x07 => quoted.extend_from_slice(br"\a"),
+            0x08 => quoted.extend_from_slice(br"\b"),
+            b'\t' => quoted.extend_from_slice(br"\t"),
+            b'\n' => quoted.extend_from_slice(br"\n"),
+            0x0b => quoted.extend_from_slice(br"\v"),
+            0x0c => quoted.extend_from_slice(br"\f"),
+            b'\r' => quoted.extend_from_slice(br"\r"),
+            b'"' => quoted.extend_from_slice(br#"\""#),
+            b'\\' => quoted.extend_from_slice(br"\\"),
+            b if needs_quoting(b) => quoted.extend_from_slice(format!("\\{:03o}", b).as_bytes()),
+            b => quoted.push(b),
+        }
+    }
+    quoted.push(b'"');
+    Cow::Owned(quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        db::Blob,
+        status::{Version, Versions},
+    };
+    use crate::test_support::init;
+    use insta::assert_snapshot;
+
+    fn oid(hex: &str) -> Oid<Blob> {
+        Oid::parse(hex.repeat(40 / hex.len())).unwrap()
+    }
+
+    fn version(mode: Mode, hex: &str) -> Version {
+        Version {
+            mode,
+            oid: oid(hex),
+        }
+    }
+
+    fn status(path: &str, index: Status, workspace: Status, versions: Versions) -> FileStatus {
+        FileStatus {
+            path: WsPath::new_unchecked(path),
+            index,
+            workspace,
+            is_dir: false,
+            versions,
+        }
+    }
+
+    fn statuses() -> Vec<FileStatus> {
+        let tracked = |hex| Versions {
+            head: Some(version(Mode::Regular, hex)),
+            merged: Some(version(Mode::Regular, hex)),
+            workspace_mode: Some(Mode::Regular),
+            ..Versions::default()
+        };
+        vec![
+            status(
+                "a.txt",
+                Status::Unmodified,
+                Status::Unmodified,
+                tracked("a"),
+            ),
+            status(
+                "added.txt",
+                Status::Added,
+                Status::Modified,
+                Versions {
+                    merged: Some(version(Mode::Regular, "b")),
+                    workspace_mode: Some(Mode::Regular),
+                    ..Versions::default()
+                },
+            ),
+            status(
+                "conflict.txt",
+                Status::Unmerged(ConflictKind::BothModified),
+                Status::Unmerged(ConflictKind::BothModified),
+                Versions {
+                    head: Some(version(Mode::Regular, "c")),
+                    base: Some(version(Mode::Regular, "c")),
+                    ours: Some(version(Mode::Regular, "d")),
+                    theirs: Some(version(Mode::Executable, "e")),
+                    workspace_mode: Some(Mode::Regular),
+                    ..Versions::default()
+                },
+            ),
+            status(
+                "removed.txt",
+                Status::Deleted,
+                Status::Deleted,
+                Versions {
+                    head: Some(version(Mode::Regular, "f")),
+                    ..Versions::default()
+                },
+            ),
+            status(
+                "renamed.txt",
+                Status::Renamed {
+                    from: WsPath::new_unchecked("old name.txt"),
+                    score: 87,
+                },
+                Status::Unmodified,
+                Versions {
+                    head: Some(version(Mode::Regular, "9")),
+                    ..tracked("8")
+                },
+            ),
+            status(
+                "run.sh",
+                Status::Unmodified,
+                Status::Modified,
+                Versions {
+                    workspace_mode: Some(Mode::Executable),
+                    ..tracked("1")
+                },
+            ),
+            FileStatus {
+                is_dir: true,
+                ..status(
+                    "new",
+                    Status::Untracked,
+                    Status::Untracked,
+                    Versions::default(),
+                )
+            },
+            status(
+                "sp ace\t\"q\".txt",
+                Status::Untracked,
+                Status::Untracked,
+                Versions::default(),
+            ),
+            FileStatus {
+                is_dir: true,
+                ..status(
+                    "target",
+                    Status::Ignored,
+                    Status::Ignored,
+                    Versions::default(),
+                )
+            },
+        ]
+    }
+
+    fn format(format: Format, branch: bool, nul_terminated: bool) -> String {
+        let opts = FormatOptions {
+            format,
+            branch,
+            nul_terminated,
+        };
+        let head = Some(Oid::parse("0123456789".repeat(4)).unwrap());
+        let mut out = Vec::new();
+        write(&mut out, &statuses(), head, opts, |path| {
+            format!("../{}", path).into()
+        })
+        .unwrap();
+        out.to_str_lossy().replace('\0', "\\0\n")
+    }
+
+    #[test]
+    fn short() {
+        init();
+        assert_snapshot!(format(Format::Short, true, false));
+    }
+
+    #[test]
+    fn porcelain_v1() {
+        init();
+        assert_snapshot!(format(Format::PorcelainV1, false, false));
+    }
+
+    #[test]
+    fn porcelain_v1_nul_terminated() {
+        init();
+        assert_snapshot!(format(Format::PorcelainV1, false, true));
+    }
+
+    #[test]
+    fn porcelain_v2() {
+        init();
+        assert_snapshot!(format(Format::PorcelainV2, true, false));
+    }
+}
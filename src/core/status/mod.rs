@@ -0,0 +1,161 @@
+pub mod format;
+pub mod renames;
+
+use std::str::FromStr;
+
+use crate::core::{
+    config::{Config, InvalidValueError},
+    db::Blob,
+    index::ConflictKind,
+    stat::Mode,
+    Oid, WsPath,
+};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[allow(clippy::module_name_repetitions)]
+pub struct FileStatus {
+    pub path: WsPath,
+    pub index: Status,
+    pub workspace: Status,
+    /// Set for an untracked or ignored directory reported as a whole instead
+    /// of the files in it.
+    pub is_dir: bool,
+    pub versions: Versions,
+}
+
+/// What HEAD, the index and the workspace have for a path, for formats that
+/// show modes and object ids. Empty for untracked and ignored paths.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Versions {
+    pub head: Option<Version>,
+    /// Intent-to-add entries have no version yet
+    pub merged: Option<Version>,
+    pub base: Option<Version>,
+    pub ours: Option<Version>,
+    pub theirs: Option<Version>,
+    pub workspace_mode: Option<Mode>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Version {
+    pub mode: Mode,
+    pub oid: Oid<Blob>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Status {
+    Untracked,
+    Modified,
+    Unmodified,
+    Deleted,
+    Added,
+    /// Only reported with [`StatusOptions::ignored`]
+    Ignored,
+    /// The path has a conflict that must be resolved before committing.
+    Unmerged(ConflictKind),
+    /// Added in the index with the same or similar contents as `from` in
+    /// HEAD, which was deleted. `score` is the similarity in percent.
+    Renamed {
+        from: WsPath,
+        score: u8,
+    },
+    /// Like [`Self::Renamed`], but `from` is still there, see
+    /// [`Renames::Copies`].
+    Copied {
+        from: WsPath,
+        score: u8,
+    },
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Untracked => "untracked",
+            Status::Modified => "modified",
+            Status::Unmodified => "unmodified",
+            Status::Deleted => "deleted",
+            Status::Added => "added",
+            Status::Ignored => "ignored",
+            Status::Unmerged(kind) => kind.name(),
+            Status::Renamed { .. } => "renamed",
+            Status::Copied { .. } => "copied",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct StatusOptions {
+    pub untracked: UntrackedFiles,
+    /// Report ignored files and directories rather than skipping them
+    pub ignored: bool,
+    pub renames: Renames,
+}
+
+/// Which untracked files to report, as with git's `--untracked-files`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UntrackedFiles {
+    /// None, without searching untracked directories
+    No,
+    /// Directories without tracked files are reported once as a whole
+    Normal,
+    /// Every file, however deep
+    All,
+}
+
+impl Default for UntrackedFiles {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl FromStr for UntrackedFiles {
+    type Err = ParseUntrackedFilesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no" | "false" => Ok(Self::No),
+            "normal" | "true" => Ok(Self::Normal),
+            "all" => Ok(Self::All),
+            _ => Err(ParseUntrackedFilesError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Invalid untracked files mode {0:?}, expected no, normal or all
+pub struct ParseUntrackedFilesError(String);
+
+/// Whether to pair up deleted and added paths, as with git's `status.renames`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Renames {
+    No,
+    /// Report [`Status::Renamed`] for paths deleted and added with similar
+    /// contents
+    Renames,
+    /// Also report [`Status::Copied`] for added paths similar to a modified
+    /// one
+    Copies,
+}
+
+impl Default for Renames {
+    fn default() -> Self {
+        Self::Renames
+    }
+}
+
+impl Renames {
+    /// Reads `status.renames`, falling back to `diff.renames`.
+    pub fn from_config(config: &Config) -> Result<Self, InvalidValueError> {
+        for key in &["status.renames", "diff.renames"] {
+            let value = config.get(key).map(|v| v.to_ascii_lowercase());
+            match value.as_deref() {
+                Some(b"copies" | b"copy") => return Ok(Self::Copies),
+                Some(_) if config.get_bool(key)? == Some(true) => return Ok(Self::Renames),
+                Some(_) => return Ok(Self::No),
+                None => {}
+            }
+        }
+        Ok(Self::default())
+    }
+}
@@ -0,0 +1,332 @@
+//! Pairs up paths deleted from HEAD with paths added to the index when their
+//! contents are the same or similar enough, like git's `diffcore-rename`.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use super::{FileStatus, Renames, Status, Version, Versions};
+use crate::core::{
+    db::{self, Blob},
+    stat::Mode,
+    Db, WsPath,
+};
+
+/// The similarity in percent above which an added path is taken to be
+/// renamed or copied from a deleted one, git's default.
+pub const DEFAULT_THRESHOLD: u8 = 50;
+
+/// Chunks end at a newline or after this many bytes, so binary files are
+/// compared too.
+const MAX_CHUNK_LEN: usize = 64;
+
+struct Source {
+    path: WsPath,
+    version: Version,
+    /// Deleted paths become renames the first time they're used, while
+    /// modified paths can only be copied from.
+    deleted: bool,
+    renamed: bool,
+}
+
+struct Dest {
+    path: WsPath,
+    version: Version,
+    found: Option<(usize, u8)>,
+}
+
+/// Replaces deleted and added statuses that turn out to be renames with a
+/// single [`Status::Renamed`] for the new path, and with [`Renames::Copies`]
+/// also reports [`Status::Copied`] for added paths similar to a deleted or
+/// modified one. Empty files are never paired up, as every empty file would
+/// match every other, and nor are submodules.
+pub fn detect(
+    db: &mut Db,
+    statuses: &mut BTreeMap<WsPath, FileStatus>,
+    renames: Renames,
+) -> Result<(), db::LoadError<Blob>> {
+    if renames == Renames::No {
+        return Ok(());
+    }
+
+    let empty = Blob::empty_oid();
+    let mut sources = Vec::new();
+    let mut dests = Vec::new();
+    for status in statuses.values() {
+        let (head, merged) = (status.versions.head, status.versions.merged);
+        // Submodules are compared by commit, never by contents
+        let is_gitlink =
+            |version: Option<Version>| version.map_or(false, |v| v.mode == Mode::Gitlink);
+        if is_gitlink(head) || is_gitlink(merged) {
+            continue;
+        }
+        match (&status.index, head, merged) {
+            (Status::Deleted, Some(version), _) if version.oid != empty => {
+                sources.push(Source {
+                    path: status.path.clone(),
+                    version,
+                    deleted: true,
+                    renamed: false,
+                });
+            }
+            (Status::Modified, Some(version), _)
+                if renames == Renames::Copies && version.oid != empty =>
+            {
+                sources.push(Source {
+                    path: status.path.clone(),
+                    version,
+                    deleted: false,
+                    renamed: false,
+                });
+            }
+            (Status::Added, _, Some(version)) if version.oid != empty => {
+                dests.push(Dest {
+                    path: status.path.clone(),
+                    version,
+                    found: None,
+                });
+            }
+            _ => {}
+        }
+    }
+    if sources.is_empty() || dests.is_empty() {
+        return Ok(());
+    }
+
+    find_exact(&sources, &mut dests, renames);
+    find_similar(db, &sources, &mut dests, renames)?;
+
+    for dest in dests {
+        let (i, score) = if let Some(found) = dest.found {
+            found
+        } else {
+            continue;
+        };
+        let source = &sources[i];
+        let status = statuses.get_mut(&dest.path).expect("Dest has a status");
+        status.versions.head = Some(source.version);
+        status.index = if source.deleted && !source.renamed {
+            Status::Renamed {
+                from: source.path.clone(),
+                score,
+            }
+        } else {
+            Status::Copied {
+                from: source.path.clone(),
+                score,
+            }
+        };
+
+        if let Status::Renamed { .. } = status.index {
+            sources[i].renamed = true;
+            remove_source(statuses, &sources[i].path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The deleted path is now part of the rename, unless something untracked
+/// has taken its place in the workspace.
+fn remove_source(statuses: &mut BTreeMap<WsPath, FileStatus>, path: &WsPath) {
+    let status = statuses.get_mut(path).expect("Source has a status");
+    if status.workspace == Status::Untracked {
+        status.index = Status::Untracked;
+        status.versions = Versions::default();
+    } else {
+        statuses.remove(path);
+    }
+}
+
+/// Pairs up identical contents first, as that's cheap and leaves fewer paths
+/// for [`find_similar`] to compare.
+fn find_exact(sources: &[Source], dests: &mut [Dest], renames: Renames) {
+    let mut taken = vec![false; sources.len()];
+    for dest in dests.iter_mut() {
+        let same = |source: &Source| source.version.oid == dest.version.oid;
+        // Prefer renaming a deleted path, falling back to copying
+        let found = sources
+            .iter()
+            .enumerate()
+            .position(|(i, source)| source.deleted && !taken[i] && same(source))
+            .or_else(|| {
+                if renames == Renames::Copies {
+                    sources.iter().position(same)
+                } else {
+                    None
+                }
+            });
+        if let Some(i) = found {
+            taken[i] = taken[i] || sources[i].deleted;
+            dest.found = Some((i, 100));
+        }
+    }
+}
+
+fn find_similar(
+    db: &mut Db,
+    sources: &[Source],
+    dests: &mut [Dest],
+    renames: Renames,
+) -> Result<(), db::LoadError<Blob>> {
+    let taken: Vec<bool> = {
+        let mut taken = vec![false; sources.len()];
+        for dest in dests.iter() {
+            if let Some((i, _)) = dest.found {
+                taken[i] = taken[i] || sources[i].deleted;
+            }
+        }
+        taken
+    };
+    let usable = |i: usize| !taken[i] || renames == Renames::Copies;
+
+    let wanted = dests.iter().any(|dest| dest.found.is_none());
+    if !wanted || !(0..sources.len()).any(usable) {
+        return Ok(());
+    }
+
+    let mut source_chunks = Vec::with_capacity(sources.len());
+    for (i, source) in sources.iter().enumerate() {
+        source_chunks.push(if usable(i) {
+            Some(Chunks::new(&db.load(source.version.oid)?.bytes))
+        } else {
+            None
+        });
+    }
+
+    let mut candidates = Vec::new();
+    for (d, dest) in dests.iter().enumerate() {
+        if dest.found.is_some() {
+            continue;
+        }
+        let dest_chunks = Chunks::new(&db.load(dest.version.oid)?.bytes);
+        for (s, chunks) in source_chunks.iter().enumerate() {
+            if let Some(chunks) = chunks {
+                let score = chunks.similarity(&dest_chunks);
+                if score >= DEFAULT_THRESHOLD {
+                    candidates.push((score, d, s));
+                }
+            }
+        }
+    }
+
+    // Best matches first, ties broken by path order. Like git, renames are
+    // found before copies, so a deleted path wins over a modified one.
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+    let mut taken = taken;
+    for copies in [false, true] {
+        if copies && renames != Renames::Copies {
+            break;
+        }
+        for &(score, d, s) in &candidates {
+            let renamable = sources[s].deleted && !taken[s];
+            if dests[d].found.is_some() || !(renamable || copies) {
+                continue;
+            }
+            taken[s] = taken[s] || sources[s].deleted;
+            dests[d].found = Some((s, score));
+        }
+    }
+
+    Ok(())
+}
+
+/// How many bytes of a file fall in each distinct chunk, see
+/// `diffcore-delta.c` in git.
+struct Chunks {
+    len: usize,
+    counts: HashMap<u64, usize>,
+}
+
+impl Chunks {
+    fn new(bytes: &[u8]) -> Self {
+        let mut counts = HashMap::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let end = rest
+                .iter()
+                .take(MAX_CHUNK_LEN)
+                .position(|&b| b == b'\n')
+                .map_or_else(|| rest.len().min(MAX_CHUNK_LEN), |i| i + 1);
+            let (chunk, next) = rest.split_at(end);
+            let mut hasher = DefaultHasher::new();
+            chunk.hash(&mut hasher);
+            *counts.entry(hasher.finish()).or_insert(0) += chunk.len();
+            rest = next;
+        }
+        Self {
+            len: bytes.len(),
+            counts,
+        }
+    }
+
+    /// The share of the larger file in percent that's also in the other one.
+    #[allow(clippy::cast_possible_truncation)]
+    fn similarity(&self, other: &Self) -> u8 {
+        let (min, max) = if self.len < other.len {
+            (self.len, other.len)
+        } else {
+            (other.len, self.len)
+        };
+        if max == 0 {
+            return 100;
+        }
+        // Too different in size to be similar enough
+        if (max - min) * 100 > max * usize::from(100 - DEFAULT_THRESHOLD) {
+            return 0;
+        }
+
+        let common: usize = self
+            .counts
+            .iter()
+            .filter_map(|(hash, &count)| Some(count.min(*other.counts.get(hash)?)))
+            .sum();
+        (common * 100 / max) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    fn similarity(a: &str, b: &str) -> u8 {
+        Chunks::new(a.as_bytes()).similarity(&Chunks::new(b.as_bytes()))
+    }
+
+    #[test]
+    fn identical_is_100() {
+        init();
+        assert_eq!(100, similarity("a\nb\nc\n", "a\nb\nc\n"));
+    }
+
+    #[test]
+    fn shares_lines() {
+        init();
+        assert_eq!(75, similarity("a\nb\nc\nd\n", "a\nb\nc\ne\n"));
+        assert_eq!(50, similarity("a\nb\nc\nd\n", "c\nd\ne\nf\n"));
+        assert_eq!(0, similarity("a\nb\n", "c\nd\n"));
+        assert_eq!(0, similarity("removed", "added"));
+    }
+
+    #[test]
+    fn order_does_not_matter() {
+        init();
+        assert_eq!(100, similarity("a\nb\nc\n", "c\nb\na\n"));
+    }
+
+    #[test]
+    fn very_different_sizes_are_not_similar() {
+        init();
+        assert_eq!(0, similarity("a\n", "a\nb\nc\nd\ne\n"));
+    }
+
+    #[test]
+    fn long_lines_are_chunked() {
+        init();
+        let long = "x".repeat(200);
+        assert_eq!(96, similarity(&long, &format!("{}yyyyyyyy", &long[..192])));
+    }
+}
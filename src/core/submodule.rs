@@ -0,0 +1,250 @@
+//! Submodules: other repositories checked out inside the workspace, like `git
+//! submodule`.
+//!
+//! A submodule is recorded in trees and the index as a gitlink, an entry with
+//! [`Mode::Gitlink`] whose oid is the commit to check out in it. Its name,
+//! path and URL are declared in `.gitmodules` at the top of the workspace:
+//!
+//! ```text
+//! [submodule "lib"]
+//!     path = vendor/lib
+//!     url = ../lib
+//! ```
+//!
+//! `submodule init` copies the URL to `submodule.<name>.url` in the
+//! repository's config, and `submodule update` clones it into
+//! `modules/<name>` in the git directory, with a `.git` file in the
+//! submodule's directory pointing there.
+//!
+//! [`Mode::Gitlink`]: crate::core::stat::Mode::Gitlink
+
+use bstr::ByteSlice;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+use crate::core::{
+    config,
+    db::{Blob, Commit},
+    discover, refs, Config, Oid, Refs, Workspace, WsPath,
+};
+
+pub const FILE_NAME: &str = ".gitmodules";
+const DIR: &str = "modules";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Submodule {
+    pub name: String,
+    pub path: WsPath,
+    /// As given in `.gitmodules`, so maybe relative to the workspace
+    pub url: Option<String>,
+}
+
+/// What `submodule status` reports about a submodule.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SubmoduleStatus {
+    pub submodule: Submodule,
+    /// The commit recorded in the index, `None` if it's conflicted
+    pub recorded: Option<Oid<Commit>>,
+    /// The commit checked out in the submodule, `None` if it isn't checked
+    /// out
+    pub checked_out: Option<Oid<Commit>>,
+}
+
+impl SubmoduleStatus {
+    /// The character git prints before the commit: `-` if it isn't checked
+    /// out, `+` if another commit than the recorded one is, `U` if it's
+    /// conflicted and otherwise a space.
+    pub fn prefix(&self) -> char {
+        match (self.recorded, self.checked_out) {
+            (None, _) => 'U',
+            (Some(_), None) => '-',
+            (Some(recorded), Some(checked_out)) if recorded != checked_out => '+',
+            _ => ' ',
+        }
+    }
+
+    /// The commit git prints: the one checked out if there is one.
+    pub fn commit(&self) -> Option<Oid<Commit>> {
+        self.checked_out.or(self.recorded)
+    }
+}
+
+/// The submodules declared in `.gitmodules` at the top of `work`, in the
+/// order they appear. A missing file declares none.
+pub fn load(work: &Workspace) -> Result<Vec<Submodule>, Error> {
+    let config = Config::load_file(work.path().join(FILE_NAME))?;
+    Ok(parse(&config))
+}
+
+/// Like git, submodules without a path are skipped.
+pub fn parse(config: &Config) -> Vec<Submodule> {
+    let mut submodules = Vec::new();
+    for name in config.subsections("submodule") {
+        let name = name.to_str_lossy().into_owned();
+        let path = if let Some(path) = config.get(&format!("submodule.{}.path", name)) {
+            WsPath::new_unchecked_bytes(path.trim_end_with(|c| c == '/'))
+        } else {
+            warn!("Ignoring submodule {:?} without a path", name);
+            continue;
+        };
+        let url = config
+            .get(&format!("submodule.{}.url", name))
+            .map(|url| url.to_str_lossy().into_owned());
+        submodules.push(Submodule { name, path, url });
+    }
+    submodules
+}
+
+/// The commit checked out in the submodule whose workspace is at `dir`, or
+/// `None` if nothing is.
+pub fn head(dir: &Path) -> Result<Option<Oid<Commit>>, Error> {
+    let git_dir = match discover::git_dir_in(dir)? {
+        Some(git_dir) => git_dir,
+        None => return Ok(None),
+    };
+    let common_dir = discover::common_dir(&git_dir)?;
+    Ok(Refs::for_worktree(&git_dir, &common_dir).head()?)
+}
+
+/// A gitlink's oid is a commit, stored where other entries have a blob.
+pub fn commit_of(gitlink: Oid<Blob>) -> Oid<Commit> {
+    gitlink.into_untyped().to_typed()
+}
+
+pub fn gitlink_of(commit: Oid<Commit>) -> Oid<Blob> {
+    commit.into_untyped().to_typed()
+}
+
+/// Where the git directory of the submodule named `name` goes.
+pub fn git_dir(common_dir: &Path, name: &str) -> PathBuf {
+    common_dir.join(DIR).join(name)
+}
+
+/// Like git without a remote to resolve against, a URL starting with `./`
+/// or `../` is relative to the workspace.
+pub fn resolve_url(work: &Workspace, url: &str) -> String {
+    if url.starts_with("./") || url.starts_with("../") {
+        let mut path = work.path().to_owned();
+        for component in url.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    path.pop();
+                }
+                component => path.push(component),
+            }
+        }
+        path.to_string_lossy().into_owned()
+    } else {
+        url.to_owned()
+    }
+}
+
+/// Creates an empty git directory at `git_dir` for a submodule cloned from
+/// `url`, unless one is left from before, and the `.git` file in its
+/// workspace at `dir` pointing to it.
+pub fn create(git_dir: &Path, dir: &Path, url: &str) -> Result<(), Error> {
+    if !git_dir.exists() {
+        for sub_dir in &["objects", "refs"] {
+            let path = git_dir.join(sub_dir);
+            fs::create_dir_all(&path).map_err(|e| Error::Io(path, e))?;
+        }
+        config::append(git_dir.join("config"), "remote.origin.url", url)?;
+    }
+
+    fs::create_dir_all(dir).map_err(|e| Error::Io(dir.to_owned(), e))?;
+    let dot_git = dir.join(".git");
+    fs::write(&dot_git, format!("gitdir: {}\n", git_dir.display()))
+        .map_err(|e| Error::Io(dot_git, e))
+}
+
+/// Copies the objects in the repository at `from` that are missing from the
+/// one at `to`, which is all a clone or fetch from a local path needs when
+/// `HEAD` is set directly.
+pub fn copy_objects(from: &Path, to: &Path) -> Result<(), Error> {
+    copy_missing(&from.join("objects"), &to.join("objects"))
+}
+
+fn copy_missing(from: &Path, to: &Path) -> Result<(), Error> {
+    fs::create_dir_all(to).map_err(|e| Error::Io(to.to_owned(), e))?;
+    let entries = fs::read_dir(from).map_err(|e| Error::Io(from.to_owned(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::Io(from.to_owned(), e))?;
+        let (from, to) = (entry.path(), to.join(entry.file_name()));
+        if from.is_dir() {
+            copy_missing(&from, &to)?;
+        } else if !to.exists() {
+            fs::copy(&from, &to).map_err(|e| Error::Io(from, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum Error {
+    /// IO error on submodule file {0:?}
+    Io(PathBuf, #[source] io::Error),
+    /// Failed to load .gitmodules
+    Load(#[from] config::LoadError),
+    /// Failed to write submodule config
+    WriteConfig(#[from] config::WriteError),
+    /// Failed to find submodule's git directory
+    Discover(#[from] discover::Error),
+    /// Failed to read HEAD of submodule
+    ReadHead(#[from] refs::ReadError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_gitmodules() -> eyre::Result<()> {
+        init();
+        let config = Config::parse(
+            br#"
+[submodule "lib"]
+	path = vendor/lib/
+	url = ../lib
+[submodule "no-path"]
+	url = /elsewhere
+[submodule "no-url"]
+	path = tools
+"#
+            .as_bstr(),
+        )?;
+
+        assert_eq!(
+            vec![
+                Submodule {
+                    name: "lib".to_owned(),
+                    path: WsPath::new_unchecked("vendor/lib"),
+                    url: Some("../lib".to_owned()),
+                },
+                Submodule {
+                    name: "no-url".to_owned(),
+                    path: WsPath::new_unchecked("tools"),
+                    url: None,
+                },
+            ],
+            parse(&config)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_relative_urls() {
+        init();
+        let work = Workspace::new(PathBuf::from("/work/super"));
+
+        assert_eq!("/work/lib", resolve_url(&work, "../lib"));
+        assert_eq!("/work/super/sub", resolve_url(&work, "./sub/"));
+        assert_eq!("/abs/lib", resolve_url(&work, "/abs/lib"));
+        assert_eq!("https://host/lib", resolve_url(&work, "https://host/lib"));
+    }
+}
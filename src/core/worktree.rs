@@ -0,0 +1,240 @@
+//! Worktrees: workspaces attached to one repository, each with its own
+//! `HEAD` and index but sharing objects, refs and config, like `git
+//! worktree`.
+//!
+//! The main worktree is the one next to the repository's git directory. Each
+//! linked worktree has a git directory of its own at `worktrees/<name>` in
+//! the main one, holding:
+//!
+//! - `HEAD` and `index`, as in any git directory
+//! - `commondir`, the path of the main git directory relative to it
+//! - `gitdir`, the absolute path of the `.git` file in the worktree, which
+//!   in turn names this directory
+//! - `locked`, if the worktree mustn't be pruned, containing the reason
+
+use bstr::ByteSlice;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::core::{config, db::Commit, refs, Config, Oid, Refs};
+
+const DIR: &str = "worktrees";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Worktree {
+    /// The top of its workspace, or for a bare repository its git directory
+    pub path: PathBuf,
+    pub git_dir: PathBuf,
+    /// Only the main worktree can be bare, when it has no workspace
+    pub bare: bool,
+    /// The name of its directory under `worktrees`, or `None` for the main
+    /// worktree
+    pub name: Option<String>,
+    pub head: Option<Oid<Commit>>,
+    /// Why it's locked against pruning, which may be empty
+    pub locked: Option<String>,
+    /// Why it can be pruned, if it can
+    pub prunable: Option<String>,
+}
+
+impl Worktree {
+    pub fn is_main(&self) -> bool {
+        self.name.is_none()
+    }
+}
+
+/// The main worktree, then the linked ones by name.
+pub fn list(common_dir: &Path) -> Result<Vec<Worktree>, Error> {
+    let config = Config::load_file(common_dir.join("config"))?;
+    let bare = config.get_bool("core.bare")?.unwrap_or(false);
+    let path = if bare {
+        common_dir
+    } else {
+        common_dir.parent().unwrap_or(common_dir)
+    };
+    let main = Worktree {
+        path: path.to_owned(),
+        git_dir: common_dir.to_owned(),
+        bare,
+        name: None,
+        head: Refs::new(common_dir).head()?,
+        locked: None,
+        prunable: None,
+    };
+
+    let dir = common_dir.join(DIR);
+    let mut names = Vec::new();
+    match fs::read_dir(&dir) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = entry.map_err(|e| Error::Io(dir.clone(), e))?;
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(Error::Io(dir, err)),
+    }
+    names.sort();
+
+    let mut worktrees = vec![main];
+    for name in names {
+        worktrees.push(read_linked(common_dir, name)?);
+    }
+    Ok(worktrees)
+}
+
+fn read_linked(common_dir: &Path, name: String) -> Result<Worktree, Error> {
+    let git_dir = common_dir.join(DIR).join(&name);
+
+    let (path, prunable) = match read_optional(&git_dir.join("gitdir"))? {
+        Some(dot_git) => {
+            let dot_git = git_dir.join(dot_git);
+            let prunable = if dot_git.exists() {
+                None
+            } else {
+                Some("gitdir file points to non-existent location")
+            };
+            (dot_git.parent().unwrap_or(&dot_git).to_owned(), prunable)
+        }
+        None => (PathBuf::new(), Some("gitdir file does not exist")),
+    };
+    let locked = read_optional(&git_dir.join("locked"))?;
+
+    Ok(Worktree {
+        path,
+        head: Refs::new(&git_dir).head()?,
+        bare: false,
+        name: Some(name),
+        // Locking is what stops a worktree being pruned
+        prunable: prunable.filter(|_| locked.is_none()).map(str::to_owned),
+        locked,
+        git_dir,
+    })
+}
+
+/// The contents of the file at `path` without trailing whitespace, or
+/// `None` if it doesn't exist.
+fn read_optional(path: &Path) -> Result<Option<String>, Error> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents.trim_end().to_str_lossy().into_owned())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::Io(path.to_owned(), err)),
+    }
+}
+
+/// Creates the git directory for a new linked worktree at `path`, an
+/// existing absolute directory, with `HEAD` at `head`, and the `.git` file
+/// linking `path` to it. Returns the new git directory.
+pub fn create(common_dir: &Path, path: &Path, head: &Oid<Commit>) -> Result<PathBuf, Error> {
+    let dir = common_dir.join(DIR);
+    let name = unique_name(&dir, path);
+    let git_dir = dir.join(&name);
+
+    let write = |path: &Path, contents: String| {
+        fs::write(path, contents).map_err(|e| Error::Io(path.to_owned(), e))
+    };
+    fs::create_dir_all(&git_dir).map_err(|e| Error::Io(git_dir.clone(), e))?;
+    write(&git_dir.join("commondir"), "../..\n".to_owned())?;
+    write(
+        &git_dir.join("gitdir"),
+        format!("{}\n", path.join(".git").display()),
+    )?;
+    Refs::new(&git_dir).update_head(head)?;
+    write(
+        &path.join(".git"),
+        format!("gitdir: {}\n", git_dir.display()),
+    )?;
+
+    Ok(git_dir)
+}
+
+/// Like git, the name is the last component of the worktree's path with
+/// anything unusual replaced, followed by a number if it's taken.
+fn unique_name(dir: &Path, path: &Path) -> String {
+    let base = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    let base = match base.trim_start_matches('.') {
+        "" => "worktree".to_owned(),
+        base => base.to_owned(),
+    };
+
+    let mut name = base.clone();
+    let mut counter = 1;
+    while dir.join(&name).exists() {
+        name = format!("{base}{counter}");
+        counter += 1;
+    }
+    name
+}
+
+/// Removes the git directory of a linked worktree, leaving its workspace.
+pub fn remove(worktree: &Worktree) -> Result<(), Error> {
+    fs::remove_dir_all(&worktree.git_dir).map_err(|e| Error::Io(worktree.git_dir.clone(), e))
+}
+
+/// Removes the git directories of linked worktrees whose workspaces have
+/// gone, unless they're locked. Returns those worktrees, which with
+/// `dry_run` are left in place.
+pub fn prune(common_dir: &Path, dry_run: bool) -> Result<Vec<Worktree>, Error> {
+    let prunable = list(common_dir)?
+        .into_iter()
+        .filter(|worktree| worktree.prunable.is_some())
+        .collect::<Vec<_>>();
+    if !dry_run {
+        for worktree in &prunable {
+            remove(worktree)?;
+        }
+    }
+    Ok(prunable)
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum Error {
+    /// IO error on worktree file {0:?}
+    Io(PathBuf, #[source] io::Error),
+    /// Failed to load config
+    LoadConfig(#[from] config::LoadError),
+    /// Invalid config
+    InvalidConfig(#[from] config::InvalidValueError),
+    /// Failed to read HEAD of worktree
+    ReadHead(#[from] refs::ReadError),
+    /// Failed to write HEAD of worktree
+    UpdateHead(#[from] refs::UpdateError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    #[test]
+    fn names_are_unique() -> eyre::Result<()> {
+        init();
+        let dir = tempdir()?;
+        let dir = dir.path();
+        fs::create_dir_all(dir.join("wt"))?;
+        fs::create_dir_all(dir.join("wt1"))?;
+
+        assert_eq!("other", unique_name(dir, Path::new("/a/other")));
+        assert_eq!("wt2", unique_name(dir, Path::new("/a/wt")));
+        assert_eq!("my-tree", unique_name(dir, Path::new("/a/my tree")));
+        assert_eq!("hidden", unique_name(dir, Path::new("/a/.hidden")));
+        assert_eq!("worktree", unique_name(dir, Path::new("/")));
+        Ok(())
+    }
+}
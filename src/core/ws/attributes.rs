@@ -0,0 +1,565 @@
+//! Gitattributes, see `gitattributes(5)`, and the line ending conversion the
+//! `text` and `eol` attributes control.
+
+use bstr::{BStr, BString, ByteSlice};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::{ignore::Pattern, WsPath};
+use crate::core::{
+    config::{xdg_config_home, InvalidValueError},
+    Config,
+};
+
+/// The value of an attribute for a path.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum State {
+    /// `name`
+    Set,
+    /// `-name`
+    Unset,
+    /// `name=value`
+    Value(BString),
+    /// `!name`, or no matching line mentions it
+    Unspecified,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Rule {
+    pattern: Pattern,
+    attrs: Vec<(BString, State)>,
+}
+
+impl Rule {
+    fn parse(line: &[u8], base: &BStr, source: &Arc<PathBuf>, line_num: usize) -> Option<Self> {
+        let line = line.trim_with(|c| c == ' ' || c == '\t' || c == '\r');
+        // Macros can't be defined yet, only the built-in `binary` is known
+        if line.is_empty() || line[0] == b'#' || line.starts_with(b"[attr]") {
+            return None;
+        }
+
+        let (pattern, rest) = if line[0] == b'"' {
+            unquote(&line[1..])?
+        } else {
+            let end = line.find_byteset(b" \t").unwrap_or(line.len());
+            (line[..end].into(), &line[end..])
+        };
+        // Negative patterns are forbidden in attributes files
+        if pattern.starts_with(b"!") {
+            return None;
+        }
+        let pattern = Pattern::parse(&pattern, base, source, line_num)?;
+
+        let mut attrs = Vec::new();
+        for attr in rest.fields() {
+            let (name, state) = if let Some(name) = attr.strip_prefix(b"-") {
+                (name, State::Unset)
+            } else if let Some(name) = attr.strip_prefix(b"!") {
+                (name, State::Unspecified)
+            } else if let Some(i) = attr.find_byte(b'=') {
+                (&attr[..i], State::Value(attr[i + 1..].into()))
+            } else {
+                (attr, State::Set)
+            };
+            if name == b"binary" && state == State::Set {
+                for name in &["diff", "merge", "text"] {
+                    attrs.push((BString::from(*name), State::Unset));
+                }
+            }
+            attrs.push((name.into(), state));
+        }
+
+        Some(Self { pattern, attrs })
+    }
+
+    fn state_of(&self, name: &[u8]) -> Option<&State> {
+        self.attrs
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, state)| state)
+    }
+}
+
+/// Reads a pattern in double quotes, with C-style escapes, returning it and
+/// the rest of the line after the closing quote.
+fn unquote(quoted: &[u8]) -> Option<(BString, &[u8])> {
+    let mut pattern = BString::from("");
+    let mut bytes = quoted.iter().enumerate();
+    while let Some((i, &b)) = bytes.next() {
+        match b {
+            b'"' => return Some((pattern, &quoted[i + 1..])),
+            b'\\' => {
+                let (_, &escaped) = bytes.next()?;
+                pattern.push(match escaped {
+                    b'a' => 0x07,
+                    b'b' => 0x08,
+                    b't' => b'\t',
+                    b'n' => b'\n',
+                    b'v' => 0x0b,
+                    b'f' => 0x0c,
+                    b'r' => b'\r',
+                    b'0'..=b'7' => {
+                        let (_, &second) = bytes.next()?;
+                        let (_, &third) = bytes.next()?;
+                        let digits = [escaped, second, third];
+                        u8::from_str_radix(digits.to_str().ok()?, 8).ok()?
+                    }
+                    other => other,
+                });
+            }
+            b => pattern.push(b),
+        }
+    }
+    None
+}
+
+/// The rules from one file. Later rules take precedence.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RuleList(Vec<Rule>);
+
+impl RuleList {
+    pub fn parse(input: &BStr, base: &BStr, source: impl Into<PathBuf>) -> Self {
+        let source = Arc::new(source.into());
+        let rules = input
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| Rule::parse(line, base, &source, i + 1))
+            .collect();
+        Self(rules)
+    }
+
+    /// A missing file has no rules.
+    fn load(
+        path: &Path,
+        base: &BStr,
+        source: impl Into<PathBuf>,
+    ) -> Result<Option<Self>, LoadError> {
+        match fs::read(path) {
+            Ok(input) => Ok(Some(Self::parse(input.as_bstr(), base, source))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(LoadError::Read(path.to_owned(), err)),
+        }
+    }
+
+    fn state_of(&self, path: &WsPath, name: &[u8]) -> Option<&State> {
+        self.0
+            .iter()
+            .rev()
+            .filter(|rule| rule.pattern.matches(path.as_bstr(), false))
+            .find_map(|rule| rule.state_of(name))
+    }
+}
+
+/// `core.autocrlf`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AutoCrlf {
+    False,
+    /// Text files have CRLF line endings in the workspace, converted to LF in
+    /// the repository
+    True,
+    /// Convert CRLF to LF when adding, but never back
+    Input,
+}
+
+/// A line ending in the workspace.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Eol {
+    Lf,
+    Crlf,
+}
+
+/// How a path's contents are converted between the workspace and the
+/// repository, decided by its attributes and the config.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Conversion {
+    /// Stored as is
+    None,
+    /// CRLF is converted to LF
+    Text,
+    /// Like [`Self::Text`], but only if the contents don't look binary
+    Auto,
+}
+
+/// The attributes from `.git/info/attributes` and `core.attributesFile`,
+/// along with the config deciding line endings. Those from `.gitattributes`
+/// files are read for each path looked up.
+#[derive(Debug, Clone)]
+pub struct Attributes {
+    /// The lowest precedence, below `.gitattributes` files
+    global: Option<Arc<RuleList>>,
+    /// The highest precedence
+    info: Option<Arc<RuleList>>,
+    autocrlf: AutoCrlf,
+    /// `core.eol`
+    eol: Eol,
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            global: None,
+            info: None,
+            autocrlf: AutoCrlf::False,
+            eol: Eol::Lf,
+        }
+    }
+}
+
+impl Attributes {
+    pub const FILE_NAME: &'static str = ".gitattributes";
+
+    pub fn load(git_dir: &Path, config: &Config) -> Result<Self, LoadError> {
+        let attributes_file = config
+            .get_path("core.attributesFile")
+            .or_else(|| xdg_config_home().map(|config_home| config_home.join("git/attributes")));
+
+        let global = match attributes_file {
+            Some(path) => RuleList::load(&path, "".into(), &path)?.map(Arc::new),
+            None => None,
+        };
+
+        let info = RuleList::load(
+            &git_dir.join("info/attributes"),
+            "".into(),
+            Path::new(".git/info/attributes"),
+        )?
+        .map(Arc::new);
+
+        let autocrlf = match config.get("core.autocrlf") {
+            Some(value) if value.eq_ignore_ascii_case(b"input") => AutoCrlf::Input,
+            _ if config.get_bool("core.autocrlf")? == Some(true) => AutoCrlf::True,
+            _ => AutoCrlf::False,
+        };
+        let eol = match config.get("core.eol").map(|v| v.to_ascii_lowercase()) {
+            Some(value) if value == b"crlf" => Eol::Crlf,
+            Some(value) if value == b"lf" || value == b"native" => Eol::Lf,
+            None => Eol::Lf,
+            Some(value) => return Err(InvalidValueError::new("core.eol", value).into()),
+        };
+
+        Ok(Self {
+            global,
+            info,
+            autocrlf,
+            eol,
+        })
+    }
+
+    /// The rules that apply to `path`, reading the `.gitattributes` files in
+    /// the workspace at `root` along the way.
+    pub fn for_path(&self, root: &Path, path: &WsPath) -> Result<PathAttributes, LoadError> {
+        let mut lists = Vec::new();
+        lists.extend(self.info.clone());
+
+        // Deeper files take precedence
+        let mut dirs = vec![WsPath::root()];
+        dirs.extend(path.parents());
+        for dir in dirs.iter().rev() {
+            let rel_file = dir.as_path().join(Self::FILE_NAME);
+            let list = RuleList::load(&root.join(&rel_file), dir.as_bstr(), rel_file)?;
+            lists.extend(list.map(Arc::new));
+        }
+
+        lists.extend(self.global.clone());
+        Ok(PathAttributes {
+            path: path.clone(),
+            lists,
+        })
+    }
+
+    /// Like git, `text` decides the conversion, then `eol`, which implies
+    /// `text`, then `core.autocrlf`.
+    pub fn conversion_of(&self, attrs: &PathAttributes) -> Conversion {
+        match attrs.state("text") {
+            State::Set => Conversion::Text,
+            State::Unset => Conversion::None,
+            State::Value(value) if value == "auto" => Conversion::Auto,
+            State::Value(_) | State::Unspecified => match eol_attr(attrs) {
+                Some(_) => Conversion::Text,
+                None if self.autocrlf == AutoCrlf::False => Conversion::None,
+                None => Conversion::Auto,
+            },
+        }
+    }
+
+    /// The line ending text files should have in the workspace, or `None` if
+    /// the path isn't converted.
+    pub fn workspace_eol(&self, attrs: &PathAttributes) -> Option<Eol> {
+        if self.conversion_of(attrs) == Conversion::None {
+            return None;
+        }
+        let eol = match (eol_attr(attrs), self.autocrlf) {
+            (Some(eol), _) => eol,
+            (None, AutoCrlf::True) => Eol::Crlf,
+            (None, AutoCrlf::Input) => Eol::Lf,
+            (None, AutoCrlf::False) => self.eol,
+        };
+        Some(eol)
+    }
+
+    /// Converts the line endings of a workspace file to what's stored in the
+    /// repository.
+    pub fn convert_to_git(&self, attrs: &PathAttributes, data: BString) -> BString {
+        let convert = match self.conversion_of(attrs) {
+            Conversion::None => false,
+            Conversion::Text => true,
+            Conversion::Auto => !is_binary(&data),
+        };
+        if !convert || !data.contains_str("\r\n") {
+            return data;
+        }
+
+        let mut converted = BString::from(Vec::with_capacity(data.len()));
+        let mut bytes = data.iter().peekable();
+        while let Some(&b) = bytes.next() {
+            if b != b'\r' || bytes.peek() != Some(&&b'\n') {
+                converted.push(b);
+            }
+        }
+        converted
+    }
+
+    /// Converts the line endings of a blob to what the workspace file should
+    /// have. Like git, with `text=auto` contents that already have CRs are
+    /// left alone.
+    pub fn convert_to_workspace(&self, attrs: &PathAttributes, data: BString) -> BString {
+        if self.workspace_eol(attrs) != Some(Eol::Crlf) {
+            return data;
+        }
+        if self.conversion_of(attrs) == Conversion::Auto
+            && (data.contains(&b'\r') || is_binary(&data))
+        {
+            return data;
+        }
+
+        let mut converted = BString::from(Vec::with_capacity(data.len()));
+        let mut prev = None;
+        for &b in data.iter() {
+            if b == b'\n' && prev != Some(b'\r') {
+                converted.push(b'\r');
+            }
+            converted.push(b);
+            prev = Some(b);
+        }
+        converted
+    }
+}
+
+/// The rules that apply to one path, see [`Attributes::for_path`].
+#[derive(Debug, Clone)]
+pub struct PathAttributes {
+    path: WsPath,
+    /// In decreasing order of precedence
+    lists: Vec<Arc<RuleList>>,
+}
+
+impl PathAttributes {
+    pub fn state(&self, name: &str) -> State {
+        self.lists
+            .iter()
+            .find_map(|list| list.state_of(&self.path, name.as_bytes()))
+            .cloned()
+            .unwrap_or(State::Unspecified)
+    }
+}
+
+fn eol_attr(attrs: &PathAttributes) -> Option<Eol> {
+    match attrs.state("eol") {
+        State::Value(value) if value == "lf" => Some(Eol::Lf),
+        State::Value(value) if value == "crlf" => Some(Eol::Crlf),
+        _ => None,
+    }
+}
+
+/// Guesses like git whether contents are binary, in which case `text=auto`
+/// leaves them alone: they contain NUL, a CR not followed by LF, or many
+/// control characters.
+fn is_binary(data: &[u8]) -> bool {
+    let mut printable = 0_usize;
+    let mut nonprintable = 0_usize;
+    let mut bytes = data.iter().peekable();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\r' if bytes.peek() == Some(&&b'\n') => {
+                bytes.next();
+            }
+            b'\r' | b'\0' => return true,
+            b'\n' => {}
+            // Backspace, tab, escape and form feed are common in text
+            0x08 | b'\t' | 0x1b | 0x0c => printable += 1,
+            0x7f => nonprintable += 1,
+            b if b < 0x20 => nonprintable += 1,
+            _ => printable += 1,
+        }
+    }
+    (printable >> 7) < nonprintable
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum LoadError {
+    /// Failed to read attributes file {0:?}
+    Read(PathBuf, #[source] io::Error),
+    /// Invalid line ending config
+    Config(#[from] InvalidValueError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{init, tempdir, write_to};
+    use pretty_assertions::assert_eq;
+
+    fn attributes(info: &str, autocrlf: AutoCrlf) -> Attributes {
+        Attributes {
+            info: Some(Arc::new(RuleList::parse(
+                info.into(),
+                "".into(),
+                "info/attributes",
+            ))),
+            autocrlf,
+            ..Attributes::default()
+        }
+    }
+
+    fn state(attributes: &Attributes, root: &Path, path: &str, name: &str) -> State {
+        attributes
+            .for_path(root, &WsPath::new_unchecked(path))
+            .unwrap()
+            .state(name)
+    }
+
+    fn for_path(attributes: &Attributes, path: &str) -> PathAttributes {
+        attributes
+            .for_path(Path::new("/nonexistent"), &WsPath::new_unchecked(path))
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_states() {
+        init();
+        let attrs = attributes("*.txt text -diff !merge eol=crlf", AutoCrlf::False);
+        let root = Path::new("/nonexistent");
+        assert_eq!(State::Set, state(&attrs, root, "a.txt", "text"));
+        assert_eq!(State::Unset, state(&attrs, root, "a.txt", "diff"));
+        assert_eq!(State::Unspecified, state(&attrs, root, "a.txt", "merge"));
+        assert_eq!(
+            State::Value("crlf".into()),
+            state(&attrs, root, "a.txt", "eol")
+        );
+        assert_eq!(State::Unspecified, state(&attrs, root, "a.bin", "text"));
+    }
+
+    #[test]
+    fn later_lines_take_precedence() {
+        init();
+        let attrs = attributes("* text\n*.png binary\n", AutoCrlf::False);
+        let root = Path::new("/nonexistent");
+        assert_eq!(State::Set, state(&attrs, root, "a.txt", "text"));
+        assert_eq!(State::Unset, state(&attrs, root, "dir/a.png", "text"));
+        assert_eq!(State::Unset, state(&attrs, root, "dir/a.png", "diff"));
+        assert_eq!(State::Set, state(&attrs, root, "dir/a.png", "binary"));
+    }
+
+    #[test]
+    fn skips_negative_and_quoted_patterns() {
+        init();
+        let attrs = attributes("!*.txt text\n\"a \\\"b\\\".md\" -text\n", AutoCrlf::False);
+        let root = Path::new("/nonexistent");
+        assert_eq!(State::Unspecified, state(&attrs, root, "a.txt", "text"));
+        assert_eq!(State::Unset, state(&attrs, root, "a \"b\".md", "text"));
+    }
+
+    #[test]
+    fn deeper_files_take_precedence() -> eyre::Result<()> {
+        init();
+        let dir = tempdir()?;
+        let root = dir.path();
+        write_to(root.join(".gitattributes"), "*.txt text\n/top.txt -text\n")?;
+        write_to(root.join("a/.gitattributes"), "*.txt -text\n")?;
+        write_to(root.join("a/b/.gitattributes"), "c.txt text=auto\n")?;
+
+        let attrs = Attributes::default();
+        assert_eq!(State::Set, state(&attrs, root, "x.txt", "text"));
+        assert_eq!(State::Unset, state(&attrs, root, "top.txt", "text"));
+        assert_eq!(State::Set, state(&attrs, root, "sub/top.txt", "text"));
+        assert_eq!(State::Unset, state(&attrs, root, "a/x.txt", "text"));
+        assert_eq!(State::Unset, state(&attrs, root, "a/b/x.txt", "text"));
+        assert_eq!(
+            State::Value("auto".into()),
+            state(&attrs, root, "a/b/c.txt", "text")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decides_conversion() {
+        init();
+        let conversion = |info: &str, autocrlf| {
+            let attributes = attributes(info, autocrlf);
+            attributes.conversion_of(&for_path(&attributes, "a.txt"))
+        };
+        assert_eq!(Conversion::None, conversion("", AutoCrlf::False));
+        assert_eq!(Conversion::Auto, conversion("", AutoCrlf::True));
+        assert_eq!(Conversion::Auto, conversion("", AutoCrlf::Input));
+        assert_eq!(Conversion::Text, conversion("* text", AutoCrlf::False));
+        assert_eq!(Conversion::Text, conversion("* eol=lf", AutoCrlf::False));
+        assert_eq!(Conversion::Auto, conversion("* text=auto", AutoCrlf::False));
+        assert_eq!(Conversion::None, conversion("* -text", AutoCrlf::True));
+        assert_eq!(Conversion::None, conversion("* binary", AutoCrlf::True));
+    }
+
+    #[test]
+    fn decides_workspace_eol() {
+        init();
+        let eol = |info: &str, autocrlf, core_eol| {
+            let attributes = Attributes {
+                eol: core_eol,
+                ..attributes(info, autocrlf)
+            };
+            attributes.workspace_eol(&for_path(&attributes, "a.txt"))
+        };
+        assert_eq!(None, eol("", AutoCrlf::False, Eol::Crlf));
+        assert_eq!(Some(Eol::Crlf), eol("", AutoCrlf::True, Eol::Lf));
+        assert_eq!(Some(Eol::Lf), eol("", AutoCrlf::Input, Eol::Crlf));
+        assert_eq!(Some(Eol::Crlf), eol("* text", AutoCrlf::False, Eol::Crlf));
+        assert_eq!(Some(Eol::Lf), eol("* eol=lf", AutoCrlf::True, Eol::Crlf));
+    }
+
+    #[test]
+    fn converts_crlf_to_lf() {
+        init();
+        let convert = |info: &str, data: &str| {
+            let attributes = attributes(info, AutoCrlf::False);
+            attributes.convert_to_git(&for_path(&attributes, "a.txt"), data.into())
+        };
+        assert_eq!("a\nb\n", convert("* text", "a\r\nb\r\n"));
+        assert_eq!("a\rb\n", convert("* text", "a\rb\r\n"));
+        assert_eq!("a\r\nb\n", convert("* -text", "a\r\nb\n"));
+        assert_eq!("a\nb\n", convert("* text=auto", "a\r\nb\n"));
+        assert_eq!("a\r\n\0", convert("* text=auto", "a\r\n\0"));
+        assert_eq!("a\rb\r\n", convert("* text=auto", "a\rb\r\n"));
+    }
+
+    #[test]
+    fn converts_lf_to_crlf() {
+        init();
+        let convert = |info: &str, autocrlf, data: &str| {
+            let attributes = attributes(info, autocrlf);
+            attributes.convert_to_workspace(&for_path(&attributes, "a.txt"), data.into())
+        };
+        assert_eq!("a\r\nb\r\n", convert("", AutoCrlf::True, "a\nb\n"));
+        assert_eq!("a\nb\n", convert("", AutoCrlf::Input, "a\nb\n"));
+        assert_eq!("a\nb\n", convert("", AutoCrlf::False, "a\nb\n"));
+        assert_eq!(
+            "a\r\nb\r\n",
+            convert("* eol=crlf", AutoCrlf::False, "a\r\nb\n")
+        );
+        // Already has CRs, so might not be meant as text
+        assert_eq!("a\r\nb\n", convert("", AutoCrlf::True, "a\r\nb\n"));
+        assert_eq!("a\n\0", convert("", AutoCrlf::True, "a\n\0"));
+    }
+}
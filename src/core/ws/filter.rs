@@ -0,0 +1,468 @@
+//! Filter drivers that convert files as they're added and checked out, set
+//! with the `filter` attribute and configured with `filter.<driver>.*`, see
+//! "filter" in `gitattributes(5)`.
+//!
+//! A driver with a `process` command is started once and fed every file over
+//! git's long-running filter protocol, see `gitprotocol-common(5)` for the
+//! pkt-line format.
+
+use bstr::{BStr, BString, ByteSlice};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio},
+    sync::Mutex,
+    thread,
+};
+use tracing::{debug, warn};
+
+use super::WsPath;
+use crate::core::{config::InvalidValueError, Config};
+
+/// The most data a pkt-line can carry, after its four byte length.
+const MAX_PACKET_DATA: usize = 65516;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Direction {
+    /// From the workspace to the repository
+    Clean,
+    /// From the repository to the workspace
+    Smudge,
+}
+
+impl Direction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Clean => "clean",
+            Direction::Smudge => "smudge",
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct Driver {
+    clean: Option<String>,
+    smudge: Option<String>,
+    /// Takes precedence over `clean` and `smudge`
+    process: Option<String>,
+    /// Failing to filter is an error rather than leaving contents as they are
+    required: bool,
+}
+
+impl Driver {
+    fn command(&self, direction: Direction) -> Option<&str> {
+        match direction {
+            Direction::Clean => self.clean.as_deref(),
+            Direction::Smudge => self.smudge.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Filters {
+    drivers: HashMap<BString, Driver>,
+    /// Long-running filters, started the first time they're needed. Only one
+    /// file can be filtered by each at a time.
+    processes: Mutex<HashMap<BString, Process>>,
+}
+
+impl Filters {
+    pub fn load(config: &Config) -> Result<Self, InvalidValueError> {
+        let mut drivers = HashMap::new();
+        for name in config.subsections("filter") {
+            let get = |key: &str| {
+                let key = format!("filter.{}.{}", name, key);
+                config.get(&key).map(|v| v.to_str_lossy().into_owned())
+            };
+            let driver = Driver {
+                clean: get("clean"),
+                smudge: get("smudge"),
+                process: get("process"),
+                required: config
+                    .get_bool(&format!("filter.{}.required", name))?
+                    .unwrap_or(false),
+            };
+            drivers.insert(name.to_owned(), driver);
+        }
+        Ok(Self {
+            drivers,
+            processes: Mutex::default(),
+        })
+    }
+
+    /// Passes `data` through the driver named `name`. Contents are left as
+    /// they are if the driver isn't configured or fails, unless it's
+    /// required.
+    pub fn apply(
+        &self,
+        name: &BStr,
+        direction: Direction,
+        root: &Path,
+        path: &WsPath,
+        data: BString,
+    ) -> Result<BString, Error> {
+        let driver = if let Some(driver) = self.drivers.get(name) {
+            driver
+        } else {
+            debug!("No filter driver {name} configured for {path}");
+            return Ok(data);
+        };
+
+        let filtered = if let Some(cmd) = &driver.process {
+            self.run_process(name, cmd, direction, root, path, &data)
+        } else if let Some(cmd) = driver.command(direction) {
+            run_command(cmd, root, path, &data).map(Some)
+        } else {
+            Ok(None)
+        };
+
+        match filtered {
+            Ok(Some(filtered)) => Ok(filtered),
+            Ok(None) if driver.required => {
+                Err(Error::Required(name.to_owned(), direction, path.clone()))
+            }
+            Err(err) if driver.required => Err(err),
+            Ok(None) => Ok(data),
+            Err(err) => {
+                warn!("{err}, leaving {path} unfiltered");
+                Ok(data)
+            }
+        }
+    }
+
+    /// `None` if the filter can't filter in `direction`.
+    fn run_process(
+        &self,
+        name: &BStr,
+        cmd: &str,
+        direction: Direction,
+        root: &Path,
+        path: &WsPath,
+        data: &[u8],
+    ) -> Result<Option<BString>, Error> {
+        let mut processes = self.processes.lock().expect("Filter process panicked");
+        if !processes.contains_key(name) {
+            let process = Process::start(cmd, root)?;
+            processes.insert(name.to_owned(), process);
+        }
+        let process = processes.get_mut(name).expect("Just inserted");
+
+        let result = process.filter(direction, path, data);
+        if let Err(Error::Io(..) | Error::Protocol(..)) = result {
+            // Start afresh for the next file rather than continue a broken
+            // conversation
+            processes.remove(name);
+        }
+        result.map_err(|e| e.for_command(cmd))
+    }
+}
+
+/// Runs a single-file filter through the shell, with `%f` replaced by the
+/// path.
+fn run_command(cmd: &str, root: &Path, path: &WsPath, data: &[u8]) -> Result<BString, Error> {
+    let quoted = format!("'{}'", path.to_string().replace('\'', r"'\''"));
+    let cmd = cmd.replace("%f", &quoted);
+    debug!("Running filter {cmd:?} for {path}");
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&cmd)
+        .current_dir(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Spawn(cmd.clone(), e))?;
+
+    // Writing from another thread, so the filter can't block us by filling
+    // its stdout before it has read all of its input
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = data.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| Error::Io(cmd.clone(), e))?;
+    match writer.join().expect("Writing to filter panicked") {
+        // Filters may finish without reading everything
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(Error::Io(cmd, err)),
+        _ => {}
+    }
+    if !output.status.success() {
+        return Err(Error::Failed(cmd, output.status));
+    }
+    Ok(output.stdout.into())
+}
+
+#[derive(Debug)]
+struct Process {
+    child: Child,
+    /// Taken when dropped, so the filter sees EOF and exits
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    capabilities: Vec<Direction>,
+}
+
+impl Process {
+    fn start(cmd: &str, root: &Path) -> Result<Self, Error> {
+        debug!("Starting filter process {cmd:?}");
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Spawn(cmd.to_owned(), e))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        let mut process = Self {
+            child,
+            stdin: Some(stdin),
+            stdout,
+            capabilities: Vec::new(),
+        };
+        process.handshake().map_err(|e| e.for_command(cmd))?;
+        Ok(process)
+    }
+
+    fn stdin(&mut self) -> &mut ChildStdin {
+        self.stdin.as_mut().expect("Only taken when dropped")
+    }
+
+    fn handshake(&mut self) -> Result<(), Error> {
+        let stdin = self.stdin();
+        write_packet(stdin, b"git-filter-client\n")?;
+        write_packet(stdin, b"version=2\n")?;
+        write_flush(stdin)?;
+
+        let welcome = read_list(&mut self.stdout)?;
+        if welcome.first().map(|l| l.as_bstr()) != Some(b"git-filter-server".as_bstr()) {
+            return Err(Error::Protocol(String::new(), "bad welcome".to_owned()));
+        }
+        if !welcome.iter().any(|l| l == "version=2") {
+            return Err(Error::Protocol(String::new(), "no version 2".to_owned()));
+        }
+
+        let stdin = self.stdin();
+        write_packet(stdin, b"capability=clean\n")?;
+        write_packet(stdin, b"capability=smudge\n")?;
+        write_flush(stdin)?;
+
+        for line in read_list(&mut self.stdout)? {
+            match line.as_bytes() {
+                b"capability=clean" => self.capabilities.push(Direction::Clean),
+                b"capability=smudge" => self.capabilities.push(Direction::Smudge),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn filter(
+        &mut self,
+        direction: Direction,
+        path: &WsPath,
+        data: &[u8],
+    ) -> Result<Option<BString>, Error> {
+        if !self.capabilities.contains(&direction) {
+            return Ok(None);
+        }
+
+        let stdin = self.stdin();
+        write_packet(stdin, format!("command={}\n", direction).as_bytes())?;
+        write_packet(stdin, format!("pathname={}\n", path).as_bytes())?;
+        write_flush(stdin)?;
+        for chunk in data.chunks(MAX_PACKET_DATA) {
+            write_packet(stdin, chunk)?;
+        }
+        write_flush(stdin)?;
+
+        let status = status_of(&read_list(&mut self.stdout)?);
+        if let Some(status) = status.filter(|s| s != "success") {
+            return self.failed(direction, path, status);
+        }
+
+        let mut filtered = BString::from(Vec::with_capacity(data.len()));
+        while let Some(packet) = read_packet(&mut self.stdout)? {
+            filtered.extend_from_slice(&packet);
+        }
+
+        // The filter can change its mind after sending the contents
+        if let Some(status) = status_of(&read_list(&mut self.stdout)?) {
+            if status != "success" {
+                return self.failed(direction, path, status);
+            }
+        }
+        Ok(Some(filtered))
+    }
+
+    fn failed(
+        &mut self,
+        direction: Direction,
+        path: &WsPath,
+        status: BString,
+    ) -> Result<Option<BString>, Error> {
+        if status == "abort" {
+            // Like git, stop asking the filter to do what it gave up on
+            self.capabilities.retain(|&d| d != direction);
+        }
+        Err(Error::Status(String::new(), path.clone(), status))
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        drop(self.stdin.take());
+        if let Err(err) = self.child.wait() {
+            warn!("Failed to wait for filter process: {err}");
+        }
+    }
+}
+
+fn status_of(list: &[BString]) -> Option<BString> {
+    list.iter()
+        .rev()
+        .find_map(|line| line.strip_prefix(b"status="))
+        .map(BString::from)
+}
+
+fn write_packet(out: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    write!(out, "{:04x}", data.len() + 4)?;
+    out.write_all(data)
+}
+
+fn write_flush(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(b"0000")?;
+    out.flush()
+}
+
+/// `None` for a flush packet.
+fn read_packet(input: &mut impl BufRead) -> Result<Option<Vec<u8>>, Error> {
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    let len = len
+        .to_str()
+        .ok()
+        .and_then(|len| usize::from_str_radix(len, 16).ok())
+        .ok_or_else(|| Error::Protocol(String::new(), format!("bad packet length {len:?}")))?;
+    match len {
+        0 => Ok(None),
+        1..=3 => Err(Error::Protocol(
+            String::new(),
+            format!("bad packet length {len}"),
+        )),
+        _ => {
+            let mut data = vec![0; len - 4];
+            input.read_exact(&mut data)?;
+            Ok(Some(data))
+        }
+    }
+}
+
+/// Reads text packets up to a flush, without their trailing newlines.
+fn read_list(input: &mut impl BufRead) -> Result<Vec<BString>, Error> {
+    let mut lines = Vec::new();
+    while let Some(mut packet) = read_packet(input)? {
+        if packet.last() == Some(&b'\n') {
+            packet.pop();
+        }
+        lines.push(packet.into());
+    }
+    Ok(lines)
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum Error {
+    /// Failed to start filter {0:?}
+    Spawn(String, #[source] io::Error),
+    /// Failed to talk to filter {0:?}
+    Io(String, #[source] io::Error),
+    /// Filter {0:?} failed with {1}
+    Failed(String, ExitStatus),
+    /// Filter {0:?} broke the filter protocol: {1}
+    Protocol(String, String),
+    /// Filter {0:?} reported status {2} for {1}
+    Status(String, WsPath, BString),
+    /// Required {1} filter {0} did not filter {2}
+    Required(BString, Direction, WsPath),
+}
+
+impl Error {
+    /// Errors talking to a process are created without knowing its command.
+    fn for_command(self, cmd: &str) -> Self {
+        match self {
+            Self::Io(_, err) => Self::Io(cmd.to_owned(), err),
+            Self::Protocol(_, msg) => Self::Protocol(cmd.to_owned(), msg),
+            Self::Status(_, path, status) => Self::Status(cmd.to_owned(), path, status),
+            err => err,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(String::new(), err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reads_and_writes_packets() -> eyre::Result<()> {
+        init();
+        let mut out = Vec::new();
+        write_packet(&mut out, b"version=2\n")?;
+        write_packet(&mut out, b"")?;
+        write_flush(&mut out)?;
+        assert_eq!(b"000eversion=2\n00040000".as_bstr(), out.as_bstr());
+
+        let mut input = out.as_slice();
+        assert_eq!(
+            vec![BString::from("version=2"), BString::from("")],
+            read_list(&mut input)?
+        );
+        assert!(input.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_packets() {
+        init();
+        assert!(matches!(
+            read_packet(&mut b"00zz".as_ref()),
+            Err(Error::Protocol(..))
+        ));
+        assert!(matches!(
+            read_packet(&mut b"0002".as_ref()),
+            Err(Error::Protocol(..))
+        ));
+        assert!(matches!(
+            read_packet(&mut b"0009abc".as_ref()),
+            Err(Error::Io(..))
+        ));
+    }
+
+    #[test]
+    fn finds_last_status() {
+        init();
+        let list = |lines: &[&str]| lines.iter().map(|&l| BString::from(l)).collect::<Vec<_>>();
+        assert_eq!(None, status_of(&list(&[])));
+        assert_eq!(
+            Some(BString::from("error")),
+            status_of(&list(&["status=success", "status=error"]))
+        );
+    }
+}
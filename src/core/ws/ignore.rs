@@ -0,0 +1,527 @@
+//! Gitignore patterns, see `gitignore(5)`.
+
+use bstr::{BStr, BString, ByteSlice};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::core::{config::xdg_config_home, Config};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Pattern {
+    /// The line as written, for display
+    pub original: BString,
+    glob: BString,
+    /// The directory of the `.gitignore` file the pattern came from, relative
+    /// to the workspace. Empty for patterns that apply to the whole workspace.
+    base: BString,
+    pub negated: bool,
+    dir_only: bool,
+    /// Patterns containing a slash are matched against the path relative to
+    /// [`Self::base`], others against just the file name.
+    anchored: bool,
+    pub source: Arc<PathBuf>,
+    /// 1-indexed
+    pub line: usize,
+}
+
+impl Pattern {
+    pub(super) fn parse(
+        line: &[u8],
+        base: &BStr,
+        source: &Arc<PathBuf>,
+        line_num: usize,
+    ) -> Option<Self> {
+        let original = trim_trailing_spaces(line);
+        if original.is_empty() || original[0] == b'#' {
+            return None;
+        }
+
+        let mut glob = original;
+        let negated = glob[0] == b'!';
+        if negated {
+            glob = &glob[1..];
+        }
+        if glob.starts_with(br"\!") || glob.starts_with(br"\#") {
+            glob = &glob[1..];
+        }
+
+        let dir_only = glob.ends_with(b"/");
+        if dir_only {
+            glob = &glob[..glob.len() - 1];
+        }
+
+        let anchored = glob.contains(&b'/');
+        if glob.starts_with(b"/") {
+            glob = &glob[1..];
+        }
+
+        if glob.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            original: original.into(),
+            glob: glob.into(),
+            base: base.to_owned(),
+            negated,
+            dir_only,
+            anchored,
+            source: Arc::clone(source),
+            line: line_num,
+        })
+    }
+
+    /// `path` is relative to the workspace.
+    pub fn matches(&self, path: &BStr, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let rel = if self.base.is_empty() {
+            path.as_bytes()
+        } else if let Some(rel) = path
+            .strip_prefix(self.base.as_bytes())
+            .and_then(|rest| rest.strip_prefix(b"/"))
+        {
+            rel
+        } else {
+            return false;
+        };
+
+        if self.anchored {
+            wildmatch(&self.glob, rel)
+        } else {
+            let name = rel.rsplit_str("/").next().unwrap_or(rel);
+            wildmatch(&self.glob, name)
+        }
+    }
+}
+
+/// Trailing spaces are ignored unless escaped with a backslash.
+fn trim_trailing_spaces(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let mut end = line.len();
+    while end > 0 && line[end - 1] == b' ' {
+        if end > 1 && line[end - 2] == b'\\' {
+            break;
+        }
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// The patterns from one file. Later patterns take precedence.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PatternList(Vec<Pattern>);
+
+impl PatternList {
+    pub fn parse(input: &BStr, base: &BStr, source: impl Into<PathBuf>) -> Self {
+        let source = Arc::new(source.into());
+        let patterns = input
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| Pattern::parse(line, base, &source, i + 1))
+            .collect();
+        Self(patterns)
+    }
+
+    /// A missing file has no patterns. `source` is how the file is named when
+    /// reporting matches.
+    pub fn load(
+        path: &Path,
+        base: &BStr,
+        source: impl Into<PathBuf>,
+    ) -> Result<Option<Self>, LoadError> {
+        match fs::read(path) {
+            Ok(input) => Ok(Some(Self::parse(input.as_bstr(), base, source))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(LoadError(path.to_owned(), err)),
+        }
+    }
+
+    fn last_match(&self, path: &BStr, is_dir: bool) -> Option<&Pattern> {
+        self.0.iter().rev().find(|p| p.matches(path, is_dir))
+    }
+}
+
+/// The patterns that apply to a directory: those from `.gitignore` files in it
+/// and its parents, `.git/info/exclude` and `core.excludesFile`.
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    /// In increasing order of precedence
+    lists: Vec<Arc<PatternList>>,
+}
+
+impl Excludes {
+    pub const FILE_NAME: &'static str = ".gitignore";
+
+    /// Loads the patterns that apply to the whole workspace. Those from
+    /// `.gitignore` files are loaded as directories are visited, see
+    /// [`Self::for_dir`].
+    pub fn load(git_dir: &Path, config: &Config) -> Result<Self, LoadError> {
+        let excludes_file = config
+            .get_path("core.excludesFile")
+            .or_else(|| xdg_config_home().map(|config_home| config_home.join("git/ignore")));
+
+        let mut lists = Vec::new();
+        if let Some(path) = excludes_file {
+            lists.extend(PatternList::load(&path, "".into(), &path)?);
+        }
+
+        let info_exclude = git_dir.join("info/exclude");
+        lists.extend(PatternList::load(
+            &info_exclude,
+            "".into(),
+            Path::new(".git/info/exclude"),
+        )?);
+
+        Ok(Self {
+            lists: lists.into_iter().map(Arc::new).collect(),
+        })
+    }
+
+    /// Adds the patterns from the `.gitignore` in `dir`, if there is one.
+    /// `dir` is relative to the workspace at `root`.
+    pub fn for_dir(&self, root: &Path, dir: &BStr) -> Result<Self, LoadError> {
+        let rel_file = if dir.is_empty() {
+            PathBuf::from(Self::FILE_NAME)
+        } else {
+            dir.to_path_lossy().join(Self::FILE_NAME)
+        };
+
+        let mut excludes = self.clone();
+        if let Some(list) = PatternList::load(&root.join(&rel_file), dir, rel_file)? {
+            excludes.lists.push(Arc::new(list));
+        }
+        Ok(excludes)
+    }
+
+    /// The pattern that decides whether `path` is ignored, if any. Only the
+    /// path itself is checked, not its parents. The path is ignored if the
+    /// pattern isn't negated.
+    pub fn matching(&self, path: &BStr, is_dir: bool) -> Option<&Pattern> {
+        self.lists
+            .iter()
+            .rev()
+            .find_map(|list| list.last_match(path, is_dir))
+    }
+
+    pub fn is_ignored(&self, path: &BStr, is_dir: bool) -> bool {
+        self.matching(path, is_dir).map_or(false, |p| !p.negated)
+    }
+}
+
+impl fmt::Display for Pattern {
+    /// In the format of `git check-ignore -v`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.source.display(),
+            self.line,
+            self.original
+        )
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Failed to read ignore file {0:?}
+pub struct LoadError(PathBuf, #[source] io::Error);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Wild {
+    Match,
+    NoMatch,
+    /// No later position in the text could match either
+    AbortAll,
+    /// Only a `**` earlier in the pattern could still produce a match
+    AbortToStarStar,
+}
+
+/// Matches like git's `wildmatch` with `WM_PATHNAME`: `*`, `?` and classes
+/// don't match `/`, but `**` between slashes matches any number of
+/// directories.
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    dowild(pattern, text, true) == Wild::Match
+}
+
+/// Matches like git's `wildmatch` without `WM_PATHNAME`, as `fnmatch` does:
+/// `/` is an ordinary character, so `*` and `?` match it too.
+pub fn fnmatch(pattern: &[u8], text: &[u8]) -> bool {
+    dowild(pattern, text, false) == Wild::Match
+}
+
+#[allow(clippy::too_many_lines)]
+fn dowild(p: &[u8], text: &[u8], pathname: bool) -> Wild {
+    let mut pi = 0;
+    let mut ti = 0;
+
+    while pi < p.len() {
+        let p_ch = p[pi];
+        let t_ch = text.get(ti).copied();
+        if t_ch.is_none() && p_ch != b'*' {
+            return Wild::AbortAll;
+        }
+        let t_ch = t_ch.unwrap_or(0);
+
+        match p_ch {
+            b'\\' => {
+                pi += 1;
+                if p.get(pi) != Some(&t_ch) {
+                    return Wild::NoMatch;
+                }
+            }
+            b'?' => {
+                if pathname && t_ch == b'/' {
+                    return Wild::NoMatch;
+                }
+            }
+            b'*' => {
+                let match_slash;
+                pi += 1;
+                if p.get(pi) == Some(&b'*') {
+                    let first_star = pi - 1;
+                    while p.get(pi) == Some(&b'*') {
+                        pi += 1;
+                    }
+                    let at_dir_start = first_star == 0 || p[first_star - 1] == b'/';
+                    let at_dir_end = pi == p.len()
+                        || p[pi] == b'/'
+                        || (p[pi] == b'\\' && p.get(pi + 1) == Some(&b'/'));
+                    if at_dir_start && at_dir_end {
+                        // "**/" can match nothing at all
+                        if p.get(pi) == Some(&b'/')
+                            && dowild(&p[pi + 1..], &text[ti..], pathname) == Wild::Match
+                        {
+                            return Wild::Match;
+                        }
+                        match_slash = true;
+                    } else {
+                        // Without surrounding slashes "**" is just "*"
+                        match_slash = !pathname;
+                    }
+                } else {
+                    match_slash = !pathname;
+                }
+
+                if pi == p.len() {
+                    // Trailing "*" matches the rest, unless it has a slash
+                    if !match_slash && text[ti..].contains(&b'/') {
+                        return Wild::AbortToStarStar;
+                    }
+                    return Wild::Match;
+                } else if !match_slash && p[pi] == b'/' {
+                    // "*/" only has to find the next slash
+                    match text[ti..].iter().position(|&c| c == b'/') {
+                        Some(offset) => ti += offset,
+                        None => return Wild::AbortAll,
+                    }
+                    // The slashes are consumed below
+                } else {
+                    while ti < text.len() {
+                        let matched = dowild(&p[pi..], &text[ti..], pathname);
+                        if matched != Wild::NoMatch {
+                            if !match_slash || matched != Wild::AbortToStarStar {
+                                return matched;
+                            }
+                        } else if !match_slash && text[ti] == b'/' {
+                            return Wild::AbortToStarStar;
+                        }
+                        ti += 1;
+                    }
+                    return Wild::AbortAll;
+                }
+            }
+            b'[' => {
+                pi += 1;
+                let negated = matches!(p.get(pi), Some(b'!' | b'^'));
+                if negated {
+                    pi += 1;
+                }
+
+                let mut matched = false;
+                let mut prev_ch = 0;
+                // The first character is part of the class even if it's "]"
+                let mut first = true;
+                loop {
+                    let mut c = match p.get(pi) {
+                        None => return Wild::AbortAll,
+                        Some(b']') if !first => break,
+                        Some(&c) => c,
+                    };
+                    first = false;
+
+                    if c == b'\\' {
+                        pi += 1;
+                        c = match p.get(pi) {
+                            None => return Wild::AbortAll,
+                            Some(&c) => c,
+                        };
+                        if t_ch == c {
+                            matched = true;
+                        }
+                    } else if c == b'-'
+                        && prev_ch != 0
+                        && p.get(pi + 1).map_or(false, |&next| next != b']')
+                    {
+                        pi += 1;
+                        let mut end = p[pi];
+                        if end == b'\\' {
+                            pi += 1;
+                            end = match p.get(pi) {
+                                None => return Wild::AbortAll,
+                                Some(&c) => c,
+                            };
+                        }
+                        if prev_ch <= t_ch && t_ch <= end {
+                            matched = true;
+                        }
+                        // A range can't start another range
+                        c = 0;
+                    } else if c == b'[' && p.get(pi + 1) == Some(&b':') {
+                        let class_start = pi + 2;
+                        let class_len = match p[class_start..].find(":]") {
+                            Some(len) => len,
+                            None => return Wild::AbortAll,
+                        };
+                        let class = &p[class_start..class_start + class_len];
+                        match char_class_matches(class, t_ch) {
+                            Some(true) => matched = true,
+                            Some(false) => {}
+                            None => return Wild::AbortAll,
+                        }
+                        pi = class_start + class_len + 1;
+                        c = 0;
+                    } else if t_ch == c {
+                        matched = true;
+                    }
+
+                    prev_ch = c;
+                    pi += 1;
+                }
+
+                if matched == negated || (pathname && t_ch == b'/') {
+                    return Wild::NoMatch;
+                }
+            }
+            _ => {
+                if t_ch != p_ch {
+                    return Wild::NoMatch;
+                }
+            }
+        }
+
+        pi += 1;
+        ti += 1;
+    }
+
+    if ti < text.len() {
+        Wild::NoMatch
+    } else {
+        Wild::Match
+    }
+}
+
+/// `None` if the class isn't known.
+fn char_class_matches(class: &[u8], c: u8) -> Option<bool> {
+    let matches = match class {
+        b"alnum" => c.is_ascii_alphanumeric(),
+        b"alpha" => c.is_ascii_alphabetic(),
+        b"blank" => c == b' ' || c == b'\t',
+        b"cntrl" => c.is_ascii_control(),
+        b"digit" => c.is_ascii_digit(),
+        b"graph" => c.is_ascii_graphic(),
+        b"lower" => c.is_ascii_lowercase(),
+        b"print" => c.is_ascii_graphic() || c == b' ',
+        b"punct" => c.is_ascii_punctuation(),
+        b"space" => c.is_ascii_whitespace() || c == 0x0b,
+        b"upper" => c.is_ascii_uppercase(),
+        b"xdigit" => c.is_ascii_hexdigit(),
+        _ => return None,
+    };
+    Some(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn wildmatch_matches_like_git() {
+        init();
+
+        let cases: &[(&str, &str, bool)] = &[
+            ("foo", "foo", true),
+            ("foo", "bar", false),
+            ("*.o", "main.o", true),
+            ("*.o", "src/main.o", false),
+            ("?oo", "foo", true),
+            ("?oo", "/oo", false),
+            ("a/*/c", "a/b/c", true),
+            ("a/*/c", "a/b/x/c", false),
+            ("**/foo", "foo", true),
+            ("**/foo", "a/b/foo", true),
+            ("foo/**", "foo/a/b", true),
+            ("foo/**", "foo", false),
+            ("a/**/b", "a/b", true),
+            ("a/**/b", "a/x/y/b", true),
+            ("a**b", "a/b", false),
+            ("a**b", "axxb", true),
+            ("[a-c]at", "bat", true),
+            ("[!a-c]at", "bat", false),
+            ("[!a-c]at", "rat", true),
+            ("[]]", "]", true),
+            ("[[:digit:]]x", "1x", true),
+            ("[[:digit:]]x", "ax", false),
+            (r"\*", "*", true),
+            (r"\*", "a", false),
+            ("*", "", true),
+        ];
+
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                *expected,
+                wildmatch(pattern.as_bytes(), text.as_bytes()),
+                "{} against {}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn later_patterns_and_deeper_files_take_precedence() {
+        init();
+
+        let root = PatternList::parse(
+            b"*.log\n!keep.log\nbuild/\n".as_bstr(),
+            "".into(),
+            ".gitignore",
+        );
+        let sub = PatternList::parse(
+            b"# comment\n\nkeep.log\n/local\n".as_bstr(),
+            "sub".into(),
+            "sub/.gitignore",
+        );
+        let excludes = Excludes {
+            lists: vec![Arc::new(root), Arc::new(sub)],
+        };
+
+        assert!(excludes.is_ignored("a.log".into(), false));
+        assert!(!excludes.is_ignored("keep.log".into(), false));
+        assert!(excludes.is_ignored("sub/keep.log".into(), false));
+        assert!(excludes.is_ignored("build".into(), true));
+        assert!(!excludes.is_ignored("build".into(), false));
+        assert!(excludes.is_ignored("sub/local".into(), false));
+        assert!(!excludes.is_ignored("local".into(), false));
+        assert!(!excludes.is_ignored("sub/x/local".into(), false));
+
+        let pattern = excludes.matching("sub/keep.log".into(), false).unwrap();
+        assert_eq!("sub/.gitignore:3:keep.log", pattern.to_string());
+    }
+}
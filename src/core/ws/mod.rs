@@ -1,98 +1,392 @@
+pub mod attributes;
+pub mod filter;
+pub mod ignore;
 pub mod path;
+pub mod sparse;
+pub use attributes::Attributes;
+pub use filter::Filters;
+pub use ignore::{Excludes, Pattern};
 pub use path::WsPath;
+pub use sparse::Sparse;
 
-use crate::core::Stat;
+use crate::core::{stat::Mode, Pathspec, Stat, StatusOptions, UntrackedFiles};
 
 use bstr::BString;
+use rayon::prelude::*;
 use std::{
-    fmt, fs, io,
+    fs,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
     path::{Path, PathBuf},
+    sync::Arc,
 };
-use tracing::instrument;
+use tracing::{debug, instrument};
 
 #[derive(Debug, Clone)]
 pub struct Workspace {
     path: PathBuf,
+    /// Patterns that apply to the whole workspace. Patterns from
+    /// `.gitignore` files are loaded as they're needed.
+    excludes: Excludes,
+    attributes: Attributes,
+    /// Shared between clones, so long-running filters are only started once
+    filters: Arc<Filters>,
+    sparse: Sparse,
+}
+
+/// What [`Workspace::walk`] finds.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Found {
+    File(WsPath),
+    /// A directory with no tracked files, reported instead of its files
+    UntrackedDir(WsPath),
+    IgnoredFile(WsPath),
+    /// An ignored directory with no tracked files
+    IgnoredDir(WsPath),
+}
+
+struct Walk<'p, F> {
+    pathspec: &'p Pathspec,
+    opts: StatusOptions,
+    is_tracked_dir: F,
 }
 
 impl Workspace {
     pub fn new<P: Into<PathBuf>>(path: P) -> Self {
-        Self { path: path.into() }
+        Self {
+            path: path.into(),
+            excludes: Excludes::default(),
+            attributes: Attributes::default(),
+            filters: Arc::default(),
+            sparse: Sparse::all(),
+        }
+    }
+
+    pub fn with_excludes(mut self, excludes: Excludes) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    pub fn with_attributes(mut self, attributes: Attributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn with_filters(mut self, filters: Filters) -> Self {
+        self.filters = Arc::new(filters);
+        self
+    }
+
+    pub fn with_sparse(mut self, sparse: Sparse) -> Self {
+        self.sparse = sparse;
+        self
+    }
+
+    pub fn attributes(&self) -> &Attributes {
+        &self.attributes
     }
 
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    pub fn sparse(&self) -> &Sparse {
+        &self.sparse
+    }
+
+    /// Lists the files matching `pathspec`, skipping those that are ignored.
+    /// Only the parts of the workspace that can contain matches are searched.
     #[instrument(err)]
-    pub fn find_files<I, P>(&self, paths: I) -> Result<Vec<WsPath>, ListFilesError>
+    pub fn find_files(&self, pathspec: &Pathspec) -> Result<Vec<WsPath>, ListFilesError> {
+        let opts = StatusOptions {
+            untracked: UntrackedFiles::All,
+            ..StatusOptions::default()
+        };
+        let files = self
+            .walk(pathspec, opts, |_| true)?
+            .into_iter()
+            .filter_map(|found| match found {
+                Found::File(path) => Some(path),
+                _ => None,
+            })
+            .collect();
+        Ok(files)
+    }
+
+    /// Finds what status reports about the workspace. Unless
+    /// [`UntrackedFiles::All`] is given, directories for which
+    /// `is_tracked_dir` is false are reported as a whole, and are searched
+    /// only as far as needed to tell whether they contain anything matching
+    /// `pathspec`.
+    ///
+    /// Paths left out of a sparse checkout aren't searched, so untracked
+    /// files there aren't found.
+    pub fn walk<F>(
+        &self,
+        pathspec: &Pathspec,
+        opts: StatusOptions,
+        is_tracked_dir: F,
+    ) -> Result<Vec<Found>, ListFilesError>
     where
-        I: IntoIterator<Item = P> + fmt::Debug,
-        P: AsRef<Path>,
+        F: Fn(&WsPath) -> bool + Sync,
     {
-        let mut files = Vec::new();
-
-        for rel_path in paths {
-            let rel_path = rel_path.as_ref();
-            let abs_path = self
-                .path
-                .join(rel_path)
-                .canonicalize()
-                .map_err(|e| ListFilesError::Canonicalize(rel_path.to_owned(), e))?;
-            self.list_files_in(&abs_path, &mut files)?;
+        let walk = Walk {
+            pathspec,
+            opts,
+            is_tracked_dir,
+        };
+        let mut found = Vec::new();
+
+        for root in pathspec.walk_roots() {
+            if Self::is_git_dir(&root) {
+                continue;
+            }
+
+            let abs_path = root.to_absolute(self);
+            let meta = match abs_path.metadata() {
+                Ok(meta) => meta,
+                // Matching nothing is for the caller to report
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(ListFilesError::GetMetadata(abs_path, err)),
+            };
+
+            let (excludes, ignored) = if root.as_bstr().is_empty() {
+                (self.excludes.clone(), false)
+            } else {
+                let (excludes, ignored_parent) = self.excludes_along(&root)?;
+                let ignored =
+                    ignored_parent.is_some() || excludes.is_ignored(root.as_bstr(), meta.is_dir());
+                (excludes, ignored)
+            };
+            if ignored && !opts.ignored {
+                debug!("Ignoring {}", root);
+                continue;
+            }
+
+            self.walk_in(
+                &walk, root, &abs_path, &meta, &excludes, ignored, &mut found,
+            )?;
         }
 
-        Ok(files)
+        Ok(found)
     }
 
-    #[instrument(err)]
-    pub fn list_files(&self) -> Result<Vec<WsPath>, ListFilesError> {
-        let mut files = Vec::new();
-        self.list_files_in(&self.path, &mut files)?;
-        Ok(files)
-    }
-
-    fn list_files_in(
+    /// `excludes` are those that apply in the parent of `abs_path`.
+    #[allow(clippy::too_many_arguments)]
+    fn walk_in<F>(
         &self,
+        walk: &Walk<F>,
+        rel_path: WsPath,
         abs_path: &Path,
-        files: &mut Vec<WsPath>,
-    ) -> Result<(), ListFilesError> {
-        // we re-compute this to canonicalize
-        let rel_path = abs_path
-            .strip_prefix(&self.path)
-            .map_err(|_| ListFilesError::OutsideOfWorkspace(abs_path.to_owned()))?;
-
-        let meta = abs_path
-            .metadata()
-            .map_err(|e| ListFilesError::GetMetadata(abs_path.to_owned(), e))?;
-
-        if Self::is_ignored(&rel_path) {
+        meta: &fs::Metadata,
+        excludes: &Excludes,
+        ignored: bool,
+        found: &mut Vec<Found>,
+    ) -> Result<(), ListFilesError>
+    where
+        F: Fn(&WsPath) -> bool + Sync,
+    {
+        if meta.is_file() || Self::is_nested_repo(&rel_path, abs_path, meta) {
+            if walk.pathspec.matches(&rel_path) && self.sparse.includes_file(&rel_path) {
+                found.push(if ignored {
+                    Found::IgnoredFile(rel_path)
+                } else {
+                    Found::File(rel_path)
+                });
+            }
+            return Ok(());
+        } else if !meta.is_dir() {
+            return Err(ListFilesError::InvalidFileType(rel_path.into_path_buf()));
+        } else if !self.sparse.includes_dir(&rel_path) {
+            debug!("Skipping {} outside sparse checkout", rel_path);
             return Ok(());
         }
 
-        if meta.is_dir() {
-            let children = abs_path
-                .read_dir()
-                .map_err(|e| ListFilesError::ReadDir(abs_path.to_owned(), e))?
-                .map(|entry| {
-                    let entry =
-                        entry.map_err(|e| ListFilesError::ReadDirEntry(abs_path.to_owned(), e))?;
-                    Ok(rel_path.join(entry.file_name()))
-                })
-                .collect::<Result<Vec<_>, ListFilesError>>()?;
-
-            files.extend(self.find_files(children)?);
-        } else if meta.is_file() {
-            files.push(WsPath::new_unchecked(rel_path));
+        let whole = !rel_path.as_bstr().is_empty()
+            && walk.opts.untracked != UntrackedFiles::All
+            && !(walk.is_tracked_dir)(&rel_path);
+        if !whole {
+            return self.walk_children(walk, &rel_path, abs_path, excludes, ignored, found);
+        }
+
+        if ignored {
+            if self.contains_match(walk, &rel_path, abs_path, None)? {
+                found.push(Found::IgnoredDir(rel_path));
+            }
+        } else if walk.opts.ignored {
+            // Ignored files in an untracked directory are still reported
+            // individually, so it has to be searched fully
+            let mut inner = Vec::new();
+            self.walk_children(walk, &rel_path, abs_path, excludes, false, &mut inner)?;
+            let mut any_untracked = false;
+            for inner in inner {
+                match inner {
+                    Found::File(_) | Found::UntrackedDir(_) => any_untracked = true,
+                    ignored => found.push(ignored),
+                }
+            }
+            if any_untracked && walk.opts.untracked == UntrackedFiles::Normal {
+                found.push(Found::UntrackedDir(rel_path));
+            }
+        } else if walk.opts.untracked == UntrackedFiles::Normal
+            && self.contains_match(walk, &rel_path, abs_path, Some(excludes))?
+        {
+            found.push(Found::UntrackedDir(rel_path));
+        }
+
+        Ok(())
+    }
+
+    fn walk_children<F>(
+        &self,
+        walk: &Walk<F>,
+        rel_path: &WsPath,
+        abs_path: &Path,
+        excludes: &Excludes,
+        ignored: bool,
+        found: &mut Vec<Found>,
+    ) -> Result<(), ListFilesError>
+    where
+        F: Fn(&WsPath) -> bool + Sync,
+    {
+        // Everything in an ignored directory is ignored, whatever it says
+        let excludes = if ignored {
+            excludes.clone()
         } else {
-            return Err(ListFilesError::InvalidFileType(rel_path.into()));
+            excludes.for_dir(&self.path, rel_path.as_bstr())?
+        };
+
+        let children = Self::read_dir(abs_path)?;
+
+        let child_found = children
+            .into_par_iter()
+            .map(|child| -> Result<_, ListFilesError> {
+                let mut found = Vec::new();
+                let child_rel = rel_path.join(child.file_name().expect("Has name"));
+                if Self::is_git_dir(&child_rel) {
+                    return Ok(found);
+                }
+
+                let meta = child
+                    .metadata()
+                    .map_err(|e| ListFilesError::GetMetadata(child.clone(), e))?;
+                let child_ignored =
+                    ignored || excludes.is_ignored(child_rel.as_bstr(), meta.is_dir());
+                if child_ignored && !walk.opts.ignored {
+                    debug!("Ignoring {}", child_rel);
+                    return Ok(found);
+                }
+
+                self.walk_in(
+                    walk,
+                    child_rel,
+                    &child,
+                    &meta,
+                    &excludes,
+                    child_ignored,
+                    &mut found,
+                )?;
+                Ok(found)
+            })
+            .collect::<Vec<_>>();
+        for child_found in child_found {
+            found.extend(child_found?);
         }
 
         Ok(())
     }
 
-    fn is_ignored(rel_path: &Path) -> bool {
-        rel_path.starts_with(".git")
+    /// Whether there's a file under `abs_path` matching the pathspec,
+    /// skipping ignored files unless `excludes` is `None`. Stops at the first.
+    fn contains_match<F>(
+        &self,
+        walk: &Walk<F>,
+        rel_path: &WsPath,
+        abs_path: &Path,
+        excludes: Option<&Excludes>,
+    ) -> Result<bool, ListFilesError> {
+        let excludes = match excludes {
+            Some(excludes) => Some(excludes.for_dir(&self.path, rel_path.as_bstr())?),
+            None => None,
+        };
+
+        for child in Self::read_dir(abs_path)? {
+            let child_rel = rel_path.join(child.file_name().expect("Has name"));
+            let meta = child
+                .metadata()
+                .map_err(|e| ListFilesError::GetMetadata(child.clone(), e))?;
+            if let Some(excludes) = &excludes {
+                if excludes.is_ignored(child_rel.as_bstr(), meta.is_dir()) {
+                    continue;
+                }
+            }
+
+            let matched = if meta.is_dir() && !Self::is_nested_repo(&child_rel, &child, &meta) {
+                self.contains_match(walk, &child_rel, &child, excludes.as_ref())?
+            } else {
+                walk.pathspec.matches(&child_rel)
+            };
+            if matched {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn read_dir(abs_path: &Path) -> Result<Vec<PathBuf>, ListFilesError> {
+        abs_path
+            .read_dir()
+            .map_err(|e| ListFilesError::ReadDir(abs_path.to_owned(), e))?
+            .map(|entry| {
+                let entry =
+                    entry.map_err(|e| ListFilesError::ReadDirEntry(abs_path.to_owned(), e))?;
+                Ok(entry.path())
+            })
+            .collect()
+    }
+
+    fn is_git_dir(rel_path: &WsPath) -> bool {
+        rel_path.as_path().starts_with(".git")
+    }
+
+    /// A directory with a `.git` of its own is another repository, such as a
+    /// submodule, so it's reported like a file rather than searched.
+    fn is_nested_repo(rel_path: &WsPath, abs_path: &Path, meta: &fs::Metadata) -> bool {
+        meta.is_dir()
+            && !rel_path.as_bstr().is_empty()
+            && abs_path.join(".git").symlink_metadata().is_ok()
+    }
+
+    /// The pattern deciding whether `path` is ignored, if any. If a parent
+    /// directory is ignored the path is too, and the pattern that matched the
+    /// parent is returned.
+    pub fn ignore_match(&self, path: &WsPath) -> Result<Option<Pattern>, ignore::LoadError> {
+        let (excludes, ignored_parent) = self.excludes_along(path)?;
+        if ignored_parent.is_some() {
+            return Ok(ignored_parent);
+        }
+
+        let is_dir = self.is_dir(path);
+        Ok(excludes.matching(path.as_bstr(), is_dir).cloned())
+    }
+
+    /// Loads the excludes that apply in the directory containing `path`,
+    /// stopping early at the first parent that is ignored.
+    fn excludes_along(
+        &self,
+        path: &WsPath,
+    ) -> Result<(Excludes, Option<Pattern>), ignore::LoadError> {
+        let mut excludes = self.excludes.for_dir(&self.path, "".into())?;
+        for parent in path.parents() {
+            if let Some(pattern) = excludes.matching(parent.as_bstr(), true) {
+                if !pattern.negated {
+                    return Ok((excludes.clone(), Some(pattern.clone())));
+                }
+            }
+            excludes = excludes.for_dir(&self.path, parent.as_bstr())?;
+        }
+        Ok((excludes, None))
     }
 
     pub fn read_file(&self, path: &WsPath) -> Result<BString, ReadFileError> {
@@ -100,6 +394,104 @@ impl Workspace {
         Ok(bytes.into())
     }
 
+    /// Converts the contents of the file at `path` to what's stored in the
+    /// repository, as decided by its attributes: first through its clean
+    /// filter, then converting line endings.
+    pub fn convert_to_git(&self, path: &WsPath, data: BString) -> Result<BString, ConvertError> {
+        let attrs = self.attributes.for_path(&self.path, path)?;
+        let data = match attrs.state("filter") {
+            attributes::State::Value(name) => self.filters.apply(
+                name.as_ref(),
+                filter::Direction::Clean,
+                &self.path,
+                path,
+                data,
+            )?,
+            _ => data,
+        };
+        Ok(self.attributes.convert_to_git(&attrs, data))
+    }
+
+    /// Converts contents stored in the repository to what's written to the
+    /// file at `path`, the reverse of [`Workspace::convert_to_git`].
+    pub fn convert_to_workspace(
+        &self,
+        path: &WsPath,
+        data: BString,
+    ) -> Result<BString, ConvertError> {
+        let attrs = self.attributes.for_path(&self.path, path)?;
+        let data = self.attributes.convert_to_workspace(&attrs, data);
+        match attrs.state("filter") {
+            attributes::State::Value(name) => Ok(self.filters.apply(
+                name.as_ref(),
+                filter::Direction::Smudge,
+                &self.path,
+                path,
+                data,
+            )?),
+            _ => Ok(data),
+        }
+    }
+
+    /// Removes the file and any parent directories left empty. A file that is
+    /// already missing is not an error.
+    pub fn remove_file(&self, path: &WsPath) -> Result<(), RemoveFileError> {
+        match fs::remove_file(path.to_absolute(self)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(RemoveFileError(path.clone(), err)),
+        }
+
+        for parent in path.parents().collect::<Vec<_>>().into_iter().rev() {
+            // Fails if the directory isn't empty
+            if fs::remove_dir(parent.to_absolute(self)).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `data` to the file at `path`, creating any missing parent
+    /// directories. Like git, the permissions of a new file are left to the
+    /// umask apart from whether it's executable, and a submodule is written
+    /// as an empty directory for `submodule update` to fill.
+    pub fn write_file(&self, path: &WsPath, data: &[u8], mode: Mode) -> Result<(), WriteFileError> {
+        let abs = path.to_absolute(self);
+        let write = || {
+            if let Some(parent) = abs.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let perms = match mode {
+                Mode::Regular => 0o666,
+                Mode::Executable => 0o777,
+                Mode::Gitlink => return fs::create_dir_all(&abs),
+            };
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(perms)
+                .open(&abs)?
+                .write_all(data)
+        };
+        write().map_err(|e| WriteFileError(path.clone(), e))
+    }
+
+    pub fn rename(&self, from: &WsPath, to: &WsPath) -> Result<(), RenameFileError> {
+        fs::rename(from.to_absolute(self), to.to_absolute(self))
+            .map_err(|e| RenameFileError(from.clone(), to.clone(), e))
+    }
+
+    /// Doesn't follow symlinks, so a dangling link still exists.
+    pub fn exists(&self, path: &WsPath) -> bool {
+        path.to_absolute(self).symlink_metadata().is_ok()
+    }
+
+    pub fn is_dir(&self, path: &WsPath) -> bool {
+        path.to_absolute(self).is_dir()
+    }
+
     pub fn stat(&self, path: &WsPath) -> Result<Stat, StatFileError> {
         self.path
             .join(path)
@@ -123,14 +515,34 @@ impl StatFileError {
 /// Failed to read file {0:?}
 pub struct ReadFileError(WsPath, io::Error);
 
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum ConvertError {
+    /// Failed to load attributes
+    Attributes(#[from] attributes::LoadError),
+    /// Failed to filter file
+    Filter(#[from] filter::Error),
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Failed to write file {0:?}
+pub struct WriteFileError(WsPath, #[source] io::Error);
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Failed to rename {0:?} to {1:?}
+pub struct RenameFileError(WsPath, WsPath, #[source] io::Error);
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Failed to remove file {0:?}
+pub struct RemoveFileError(WsPath, #[source] io::Error);
+
 #[derive(Debug, displaydoc::Display, thiserror::Error)]
 pub enum ListFilesError {
+    /// Failed to load ignore patterns
+    LoadExcludes(#[from] ignore::LoadError),
     /// {0:?} is neither a file nor a directory.
     InvalidFileType(PathBuf),
     /// Path {0:?} is outside the workspace
     OutsideOfWorkspace(PathBuf),
-    /// Failed to canonicalize path {0:?}
-    Canonicalize(PathBuf, #[source] io::Error),
     /// Failed to get metadata of {0:?}
     GetMetadata(PathBuf, #[source] io::Error),
     /// Failed to read directory {0:?}
@@ -159,7 +571,7 @@ mod tests {
         fs::write(dir.join("b"), "foo")?;
 
         let workspace = Workspace::new(dir);
-        let actual = workspace.find_files(vec!["."])?;
+        let actual = workspace.find_files(&Pathspec::all())?;
 
         let expected = vec![WsPath::new_unchecked("b")];
 
@@ -187,16 +599,17 @@ mod tests {
         fs::write(dir.join("dir_1/dir_a/e"), "")?;
 
         let workspace = Workspace::new(dir);
+        let pathspec = |spec| Pathspec::parse([spec], &WsPath::root(), &workspace);
 
-        let mut files = workspace.find_files(vec!["a"])?;
+        let mut files = workspace.find_files(&pathspec("a")?)?;
         files.sort();
         assert_debug_snapshot!("a", files);
 
-        let mut files = workspace.find_files(vec!["."])?;
+        let mut files = workspace.find_files(&pathspec(".")?)?;
         files.sort();
         assert_debug_snapshot!("dot", files);
 
-        let mut files = workspace.find_files(vec!["dir_1"])?;
+        let mut files = workspace.find_files(&pathspec("dir_1")?)?;
         files.sort();
         assert_debug_snapshot!("dir_1", files);
 
@@ -2,7 +2,7 @@ use std::{
     ffi::{OsStr, OsString},
     fmt,
     os::unix::prelude::{OsStrExt, OsStringExt},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use bstr::{BStr, BString, ByteSlice};
@@ -31,6 +31,38 @@ impl WsPath {
         }
     }
 
+    /// Like [`Self::new_canonicalized`], but purely lexical so that `path`
+    /// need not exist. `.` and `..` components are resolved without following
+    /// symlinks.
+    pub fn new_normalized(
+        path: impl AsRef<Path>,
+        workspace: &Workspace,
+    ) -> Result<Self, NewCanonicalizeError> {
+        let path = path.as_ref();
+        let abs = workspace.path().join(path);
+        let rel = abs
+            .strip_prefix(workspace.path())
+            .map_err(|_| NewCanonicalizeError::NotInWorkspace(path.to_owned()))?;
+
+        let mut normalized = PathBuf::new();
+        for component in rel.components() {
+            match component {
+                Component::Normal(name) => normalized.push(name),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return Err(NewCanonicalizeError::NotInWorkspace(path.to_owned()));
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(NewCanonicalizeError::NotInWorkspace(path.to_owned()))
+                }
+            }
+        }
+
+        Ok(Self(normalized))
+    }
+
     /// Path must be in canonical form and inside the workspace you use it with
     pub fn new_unchecked(path: impl Into<PathBuf>) -> Self {
         Self(path.into())
@@ -0,0 +1,274 @@
+//! Sparse checkout in cone mode, like `git sparse-checkout`: only some
+//! directories of the workspace are checked out. Index entries for the rest
+//! get the skip-worktree bit, so they're kept in commits without being in the
+//! workspace.
+//!
+//! Besides everything under the listed directories, the files directly in
+//! each of their parents are checked out, and so are the files at the top of
+//! the workspace. The directories are kept in `info/sparse-checkout` in the
+//! git directory as the patterns git writes for them: for `a/b`,
+//!
+//! ```text
+//! /*
+//! !/*/
+//! /a/
+//! !/a/*/
+//! /a/b/
+//! ```
+//!
+//! and they only apply when `core.sparseCheckout` is set, which like git we
+//! set in the worktree's own config so that other worktrees aren't affected.
+
+use bstr::{BStr, ByteSlice};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::core::{config::InvalidValueError, Config, WsPath};
+
+pub const FILE: &str = "info/sparse-checkout";
+
+const HEADER: &[&[u8]] = &[b"/*", b"!/*/"];
+
+/// Which parts of the workspace are checked out.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Sparse {
+    /// `None` if everything is checked out. None of them are inside another.
+    dirs: Option<BTreeSet<WsPath>>,
+}
+
+impl Sparse {
+    /// Checks out everything.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Checks out `dirs`, and the files in their parents.
+    pub fn cone(dirs: impl IntoIterator<Item = WsPath>) -> Self {
+        let dirs = dirs.into_iter().collect::<BTreeSet<_>>();
+        // Listing a directory inside another changes nothing
+        let outermost = dirs
+            .iter()
+            .filter(|dir| !dir.parents().any(|parent| dirs.contains(&parent)))
+            .cloned()
+            .collect();
+        Self {
+            dirs: Some(outermost),
+        }
+    }
+
+    /// The patterns in `info/sparse-checkout` apply if `core.sparseCheckout`
+    /// is set, and must be in cone mode.
+    pub fn load(git_dir: &Path, config: &Config) -> Result<Self, LoadError> {
+        if config.get_bool("core.sparseCheckout")? != Some(true) {
+            return Ok(Self::all());
+        }
+        let path = git_dir.join(FILE);
+        match fs::read(&path) {
+            Ok(contents) => Self::parse(contents.as_bstr()),
+            // Like git, no patterns means nothing is excluded
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::all()),
+            Err(err) => Err(LoadError::Read(path, err)),
+        }
+    }
+
+    /// Git writes the patterns of each parent directory, which only include
+    /// the files directly in it, followed by a pattern excluding its
+    /// subdirectories. The rest are the listed directories.
+    pub fn parse(contents: &BStr) -> Result<Self, LoadError> {
+        let mut included = Vec::new();
+        let mut parents = BTreeSet::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with(b"#") || HEADER.contains(&line) {
+                continue;
+            }
+            let not_cone = || LoadError::NotCone(i + 1);
+            if let Some(parent) = line.strip_prefix(b"!/") {
+                let parent = parent.strip_suffix(b"/*/").ok_or_else(not_cone)?;
+                parents.insert(unescape(parent));
+            } else {
+                let dir = line.strip_prefix(b"/").ok_or_else(not_cone)?;
+                let dir = dir.strip_suffix(b"/").ok_or_else(not_cone)?;
+                if dir.is_empty() || has_wildcard(dir) {
+                    return Err(not_cone());
+                }
+                included.push(unescape(dir));
+            }
+        }
+
+        let dirs = included
+            .into_iter()
+            .filter(|dir| !parents.contains(dir))
+            .collect::<Vec<_>>();
+        Ok(Self::cone(dirs))
+    }
+
+    /// Writes the patterns for this to `info/sparse-checkout` in `git_dir`.
+    pub fn write(&self, git_dir: &Path) -> Result<(), WriteError> {
+        let path = git_dir.join(FILE);
+        let write = || {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, self.to_patterns())
+        };
+        write().map_err(|e| WriteError(path.clone(), e))
+    }
+
+    pub fn to_patterns(&self) -> String {
+        let mut lines = HEADER
+            .iter()
+            .map(|line| line.to_str_lossy().into_owned())
+            .collect::<Vec<_>>();
+        let parents = self
+            .dirs()
+            .flat_map(WsPath::parents)
+            .collect::<BTreeSet<_>>();
+        for path in parents.iter().chain(self.dirs()).collect::<BTreeSet<_>>() {
+            let escaped = escape(path.as_bstr());
+            lines.push(format!("/{escaped}/"));
+            if parents.contains(path) {
+                lines.push(format!("!/{escaped}/*/"));
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        self.dirs.is_some()
+    }
+
+    /// The directories checked out, which is none if everything is.
+    pub fn dirs(&self) -> impl Iterator<Item = &WsPath> {
+        self.dirs.iter().flatten()
+    }
+
+    /// Whether the file at `path` is checked out.
+    pub fn includes_file(&self, path: &WsPath) -> bool {
+        self.includes_dir(&path.parent())
+    }
+
+    /// Whether anything in the directory at `path` is checked out, as it's
+    /// inside one of the directories or a parent of one.
+    pub fn includes_dir(&self, path: &WsPath) -> bool {
+        match &self.dirs {
+            None => true,
+            Some(dirs) => dirs.iter().any(|dir| {
+                path.as_path().starts_with(dir.as_path())
+                    || dir.as_path().starts_with(path.as_path())
+            }),
+        }
+    }
+}
+
+/// Git escapes the characters that are special in patterns.
+fn escape(path: &BStr) -> String {
+    let mut escaped = String::new();
+    for c in path.to_str_lossy().chars() {
+        if "\\*?[]!#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether there's a `*` in the pattern that isn't escaped.
+fn has_wildcard(pattern: &[u8]) -> bool {
+    let mut bytes = pattern.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\\' => {
+                bytes.next();
+            }
+            b'*' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn unescape(path: &[u8]) -> WsPath {
+    let mut unescaped = Vec::with_capacity(path.len());
+    let mut bytes = path.iter();
+    while let Some(&b) = bytes.next() {
+        if b == b'\\' {
+            if let Some(&next) = bytes.next() {
+                unescaped.push(next);
+            }
+        } else {
+            unescaped.push(b);
+        }
+    }
+    WsPath::new_unchecked_bytes(unescaped)
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum LoadError {
+    /// Failed to read sparse-checkout file {0:?}
+    Read(PathBuf, #[source] io::Error),
+    /// Invalid sparse-checkout config
+    Config(#[from] InvalidValueError),
+    /// Only cone mode sparse-checkout patterns are supported, unlike line {0}
+    NotCone(usize),
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+/// Failed to write sparse-checkout file {0:?}
+pub struct WriteError(PathBuf, #[source] io::Error);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::init;
+    use pretty_assertions::assert_eq;
+
+    fn path(path: &str) -> WsPath {
+        WsPath::new_unchecked(path)
+    }
+
+    #[test]
+    fn includes_dirs_and_files_of_parents() {
+        init();
+        let sparse = Sparse::cone(vec![path("a/b"), path("a/b/c"), path("d")]);
+
+        assert_eq!(
+            vec![&path("a/b"), &path("d")],
+            sparse.dirs().collect::<Vec<_>>()
+        );
+        for included in &["top.txt", "a/file", "a/b/file", "a/b/c/deep/file", "d/e/f"] {
+            assert!(sparse.includes_file(&path(included)), "{}", included);
+        }
+        for excluded in &["a/other/file", "x/file", "ab/file"] {
+            assert!(!sparse.includes_file(&path(excluded)), "{}", excluded);
+        }
+        assert!(sparse.includes_dir(&path("a")));
+        assert!(!sparse.includes_dir(&path("a/other")));
+        assert!(Sparse::all().includes_file(&path("x/file")));
+    }
+
+    #[test]
+    fn round_trips_patterns() -> eyre::Result<()> {
+        init();
+        let sparse = Sparse::cone(vec![path("a/b"), path("a/c"), path("we*rd")]);
+        let patterns = sparse.to_patterns();
+
+        assert_eq!("/*\n!/*/\n/a/\n!/a/*/\n/a/b/\n/a/c/\n/we\\*rd/\n", patterns);
+        assert_eq!(sparse, Sparse::parse(patterns.as_bytes().as_bstr())?);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_other_patterns() {
+        init();
+        let err = Sparse::parse(b"/*\n!/*/\n*.txt\n".as_bstr()).unwrap_err();
+        assert_eq!(
+            "Only cone mode sparse-checkout patterns are supported, unlike line 3",
+            err.to_string()
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use std::{
-    fmt,
+    env, fmt,
+    io::{self, Write},
     path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant},
 };
 
 use console::style;
@@ -8,19 +11,63 @@ use eyre::eyre;
 use structopt::StructOpt;
 use tracing::debug;
 
-use bstr::ByteSlice;
-
 use crate::core;
 
 #[derive(StructOpt, Debug, Clone)]
 pub enum Opt {
     Init {
+        /// Create a repository without a workspace, to be pushed to
+        #[structopt(long)]
+        bare: bool,
         #[structopt(default_value = ".")]
         dir: PathBuf,
     },
     Add {
+        /// Record only that the files will be added later
+        #[structopt(short = "N", long)]
+        intent_to_add: bool,
         files: Vec<PathBuf>,
     },
+    /// Set or clear flags of index entries
+    UpdateIndex {
+        #[structopt(long)]
+        assume_unchanged: bool,
+        #[structopt(long, conflicts_with = "assume-unchanged")]
+        no_assume_unchanged: bool,
+        #[structopt(long)]
+        skip_worktree: bool,
+        #[structopt(long, conflicts_with = "skip-worktree")]
+        no_skip_worktree: bool,
+        paths: Vec<PathBuf>,
+    },
+    /// Remove files from the index and the workspace
+    Rm {
+        /// Only remove from the index
+        #[structopt(long)]
+        cached: bool,
+        /// Allow recursive removal when a directory is given
+        #[structopt(short)]
+        recursive: bool,
+        /// Remove even if the files have changes that would be lost
+        #[structopt(long, short)]
+        force: bool,
+        #[structopt(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Move or rename files in the workspace and the index
+    Mv {
+        #[structopt(required = true)]
+        sources: Vec<PathBuf>,
+        dest: PathBuf,
+    },
+    /// Show whether paths are ignored
+    CheckIgnore {
+        /// Show the pattern that matched each path, even if it un-ignores it
+        #[structopt(long, short)]
+        verbose: bool,
+        #[structopt(required = true)]
+        paths: Vec<PathBuf>,
+    },
     Commit {
         #[structopt(long)]
         name: String,
@@ -29,10 +76,367 @@ pub enum Opt {
         #[structopt(long, short)]
         message: String,
     },
-    Status,
+    Status(StatusOpt),
+    /// Show the commits reachable from HEAD or the revisions, newest first
+    Log(LogOpt),
+    /// List the commits reachable from the revisions, newest first
+    #[structopt(name = "rev-list")]
+    RevList {
+        #[structopt(flatten)]
+        commits: CommitsOpt,
+    },
+    /// Show commits with their diffs, trees as lists of their entries,
+    /// blobs as they are, and annotated tags followed by what they tag
+    Show(ShowOpt),
+    /// Show how the workspace differs from the index, the index from HEAD,
+    /// or one commit from another
+    Diff(DiffOpt),
+    /// Watch the workspace so status only checks files that changed, used
+    /// when `core.fsmonitor` is set
+    #[structopt(name = "fsmonitor--daemon")]
+    FsmonitorDaemon(FsmonitorOpt),
+    /// Manage worktrees attached to the repository
+    Worktree(WorktreeOpt),
+    /// Manage submodules, other repositories checked out in this one
+    Submodule(SubmoduleOpt),
+    /// Check out only some directories of the workspace
+    #[structopt(name = "sparse-checkout")]
+    SparseCheckout(SparseCheckoutOpt),
     Plumb(PlumbOpt),
 }
 
+#[allow(clippy::struct_excessive_bools)]
+#[derive(StructOpt, Debug, Clone)]
+pub struct StatusOpt {
+    /// Which untracked files to show: no, normal or all
+    #[structopt(short, long, default_value = "normal")]
+    untracked_files: core::UntrackedFiles,
+    /// Show ignored files too
+    #[structopt(long)]
+    ignored: bool,
+    /// Give the output in the short format
+    #[structopt(short, long)]
+    short: bool,
+    /// Give the output in a stable format for scripts, v1 or v2
+    #[structopt(long, require_equals = true)]
+    #[allow(clippy::option_option)] // Given with or without a version
+    porcelain: Option<Option<core::status::format::Format>>,
+    /// Show the branch in the short and porcelain formats too
+    #[structopt(short, long)]
+    branch: bool,
+    /// Terminate entries with NUL. Implies --porcelain=v1 if no other
+    /// format is given
+    #[structopt(short = "z")]
+    nul_terminated: bool,
+    /// Don't detect renames, whatever `status.renames` says
+    #[structopt(long)]
+    no_renames: bool,
+    paths: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct LogOpt {
+    /// Show each commit on one line, short for --format=oneline
+    #[structopt(long, conflicts_with = "format")]
+    oneline: bool,
+    /// How to show each commit: medium, oneline, or placeholders like
+    /// "%h %s", optionally after format: or tformat:
+    #[structopt(long, alias = "pretty")]
+    format: Option<core::log::Format>,
+    #[structopt(flatten)]
+    commits: CommitsOpt,
+    #[structopt(flatten)]
+    patch: PatchOpt,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ShowOpt {
+    /// Show commits on one line, short for --format=oneline
+    #[structopt(long, conflicts_with = "format")]
+    oneline: bool,
+    /// How to show commits, like with log
+    #[structopt(long, alias = "pretty")]
+    format: Option<core::log::Format>,
+    /// Leave out diffs
+    #[structopt(short = "s", long)]
+    no_patch: bool,
+    #[structopt(flatten)]
+    patch: PatchOpt,
+    /// Revisions, or `<revision>:<path>` for a file or directory in
+    /// one
+    #[structopt(default_value = "HEAD")]
+    objects: Vec<String>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct DiffOpt {
+    /// Show how the index differs from HEAD
+    #[structopt(long, alias = "staged")]
+    cached: bool,
+    #[structopt(flatten)]
+    patch: PatchOpt,
+    /// Two commits to compare, as old new or old..new, followed by paths
+    /// to only show changes to them
+    args: Vec<String>,
+    /// Only show changes to these paths
+    #[structopt(last = true)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum FsmonitorOpt {
+    /// Start the daemon in the background
+    Start,
+    /// Run the daemon in the foreground
+    Run,
+    /// Stop the daemon
+    Stop,
+    /// Show whether the daemon is running
+    Status,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum WorktreeOpt {
+    /// Create a worktree at the path, with HEAD detached at the commit or
+    /// else the current HEAD
+    Add {
+        path: PathBuf,
+        commit: Option<String>,
+    },
+    /// List the worktrees
+    List {
+        /// Give the output in a stable format for scripts
+        #[structopt(long)]
+        porcelain: bool,
+    },
+    /// Remove a worktree
+    Remove {
+        /// Remove even if it's locked or has changes that would be lost
+        #[structopt(long, short)]
+        force: bool,
+        path: PathBuf,
+    },
+    /// Remove what's left of worktrees whose directories have gone
+    Prune {
+        /// Only report what would be removed
+        #[structopt(long, short = "n")]
+        dry_run: bool,
+        /// Report what's removed
+        #[structopt(long, short)]
+        verbose: bool,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum SubmoduleOpt {
+    /// Show the commit checked out in each submodule, prefixed with `-` if
+    /// it isn't checked out and `+` if it isn't the recorded commit
+    Status,
+    /// Register the submodules in .gitmodules in the repository's config
+    Init,
+    /// Check out the recorded commit in each registered submodule, cloning
+    /// it if needed
+    Update {
+        /// Register the submodules first, as with init
+        #[structopt(long)]
+        init: bool,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum SparseCheckoutOpt {
+    /// Check out only the directories, relative to the top of the
+    /// workspace, and the files in their parents
+    Set { dirs: Vec<String> },
+    /// List the directories checked out
+    List,
+    /// Check out everything again
+    Disable,
+}
+
+/// Which commits log and rev-list show, and in what order.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(StructOpt, Debug, Clone)]
+pub struct CommitsOpt {
+    /// Show at most this many commits
+    #[structopt(short = "n", long)]
+    max_count: Option<usize>,
+    /// Only show commits whose author matches the regex
+    #[structopt(long)]
+    author: Option<regex::bytes::Regex>,
+    /// Only show commits whose message matches the regex
+    #[structopt(long)]
+    grep: Option<regex::bytes::Regex>,
+    /// Only show commits more recent than the date
+    #[structopt(long, alias = "after")]
+    since: Option<String>,
+    /// Only show commits older than the date
+    #[structopt(long, alias = "before")]
+    until: Option<String>,
+    /// Show no commit before its children, keeping lines of history together
+    #[structopt(long, conflicts_with = "date-order")]
+    topo_order: bool,
+    /// Show no commit before its children, otherwise by date
+    #[structopt(long)]
+    date_order: bool,
+    /// Show the commits oldest first
+    #[structopt(long)]
+    reverse: bool,
+    /// Only follow the first parent of merges
+    #[structopt(long)]
+    first_parent: bool,
+    /// Revisions like main, ^old, old..new or left...right, followed by paths
+    /// to only show commits changing them
+    args: Vec<String>,
+    /// Only show commits changing these paths
+    #[structopt(last = true)]
+    paths: Vec<PathBuf>,
+}
+
+impl CommitsOpt {
+    /// The options, besides the revisions and paths in the arguments.
+    fn log_options(&self) -> eyre::Result<core::log::LogOptions> {
+        let now = chrono::Local::now();
+        let parse_date = |date: &Option<String>| {
+            date.as_ref()
+                .map(|date| core::log::parse_date(date, now))
+                .transpose()
+        };
+        let order = if self.topo_order {
+            core::rev_walk::Order::Topo
+        } else if self.date_order {
+            core::rev_walk::Order::Date
+        } else {
+            core::rev_walk::Order::Walk
+        };
+        Ok(core::log::LogOptions {
+            max_count: self.max_count,
+            author: self.author.clone(),
+            grep: self.grep.clone(),
+            since: parse_date(&self.since)?,
+            until: parse_date(&self.until)?,
+            order,
+            reverse: self.reverse,
+            first_parent: self.first_parent,
+            ..core::log::LogOptions::default()
+        })
+    }
+}
+
+/// How diffs are shown.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(StructOpt, Debug, Clone)]
+pub struct PatchOpt {
+    /// Show the patch, which diff does unless another output is asked for
+    #[structopt(short, long)]
+    patch: bool,
+    /// Show how many lines changed in each file, with a graph
+    #[structopt(long)]
+    stat: bool,
+    /// Show how many lines were added and deleted in each file
+    #[structopt(long)]
+    numstat: bool,
+    /// Show only how many files and lines changed in all
+    #[structopt(long)]
+    shortstat: bool,
+    /// Show the files created and deleted and whose modes changed
+    #[structopt(long)]
+    summary: bool,
+    /// Show only the names of the files that changed
+    #[structopt(long, conflicts_with = "name-status")]
+    name_only: bool,
+    /// Show only the names of the files that changed and how
+    #[structopt(long)]
+    name_status: bool,
+    /// Show this many unchanged lines around changes
+    #[structopt(short = "U", long, default_value = "3")]
+    unified: usize,
+    /// How to find changed lines: myers, minimal, patience or histogram.
+    /// Defaults to `diff.algorithm`
+    #[structopt(long)]
+    diff_algorithm: Option<core::diff::Algorithm>,
+    /// Ignore whitespace when comparing lines
+    #[structopt(short = "w", long)]
+    ignore_all_space: bool,
+    /// Ignore changes in the amount of whitespace, and whitespace at the end
+    /// of lines
+    #[structopt(short = "b", long)]
+    ignore_space_change: bool,
+    /// Show the words that changed, marked as plain text or only by colour
+    #[structopt(long, require_equals = true)]
+    #[allow(clippy::option_option)] // Given with or without a mode
+    word_diff: Option<Option<core::diff::words::WordDiff>>,
+}
+
+impl PatchOpt {
+    fn patch_options(
+        &self,
+        config: &core::Config,
+    ) -> eyre::Result<core::diff::patch::PatchOptions> {
+        use core::diff::{words::WordDiff, Algorithm, LineOptions, Whitespace};
+
+        let algorithm = match self.diff_algorithm {
+            Some(algorithm) => algorithm,
+            None => Algorithm::from_config(config)?,
+        };
+        let whitespace = if self.ignore_all_space {
+            Whitespace::IgnoreAll
+        } else if self.ignore_space_change {
+            Whitespace::IgnoreChange
+        } else {
+            Whitespace::All
+        };
+        Ok(core::diff::patch::PatchOptions {
+            context: self.unified,
+            color: console::colors_enabled(),
+            lines: LineOptions {
+                algorithm,
+                whitespace,
+            },
+            word_diff: self.word_diff.map(|mode| mode.unwrap_or(WordDiff::Plain)),
+            stat_width: stat_width(),
+        })
+    }
+
+    /// Which outputs to show, with the patch if no other is asked for and
+    /// `default_patch` is set.
+    fn formats(&self, default_patch: bool) -> core::diff::format::Formats {
+        use core::diff::format::{Formats, Names};
+
+        let names = if self.name_only {
+            Some(Names::Only)
+        } else if self.name_status {
+            Some(Names::Status)
+        } else {
+            None
+        };
+        let other = self.stat || self.numstat || self.shortstat || self.summary;
+        Formats {
+            patch: self.patch || (default_patch && !other && names.is_none()),
+            stat: self.stat,
+            numstat: self.numstat,
+            shortstat: self.shortstat,
+            summary: self.summary,
+            names,
+        }
+    }
+}
+
+/// Like git, stats are as wide as `COLUMNS` says, or else the terminal.
+fn stat_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+        .or_else(|| {
+            let term = console::Term::stdout();
+            term.size_checked()
+                .filter(|_| term.is_term())
+                .map(|(_, columns)| columns.into())
+        })
+        .unwrap_or(80)
+}
+
 #[derive(StructOpt, Debug, Clone)]
 pub enum PlumbOpt {
     ShowHead,
@@ -40,6 +444,9 @@ pub enum PlumbOpt {
 
 pub struct Ui {
     repo: core::Repo,
+    /// Path arguments are relative to this, and paths are shown relative to
+    /// it.
+    cwd: PathBuf,
 }
 
 #[macro_export]
@@ -51,33 +458,76 @@ macro_rules! println_style {
 }
 
 impl Ui {
-    pub fn new(repo: core::Repo) -> Self {
-        Self { repo }
+    pub fn new(repo: core::Repo, cwd: PathBuf) -> Self {
+        Self { repo, cwd }
     }
+
     pub fn for_current_dir() -> eyre::Result<Self> {
         let repo = core::Repo::for_current_dir()?;
-        Ok(Self::new(repo))
+        let cwd = env::current_dir()?.canonicalize()?;
+        Ok(Self::new(repo, cwd))
+    }
+
+    fn resolve_paths<I, P>(&self, paths: I) -> Vec<PathBuf>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        paths.into_iter().map(|p| self.cwd.join(p)).collect()
+    }
+
+    /// Pathspecs given as arguments are relative to the current directory.
+    fn pathspec<I, P>(&self, specs: I) -> eyre::Result<core::Pathspec>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let workspace = self.repo.workspace()?;
+        let prefix = core::WsPath::new_normalized(&self.cwd, workspace)?;
+        Ok(core::Pathspec::parse(specs, &prefix, workspace)?)
     }
 
-    pub fn init(workspace: impl Into<PathBuf>) -> eyre::Result<Self> {
-        let workspace = workspace.into();
+    fn display_path(&self, path: &core::WsPath) -> String {
+        // Only operations on a workspace give paths in it
+        let path = match self.repo.workspace() {
+            Ok(workspace) => path.to_absolute(workspace),
+            Err(_) => path.as_path().to_owned(),
+        };
+        let rel = relative_to(&path, &self.cwd);
+        if rel.as_os_str().is_empty() {
+            ".".to_owned()
+        } else {
+            rel.to_string_lossy().into_owned()
+        }
+    }
+
+    /// Directories reported as a whole get a trailing slash, like git.
+    fn display_status_path(&self, status: &core::FileStatus) -> String {
+        let mut path = self.display_path(&status.path);
+        if status.is_dir {
+            path.push('/');
+        }
+        path
+    }
 
-        let workspace_name = workspace.clone();
-        let workspace_name = workspace_name.to_string_lossy();
+    pub fn init(dir: impl Into<PathBuf>, opts: core::repo::InitOptions) -> eyre::Result<Self> {
+        let dir = dir.into();
+        let name = dir.to_string_lossy().into_owned();
 
-        let repo = core::Repo::init(workspace)?;
+        let repo = core::Repo::init_with_options(dir, opts)?;
 
-        println!("Initialized repository in {}", workspace_name);
+        println!("Initialized repository in {}", name);
 
-        Ok(Self::new(repo))
+        let cwd = env::current_dir()?.canonicalize()?;
+        Ok(Self::new(repo, cwd))
     }
 
-    pub fn add<I, P>(&mut self, files: I) -> eyre::Result<()>
+    pub fn add<I, P>(&mut self, files: I, opts: core::repo::AddOptions) -> eyre::Result<()>
     where
         I: IntoIterator<Item = P> + fmt::Debug,
         P: AsRef<Path>,
     {
-        let added = self.repo.add(files)?;
+        let added = self.repo.add_with_options(self.pathspec(files)?, opts)?;
 
         if added.is_empty() {
             return Err(eyre!("No files match paths specified"));
@@ -85,13 +535,52 @@ impl Ui {
 
         let added = added
             .iter()
-            .map(|p| p.as_bstr().to_str_lossy())
+            .map(|p| self.display_path(p))
             .collect::<Vec<_>>()
             .join(", ");
         println_style!("Added file(s): {added}".green());
         Ok(())
     }
 
+    pub fn update_index<I, P>(
+        &mut self,
+        paths: I,
+        opts: core::repo::UpdateIndexOptions,
+    ) -> eyre::Result<()>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        self.repo.update_index(self.resolve_paths(paths), opts)?;
+        Ok(())
+    }
+
+    pub fn rm<I, P>(&mut self, paths: I, opts: core::repo::RmOptions) -> eyre::Result<()>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        for path in self.repo.rm(self.pathspec(paths)?, opts)? {
+            let path = self.display_path(&path);
+            println!("rm '{path}'");
+        }
+        Ok(())
+    }
+
+    pub fn mv<I, P>(&mut self, sources: I, dest: impl AsRef<Path> + fmt::Debug) -> eyre::Result<()>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        let sources = self.resolve_paths(sources);
+        let dest = self.cwd.join(dest);
+        for (from, to) in self.repo.mv(sources, dest)? {
+            let (from, to) = (self.display_path(&from), self.display_path(&to));
+            println!("Renamed {from} -> {to}");
+        }
+        Ok(())
+    }
+
     pub fn commit(
         &mut self,
         name: impl Into<String> + fmt::Debug,
@@ -103,24 +592,31 @@ impl Ui {
         Ok(())
     }
 
-    pub fn status(&mut self) -> eyre::Result<()> {
-        let status = self.repo.status()?;
+    pub fn status<I, P>(&mut self, paths: I, opts: core::StatusOptions) -> eyre::Result<()>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        let status = self.repo.status_with_options(self.pathspec(paths)?, opts)?;
 
         let mut to_commit = Vec::new();
+        let mut unmerged = Vec::new();
         let mut not_staged = Vec::new();
         let mut untracked = Vec::new();
+        let mut ignored = Vec::new();
 
         for (path, status) in status {
-            if status.workspace == core::Status::Untracked {
-                untracked.push(path);
+            if let core::Status::Unmerged(_) = status.index {
+                unmerged.push((path, status.index));
+            } else if status.workspace == core::Status::Untracked {
+                untracked.push(self.display_status_path(&status));
+            } else if status.workspace == core::Status::Ignored {
+                ignored.push(self.display_status_path(&status));
             } else {
-                let index = status.index;
-                let ws = status.workspace;
-
-                if index != core::Status::Unmodified {
+                if status.index != core::Status::Unmodified {
                     to_commit.push((path.clone(), status.index));
                 }
-                if ws != core::Status::Unmodified {
+                if status.workspace != core::Status::Unmodified {
                     not_staged.push((path, status.workspace));
                 }
             }
@@ -129,15 +625,32 @@ impl Ui {
         if !to_commit.is_empty() {
             println!("Changes to be committed:");
             for (path, status) in to_commit {
+                let mut path = self.display_path(&path);
+                if let core::Status::Renamed { from, .. } | core::Status::Copied { from, .. } =
+                    &status
+                {
+                    path = format!("{} -> {}", self.display_path(from), path);
+                }
                 let status = style(status.name());
                 println_style!("    {status}: {path}".green());
             }
             println!();
         }
 
+        if !unmerged.is_empty() {
+            println!("Unmerged paths:");
+            for (path, status) in unmerged {
+                let path = self.display_path(&path);
+                let status = status.name();
+                println_style!("    {status}: {path}".red());
+            }
+            println!();
+        }
+
         if !not_staged.is_empty() {
             println!("Changes not staged for commit:");
             for (path, status) in not_staged {
+                let path = self.display_path(&path);
                 let status = status.name();
                 println_style!("    {status}: {path}".green());
             }
@@ -152,9 +665,456 @@ impl Ui {
             println!();
         }
 
+        if !ignored.is_empty() {
+            println!("Ignored files:");
+            for path in ignored {
+                println_style!("    {path}".red());
+            }
+            println!();
+        }
+
+        Ok(())
+    }
+
+    pub fn status_formatted<I, P>(
+        &mut self,
+        paths: I,
+        opts: core::StatusOptions,
+        format: core::status::format::FormatOptions,
+    ) -> eyre::Result<()>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        let status = self.repo.status_with_options(self.pathspec(paths)?, opts)?;
+        let head = self.repo.refs.head()?;
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        core::status::format::write(&mut out, status.values(), head, format, |path| {
+            self.display_path(path).into()
+        })?;
+        out.flush()?;
         Ok(())
     }
 
+    /// The commits `opts` selects, see [`Self::log_options`].
+    fn commits(&mut self, opts: &CommitsOpt) -> eyre::Result<Vec<core::db::Commit>> {
+        let log_opts = self.log_options(opts)?;
+        Ok(self.repo.log(&log_opts)?)
+    }
+
+    /// Like git, each argument before `--` is a revision if it names
+    /// commits, and otherwise must be an existing path, after which the rest
+    /// are paths too.
+    fn log_options(&mut self, opts: &CommitsOpt) -> eyre::Result<core::log::LogOptions> {
+        let mut log_opts = opts.log_options()?;
+        let mut paths = Vec::new();
+        for arg in &opts.args {
+            let revision = core::Revision::parse(arg);
+            let names_commits = revision
+                .names()
+                .into_iter()
+                .all(|name| self.repo.resolve(name).is_ok());
+            if paths.is_empty() && names_commits {
+                log_opts.revisions.push(revision);
+            } else if self.cwd.join(arg).exists() {
+                paths.push(PathBuf::from(arg));
+            } else {
+                return Err(eyre!(
+                    "ambiguous argument '{}': unknown revision or path not in the working tree",
+                    arg
+                ));
+            }
+        }
+        paths.extend(opts.paths.iter().cloned());
+        if !paths.is_empty() {
+            log_opts.pathspec = self.pathspec(paths)?;
+        }
+        Ok(log_opts)
+    }
+
+    /// Each commit is shown with how it changed the files in `formats`, see
+    /// [`core::Repo::diff_commit`].
+    pub fn log(
+        &mut self,
+        opts: &CommitsOpt,
+        format: &core::log::Format,
+        formats: &core::diff::format::Formats,
+        patch_opts: &core::diff::patch::PatchOptions,
+    ) -> eyre::Result<()> {
+        let log_opts = self.log_options(opts)?;
+        let commits = self.repo.log(&log_opts)?;
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let color = console::colors_enabled();
+        let shows_diffs = *formats != core::diff::format::Formats::default();
+        let repo = &mut self.repo;
+        core::log::format::write_with_diffs(
+            &mut out,
+            &commits,
+            format,
+            color,
+            formats.dashes(),
+            |commit| -> eyre::Result<Vec<u8>> {
+                let mut diff = Vec::new();
+                if shows_diffs {
+                    let diffs = repo.diff_commit(commit, &log_opts.pathspec)?;
+                    core::diff::format::write(&mut diff, &diffs, formats, patch_opts)?;
+                }
+                Ok(diff)
+            },
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Like `log`, except that `names` can be of any objects, commits'
+    /// ancestors aren't shown and merges are diffed against their first
+    /// parent.
+    pub fn show(
+        &mut self,
+        names: &[String],
+        format: &core::log::Format,
+        formats: &core::diff::format::Formats,
+        patch_opts: &core::diff::patch::PatchOptions,
+    ) -> eyre::Result<()> {
+        let mut objects = Vec::new();
+        for name in names {
+            let oid = self.repo.resolve_object(name)?;
+            for object in core::log::show::objects(&mut self.repo.db, oid)? {
+                objects.push((name.as_str(), object));
+            }
+        }
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let color = console::colors_enabled();
+        let shows_diffs = *formats != core::diff::format::Formats::default();
+        let repo = &mut self.repo;
+        let pathspec = core::Pathspec::default();
+        core::log::show::write(
+            &mut out,
+            objects.iter().map(|(name, object)| (*name, object)),
+            format,
+            color,
+            formats.dashes(),
+            |commit| -> eyre::Result<Vec<u8>> {
+                let mut diff = Vec::new();
+                if shows_diffs {
+                    let diffs = repo.diff_first_parent(commit, &pathspec)?;
+                    core::diff::format::write(&mut diff, &diffs, formats, patch_opts)?;
+                }
+                Ok(diff)
+            },
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn rev_list(&mut self, opts: &CommitsOpt) -> eyre::Result<()> {
+        let commits = self.commits(opts)?;
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for commit in commits {
+            writeln!(out, "{}", commit.oid.to_hex())?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Like `commits`, the arguments before `--` that name commits are
+    /// revisions and the rest are paths.
+    pub fn diff(
+        &mut self,
+        cached: bool,
+        args: &[String],
+        paths: &[PathBuf],
+        formats: &core::diff::format::Formats,
+        opts: &core::diff::patch::PatchOptions,
+    ) -> eyre::Result<()> {
+        let mut revisions = Vec::new();
+        let mut all_paths = Vec::new();
+        for arg in args {
+            let revision = core::Revision::parse(arg);
+            let names_commits = match &revision {
+                core::Revision::Include(name) => self.repo.resolve(name).is_ok(),
+                core::Revision::Range { from, to } => {
+                    self.repo.resolve(from).is_ok() && self.repo.resolve(to).is_ok()
+                }
+                _ => false,
+            };
+            if all_paths.is_empty() && names_commits {
+                revisions.push(revision);
+            } else if self.cwd.join(arg).exists() {
+                all_paths.push(PathBuf::from(arg));
+            } else {
+                return Err(eyre!(
+                    "ambiguous argument '{}': unknown revision or path not in the working tree",
+                    arg
+                ));
+            }
+        }
+        all_paths.extend(paths.iter().cloned());
+        let pathspec = self.pathspec(all_paths)?;
+
+        let names = revisions
+            .iter()
+            .flat_map(core::Revision::names)
+            .collect::<Vec<_>>();
+        let diffs = match names.as_slice() {
+            [] if cached => self.repo.diff_cached(pathspec)?,
+            [] => self.repo.diff_workspace(pathspec)?,
+            [old, new] if !cached => {
+                let old = self.repo.resolve(old)?;
+                let new = self.repo.resolve(new)?;
+                self.repo.diff_commits(Some(old), new, &pathspec)?
+            }
+            _ => {
+                return Err(eyre!(
+                    "Give either two commits, or none with or without --cached"
+                ))
+            }
+        };
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        core::diff::format::write(&mut out, &diffs, formats, opts)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Returns whether any of the paths are ignored.
+    pub fn check_ignore<I, P>(&mut self, paths: I, verbose: bool) -> eyre::Result<bool>
+    where
+        I: IntoIterator<Item = P> + fmt::Debug,
+        P: AsRef<Path>,
+    {
+        let mut any_ignored = false;
+        for (path, pattern) in self.repo.check_ignore(self.resolve_paths(paths))? {
+            let pattern = if let Some(pattern) = pattern {
+                pattern
+            } else {
+                continue;
+            };
+
+            any_ignored |= !pattern.negated;
+            let path = self.display_path(&path);
+            if verbose {
+                println!("{pattern}\t{path}");
+            } else if !pattern.negated {
+                println!("{path}");
+            }
+        }
+        Ok(any_ignored)
+    }
+
+    pub fn fsmonitor_start(&self) -> eyre::Result<()> {
+        let git_dir = self.repo.git_dir();
+        if core::fsmonitor::is_running(git_dir) {
+            return Err(eyre!("fsmonitor daemon is already running"));
+        }
+
+        process::Command::new(env::current_exe()?)
+            .args(["fsmonitor--daemon", "run"])
+            .current_dir(self.repo.workspace()?.path())
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .spawn()?;
+
+        // Only report success once the daemon is answering queries
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !core::fsmonitor::is_running(git_dir) {
+            if Instant::now() > deadline {
+                return Err(eyre!("fsmonitor daemon failed to start"));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Ok(())
+    }
+
+    pub fn fsmonitor_run(&self) -> eyre::Result<()> {
+        let daemon =
+            core::fsmonitor::Daemon::start(self.repo.git_dir(), self.repo.workspace()?.path())?;
+        daemon.wait()?;
+        Ok(())
+    }
+
+    pub fn fsmonitor_stop(&self) -> eyre::Result<()> {
+        core::fsmonitor::stop(self.repo.git_dir())?;
+        Ok(())
+    }
+
+    /// Returns whether the daemon is running.
+    pub fn fsmonitor_status(&self) -> eyre::Result<bool> {
+        let running = core::fsmonitor::is_running(self.repo.git_dir());
+        let workspace = self.repo.workspace()?.path().display();
+        if running {
+            println!("fsmonitor daemon is watching '{workspace}'");
+        } else {
+            println!("fsmonitor daemon is not watching '{workspace}'");
+        }
+        Ok(running)
+    }
+
+    pub fn worktree_add(
+        &mut self,
+        path: impl AsRef<Path>,
+        commit: Option<core::Oid<core::db::Commit>>,
+    ) -> eyre::Result<()> {
+        let opts = core::repo::WorktreeAddOptions { commit };
+        let repo = self.repo.worktree_add(self.cwd.join(path), opts)?;
+        if let Some(head) = repo.refs.head()? {
+            println!("Preparing worktree (detached HEAD {})", short_oid(&head));
+        }
+        Ok(())
+    }
+
+    pub fn worktree_list(&self, porcelain: bool) -> eyre::Result<()> {
+        let worktrees = core::worktree::list(self.repo.common_dir())?;
+
+        if porcelain {
+            for worktree in worktrees {
+                println!("worktree {}", worktree.path.display());
+                if worktree.bare {
+                    println!("bare");
+                    println!();
+                    continue;
+                }
+                if let Some(head) = worktree.head {
+                    println!("HEAD {}", head.to_hex());
+                }
+                println!("detached");
+                match worktree.locked.as_deref() {
+                    Some("") => println!("locked"),
+                    Some(reason) => println!("locked {reason}"),
+                    None => {}
+                }
+                if let Some(reason) = worktree.prunable {
+                    println!("prunable {reason}");
+                }
+                println!();
+            }
+            return Ok(());
+        }
+
+        let width = worktrees
+            .iter()
+            .map(|w| w.path.display().to_string().len())
+            .max()
+            .unwrap_or(0);
+        for worktree in worktrees {
+            let path = worktree.path.display().to_string();
+            if worktree.bare {
+                println!("{path:width$} (bare)");
+                continue;
+            }
+            let head = worktree
+                .head
+                .map_or_else(|| "0".repeat(7), |head| short_oid(&head));
+            let mut line = format!("{path:width$} {head} (detached HEAD)");
+            if worktree.locked.is_some() {
+                line.push_str(" locked");
+            }
+            if worktree.prunable.is_some() {
+                line.push_str(" prunable");
+            }
+            println!("{line}");
+        }
+        Ok(())
+    }
+
+    pub fn worktree_remove(&self, path: impl AsRef<Path>, force: bool) -> eyre::Result<()> {
+        self.repo.worktree_remove(self.cwd.join(path), force)?;
+        Ok(())
+    }
+
+    pub fn worktree_prune(&self, dry_run: bool, verbose: bool) -> eyre::Result<()> {
+        for worktree in core::worktree::prune(self.repo.common_dir(), dry_run)? {
+            if dry_run || verbose {
+                let name = worktree.name.unwrap_or_default();
+                let reason = worktree.prunable.unwrap_or_default();
+                println!("Removing worktrees/{name}: {reason}");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn submodule_status(&mut self) -> eyre::Result<()> {
+        for status in self.repo.submodule_status()? {
+            let commit = status
+                .commit()
+                .map_or_else(|| "0".repeat(40), |commit| commit.to_hex());
+            let path = self.display_path(&status.submodule.path);
+            println!("{}{} {}", status.prefix(), commit, path);
+        }
+        Ok(())
+    }
+
+    pub fn submodule_init(&mut self) -> eyre::Result<()> {
+        for submodule in self.repo.submodule_init()? {
+            println!(
+                "Submodule '{}' ({}) registered for path '{}'",
+                submodule.name,
+                submodule.url.unwrap_or_default(),
+                self.display_path(&submodule.path)
+            );
+        }
+        Ok(())
+    }
+
+    pub fn submodule_update(&mut self, init: bool) -> eyre::Result<()> {
+        if init {
+            self.submodule_init()?;
+        }
+        for (submodule, commit) in self.repo.submodule_update()? {
+            println!(
+                "Submodule path '{}': checked out '{}'",
+                self.display_path(&submodule.path),
+                commit.to_hex()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn sparse_checkout_set(&mut self, dirs: &[String]) -> eyre::Result<()> {
+        let dirs = dirs
+            .iter()
+            .map(|dir| core::WsPath::new_unchecked(dir.trim_matches('/')))
+            .collect();
+        let kept = self.repo.sparse_checkout_set(dirs)?;
+        Self::warn_not_up_to_date(&kept);
+        Ok(())
+    }
+
+    pub fn sparse_checkout_list(&self) -> eyre::Result<()> {
+        if !self.repo.workspace()?.sparse().is_sparse() {
+            return Err(eyre!("this worktree is not sparse"));
+        }
+        for dir in self.repo.workspace()?.sparse().dirs() {
+            println!("{dir}");
+        }
+        Ok(())
+    }
+
+    pub fn sparse_checkout_disable(&mut self) -> eyre::Result<()> {
+        let kept = self.repo.sparse_checkout_disable()?;
+        Self::warn_not_up_to_date(&kept);
+        Ok(())
+    }
+
+    fn warn_not_up_to_date(kept: &[core::WsPath]) {
+        if kept.is_empty() {
+            return;
+        }
+        eprintln!("warning: The following paths are not up to date and were left despite sparse patterns:");
+        for path in kept {
+            eprintln!("{path}");
+        }
+    }
+
     pub fn plumb_show_head(&mut self) -> eyre::Result<()> {
         let head = self
             .repo
@@ -189,26 +1149,231 @@ impl Ui {
     }
 }
 
+/// Abbreviated like git's default.
+fn short_oid<O: core::Object>(oid: &core::Oid<O>) -> String {
+    oid.to_hex()[..7].to_owned()
+}
+
+/// The path to `path` from `base`, both absolute.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let mut path = path.components().peekable();
+    let mut base = base.components().peekable();
+    while let (Some(a), Some(b)) = (path.peek(), base.peek()) {
+        if a != b {
+            break;
+        }
+        path.next();
+        base.next();
+    }
+
+    let mut rel = PathBuf::new();
+    for _ in base {
+        rel.push("..");
+    }
+    rel.extend(path);
+    rel
+}
+
 pub fn run_command(opt: Opt) -> eyre::Result<()> {
     debug!("Got opt {:#?}", opt);
 
     match opt {
-        Opt::Init { dir } => {
-            Ui::init(dir)?;
+        Opt::Init { bare, dir } => {
+            Ui::init(dir, core::repo::InitOptions { bare })?;
+        }
+        Opt::Add {
+            intent_to_add,
+            files,
+        } => {
+            let opts = core::repo::AddOptions { intent_to_add };
+            Ui::for_current_dir()?.add(files, opts)?;
+        }
+        Opt::UpdateIndex {
+            assume_unchanged,
+            no_assume_unchanged,
+            skip_worktree,
+            no_skip_worktree,
+            paths,
+        } => {
+            let flag = |set: bool, clear: bool| {
+                if set {
+                    Some(true)
+                } else if clear {
+                    Some(false)
+                } else {
+                    None
+                }
+            };
+            let opts = core::repo::UpdateIndexOptions {
+                assume_unchanged: flag(assume_unchanged, no_assume_unchanged),
+                skip_worktree: flag(skip_worktree, no_skip_worktree),
+            };
+            Ui::for_current_dir()?.update_index(paths, opts)?;
+        }
+        Opt::Rm {
+            cached,
+            recursive,
+            force,
+            paths,
+        } => {
+            let opts = core::repo::RmOptions {
+                cached,
+                recursive,
+                force,
+            };
+            Ui::for_current_dir()?.rm(paths, opts)?;
+        }
+        Opt::Mv { sources, dest } => Ui::for_current_dir()?.mv(sources, dest)?,
+        Opt::CheckIgnore { verbose, paths } => {
+            if !Ui::for_current_dir()?.check_ignore(paths, verbose)? {
+                // Like git, exit unsuccessfully if nothing is ignored
+                std::process::exit(1);
+            }
         }
-        Opt::Add { files } => Ui::for_current_dir()?.add(files)?,
         Opt::Commit {
             name,
             email,
             message,
         } => Ui::for_current_dir()?.commit(name, email, message)?,
-        Opt::Status => Ui::for_current_dir()?.status()?,
+        Opt::Status(opt) => run_status_command(opt)?,
+        Opt::Log(opt) => run_log_command(&opt)?,
+        Opt::Show(opt) => run_show_command(&opt)?,
+        Opt::RevList { commits } => Ui::for_current_dir()?.rev_list(&commits)?,
+        Opt::Diff(opt) => run_diff_command(&opt)?,
+        Opt::FsmonitorDaemon(opt) => run_fsmonitor_command(opt)?,
+        Opt::Worktree(opt) => run_worktree_command(opt)?,
+        Opt::Submodule(opt) => run_submodule_command(opt)?,
+        Opt::SparseCheckout(opt) => run_sparse_checkout_command(opt)?,
         Opt::Plumb(plumb) => run_plumb_command(plumb)?,
     }
 
     Ok(())
 }
 
+fn run_status_command(opt: StatusOpt) -> eyre::Result<()> {
+    use core::status::format::{Format, FormatOptions};
+
+    let mut ui = Ui::for_current_dir()?;
+    let renames = if opt.no_renames {
+        core::Renames::No
+    } else {
+        core::Renames::from_config(&ui.repo.config)?
+    };
+    let opts = core::StatusOptions {
+        untracked: opt.untracked_files,
+        ignored: opt.ignored,
+        renames,
+    };
+    let format = match (opt.porcelain, opt.short) {
+        (Some(version), _) => Some(version.unwrap_or(Format::PorcelainV1)),
+        (None, true) => Some(Format::Short),
+        (None, false) if opt.nul_terminated => Some(Format::PorcelainV1),
+        (None, false) => None,
+    };
+    let format = format.map(|format| FormatOptions {
+        format,
+        branch: opt.branch,
+        nul_terminated: opt.nul_terminated,
+    });
+
+    match format {
+        Some(format) => ui.status_formatted(opt.paths, opts, format),
+        None => ui.status(opt.paths, opts),
+    }
+}
+
+/// The format given to log or show, defaulting to medium.
+fn log_format(format: Option<&core::log::Format>, oneline: bool) -> core::log::Format {
+    match format {
+        Some(format) => format.clone(),
+        None if oneline => core::log::Format::Oneline,
+        None => core::log::Format::Medium,
+    }
+}
+
+fn run_log_command(opt: &LogOpt) -> eyre::Result<()> {
+    let format = log_format(opt.format.as_ref(), opt.oneline);
+    let mut ui = Ui::for_current_dir()?;
+    let opts = opt.patch.patch_options(&ui.repo.config)?;
+    ui.log(&opt.commits, &format, &opt.patch.formats(false), &opts)
+}
+
+fn run_show_command(opt: &ShowOpt) -> eyre::Result<()> {
+    let format = log_format(opt.format.as_ref(), opt.oneline);
+    let mut ui = Ui::for_current_dir()?;
+    let opts = opt.patch.patch_options(&ui.repo.config)?;
+    let formats = if opt.no_patch {
+        core::diff::format::Formats::default()
+    } else {
+        opt.patch.formats(true)
+    };
+    ui.show(&opt.objects, &format, &formats, &opts)
+}
+
+fn run_diff_command(opt: &DiffOpt) -> eyre::Result<()> {
+    let mut ui = Ui::for_current_dir()?;
+    let opts = opt.patch.patch_options(&ui.repo.config)?;
+    ui.diff(
+        opt.cached,
+        &opt.args,
+        &opt.paths,
+        &opt.patch.formats(true),
+        &opts,
+    )
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn run_fsmonitor_command(opt: FsmonitorOpt) -> eyre::Result<()> {
+    let ui = Ui::for_current_dir()?;
+    match opt {
+        FsmonitorOpt::Start => ui.fsmonitor_start()?,
+        FsmonitorOpt::Run => ui.fsmonitor_run()?,
+        FsmonitorOpt::Stop => ui.fsmonitor_stop()?,
+        FsmonitorOpt::Status => {
+            if !ui.fsmonitor_status()? {
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn run_worktree_command(opt: WorktreeOpt) -> eyre::Result<()> {
+    let mut ui = Ui::for_current_dir()?;
+    match opt {
+        WorktreeOpt::Add { path, commit } => {
+            let commit = commit.map(core::Oid::parse).transpose()?;
+            ui.worktree_add(path, commit)?;
+        }
+        WorktreeOpt::List { porcelain } => ui.worktree_list(porcelain)?,
+        WorktreeOpt::Remove { force, path } => ui.worktree_remove(path, force)?,
+        WorktreeOpt::Prune { dry_run, verbose } => ui.worktree_prune(dry_run, verbose)?,
+    }
+    Ok(())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn run_submodule_command(opt: SubmoduleOpt) -> eyre::Result<()> {
+    let mut ui = Ui::for_current_dir()?;
+    match opt {
+        SubmoduleOpt::Status => ui.submodule_status()?,
+        SubmoduleOpt::Init => ui.submodule_init()?,
+        SubmoduleOpt::Update { init } => ui.submodule_update(init)?,
+    }
+    Ok(())
+}
+
+fn run_sparse_checkout_command(opt: SparseCheckoutOpt) -> eyre::Result<()> {
+    let mut ui = Ui::for_current_dir()?;
+    match opt {
+        SparseCheckoutOpt::Set { dirs } => ui.sparse_checkout_set(&dirs)?,
+        SparseCheckoutOpt::List => ui.sparse_checkout_list()?,
+        SparseCheckoutOpt::Disable => ui.sparse_checkout_disable()?,
+    }
+    Ok(())
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn run_plumb_command(opt: PlumbOpt) -> eyre::Result<()> {
     match opt {
@@ -1,7 +1,10 @@
 #![allow(dead_code)] // tests don't count
 use std::{
     path::{Path, PathBuf},
+    process::Command,
     sync::Once,
+    thread,
+    time::Duration,
 };
 
 pub use cmd_lib::run_fun;
@@ -11,6 +14,10 @@ pub use std::fs;
 pub use std::os::unix::prelude::MetadataExt;
 pub use tempfile::{tempdir, TempDir};
 pub use writ::core::Repo;
+use writ::core::{
+    index::{entry::Stage, Entry},
+    Oid, Stat, Status, WsPath,
+};
 
 static INIT: Once = Once::new();
 
@@ -121,6 +128,134 @@ pub fn repo_fixture() -> eyre::Result<(TempDir, Repo)> {
     Ok((dir, repo))
 }
 
+/// A repository with `1.txt`, `a/2.txt` and `a/b/3.txt` committed.
+pub fn init_with_commit() -> eyre::Result<(TempDir, Repo)> {
+    init();
+    let (dir_h, mut repo) = repo_fixture()?;
+    let dir = dir_h.path();
+
+    write_to(dir.join("1.txt"), "one")?;
+    write_to(dir.join("a/2.txt"), "two")?;
+    write_to(dir.join("a/b/3.txt"), "three")?;
+
+    // make sure timestamp comparison detects changes
+    thread::sleep(Duration::from_nanos(10));
+
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    Ok((dir_h, repo))
+}
+
+/// A repository with what `build` writes into its directory committed.
+pub fn committed_fixture(build: impl FnOnce(&Path) -> Result) -> eyre::Result<(TempDir, Repo)> {
+    let (dir, mut repo) = repo_fixture()?;
+    build(dir.path())?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+    Ok((dir, repo))
+}
+
+/// Whether the index and workspace both match HEAD.
+pub fn is_clean(repo: &mut Repo) -> eyre::Result<bool> {
+    Ok(repo
+        .status()?
+        .values()
+        .all(|s| s.index == Status::Unmodified && s.workspace == Status::Unmodified))
+}
+
+/// The paths in the index, in order.
+pub fn tracked(repo: &Repo) -> Vec<String> {
+    repo.index()
+        .unwrap()
+        .entries()
+        .map(|e| e.path.to_string())
+        .collect()
+}
+
+/// Runs git in `dir`, with Ann as the author and Bob as the committer of
+/// anything committed at `date`.
+pub fn git(dir: &Path, date: &str, args: &[&str]) -> Result {
+    let date = format!("{} +0100", date);
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "Ann")
+        .env("GIT_AUTHOR_EMAIL", "ann@example.com")
+        .env("GIT_AUTHOR_DATE", &date)
+        .env("GIT_COMMITTER_NAME", "Bob")
+        .env("GIT_COMMITTER_EMAIL", "bob@example.com")
+        .env("GIT_COMMITTER_DATE", &date)
+        .status()?;
+    eyre::ensure!(status.success(), "git {:?} failed", args);
+    Ok(())
+}
+
+/// Detach HEAD of the repository in `dir`, bare or not, at the commit it
+/// points to.
+pub fn detach_head(dir: &Path) -> Result {
+    // We can't read symbolic refs yet
+    git(dir, "2021-01-01 10:00:00", &["update-ref", "--no-deref", "HEAD", "HEAD"])
+}
+
+/// A repository made by git, with history made by `build` from the
+/// directory it's in.
+pub fn git_fixture(build: impl FnOnce(&Path) -> Result) -> eyre::Result<(TempDir, Repo)> {
+    let dir = tempdir()?;
+    let path = dir.path();
+    git(path, "2021-01-01 10:00:00", &["init", "-q", "."])?;
+    build(path)?;
+    detach_head(path)?;
+    let repo = Repo::new(path)?;
+    Ok((dir, repo))
+}
+
+/// Creates a repository in `dir` with git, with `config` as its config.
+pub fn init_with_git(dir: &Path, config: &str) -> Result {
+    // Without HEAD git wouldn't recognize the repository, and so ignore its
+    // attributes and config
+    git(dir, "2021-01-01 10:00:00", &["init", "-q", "."])?;
+    write_to(dir.join(".git/config"), config)
+}
+
+/// Each of `paths` in `dir` with the blob `git hash-object --path` gives for
+/// it, to compare with [`index_hashes`].
+pub fn git_hashes(dir: &Path, paths: &[&str]) -> eyre::Result<Vec<(String, String)>> {
+    let dir_s = dir.to_str().unwrap();
+    let mut hashes = Vec::new();
+    for path in paths {
+        let oid = run_fun! {
+            cd $dir_s;
+            git hash-object --path=$path $path;
+        }?;
+        hashes.push((path.to_string(), oid));
+    }
+    Ok(hashes)
+}
+
+/// Each of `paths` with the blob the index has for it.
+pub fn index_hashes(repo: &Repo, paths: &[&str]) -> eyre::Result<Vec<(String, String)>> {
+    let index = repo.index()?;
+    Ok(paths
+        .iter()
+        .map(|path| {
+            let entry = index.entry(&WsPath::new_unchecked(path)).unwrap();
+            (path.to_string(), entry.oid.to_hex())
+        })
+        .collect())
+}
+
+/// Record `path` in the index as conflicted, with an entry at each of `stages`.
+pub fn record_conflict(repo: &mut Repo, path: &str, stages: &[Stage]) -> Result {
+    let path = WsPath::new_unchecked(path);
+    let mut index = repo.index_mut()?.modify()?;
+    for stage in stages {
+        index.add(Entry::new(path.clone(), Oid::zero(), Stat::zeroed()).with_stage(*stage));
+    }
+    index.commit()?;
+    Ok(())
+}
+
 pub fn write_to(path: impl AsRef<Path>, data: impl AsRef<[u8]>) -> Result {
     let path = path.as_ref();
     if let Some(parent) = path.parent() {
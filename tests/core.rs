@@ -2,9 +2,39 @@
 
 #[path = "core/add.rs"]
 mod add;
+#[path = "core/attributes.rs"]
+mod attributes;
+#[path = "core/bare.rs"]
+mod bare;
 #[path = "core/commit.rs"]
 mod commit;
+#[path = "core/diff.rs"]
+mod diff;
+#[path = "core/filter.rs"]
+mod filter;
+#[path = "core/fsmonitor.rs"]
+mod fsmonitor;
+#[path = "core/ignore.rs"]
+mod ignore;
+#[path = "core/log.rs"]
+mod log;
+#[path = "core/mv.rs"]
+mod mv;
+#[path = "core/pathspec.rs"]
+mod pathspec;
 #[path = "core/repo_init.rs"]
 mod repo_init;
+#[path = "core/rev_walk.rs"]
+mod rev_walk;
+#[path = "core/rm.rs"]
+mod rm;
+#[path = "core/show.rs"]
+mod show;
+#[path = "core/sparse_checkout.rs"]
+mod sparse_checkout;
 #[path = "core/status.rs"]
 mod status;
+#[path = "core/submodule.rs"]
+mod submodule;
+#[path = "core/worktree.rs"]
+mod worktree;
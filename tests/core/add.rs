@@ -1,5 +1,7 @@
+use filetime::FileTime;
 use test_support::assert_eq;
 use test_support::*;
+use writ::core::repo::AddOptions;
 
 #[test]
 fn can_basic_add() -> Result {
@@ -111,6 +113,37 @@ fn can_nested_add() -> Result {
     Ok(())
 }
 
+#[test]
+fn can_add_many_files() -> Result {
+    init();
+
+    let (dir_handle, mut repo) = repo_fixture()?;
+    let dir = dir_handle.path();
+    let dir_s = dir.to_str().unwrap();
+
+    // Enough files that objects are stored into the same directories
+    // concurrently
+    for i in 0..500 {
+        write_to(dir.join(format!("{}/{}.txt", i % 7, i)), i.to_string())?;
+    }
+
+    repo.add(vec!["."])?;
+    let actual = fs::read(dir.join(".git/index"))?;
+
+    write_to(dir.join(".git/HEAD"), "ref: refs/heads/master")?;
+    (run_fun! {
+        cd $dir_s;
+        rm .git/index;
+        git add .;
+        git fsck --no-dangling;
+    })?;
+    let expected = fs::read(dir.join(".git/index"))?;
+
+    hex_assert_eq!(expected, actual);
+
+    Ok(())
+}
+
 #[test]
 fn can_duplicate_add() -> Result {
     init();
@@ -238,3 +271,91 @@ fn can_add_file_where_name_is_multiple_of_padding_size() -> Result {
     repo.status()?;
     Ok(())
 }
+
+#[test]
+fn can_add_intent_to_add() -> Result {
+    init();
+
+    let (dir_handle, mut repo) = repo_fixture()?;
+    let dir = dir_handle.path();
+    let dir_s = dir.to_str().unwrap();
+
+    write_to(dir.join("random_name"), b"some contents")?;
+
+    let opts = AddOptions {
+        intent_to_add: true,
+    };
+    repo.add_with_options(vec!["random_name"], opts)?;
+    let actual = fs::read(dir.join(".git/index"))?;
+
+    // Needed for git to accept
+    write_to(dir.join(".git/HEAD"), "ref: refs/heads/master")?;
+
+    (run_fun! {
+        cd $dir_s;
+        rm .git/index;
+        git add -N random_name;
+    })?;
+
+    let expected = fs::read(dir.join(".git/index"))?;
+
+    hex_assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[test]
+fn preserves_flags_written_by_git() -> Result {
+    init();
+
+    let dir_handle = tempdir()?;
+    let dir = dir_handle.path();
+    let dir_s = dir.to_str().unwrap();
+
+    write_to(dir.join("assumed"), b"assumed")?;
+    write_to(dir.join("skipped"), b"skipped")?;
+    write_to(dir.join("intended"), b"intended")?;
+    // Keep the entries from being racy, otherwise they'd be smudged on commit
+    let past = FileTime::from_unix_time(FileTime::now().unix_seconds() - 60, 0);
+    for file in &["assumed", "skipped", "intended"] {
+        filetime::set_file_mtime(dir.join(file), past)?;
+    }
+    (run_fun! {
+        cd $dir_s;
+        git init;
+        git add assumed skipped;
+        git add -N intended;
+        git update-index --assume-unchanged assumed;
+        git update-index --skip-worktree skipped;
+    })?;
+    let expected = fs::read(dir.join(".git/index"))?;
+
+    let mut repo = Repo::new(dir)?;
+    let flags = repo
+        .index()?
+        .entries()
+        .map(|e| {
+            (
+                e.path.to_string(),
+                e.assume_unchanged(),
+                e.skip_worktree(),
+                e.intent_to_add(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            ("assumed".to_string(), true, false, false),
+            ("intended".to_string(), false, false, true),
+            ("skipped".to_string(), false, true, false),
+        ],
+        flags
+    );
+
+    repo.index_mut()?.modify()?.commit()?;
+    let actual = fs::read(dir.join(".git/index"))?;
+
+    hex_assert_eq!(expected, actual);
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::time::{Duration, SystemTime};
+
+use filetime::FileTime;
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{Status, WsPath};
+
+const FILES: &[(&str, &[u8])] = &[
+    ("crlf.txt", b"a\r\nb\r\n"),
+    ("mixed.txt", b"a\r\nb\nc\r\n"),
+    ("lone-cr.txt", b"a\rb\r\n"),
+    ("nul.dat", b"a\r\n\0b\r\n"),
+    ("forced.dat", b"a\r\n\0b\r\n"),
+    ("raw.txt", b"a\r\nb\r\n"),
+    ("windows.txt", b"a\r\nb\r\n"),
+    ("dir/nested.txt", b"a\r\nb\r\n"),
+];
+
+/// Adds [`FILES`] with the given config and attributes, checking each blob
+/// against `git hash-object --path`.
+fn assert_adds_like_git(config: &str, attributes: &str) -> Result {
+    let dir = tempdir()?;
+    let dir = dir.path();
+    init_with_git(dir, config)?;
+    write_to(dir.join(".gitattributes"), attributes)?;
+    write_to(dir.join("dir/.gitattributes"), "*.txt -text\n")?;
+    for (path, data) in FILES {
+        write_to(dir.join(path), data)?;
+    }
+    let paths = FILES.iter().map(|(path, _)| *path).collect::<Vec<_>>();
+    let expected = git_hashes(dir, &paths)?;
+
+    let mut repo = Repo::new(dir)?;
+    repo.add(vec!["."])?;
+    assert_eq!(
+        expected,
+        index_hashes(&repo, &paths)?,
+        "config: {config:?}, attributes: {attributes:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn add_converts_line_endings_like_git() -> Result {
+    init();
+    let attributes = "*.txt text=auto\nforced.dat text\nraw.txt -text\nwindows.txt eol=crlf\n";
+    for config in [
+        "",
+        "[core]\n\tautocrlf = false\n",
+        "[core]\n\tautocrlf = true\n",
+        "[core]\n\tautocrlf = input\n\teol = crlf\n",
+    ] {
+        assert_adds_like_git(config, "")?;
+        assert_adds_like_git(config, attributes)?;
+        assert_adds_like_git(config, "* binary\n")?;
+    }
+    Ok(())
+}
+
+#[test]
+fn info_attributes_take_precedence() -> Result {
+    init();
+    let (dir, _) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join(".gitattributes"), "* -text\n")?;
+    write_to(dir.join(".git/info/attributes"), "*.txt text\n")?;
+    write_to(dir.join("a.txt"), "a\r\n")?;
+    write_to(dir.join("a.dat"), "a\r\n")?;
+
+    let mut repo = Repo::new(dir)?;
+    repo.add(vec!["."])?;
+    let data = |repo: &mut Repo, path: &str| -> eyre::Result<String> {
+        let oid = repo
+            .index()?
+            .entry(&WsPath::new_unchecked(path))
+            .unwrap()
+            .oid;
+        Ok(repo.db.load(oid)?.bytes.to_string())
+    };
+    assert_eq!("a\n", data(&mut repo, "a.txt")?);
+    assert_eq!("a\r\n", data(&mut repo, "a.dat")?);
+
+    Ok(())
+}
+
+#[test]
+fn status_compares_converted_contents() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join(".gitattributes"), "*.txt text\n")?;
+    write_to(dir.join("a.txt"), "a\r\nb\r\n")?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    // Only the timestamps change, so the contents are rehashed
+    let later = SystemTime::now() + Duration::from_secs(60);
+    filetime::set_file_mtime(dir.join("a.txt"), FileTime::from_system_time(later))?;
+    let status = repo.status()?;
+    assert_eq!(
+        Status::Unmodified,
+        status[&WsPath::new_unchecked("a.txt")].workspace
+    );
+
+    // Same size, different contents
+    write_to(dir.join("a.txt"), "a\r\nc\r\n")?;
+    filetime::set_file_mtime(dir.join("a.txt"), FileTime::from_system_time(later))?;
+    let status = repo.status()?;
+    assert_eq!(
+        Status::Modified,
+        status[&WsPath::new_unchecked("a.txt")].workspace
+    );
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use cmd_lib::run_cmd;
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    repo::{AddError, CommitError, InitOptions, StatusError, WorktreeAddOptions},
+    worktree,
+};
+
+fn bare_fixture() -> eyre::Result<(TempDir, Repo)> {
+    let dir = tempdir()?;
+    let repo = Repo::init_with_options(dir.path().join("repo.git"), InitOptions { bare: true })?;
+    Ok((dir, repo))
+}
+
+#[test]
+fn can_init_bare() -> Result {
+    init();
+    let (dir, repo) = bare_fixture()?;
+    let git_dir = dir.path().join("repo.git");
+
+    assert!(repo.is_bare());
+    assert!(repo.workspace().is_err());
+    assert!(repo.index().is_err());
+    let mut entries = all_entries(&git_dir)?;
+    entries.sort();
+    assert_eq!(vec!["config", "objects", "refs"], entries);
+    assert_eq!(
+        "[core]\n\tbare = true\n",
+        fs::read_to_string(git_dir.join("config"))?
+    );
+
+    let err = Repo::init_with_options(&git_dir, InitOptions { bare: true }).unwrap_err();
+    assert_eq!(
+        format!("Directory {:?} already exists", git_dir),
+        err.to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn opens_and_discovers_bare() -> Result {
+    init();
+    let (dir, _) = bare_fixture()?;
+    let git_dir = dir.path().join("repo.git").canonicalize()?;
+
+    let repo = Repo::new(&git_dir)?;
+    assert!(repo.is_bare());
+    assert_eq!(git_dir, repo.git_dir());
+
+    let repo = Repo::discover(git_dir.join("objects"))?;
+    assert!(repo.is_bare());
+    assert_eq!(git_dir, repo.git_dir());
+    Ok(())
+}
+
+#[test]
+fn workspace_operations_fail_without_touching_files() -> Result {
+    init();
+    let (dir, mut repo) = bare_fixture()?;
+    let git_dir = dir.path().join("repo.git");
+    write_to(git_dir.join("file.txt"), "")?;
+
+    let err = repo.add(vec!["file.txt"]).unwrap_err();
+    assert!(matches!(err, AddError::Bare(_)), "{:?}", err);
+    assert_eq!(
+        "This operation must be run in a work tree, not a bare repository",
+        err.to_string()
+    );
+    let err = repo.status().unwrap_err();
+    assert!(matches!(err, StatusError::Bare(_)), "{:?}", err);
+    let err = repo.commit(NAME, EMAIL, MSG).unwrap_err();
+    assert!(matches!(err, CommitError::Bare(_)), "{:?}", err);
+
+    assert!(!git_dir.join("index").exists());
+    assert_eq!(0, fs::read_dir(git_dir.join("objects"))?.count());
+    Ok(())
+}
+
+#[test]
+fn bare_repo_made_by_git_has_worktrees() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+    write_to(dir.join("a.txt"), "a\n")?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+    let head = repo.refs.head()?.unwrap();
+
+    let bare_dir = tempdir()?;
+    let bare = bare_dir.path().join("clone.git");
+    let (dir_s, bare_s) = (dir.to_str().unwrap(), bare.to_str().unwrap());
+    run_cmd!(git clone -q --bare $dir_s $bare_s)?;
+    detach_head(&bare)?;
+
+    let mut repo = Repo::new(&bare)?;
+    assert!(repo.is_bare());
+    assert_eq!(Some(head), repo.refs.head()?);
+
+    let wt = repo.worktree_add(bare_dir.path().join("wt"), WorktreeAddOptions::default())?;
+    assert!(!wt.is_bare());
+    assert_eq!(
+        "a\n",
+        fs::read_to_string(wt.workspace()?.path().join("a.txt"))?
+    );
+
+    let worktrees = worktree::list(repo.common_dir())?;
+    assert!(worktrees[0].bare);
+    assert_eq!(bare.canonicalize()?, worktrees[0].path);
+    assert!(!worktrees[1].bare);
+    Ok(())
+}
@@ -1,5 +1,10 @@
 use test_support::assert_eq;
 use test_support::*;
+use writ::core::{
+    index::entry::Stage,
+    repo::{AddOptions, CommitError},
+    WsPath,
+};
 
 #[test]
 fn can_basic_commit() -> Result {
@@ -72,3 +77,50 @@ fn can_commit_with_nested_files() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn refuses_to_commit_with_unmerged_paths() -> Result {
+    init();
+
+    let (dir, mut repo) = repo_fixture()?;
+    write_to(dir.path().join("file.txt"), "File contents\n")?;
+    repo.add(vec!["file.txt"])?;
+    record_conflict(&mut repo, "conflict.txt", &[Stage::Ours, Stage::Theirs])?;
+
+    match repo.commit(NAME, EMAIL, MSG) {
+        Err(CommitError::Unmerged(paths)) => {
+            assert_eq!(vec![WsPath::new_unchecked("conflict.txt")], paths)
+        }
+        other => panic!("Expected unmerged error, got {:?}", other),
+    }
+    assert!(repo.refs.head()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn leaves_out_intent_to_add() -> Result {
+    init();
+
+    let (dir, mut repo) = repo_fixture()?;
+    write_to(dir.path().join("file.txt"), "File contents\n")?;
+    write_to(dir.path().join("intended.txt"), "Not yet\n")?;
+    repo.add(vec!["file.txt"])?;
+    let opts = AddOptions {
+        intent_to_add: true,
+    };
+    repo.add_with_options(vec!["intended.txt"], opts)?;
+
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    let head = repo.refs.head()?.unwrap();
+    let tree = repo.db.load(head)?.tree;
+    let files = repo
+        .db
+        .load_tree_files(&WsPath::root(), tree)?
+        .into_keys()
+        .collect::<Vec<_>>();
+    assert_eq!(vec![WsPath::new_unchecked("file.txt")], files);
+
+    Ok(())
+}
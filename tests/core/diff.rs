@@ -0,0 +1,393 @@
+use std::{os::unix::fs::PermissionsExt, path::Path, process::Command};
+
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    diff::{
+        format::{self, Formats, Names},
+        patch::{self, PatchOptions},
+        words::WordDiff,
+        Algorithm, FileDiff, LineOptions, Whitespace,
+    },
+    Pathspec, WsPath,
+};
+
+const DATE: &str = "2021-01-01 10:00:00";
+
+/// Two commits made by git that change, add and delete text and binary
+/// files and change a mode, with more changes staged and in the
+/// workspace.
+fn changes_fixture() -> eyre::Result<(TempDir, Repo)> {
+    git_fixture(|path| {
+        let lines = (1..=20).map(|i| i.to_string() + "\n").collect::<String>();
+        write_to(path.join("text"), &lines)?;
+        write_to(path.join("bin"), "a\0b")?;
+        write_to(path.join("script"), "echo\n")?;
+        write_to(path.join("gone"), "gone\n")?;
+        git(path, DATE, &["add", "."])?;
+        git(path, DATE, &["commit", "-qm", "first"])?;
+
+        write_to(path.join("text"), lines.replace("\n5\n", "\nfive\n"))?;
+        write_to(path.join("bin"), "a\0c")?;
+        fs::set_permissions(path.join("script"), fs::Permissions::from_mode(0o755))?;
+        fs::remove_file(path.join("gone"))?;
+        write_to(path.join("dir/new"), "no newline")?;
+        git(path, DATE, &["add", "-A"])?;
+        git(path, DATE, &["commit", "-qm", "second"])?;
+
+        write_to(path.join("text"), lines.replace("\n18\n", "\n"))?;
+        git(path, DATE, &["add", "text"])?;
+        write_to(path.join("text"), lines.replace("\n18\n", "\n") + "21")?;
+        write_to(path.join("dir/new"), "no newline\n")?;
+        fs::remove_file(path.join("bin"))?;
+        Ok(())
+    })
+}
+
+const OLD_C: &str = r#"#include <stdio.h>
+
+// Frobs foo heartily
+int frobnitz(int foo)
+{
+    int i;
+    for(i = 0; i < 10; i++)
+    {
+        printf("Your answer is: ");
+        printf("%d\n", foo);
+    }
+}
+
+int fact(int n)
+{
+    if(n > 1)
+    {
+        return fact(n-1) * n;
+    }
+    return 1;
+}
+
+int main(int argc, char **argv)
+{
+    frobnitz(fact(10));
+}
+"#;
+
+const NEW_C: &str = r#"#include <stdio.h>
+
+int fib(int n)
+{
+    if(n > 2)
+    {
+        return fib(n-1) + fib(n-2);
+    }
+    return 1;
+}
+
+// Frobs foo heartily
+int frobnitz(int foo)
+{
+    int i;
+    for(i = 0; i < 10; i++)
+    {
+        printf("%d\n", foo);
+    }
+}
+
+int main(int argc, char **argv)
+{
+    frobnitz(fib(10));
+}
+"#;
+
+/// A C file where Myers' algorithm matches up braces where the others keep
+/// functions together, and files where whitespace changed, only or among
+/// other changes.
+fn lines_fixture() -> eyre::Result<(TempDir, Repo)> {
+    git_fixture(|path| {
+        write_to(path.join("frob.c"), OLD_C)?;
+        write_to(path.join("spaces"), "a  b\n  c\nd\n")?;
+        write_to(path.join("words"), "one two three\nfour five\nsix\n")?;
+        git(path, DATE, &["add", "."])?;
+        git(path, DATE, &["commit", "-qm", "first"])?;
+
+        write_to(path.join("frob.c"), NEW_C)?;
+        write_to(path.join("spaces"), "a b\n\tc \nd\ne f\n")?;
+        write_to(path.join("words"), "one 2 three\nfour  five\nseven\n")?;
+        Ok(())
+    })
+}
+
+fn git_diff(dir: &Path, args: &[&str]) -> eyre::Result<String> {
+    let out = Command::new("git")
+        .arg("diff")
+        .args(args)
+        .current_dir(dir)
+        .env_remove("COLUMNS")
+        .output()?;
+    Ok(String::from_utf8(out.stdout)?)
+}
+
+fn writ_diff(diffs: &[FileDiff], opts: &PatchOptions) -> eyre::Result<String> {
+    let mut out = Vec::new();
+    patch::write(&mut out, diffs, opts)?;
+    Ok(String::from_utf8(out)?)
+}
+
+fn writ_formats(
+    diffs: &[FileDiff],
+    formats: &Formats,
+    opts: &PatchOptions,
+) -> eyre::Result<String> {
+    let mut out = Vec::new();
+    format::write(&mut out, diffs, formats, opts)?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[test]
+fn diffs_workspace_like_git() -> Result {
+    let (dir, mut repo) = changes_fixture()?;
+    let diffs = repo.diff_workspace(Pathspec::all())?;
+
+    assert_eq!(
+        git_diff(dir.path(), &[])?,
+        writ_diff(&diffs, &PatchOptions::default())?
+    );
+    let opts = PatchOptions {
+        color: true,
+        ..PatchOptions::default()
+    };
+    assert_eq!(
+        git_diff(dir.path(), &["--color=always"])?,
+        writ_diff(&diffs, &opts)?
+    );
+    Ok(())
+}
+
+#[test]
+fn diffs_index_like_git() -> Result {
+    let (dir, mut repo) = changes_fixture()?;
+    let diffs = repo.diff_cached(Pathspec::all())?;
+
+    assert_eq!(
+        git_diff(dir.path(), &["--cached"])?,
+        writ_diff(&diffs, &PatchOptions::default())?
+    );
+    Ok(())
+}
+
+#[test]
+fn diffs_commits_like_git() -> Result {
+    let (dir, mut repo) = changes_fixture()?;
+    let old = repo.resolve("HEAD~")?;
+    let new = repo.resolve("HEAD")?;
+
+    let diffs = repo.diff_commits(Some(old), new, &Pathspec::all())?;
+    assert_eq!(
+        git_diff(dir.path(), &["HEAD~", "HEAD"])?,
+        writ_diff(&diffs, &PatchOptions::default())?
+    );
+
+    let opts = PatchOptions {
+        context: 1,
+        ..PatchOptions::default()
+    };
+    let pathspec = Pathspec::parse(["text"], &WsPath::root(), repo.workspace()?)?;
+    let diffs = repo.diff_commits(Some(old), new, &pathspec)?;
+    assert_eq!(
+        git_diff(dir.path(), &["-U1", "HEAD~", "HEAD", "--", "text"])?,
+        writ_diff(&diffs, &opts)?
+    );
+    Ok(())
+}
+
+#[test]
+fn diff_attribute_decides_binary() -> Result {
+    let (dir, mut repo) = changes_fixture()?;
+    write_to(dir.path().join(".gitattributes"), "text -diff\nbin diff\n")?;
+    let diffs = repo.diff_workspace(Pathspec::all())?;
+
+    assert_eq!(
+        vec![
+            ("bin".to_owned(), false),
+            ("dir/new".to_owned(), false),
+            ("text".to_owned(), true)
+        ],
+        diffs
+            .iter()
+            .map(|diff| (diff.path.to_string(), diff.binary))
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn diffs_with_each_algorithm_like_git() -> Result {
+    let (dir, mut repo) = lines_fixture()?;
+    let diffs = repo.diff_workspace(Pathspec::all())?;
+
+    for (name, algorithm) in &[
+        ("myers", Algorithm::Myers),
+        ("patience", Algorithm::Patience),
+        ("histogram", Algorithm::Histogram),
+    ] {
+        let opts = PatchOptions {
+            lines: LineOptions {
+                algorithm: *algorithm,
+                ..LineOptions::default()
+            },
+            ..PatchOptions::default()
+        };
+        assert_eq!(
+            git_diff(dir.path(), &["--diff-algorithm", name])?,
+            writ_diff(&diffs, &opts)?,
+            "{}",
+            name
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn ignores_whitespace_like_git() -> Result {
+    let (dir, mut repo) = lines_fixture()?;
+    let diffs = repo.diff_workspace(Pathspec::all())?;
+
+    for (arg, whitespace) in &[
+        ("-w", Whitespace::IgnoreAll),
+        ("-b", Whitespace::IgnoreChange),
+    ] {
+        let opts = PatchOptions {
+            lines: LineOptions {
+                whitespace: *whitespace,
+                ..LineOptions::default()
+            },
+            ..PatchOptions::default()
+        };
+        assert_eq!(
+            git_diff(dir.path(), &[arg])?,
+            writ_diff(&diffs, &opts)?,
+            "{}",
+            arg
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn diffs_words_like_git() -> Result {
+    let (dir, mut repo) = lines_fixture()?;
+    let diffs = repo.diff_workspace(Pathspec::all())?;
+
+    for (args, word_diff, color) in &[
+        (&["--word-diff"][..], WordDiff::Plain, false),
+        (
+            &["--word-diff", "--color=always"][..],
+            WordDiff::Plain,
+            true,
+        ),
+        (&["--word-diff=color"][..], WordDiff::Color, false),
+    ] {
+        let opts = PatchOptions {
+            color: *color,
+            word_diff: Some(*word_diff),
+            ..PatchOptions::default()
+        };
+        assert_eq!(
+            git_diff(dir.path(), args)?,
+            writ_diff(&diffs, &opts)?,
+            "{:?}",
+            args
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn summarizes_changes_like_git() -> Result {
+    let (dir, mut repo) = changes_fixture()?;
+    let old = repo.resolve("HEAD~")?;
+    let new = repo.resolve("HEAD")?;
+    let commit_diffs = repo.diff_commits(Some(old), new, &Pathspec::all())?;
+    let workspace_diffs = repo.diff_workspace(Pathspec::all())?;
+
+    let stat = Formats {
+        stat: true,
+        ..Formats::default()
+    };
+    for (args, formats) in &[
+        (&["--stat"][..], stat),
+        (
+            &["--numstat"][..],
+            Formats {
+                numstat: true,
+                ..Formats::default()
+            },
+        ),
+        (
+            &["--shortstat"][..],
+            Formats {
+                shortstat: true,
+                ..Formats::default()
+            },
+        ),
+        (
+            &["--summary"][..],
+            Formats {
+                summary: true,
+                ..Formats::default()
+            },
+        ),
+        (
+            &["--name-only"][..],
+            Formats {
+                names: Some(Names::Only),
+                ..Formats::default()
+            },
+        ),
+        (
+            &["--name-status", "--stat"][..],
+            Formats {
+                names: Some(Names::Status),
+                ..stat
+            },
+        ),
+        (
+            &["--stat", "--summary", "-p"][..],
+            Formats {
+                summary: true,
+                patch: true,
+                ..stat
+            },
+        ),
+    ] {
+        let opts = PatchOptions::default();
+        let commit_args = [*args, &["HEAD~", "HEAD"]].concat();
+        assert_eq!(
+            git_diff(dir.path(), &commit_args)?,
+            writ_formats(&commit_diffs, formats, &opts)?,
+            "{:?}",
+            args
+        );
+        assert_eq!(
+            git_diff(dir.path(), args)?,
+            writ_formats(&workspace_diffs, formats, &opts)?,
+            "{:?}",
+            args
+        );
+    }
+
+    let opts = PatchOptions {
+        color: true,
+        stat_width: 30,
+        ..PatchOptions::default()
+    };
+    assert_eq!(
+        git_diff(
+            dir.path(),
+            &["--stat=30", "--color=always", "HEAD~", "HEAD"]
+        )?,
+        writ_formats(&commit_diffs, &stat, &opts)?
+    );
+    Ok(())
+}
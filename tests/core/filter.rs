@@ -0,0 +1,223 @@
+use std::path::Path;
+
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{repo::AddError, WsPath};
+
+/// A long-running filter that upper-cases files when cleaning and lower-cases
+/// them when smudging, logging every command it gets to `filter.log`.
+const PROCESS_FILTER: &str = r#"
+import sys
+
+inp, out = sys.stdin.buffer, sys.stdout.buffer
+log = open("filter.log", "a")
+
+def read_packet():
+    length = int(inp.read(4), 16)
+    return None if length == 0 else inp.read(length - 4)
+
+def read_list():
+    lines = []
+    while (packet := read_packet()) is not None:
+        lines.append(packet.rstrip(b"\n"))
+    return lines
+
+def write_packet(data):
+    out.write(b"%04x" % (len(data) + 4) + data)
+
+def write_flush():
+    out.write(b"0000")
+    out.flush()
+
+assert read_list() == [b"git-filter-client", b"version=2"]
+write_packet(b"git-filter-server\n")
+write_packet(b"version=2\n")
+write_flush()
+read_list()
+write_packet(b"capability=clean\n")
+write_packet(b"capability=smudge\n")
+write_flush()
+
+while True:
+    header = inp.read(4)
+    if not header:
+        break
+    length = int(header, 16)
+    lines = [inp.read(length - 4).rstrip(b"\n")] + read_list()
+    command = dict(line.split(b"=", 1) for line in lines)
+    data = b""
+    while (packet := read_packet()) is not None:
+        data += packet
+    log.write("%s %s\n" % (command[b"command"].decode(), command[b"pathname"].decode()))
+    log.flush()
+
+    if b"fail" in data:
+        write_packet(b"status=error\n")
+        write_flush()
+        continue
+    data = data.upper() if command[b"command"] == b"clean" else data.lower()
+    write_packet(b"status=success\n")
+    write_flush()
+    for i in range(0, len(data), 65516):
+        write_packet(data[i:i + 65516])
+    write_flush()
+    write_flush()
+"#;
+
+/// Writes `files` into a repository created by git with the given config and
+/// attributes, returning the blob git would store for each.
+fn hashed_by_git(
+    dir: &Path,
+    config: &str,
+    attributes: &str,
+    files: &[(&str, &str)],
+) -> eyre::Result<Vec<(String, String)>> {
+    init_with_git(dir, config)?;
+    write_to(dir.join(".git/info/attributes"), attributes)?;
+    write_to(dir.join(".git/filter.py"), PROCESS_FILTER)?;
+    for (path, data) in files {
+        write_to(dir.join(path), data)?;
+    }
+    let expected = git_hashes(dir, &paths(files))?;
+    // Only what we log counts
+    let _ = fs::remove_file(dir.join("filter.log"));
+    Ok(expected)
+}
+
+fn paths<'a>(files: &[(&'a str, &str)]) -> Vec<&'a str> {
+    files.iter().map(|(path, _)| *path).collect()
+}
+
+fn stored(repo: &mut Repo, path: &str) -> eyre::Result<String> {
+    let oid = repo
+        .index()?
+        .entry(&WsPath::new_unchecked(path))
+        .unwrap()
+        .oid;
+    Ok(repo.db.load(oid)?.bytes.to_string())
+}
+
+#[test]
+fn clean_filters_like_git() -> Result {
+    init();
+    let dir = tempdir()?;
+    let dir = dir.path();
+    let files = [
+        ("a.txt", "hello\n"),
+        ("it's here.txt", "spaces and quotes\n"),
+        ("dir/b.txt", "nested\r\n"),
+        ("c.dat", "not filtered\n"),
+    ];
+    let expected = hashed_by_git(
+        dir,
+        "[filter \"upper\"]\n\tclean = tr a-z A-Z\n\
+         [filter \"named\"]\n\tclean = \"sed s/^/%f:/\"\n",
+        "*.txt filter=upper text\nit*s* filter=named\n",
+        &files,
+    )?;
+
+    let mut repo = Repo::new(dir)?;
+    repo.add(vec!["."])?;
+    assert_eq!(expected, index_hashes(&repo, &paths(&files))?);
+    assert_eq!(
+        "it's here.txt:spaces and quotes\n",
+        stored(&mut repo, "it's here.txt")?
+    );
+    assert_eq!("NESTED\n", stored(&mut repo, "dir/b.txt")?);
+    Ok(())
+}
+
+#[test]
+fn process_filters_like_git() -> Result {
+    init();
+    let dir = tempdir()?;
+    let dir = dir.path();
+    let big = "x".repeat(100_000);
+    let files = [
+        ("a.txt", "hello\n"),
+        ("b.txt", "world\n"),
+        ("big.txt", big.as_str()),
+        ("c.dat", "not filtered\n"),
+    ];
+    let expected = hashed_by_git(
+        dir,
+        "[filter \"rot\"]\n\tprocess = python3 .git/filter.py\n\tclean = false\n",
+        "*.txt filter=rot\n",
+        &files,
+    )?;
+
+    let mut repo = Repo::new(dir)?;
+    repo.add(vec!["."])?;
+    assert_eq!(expected, index_hashes(&repo, &paths(&files))?);
+    assert_eq!("HELLO\n", stored(&mut repo, "a.txt")?);
+
+    let smudged = repo
+        .workspace()?
+        .convert_to_workspace(&WsPath::new_unchecked("a.txt"), "HELLO\n".into())?;
+    assert_eq!("hello\n", smudged);
+
+    // The filter is only given files with the attribute, in whatever order
+    // they're added
+    let log = fs::read_to_string(dir.join("filter.log"))?;
+    let mut log = log.lines().collect::<Vec<_>>();
+    log.sort_unstable();
+    assert_eq!(
+        vec![
+            "clean a.txt",
+            "clean b.txt",
+            "clean big.txt",
+            "smudge a.txt"
+        ],
+        log
+    );
+    Ok(())
+}
+
+#[test]
+fn failing_filters_leave_contents_unless_required() -> Result {
+    init();
+    let (dir, _) = repo_fixture()?;
+    let dir = dir.path();
+    write_to(
+        dir.join(".git/config"),
+        "[filter \"broken\"]\n\tclean = exit 1\n\
+         [filter \"rot\"]\n\tprocess = python3 .git/filter.py\n",
+    )?;
+    write_to(dir.join(".git/filter.py"), PROCESS_FILTER)?;
+    write_to(
+        dir.join(".gitattributes"),
+        "*.txt filter=broken\n*.dat filter=rot\n*.md filter=missing\n",
+    )?;
+    write_to(dir.join("a.txt"), "a\n")?;
+    write_to(dir.join("b.dat"), "fail\n")?;
+    write_to(dir.join("c.md"), "c\n")?;
+
+    let mut repo = Repo::new(dir)?;
+    repo.add(vec!["."])?;
+    assert_eq!("a\n", stored(&mut repo, "a.txt")?);
+    assert_eq!("fail\n", stored(&mut repo, "b.dat")?);
+    assert_eq!("c\n", stored(&mut repo, "c.md")?);
+
+    let mut config = fs::read_to_string(dir.join(".git/config"))?;
+    config.push_str("[filter \"broken\"]\n\trequired = true\n");
+    config.push_str("[filter \"rot\"]\n\trequired = true\n");
+    write_to(dir.join(".git/config"), &config)?;
+    let mut repo = Repo::new(dir)?;
+    for path in ["a.txt", "b.dat"] {
+        let err = repo.add(vec![path]).unwrap_err();
+        assert!(matches!(err, AddError::Convert(_)), "{}: {:?}", path, err);
+    }
+    // Required filters must be able to filter in the direction asked for
+    write_to(
+        dir.join(".git/config"),
+        format!("{config}[filter \"clean-only\"]\n\tclean = cat\n\trequired\n"),
+    )?;
+    write_to(dir.join(".gitattributes"), "*.md filter=clean-only\n")?;
+    let repo = Repo::new(dir)?;
+    assert!(repo
+        .workspace()?
+        .convert_to_workspace(&WsPath::new_unchecked("c.md"), "c\n".into())
+        .is_err());
+    Ok(())
+}
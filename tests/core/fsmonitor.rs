@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    fsmonitor::{self, Daemon},
+    Status, WsPath,
+};
+
+fn enable_fsmonitor(repo: &Repo) -> eyre::Result<Repo> {
+    write_to(
+        repo.git_dir().join("config"),
+        "[core]\n\tfsmonitor = true\n",
+    )?;
+    Ok(Repo::new(repo.workspace()?.path())?)
+}
+
+fn start_daemon(repo: &Repo) -> eyre::Result<Daemon> {
+    Ok(Daemon::start(repo.git_dir(), repo.workspace()?.path())?)
+}
+
+fn workspace_statuses(repo: &mut Repo) -> eyre::Result<BTreeMap<String, Status>> {
+    Ok(repo
+        .status()?
+        .into_iter()
+        .map(|(path, status)| (path.to_string(), status.workspace))
+        .collect())
+}
+
+fn paths(changes: &fsmonitor::Changes) -> Option<Vec<String>> {
+    changes.paths.as_ref().map(|paths| {
+        let mut paths = paths.iter().map(ToString::to_string).collect::<Vec<_>>();
+        paths.sort();
+        paths
+    })
+}
+
+/// Whether `path` or a directory containing it was reported.
+fn is_reported(changes: &fsmonitor::Changes, path: &str) -> bool {
+    let path = WsPath::new_unchecked(path);
+    changes.paths.as_ref().map_or(true, |paths| {
+        paths
+            .iter()
+            .any(|p| path.as_path().starts_with(p.as_path()))
+    })
+}
+
+#[test]
+fn daemon_reports_changes_since_token() -> Result {
+    init();
+    let (dir, repo) = repo_fixture()?;
+    let dir = dir.path();
+    write_to(dir.join("a.txt"), "a")?;
+    write_to(dir.join("old/b.txt"), "b")?;
+    write_to(dir.join("c.txt"), "c")?;
+    let repo = Repo::new(repo.workspace()?.path())?;
+    let daemon = start_daemon(&repo)?;
+
+    let first = fsmonitor::query(repo.git_dir(), None)?;
+    assert_eq!(
+        None, first.paths,
+        "Without a token anything may have changed"
+    );
+
+    write_to(dir.join("a.txt"), "changed")?;
+    fs::remove_file(dir.join("c.txt"))?;
+    fs::rename(dir.join("old"), dir.join("new"))?;
+    write_to(dir.join("new/b.txt"), "changed")?;
+    write_to(dir.join("created/d.txt"), "d")?;
+    write_to(dir.join(".git/ignored"), "")?;
+
+    // Files in new directories may be written before the daemon watches
+    // them, so they can be reported through their directory
+    let second = fsmonitor::query(repo.git_dir(), Some(first.token.as_ref()))?;
+    for path in &["a.txt", "c.txt", "old", "new/b.txt", "created/d.txt"] {
+        assert!(is_reported(&second, path), "{} in {:?}", path, second);
+    }
+    assert!(!is_reported(&second, ".git/ignored"), "{:?}", second);
+
+    let third = fsmonitor::query(repo.git_dir(), Some(second.token.as_ref()))?;
+    assert_eq!(Some(Vec::new()), paths(&third));
+
+    let unknown = fsmonitor::query(repo.git_dir(), Some("writ:other:1".into()))?;
+    assert_eq!(None, unknown.paths);
+
+    daemon.stop()?;
+    assert!(!fsmonitor::is_running(repo.git_dir()));
+    Ok(())
+}
+
+#[test]
+fn status_only_checks_reported_files() -> Result {
+    init();
+    let (dir, repo) = repo_fixture()?;
+    let dir = dir.path();
+    write_to(dir.join("a.txt"), "a")?;
+    write_to(dir.join("dir/b.txt"), "b")?;
+    write_to(dir.join("dir/c.txt"), "c")?;
+    let mut repo = enable_fsmonitor(&repo)?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+    let daemon = start_daemon(&repo)?;
+
+    // The first status checks everything, and records the token
+    let statuses = workspace_statuses(&mut repo)?;
+    assert!(statuses.values().all(|s| *s == Status::Unmodified));
+    assert!(repo.index()?.fsmonitor_token().is_some());
+    for path in &["a.txt", "dir/b.txt", "dir/c.txt"] {
+        assert!(repo
+            .index()?
+            .is_fsmonitor_valid(&WsPath::new_unchecked(path)));
+    }
+
+    write_to(dir.join("dir/b.txt"), "changed")?;
+    write_to(dir.join("new.txt"), "new")?;
+    let statuses = workspace_statuses(&mut repo)?;
+    assert_eq!(Status::Modified, statuses["dir/b.txt"]);
+    assert_eq!(Status::Untracked, statuses["new.txt"]);
+    assert_eq!(Status::Unmodified, statuses["a.txt"]);
+    assert!(!repo
+        .index()?
+        .is_fsmonitor_valid(&WsPath::new_unchecked("dir/b.txt")));
+    assert!(repo
+        .index()?
+        .is_fsmonitor_valid(&WsPath::new_unchecked("dir/c.txt")));
+
+    // Modified files are checked again, even though nothing new is reported
+    let statuses = workspace_statuses(&mut repo)?;
+    assert_eq!(Status::Modified, statuses["dir/b.txt"]);
+
+    fs::remove_dir_all(dir.join("dir"))?;
+    let statuses = workspace_statuses(&mut repo)?;
+    assert_eq!(Status::Deleted, statuses["dir/b.txt"]);
+    assert_eq!(Status::Deleted, statuses["dir/c.txt"]);
+
+    daemon.stop()?;
+    Ok(())
+}
+
+#[test]
+fn restarted_daemon_checks_everything() -> Result {
+    init();
+    let (dir, repo) = repo_fixture()?;
+    let dir = dir.path();
+    write_to(dir.join("a.txt"), "a")?;
+    let mut repo = enable_fsmonitor(&repo)?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    let daemon = start_daemon(&repo)?;
+    repo.status()?;
+    let old_token = repo.index()?.fsmonitor_token().unwrap().to_owned();
+    daemon.stop()?;
+
+    // Changed while nothing was watching
+    write_to(dir.join("a.txt"), "changed")?;
+
+    let daemon = start_daemon(&repo)?;
+    let statuses = workspace_statuses(&mut repo)?;
+    assert_eq!(Status::Modified, statuses["a.txt"]);
+    assert_ne!(Some(old_token.as_ref()), repo.index()?.fsmonitor_token());
+    daemon.stop()?;
+    Ok(())
+}
+
+#[test]
+fn falls_back_to_full_scan_without_daemon() -> Result {
+    init();
+    let (dir, repo) = repo_fixture()?;
+    let dir = dir.path();
+    write_to(dir.join("a.txt"), "a")?;
+    let mut repo = enable_fsmonitor(&repo)?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    write_to(dir.join("a.txt"), "changed")?;
+    let statuses = workspace_statuses(&mut repo)?;
+    assert_eq!(Status::Modified, statuses["a.txt"]);
+    assert_eq!(None, repo.index()?.fsmonitor_token());
+    Ok(())
+}
+
+#[test]
+fn git_reads_index_with_fsmonitor_extension() -> Result {
+    init();
+    let (dir, repo) = repo_fixture()?;
+    let dir = dir.path();
+    let dir_s = dir.to_str().unwrap();
+    write_to(dir.join("a.txt"), "a")?;
+    write_to(dir.join("b.txt"), "b")?;
+    let mut repo = enable_fsmonitor(&repo)?;
+    repo.add(vec!["."])?;
+    // Git only recognizes the repository once it has a HEAD
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    let daemon = start_daemon(&repo)?;
+    write_to(dir.join("b.txt"), "changed")?;
+    repo.status()?;
+    daemon.stop()?;
+    assert!(repo.index()?.fsmonitor_token().is_some());
+
+    let files = run_fun! {
+        cd $dir_s;
+        git -c core.fsmonitor=false ls-files;
+    }?;
+    assert_eq!("a.txt\nb.txt", files);
+    Ok(())
+}
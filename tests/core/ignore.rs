@@ -0,0 +1,176 @@
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    repo::AddError, FileStatus, Pathspec, Status, StatusOptions, UntrackedFiles, WsPath,
+};
+
+fn untracked(repo: &mut Repo) -> eyre::Result<Vec<String>> {
+    let opts = StatusOptions {
+        untracked: UntrackedFiles::All,
+        ..StatusOptions::default()
+    };
+    let untracked = repo
+        .status_with_options(Pathspec::all(), opts)?
+        .into_values()
+        .filter(|s| s.workspace == Status::Untracked)
+        .map(|s| s.path.to_string())
+        .collect();
+    Ok(untracked)
+}
+
+#[test]
+fn status_skips_ignored_files() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join(".gitignore"), "target/\n*.log\n!keep.log\n")?;
+    write_to(dir.join("target/debug/writ"), "")?;
+    write_to(dir.join("build.log"), "")?;
+    write_to(dir.join("keep.log"), "")?;
+    write_to(dir.join("src/main.rs"), "")?;
+    write_to(dir.join("src/.gitignore"), "/generated.rs\n")?;
+    write_to(dir.join("src/generated.rs"), "")?;
+    write_to(dir.join("src/nested/generated.rs"), "")?;
+
+    assert_eq!(
+        vec![
+            ".gitignore",
+            "keep.log",
+            "src/.gitignore",
+            "src/main.rs",
+            "src/nested/generated.rs"
+        ],
+        untracked(&mut repo)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reads_info_exclude_and_excludes_file() -> Result {
+    init();
+    let (dir, _) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join(".git/info/exclude"), "*.tmp\n")?;
+    write_to(dir.join("global-ignore"), "*.bak\n")?;
+    let excludes_file = dir.join("global-ignore");
+    let excludes_file = excludes_file.to_str().unwrap();
+    write_to(
+        dir.join(".git/config"),
+        format!("[core]\n\texcludesFile = {}\n", excludes_file),
+    )?;
+    write_to(dir.join("a.tmp"), "")?;
+    write_to(dir.join("a.bak"), "")?;
+    write_to(dir.join("a.txt"), "")?;
+
+    let mut repo = Repo::new(dir)?;
+    assert_eq!(vec!["a.txt", "global-ignore"], untracked(&mut repo)?);
+
+    Ok(())
+}
+
+#[test]
+fn add_skips_ignored_files() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join(".gitignore"), "**/cache/**\n")?;
+    write_to(dir.join("a/cache/b/c"), "")?;
+    write_to(dir.join("a/b"), "")?;
+
+    let mut added = repo.add(vec!["."])?;
+    added.sort();
+    assert_eq!(
+        vec![
+            WsPath::new_unchecked(".gitignore"),
+            WsPath::new_unchecked("a/b")
+        ],
+        added
+    );
+
+    Ok(())
+}
+
+#[test]
+fn adding_ignored_path_fails() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join(".gitignore"), "target/\n")?;
+    write_to(dir.join("target/out"), "")?;
+
+    let err = repo.add(vec!["target/out"]).unwrap_err();
+    assert!(matches!(err, AddError::Ignored(path) if path == "target/out"));
+
+    Ok(())
+}
+
+#[test]
+fn tracked_files_are_not_ignored() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join("out.log"), "one")?;
+    repo.add(vec!["out.log"])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    write_to(dir.join(".gitignore"), "*.log\n")?;
+    write_to(dir.join("out.log"), "changed")?;
+
+    assert_contains_unordered(
+        repo.status()?.into_values(),
+        [
+            |s: &FileStatus| s.workspace == Status::Modified && s.path == "out.log",
+            |s: &FileStatus| s.workspace == Status::Untracked && s.path == ".gitignore",
+        ],
+    );
+    assert_eq!(None, repo.check_ignore(vec!["out.log"])?[0].1);
+
+    Ok(())
+}
+
+#[test]
+fn check_ignore_matches_git() -> Result {
+    init();
+    let (dir, _) = repo_fixture()?;
+    let dir = dir.path();
+    let dir_s = dir.to_str().unwrap();
+
+    write_to(dir.join(".gitignore"), "# build output\ntarget\n*.o\n")?;
+    write_to(dir.join("sub/.gitignore"), "!keep.o\n/only-here\n")?;
+    write_to(dir.join(".git/info/exclude"), "secret\n")?;
+    let mut repo = Repo::new(dir)?;
+    // Needed for git to accept
+    write_to(dir.join(".git/HEAD"), "ref: refs/heads/master")?;
+
+    let paths = vec![
+        "target/debug/writ",
+        "main.o",
+        "sub/keep.o",
+        "sub/only-here",
+        "only-here",
+        "sub/deeper/secret",
+        "src/main.rs",
+    ];
+    let actual = repo
+        .check_ignore(paths.clone())?
+        .into_iter()
+        .filter_map(|(path, pattern)| pattern.map(|pattern| format!("{}\t{}", pattern, path)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let expected = run_fun! {
+        cd $dir_s;
+        git check-ignore -v --no-index $[paths];
+    }?;
+
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use std::{path::Path, process::Command};
+
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    db::Commit,
+    diff::{
+        format::{self as diff_format, Formats, Names},
+        patch::PatchOptions,
+    },
+    log::{self, Format, LogOptions},
+    repo::LogError,
+    Pathspec,
+};
+
+/// History made by git with a branch, in `d`, merged into another, with
+/// dates out of order between the two.
+fn history_fixture() -> eyre::Result<(TempDir, Repo)> {
+    git_fixture(|path| {
+        write_to(path.join("a"), "a\n")?;
+        git(path, "2021-01-01 10:00:00", &["add", "a"])?;
+        git(
+            path,
+            "2021-01-01 10:00:00",
+            &["commit", "-qm", "first\n\nwith a body"],
+        )?;
+        git(path, "2021-01-01 10:00:00", &["checkout", "-qb", "side"])?;
+        write_to(path.join("d/s"), "s\n")?;
+        git(path, "2021-01-03 10:00:00", &["add", "d"])?;
+        git(path, "2021-01-03 10:00:00", &["commit", "-qm", "side in d"])?;
+        git(path, "2021-01-03 10:00:00", &["checkout", "-q", "master"])?;
+        write_to(path.join("b"), "b\n")?;
+        git(path, "2021-01-02 10:00:00", &["add", "b"])?;
+        git(path, "2021-01-02 10:00:00", &["commit", "-qm", "main b"])?;
+        git(
+            path,
+            "2021-01-04 10:00:00",
+            &["merge", "-q", "--no-edit", "side"],
+        )?;
+        write_to(path.join("a"), "a\nc\n")?;
+        git(path, "2021-01-05 10:00:00", &["commit", "-qam", "edit a"])?;
+        Ok(())
+    })
+}
+
+fn subjects(commits: &[Commit]) -> Vec<String> {
+    commits.iter().map(|c| c.subject().to_string()).collect()
+}
+
+fn git_log(dir: &Path, args: &[&str]) -> eyre::Result<String> {
+    let out = Command::new("git")
+        .arg("log")
+        .args(args)
+        .current_dir(dir)
+        .env_remove("COLUMNS")
+        .output()?;
+    Ok(String::from_utf8(out.stdout)?)
+}
+
+fn writ_log(commits: &[Commit], format: &Format) -> eyre::Result<String> {
+    let mut out = Vec::new();
+    log::format::write(&mut out, commits, format, false)?;
+    Ok(String::from_utf8(out)?)
+}
+
+fn writ_log_with_diffs(
+    repo: &mut Repo,
+    commits: &[Commit],
+    format: &Format,
+    formats: &Formats,
+) -> eyre::Result<String> {
+    let mut out = Vec::new();
+    log::format::write_with_diffs(
+        &mut out,
+        commits,
+        format,
+        false,
+        formats.dashes(),
+        |commit| -> eyre::Result<Vec<u8>> {
+            let diffs = repo.diff_commit(commit, &Pathspec::all())?;
+            let mut diff = Vec::new();
+            diff_format::write(&mut diff, &diffs, formats, &PatchOptions::default())?;
+            Ok(diff)
+        },
+    )?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[test]
+fn lists_commits_by_date_across_merges() -> Result {
+    init();
+    let (dir, mut repo) = history_fixture()?;
+
+    let commits = repo.log(&LogOptions::default())?;
+    assert_eq!(
+        vec![
+            "edit a",
+            "Merge branch 'side'",
+            "side in d",
+            "main b",
+            "first"
+        ],
+        subjects(&commits)
+    );
+    assert_eq!(2, commits[1].parents.len());
+    assert_eq!("with a body\n", commits[4].body().to_string());
+
+    for (args, format) in &[
+        (vec![], Format::Medium),
+        (vec!["--oneline"], Format::Oneline),
+        (
+            vec!["--format=%H %p|%an <%ae>|%cn|%ad|%ci|%s%n%b"],
+            "%H %p|%an <%ae>|%cn|%ad|%ci|%s%n%b".parse()?,
+        ),
+    ] {
+        assert_eq!(git_log(dir.path(), args)?, writ_log(&commits, format)?);
+    }
+    Ok(())
+}
+
+#[test]
+fn filters_commits() -> Result {
+    init();
+    let (_dir, mut repo) = history_fixture()?;
+    let mut log =
+        |opts: LogOptions| -> eyre::Result<Vec<String>> { Ok(subjects(&repo.log(&opts)?)) };
+
+    let opts = LogOptions {
+        max_count: Some(2),
+        ..LogOptions::default()
+    };
+    assert_eq!(vec!["edit a", "Merge branch 'side'"], log(opts)?);
+
+    let opts = LogOptions {
+        grep: Some("^(side|main)".parse()?),
+        ..LogOptions::default()
+    };
+    assert_eq!(vec!["side in d", "main b"], log(opts)?);
+
+    let opts = LogOptions {
+        author: Some("Bob".parse()?),
+        ..LogOptions::default()
+    };
+    assert!(log(opts)?.is_empty());
+    let opts = LogOptions {
+        author: Some("<ann@".parse()?),
+        max_count: Some(1),
+        ..LogOptions::default()
+    };
+    assert_eq!(vec!["edit a"], log(opts)?);
+
+    let now = chrono::Local::now();
+    let opts = LogOptions {
+        since: Some(log::parse_date("2021-01-02 12:00:00 +0100", now)?),
+        until: Some(log::parse_date("2021-01-04 10:00:00 +0100", now)?),
+        ..LogOptions::default()
+    };
+    assert_eq!(vec!["Merge branch 'side'", "side in d"], log(opts)?);
+    Ok(())
+}
+
+#[test]
+fn limits_to_commits_changing_paths() -> Result {
+    init();
+    let (dir, mut repo) = history_fixture()?;
+    let mut log = |paths: &[&str]| -> eyre::Result<Vec<String>> {
+        let pathspec = Pathspec::parse(paths, &writ::core::WsPath::root(), repo.workspace()?)?;
+        let opts = LogOptions {
+            pathspec,
+            ..LogOptions::default()
+        };
+        Ok(subjects(&repo.log(&opts)?))
+    };
+
+    // The merge takes d from one parent, so doesn't change it
+    assert_eq!(vec!["side in d"], log(&["d"])?);
+    assert_eq!(vec!["edit a", "first"], log(&["a"])?);
+    // But it differs from each parent in one of these
+    let expected = vec!["Merge branch 'side'", "side in d", "main b"];
+    assert_eq!(expected, log(&["b", "d/s"])?);
+    assert_eq!(expected, log(&[":!a"])?);
+    assert!(log(&["missing"])?.is_empty());
+
+    let expected = git_log(dir.path(), &["--format=%s", "--", "b", "d/s"])?;
+    assert_eq!("Merge branch 'side'\nside in d\nmain b\n", expected);
+    Ok(())
+}
+
+#[test]
+fn shows_diffs_like_git() -> Result {
+    init();
+    let (dir, mut repo) = history_fixture()?;
+    let commits = repo.log(&LogOptions::default())?;
+
+    let stat = Formats {
+        stat: true,
+        ..Formats::default()
+    };
+    for (args, format, formats) in &[
+        (vec!["--stat"], Format::Medium, stat),
+        (
+            vec!["--stat", "-p"],
+            Format::Medium,
+            Formats {
+                patch: true,
+                ..stat
+            },
+        ),
+        (
+            vec!["--oneline", "--name-status"],
+            Format::Oneline,
+            Formats {
+                names: Some(Names::Status),
+                ..Formats::default()
+            },
+        ),
+        (
+            vec!["--format=%h %s", "--numstat"],
+            "%h %s".parse()?,
+            Formats {
+                numstat: true,
+                ..Formats::default()
+            },
+        ),
+        (
+            vec!["--format=format:%h", "--shortstat", "--summary"],
+            "format:%h".parse()?,
+            Formats {
+                shortstat: true,
+                summary: true,
+                ..Formats::default()
+            },
+        ),
+        (
+            vec!["--format=", "--name-only"],
+            "".parse()?,
+            Formats {
+                names: Some(Names::Only),
+                ..Formats::default()
+            },
+        ),
+    ] {
+        assert_eq!(
+            git_log(dir.path(), args)?,
+            writ_log_with_diffs(&mut repo, &commits, format, formats)?,
+            "{:?}",
+            args
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn empty_history_is_an_error() -> Result {
+    init();
+    let (_dir, mut repo) = repo_fixture()?;
+
+    let err = repo.log(&LogOptions::default()).unwrap_err();
+    assert!(matches!(err, LogError::NoCommits), "{:?}", err);
+    assert_eq!(
+        "Your current HEAD does not have any commits yet",
+        err.to_string()
+    );
+    Ok(())
+}
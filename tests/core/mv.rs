@@ -0,0 +1,221 @@
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    repo::{AddOptions, MvError, UpdateIndexOptions},
+    FileStatus, Status, WsPath,
+};
+
+fn moved(pairs: &[(&str, &str)]) -> Vec<(WsPath, WsPath)> {
+    pairs
+        .iter()
+        .map(|(from, to)| (WsPath::new_unchecked(from), WsPath::new_unchecked(to)))
+        .collect()
+}
+
+#[test]
+fn renames_file() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    let old_entry = repo
+        .index()?
+        .entry(&WsPath::new_unchecked("1.txt"))
+        .unwrap()
+        .clone();
+
+    let actual = repo.mv(vec!["1.txt"], "renamed.txt")?;
+
+    assert_eq!(moved(&[("1.txt", "renamed.txt")]), actual);
+    assert_eq!(vec!["a/2.txt", "a/b/3.txt", "renamed.txt"], tracked(&repo));
+    assert!(!dir.path().join("1.txt").try_exists()?);
+    assert_eq!("one", fs::read_to_string(dir.path().join("renamed.txt"))?);
+
+    let new_entry = repo
+        .index()?
+        .entry(&WsPath::new_unchecked("renamed.txt"))
+        .unwrap();
+    assert_eq!(old_entry.oid, new_entry.oid);
+    assert_eq!(old_entry.stat, new_entry.stat);
+
+    assert_contains_unordered(
+        repo.status()?.into_values(),
+        [
+            |s: &FileStatus| {
+                s.index
+                    == Status::Renamed {
+                        from: WsPath::new_unchecked("1.txt"),
+                        score: 100,
+                    }
+                    && s.workspace == Status::Unmodified
+                    && s.path == "renamed.txt"
+            },
+            |s: &FileStatus| s.path == "a/2.txt",
+            |s: &FileStatus| s.path == "a/b/3.txt",
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn moves_files_into_directory() -> Result {
+    let (_dir, mut repo) = init_with_commit()?;
+
+    let actual = repo.mv(vec!["1.txt", "a/b/3.txt"], "a")?;
+
+    assert_eq!(
+        moved(&[("1.txt", "a/1.txt"), ("a/b/3.txt", "a/3.txt")]),
+        actual
+    );
+    assert_eq!(vec!["a/1.txt", "a/2.txt", "a/3.txt"], tracked(&repo));
+
+    Ok(())
+}
+
+#[test]
+fn renames_directory() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("a/untracked.txt"), "untracked")?;
+
+    let actual = repo.mv(vec!["a"], "c")?;
+
+    assert_eq!(
+        moved(&[("a/2.txt", "c/2.txt"), ("a/b/3.txt", "c/b/3.txt")]),
+        actual
+    );
+    assert_eq!(vec!["1.txt", "c/2.txt", "c/b/3.txt"], tracked(&repo));
+    assert!(!dir.path().join("a").try_exists()?);
+    assert!(dir.path().join("c/untracked.txt").try_exists()?);
+
+    Ok(())
+}
+
+#[test]
+fn refuses_to_overwrite_tracked_file() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+
+    let err = repo.mv(vec!["a/2.txt"], "1.txt").unwrap_err();
+    assert!(matches!(err, MvError::DestinationExists(path) if path == "1.txt"));
+    assert_eq!("one", fs::read_to_string(dir.path().join("1.txt"))?);
+
+    // Even if it was deleted from the workspace
+    fs::remove_file(dir.path().join("1.txt"))?;
+    let err = repo.mv(vec!["a/2.txt"], "1.txt").unwrap_err();
+    assert!(matches!(err, MvError::DestinationExists(path) if path == "1.txt"));
+
+    assert_eq!(vec!["1.txt", "a/2.txt", "a/b/3.txt"], tracked(&repo));
+
+    Ok(())
+}
+
+#[test]
+fn refuses_to_overwrite_untracked_file() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("untracked.txt"), "untracked")?;
+
+    let err = repo.mv(vec!["1.txt"], "untracked.txt").unwrap_err();
+    assert!(matches!(err, MvError::DestinationExists(_)));
+    assert_eq!(
+        "untracked",
+        fs::read_to_string(dir.path().join("untracked.txt"))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn refuses_untracked_source() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("untracked.txt"), "untracked")?;
+
+    let err = repo.mv(vec!["untracked.txt"], "other.txt").unwrap_err();
+    assert!(matches!(err, MvError::NotTracked(_)));
+
+    let err = repo.mv(vec!["missing.txt"], "other.txt").unwrap_err();
+    assert!(matches!(err, MvError::SourceMissing(_)));
+
+    Ok(())
+}
+
+#[test]
+fn refuses_to_move_directory_into_itself() -> Result {
+    let (_dir, mut repo) = init_with_commit()?;
+
+    let err = repo.mv(vec!["a"], "a/b").unwrap_err();
+    assert!(matches!(err, MvError::IntoItself(..)));
+    assert_eq!(vec!["1.txt", "a/2.txt", "a/b/3.txt"], tracked(&repo));
+
+    Ok(())
+}
+
+#[test]
+fn refuses_multiple_sources_without_directory() -> Result {
+    let (_dir, mut repo) = init_with_commit()?;
+
+    let err = repo.mv(vec!["1.txt", "a/2.txt"], "new.txt").unwrap_err();
+    assert!(matches!(err, MvError::DestinationNotDir(_)));
+
+    Ok(())
+}
+
+#[test]
+fn keeps_intent_to_add() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("c.txt"), "not yet")?;
+    let opts = AddOptions {
+        intent_to_add: true,
+    };
+    repo.add_with_options(vec!["c.txt"], opts)?;
+
+    repo.mv(vec!["c.txt"], "d.txt")?;
+
+    let index = repo.index()?;
+    let entry = index.entry(&WsPath::new_unchecked("d.txt")).unwrap();
+    assert!(entry.intent_to_add());
+
+    // So the empty blob it stands for isn't committed
+    repo.commit(NAME, EMAIL, MSG)?;
+    let head = repo.refs.head()?.unwrap();
+    let tree = repo.db.load(head)?.tree;
+    let files = repo.db.load_tree_files(&WsPath::root(), tree)?;
+    assert!(!files.contains_key(&WsPath::new_unchecked("d.txt")));
+
+    Ok(())
+}
+
+#[test]
+fn keeps_skip_worktree_and_assume_unchanged() -> Result {
+    let (_dir, mut repo) = init_with_commit()?;
+    let opts = UpdateIndexOptions {
+        assume_unchanged: Some(true),
+        skip_worktree: Some(true),
+    };
+    repo.update_index(vec!["1.txt"], opts)?;
+
+    repo.mv(vec!["1.txt"], "renamed.txt")?;
+
+    let index = repo.index()?;
+    let entry = index.entry(&WsPath::new_unchecked("renamed.txt")).unwrap();
+    assert!(entry.skip_worktree());
+    assert!(entry.assume_unchanged());
+    assert!(!entry.intent_to_add());
+
+    Ok(())
+}
+
+#[test]
+fn undoes_renames_when_one_fails() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    fs::create_dir(dir.path().join("c"))?;
+
+    // Once a is moved, a/2.txt is no longer there to move
+    let err = repo.mv(vec!["1.txt", "a", "a/2.txt"], "c").unwrap_err();
+    assert!(matches!(err, MvError::Rename(_)));
+
+    assert_eq!(vec!["1.txt", "a/2.txt", "a/b/3.txt"], tracked(&repo));
+    assert_eq!("one", fs::read_to_string(dir.path().join("1.txt"))?);
+    assert_eq!("two", fs::read_to_string(dir.path().join("a/2.txt"))?);
+    assert!(!dir.path().join("c/1.txt").try_exists()?);
+    assert!(!dir.path().join("c/a").try_exists()?);
+
+    Ok(())
+}
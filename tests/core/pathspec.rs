@@ -0,0 +1,150 @@
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    repo::{AddError, RmError, RmOptions},
+    Pathspec, Status, StatusOptions, WsPath,
+};
+
+fn fixture() -> eyre::Result<(TempDir, Repo)> {
+    init();
+    let (dir_h, repo) = repo_fixture()?;
+    let dir = dir_h.path();
+
+    write_to(dir.join("Cargo.toml"), "")?;
+    write_to(dir.join("src/lib.rs"), "")?;
+    write_to(dir.join("src/ui/mod.rs"), "")?;
+    write_to(dir.join("tests/core.rs"), "")?;
+    write_to(dir.join("README.md"), "")?;
+
+    Ok((dir_h, repo))
+}
+
+#[test]
+fn add_matches_wildcards_across_directories() -> Result {
+    let (_dir, mut repo) = fixture()?;
+
+    repo.add(vec!["*.rs"])?;
+    assert_eq!(
+        vec!["src/lib.rs", "src/ui/mod.rs", "tests/core.rs"],
+        tracked(&repo)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn add_applies_magic() -> Result {
+    let (_dir, mut repo) = fixture()?;
+
+    repo.add(vec![".", ":!src", ":(icase)*.TOML"])?;
+    assert_eq!(
+        vec!["Cargo.toml", "README.md", "tests/core.rs"],
+        tracked(&repo)
+    );
+
+    repo.add(vec![":(glob)src/*.rs"])?;
+    assert_eq!(
+        vec!["Cargo.toml", "README.md", "src/lib.rs", "tests/core.rs"],
+        tracked(&repo)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn add_stages_deleted_files() -> Result {
+    let (dir, mut repo) = fixture()?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    fs::remove_file(dir.path().join("src/lib.rs"))?;
+    repo.add(vec!["src/lib.rs"])?;
+
+    assert_eq!(
+        vec!["Cargo.toml", "README.md", "src/ui/mod.rs", "tests/core.rs"],
+        tracked(&repo)
+    );
+    assert_eq!(
+        Status::Deleted,
+        repo.status()?[&WsPath::new_unchecked("src/lib.rs")].index
+    );
+
+    Ok(())
+}
+
+#[test]
+fn add_reports_unmatched_pathspec() -> Result {
+    let (_dir, mut repo) = fixture()?;
+
+    let err = repo.add(vec!["src", "*.c"]).unwrap_err();
+    assert!(matches!(err, AddError::DidNotMatch(spec) if spec == "*.c"));
+    assert!(tracked(&repo).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn status_is_limited_to_pathspec() -> Result {
+    let (dir, mut repo) = fixture()?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    fs::remove_file(dir.path().join("src/lib.rs"))?;
+    write_to(dir.path().join("src/new.rs"), "")?;
+    write_to(dir.path().join("README.md"), "changed")?;
+
+    let status = repo
+        .status_with_options(vec!["src"], StatusOptions::default())?
+        .into_values()
+        .filter(|s| s.workspace != Status::Unmodified)
+        .map(|s| (s.path.to_string(), s.workspace))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            ("src/lib.rs".to_owned(), Status::Deleted),
+            ("src/new.rs".to_owned(), Status::Untracked)
+        ],
+        status
+    );
+
+    Ok(())
+}
+
+#[test]
+fn pathspecs_are_relative_to_prefix() -> Result {
+    let (_dir, mut repo) = fixture()?;
+
+    let prefix = WsPath::new_unchecked("src");
+    let pathspec = Pathspec::parse(vec!["*.rs", ":/README.md"], &prefix, repo.workspace()?)?;
+    repo.add(pathspec)?;
+    assert_eq!(
+        vec!["README.md", "src/lib.rs", "src/ui/mod.rs"],
+        tracked(&repo)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn rm_matches_index_entries() -> Result {
+    let (dir, mut repo) = fixture()?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    // A wildcard matches files directly, so doesn't need recursive
+    repo.rm(vec!["src/*.rs"], RmOptions::default())?;
+    assert_eq!(
+        vec!["Cargo.toml", "README.md", "tests/core.rs"],
+        tracked(&repo)
+    );
+    assert!(!dir.path().join("src/ui/mod.rs").try_exists()?);
+
+    let err = repo.rm(vec!["tests"], RmOptions::default()).unwrap_err();
+    assert!(matches!(err, RmError::NotRecursive(spec) if spec == "tests"));
+
+    let err = repo.rm(vec!["*.c"], RmOptions::default()).unwrap_err();
+    assert!(matches!(err, RmError::DidNotMatch(spec) if spec == "*.c"));
+
+    Ok(())
+}
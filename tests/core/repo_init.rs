@@ -1,4 +1,6 @@
+use test_support::assert_eq;
 use test_support::*;
+use writ::core::{discover, repo::ReadError};
 
 #[test]
 fn can_init() -> Result {
@@ -20,3 +22,57 @@ fn can_init_nonexistent_ws() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn discovers_repo_from_subdirectory() -> Result {
+    init();
+    let (dir, _repo) = repo_fixture()?;
+    let dir = dir.path();
+    write_to(dir.join("a/b/file.txt"), "")?;
+
+    let mut repo = Repo::discover(dir.join("a/b"))?;
+    assert_eq!(dir.canonicalize()?, repo.workspace()?.path());
+    repo.add(vec!["."])?;
+    assert_eq!(
+        vec!["a/b/file.txt"],
+        repo.index()?
+            .entries()
+            .map(|e| e.path.to_string())
+            .collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn discovers_separate_git_dir() -> Result {
+    init();
+    let dir = tempdir()?;
+    let ws = dir.path().join("ws");
+    let git_dir = dir.path().join("repo.git");
+    let (ws_s, git_dir_s) = (ws.to_str().unwrap(), git_dir.to_str().unwrap());
+    run_fun!(git init --separate-git-dir $git_dir_s $ws_s)?;
+    write_to(ws.join("file.txt"), "")?;
+
+    let mut repo = Repo::discover(&ws)?;
+    repo.add(vec!["file.txt"])?;
+
+    assert!(git_dir.join("index").try_exists()?);
+    assert!(ws.join(".git").is_file());
+
+    Ok(())
+}
+
+#[test]
+fn reports_when_not_in_repo() -> Result {
+    init();
+    let dir = tempdir()?;
+
+    let err = Repo::discover(dir.path()).unwrap_err();
+    assert!(matches!(
+        err,
+        ReadError::Discover(discover::Error::NotFound(_))
+    ));
+
+    Ok(())
+}
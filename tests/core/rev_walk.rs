@@ -0,0 +1,179 @@
+use std::{path::Path, process::Command};
+
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    rev_walk::{self, Order},
+    revision::ResolveError,
+    Revision,
+};
+
+/// History made by git where `side` branches off `master`, is merged back
+/// and goes on, with a commit on `master` dated before its parent and `v1`
+/// tagging the first commit on `master` after the branch.
+///
+/// ```text
+/// c1 - m1 - m2 ------ M - m3   master
+///    \               /
+///     s1 - s2 ------- s3       side
+/// ```
+fn branches_fixture() -> eyre::Result<(TempDir, Repo)> {
+    git_fixture(|path| {
+        let commit = |file: &str, date: &str| -> Result {
+            write_to(path.join(file), file)?;
+            git(path, date, &["add", file])?;
+            git(path, date, &["commit", "-qm", file])
+        };
+        commit("c1", "2021-01-01 10:00:00")?;
+        git(path, "2021-01-01 10:00:00", &["checkout", "-qb", "side"])?;
+        commit("s1", "2021-01-03 10:00:00")?;
+        commit("s2", "2021-01-05 10:00:00")?;
+        git(path, "2021-01-05 10:00:00", &["checkout", "-q", "master"])?;
+        commit("m1", "2021-01-02 10:00:00")?;
+        git(path, "2021-01-02 10:00:00", &["tag", "v1"])?;
+        // Skewed to be older than its parent
+        commit("m2", "2020-12-31 10:00:00")?;
+        git(
+            path,
+            "2021-01-06 10:00:00",
+            &["merge", "-q", "--no-edit", "-m", "M", "side"],
+        )?;
+        commit("m3", "2021-01-07 10:00:00")?;
+        git(path, "2021-01-07 10:00:00", &["checkout", "-q", "side"])?;
+        commit("s3", "2021-01-08 10:00:00")?;
+        git(path, "2021-01-08 10:00:00", &["checkout", "-q", "master"])?;
+        Ok(())
+    })
+}
+
+fn git_rev_list(dir: &Path, args: &[&str]) -> eyre::Result<Vec<String>> {
+    let out = Command::new("git")
+        .arg("rev-list")
+        .arg("--format=%s")
+        .arg("--no-commit-header")
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    eyre::ensure!(out.status.success(), "git rev-list {:?} failed", args);
+    Ok(String::from_utf8(out.stdout)?
+        .lines()
+        .map(str::to_owned)
+        .collect())
+}
+
+/// The subjects of the commits walked with the flags and revisions in
+/// `args`, as `git rev-list` takes them.
+fn walk(repo: &mut Repo, args: &[&str]) -> eyre::Result<Vec<String>> {
+    let (flags, revisions): (Vec<&str>, Vec<_>) = args.iter().partition(|a| a.starts_with("--"));
+    let revisions = revisions
+        .into_iter()
+        .map(Revision::parse)
+        .collect::<Vec<_>>();
+    let mut walk = repo
+        .rev_walk(&revisions)?
+        .with_reverse(flags.contains(&"--reverse"))
+        .with_first_parent(flags.contains(&"--first-parent"));
+    if flags.contains(&"--topo-order") {
+        walk = walk.with_order(Order::Topo);
+    } else if flags.contains(&"--date-order") {
+        walk = walk.with_order(Order::Date);
+    }
+    walk.map(|commit| Ok(commit?.subject().to_string()))
+        .collect()
+}
+
+#[test]
+fn walks_like_git_rev_list() -> Result {
+    init();
+    let (dir, mut repo) = branches_fixture()?;
+
+    assert_eq!(
+        // By date as they're reached, so the skewed m2 is only reached late
+        vec!["m3", "M", "s2", "s1", "c1", "m2", "m1"],
+        walk(&mut repo, &["HEAD"])?
+    );
+    for args in &[
+        &["HEAD"][..],
+        &["--date-order", "HEAD"],
+        &["--topo-order", "HEAD"],
+        &["--topo-order", "master", "side"],
+        &["--date-order", "master", "side"],
+        &["--reverse", "--topo-order", "HEAD"],
+        &["--first-parent", "HEAD"],
+        &["--first-parent", "--topo-order", "side", "master"],
+        &["side"],
+    ] {
+        let expected = git_rev_list(dir.path(), args)?;
+        assert_eq!(expected, walk(&mut repo, args)?, "{:?}", args);
+    }
+    Ok(())
+}
+
+#[test]
+fn hides_excluded_commits() -> Result {
+    init();
+    let (dir, mut repo) = branches_fixture()?;
+
+    assert_eq!(vec!["s3"], walk(&mut repo, &["master..side"])?);
+    assert_eq!(
+        vec!["m3", "M", "m2", "m1"],
+        walk(&mut repo, &["side..master"])?
+    );
+    assert_eq!(
+        vec!["s3", "m3", "M", "m2", "m1"],
+        walk(&mut repo, &["side...master"])?
+    );
+    for args in &[
+        &["master..side"][..],
+        &["side..master"],
+        &["side...master"],
+        &["--topo-order", "master...side"],
+        &["^v1", "side", "master"],
+        &["--first-parent", "^side~1", "master"],
+        &["HEAD~2..HEAD"],
+        &["HEAD~1^2..", "--reverse"],
+        &["^v1~1", "HEAD^1"],
+        &["v1..v1"],
+    ] {
+        let expected = git_rev_list(dir.path(), args)?;
+        assert_eq!(expected, walk(&mut repo, args)?, "{:?}", args);
+    }
+    Ok(())
+}
+
+#[test]
+fn finds_merge_bases() -> Result {
+    init();
+    let (_dir, mut repo) = branches_fixture()?;
+    let (master, side) = (repo.resolve("master")?, repo.resolve("side")?);
+
+    let s2 = repo.resolve("side~1")?;
+    assert_eq!(vec![s2], rev_walk::merge_bases(&mut repo.db, master, side)?);
+    let (c1, v1) = (repo.resolve("v1^")?, repo.resolve("v1")?);
+    assert_eq!(vec![c1], rev_walk::merge_bases(&mut repo.db, v1, s2)?);
+    Ok(())
+}
+
+#[test]
+fn resolves_names() -> Result {
+    init();
+    let (_dir, mut repo) = branches_fixture()?;
+    let head = repo.refs.head()?.unwrap();
+
+    assert_eq!(head, repo.resolve("@")?);
+    assert_eq!(head, repo.resolve("master")?);
+    assert_eq!(head, repo.resolve("refs/heads/master")?);
+    assert_eq!(head, repo.resolve(&head.to_hex()[..7])?);
+    let v1 = repo.resolve("tags/v1")?;
+    assert_eq!(v1, repo.resolve("HEAD~2^1")?);
+    assert_eq!(repo.resolve("side~2")?, repo.resolve("HEAD^^2~1")?);
+    assert_eq!(v1, repo.resolve("v1^0")?);
+
+    let err = repo.resolve("nope").unwrap_err();
+    assert!(matches!(err, ResolveError::Unknown(_)), "{:?}", err);
+    assert_eq!("Unknown revision \"nope\"", err.to_string());
+    let err = repo.resolve("v1^2").unwrap_err();
+    assert!(matches!(err, ResolveError::Unknown(_)), "{:?}", err);
+    Ok(())
+}
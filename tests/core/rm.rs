@@ -0,0 +1,240 @@
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    repo::{AddOptions, RmError, RmOptions},
+    FileStatus, Status, WsPath,
+};
+
+#[test]
+fn removes_from_index_and_workspace() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+
+    let removed = repo.rm(vec!["1.txt"], RmOptions::default())?;
+
+    assert_eq!(vec![WsPath::new_unchecked("1.txt")], removed);
+    assert_eq!(vec!["a/2.txt", "a/b/3.txt"], tracked(&repo));
+    assert!(!dir.path().join("1.txt").try_exists()?);
+
+    assert_contains_unordered(
+        repo.status()?.into_values(),
+        [
+            |s: &FileStatus| s.index == Status::Deleted && s.path == "1.txt",
+            |s: &FileStatus| s.index == Status::Unmodified && s.path == "a/2.txt",
+            |s: &FileStatus| s.index == Status::Unmodified && s.path == "a/b/3.txt",
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cached_leaves_workspace_untouched() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+
+    let opts = RmOptions {
+        cached: true,
+        ..RmOptions::default()
+    };
+    repo.rm(vec!["1.txt"], opts)?;
+
+    assert_eq!(vec!["a/2.txt", "a/b/3.txt"], tracked(&repo));
+    assert!(dir.path().join("1.txt").try_exists()?);
+
+    assert_contains_unordered(
+        repo.status()?.into_values(),
+        [
+            |s: &FileStatus| {
+                s.index == Status::Deleted && s.workspace == Status::Untracked && s.path == "1.txt"
+            },
+            |s: &FileStatus| s.path == "a/2.txt",
+            |s: &FileStatus| s.path == "a/b/3.txt",
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn removes_already_deleted_file() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    fs::remove_file(dir.path().join("1.txt"))?;
+
+    repo.rm(vec!["1.txt"], RmOptions::default())?;
+
+    assert_eq!(vec!["a/2.txt", "a/b/3.txt"], tracked(&repo));
+
+    Ok(())
+}
+
+#[test]
+fn directory_requires_recursive() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+
+    let err = repo.rm(vec!["a"], RmOptions::default()).unwrap_err();
+    assert!(matches!(&err, RmError::NotRecursive(path) if path == "a"));
+    assert_eq!("Not removing \"a\" recursively without -r", err.to_string());
+    assert_eq!(vec!["1.txt", "a/2.txt", "a/b/3.txt"], tracked(&repo));
+
+    let opts = RmOptions {
+        recursive: true,
+        ..RmOptions::default()
+    };
+    repo.rm(vec!["a"], opts)?;
+
+    assert_eq!(vec!["1.txt"], tracked(&repo));
+    assert!(!dir.path().join("a").try_exists()?);
+
+    Ok(())
+}
+
+#[test]
+fn keeps_directories_with_untracked_files() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("a/b/untracked.txt"), "untracked")?;
+
+    let opts = RmOptions {
+        recursive: true,
+        ..RmOptions::default()
+    };
+    repo.rm(vec!["a"], opts)?;
+
+    assert_eq!(vec!["1.txt"], tracked(&repo));
+    assert_eq!(
+        vec!["b", "b/untracked.txt"],
+        all_entries(dir.path().join("a"))?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn untracked_path_fails() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("untracked.txt"), "untracked")?;
+
+    let err = repo
+        .rm(vec!["untracked.txt"], RmOptions::default())
+        .unwrap_err();
+    assert!(matches!(err, RmError::DidNotMatch(_)));
+    assert!(dir.path().join("untracked.txt").try_exists()?);
+
+    Ok(())
+}
+
+#[test]
+fn refuses_to_lose_local_changes() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("1.txt"), "changed")?;
+
+    let err = repo.rm(vec!["1.txt"], RmOptions::default()).unwrap_err();
+    assert!(matches!(err, RmError::LocalChanges(_)));
+    assert_eq!(vec!["1.txt", "a/2.txt", "a/b/3.txt"], tracked(&repo));
+    assert!(dir.path().join("1.txt").try_exists()?);
+
+    // The workspace is kept, so nothing can be lost
+    let opts = RmOptions {
+        cached: true,
+        ..RmOptions::default()
+    };
+    repo.rm(vec!["1.txt"], opts)?;
+    assert_eq!(vec!["a/2.txt", "a/b/3.txt"], tracked(&repo));
+
+    Ok(())
+}
+
+#[test]
+fn refuses_to_lose_staged_changes() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("1.txt"), "staged")?;
+    repo.add(vec!["1.txt"])?;
+
+    let err = repo.rm(vec!["1.txt"], RmOptions::default()).unwrap_err();
+    assert!(matches!(err, RmError::StagedChanges(_)));
+
+    write_to(dir.path().join("1.txt"), "staged and then modified")?;
+    let opts = RmOptions {
+        cached: true,
+        ..RmOptions::default()
+    };
+    let err = repo.rm(vec!["1.txt"], opts).unwrap_err();
+    assert!(matches!(err, RmError::StagedAndLocalChanges(_)));
+
+    assert_eq!(vec!["1.txt", "a/2.txt", "a/b/3.txt"], tracked(&repo));
+
+    Ok(())
+}
+
+#[test]
+fn force_removes_changed_files() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("1.txt"), "staged")?;
+    repo.add(vec!["1.txt"])?;
+    write_to(dir.path().join("1.txt"), "staged and then modified")?;
+
+    let opts = RmOptions {
+        force: true,
+        ..RmOptions::default()
+    };
+    repo.rm(vec!["1.txt"], opts)?;
+
+    assert_eq!(vec!["a/2.txt", "a/b/3.txt"], tracked(&repo));
+    assert!(!dir.path().join("1.txt").try_exists()?);
+
+    Ok(())
+}
+
+#[test]
+fn index_not_locked_after_refused_rm() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("1.txt"), "changed")?;
+
+    assert!(repo.rm(vec!["1.txt"], RmOptions::default()).is_err());
+    assert!(!dir.path().join(".git/index.lock").try_exists()?);
+
+    Ok(())
+}
+
+#[test]
+fn overlapping_pathspecs_remove_each_file_once() -> Result {
+    let (_dir, mut repo) = init_with_commit()?;
+
+    let opts = RmOptions {
+        recursive: true,
+        ..RmOptions::default()
+    };
+    let removed = repo.rm(vec!["a/b/3.txt", "*", "a"], opts)?;
+
+    assert_eq!(
+        vec![
+            WsPath::new_unchecked("1.txt"),
+            WsPath::new_unchecked("a/2.txt"),
+            WsPath::new_unchecked("a/b/3.txt"),
+        ],
+        removed
+    );
+    assert!(tracked(&repo).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn cached_removes_intent_to_add() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("new.txt"), "new")?;
+    let opts = AddOptions {
+        intent_to_add: true,
+    };
+    repo.add_with_options(vec!["new.txt"], opts)?;
+
+    let opts = RmOptions {
+        cached: true,
+        ..RmOptions::default()
+    };
+    repo.rm(vec!["new.txt"], opts)?;
+
+    assert_eq!(vec!["1.txt", "a/2.txt", "a/b/3.txt"], tracked(&repo));
+    assert_eq!("new", fs::read_to_string(dir.path().join("new.txt"))?);
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::{path::Path, process::Command};
+
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    diff::{
+        format::{self as diff_format, Formats},
+        patch::PatchOptions,
+    },
+    log::{show, Format},
+    revision::ResolveError,
+    Pathspec,
+};
+
+/// Two commits made by git, the second tagged `v2` and its parent `v1`,
+/// with a tag `vt` of the first one's tree and `vv` of `v2`, and a branch
+/// `merged` merging `side`, off `v1`, into `v2`.
+fn tags_fixture() -> eyre::Result<(TempDir, Repo)> {
+    git_fixture(|path| {
+        let date = "2021-01-01 10:00:00";
+        write_to(path.join("a"), "a\n")?;
+        write_to(path.join("d/b"), "b\n")?;
+        write_to(path.join("d-e"), "d-e\n")?;
+        git(path, date, &["add", "."])?;
+        git(path, date, &["commit", "-qm", "first"])?;
+        git(path, date, &["tag", "-am", "The first\n\nWith notes", "v1"])?;
+        git(path, date, &["tag", "-am", "A tree", "vt", "HEAD^{tree}"])?;
+        write_to(path.join("a"), "a\nc\n")?;
+        git(path, date, &["commit", "-qam", "second"])?;
+        git(path, date, &["tag", "-am", "The second", "v2"])?;
+        git(
+            path,
+            date,
+            &[
+                "-c",
+                "advice.nestedTag=false",
+                "tag",
+                "-am",
+                "A tag of a tag",
+                "vv",
+                "v2",
+            ],
+        )?;
+        git(path, date, &["tag", "light"])?;
+
+        git(path, date, &["checkout", "-qb", "side", "v1"])?;
+        write_to(path.join("d/b"), "b\nside\n")?;
+        git(path, date, &["commit", "-qam", "side"])?;
+        git(path, date, &["checkout", "-qb", "merged", "v2"])?;
+        git(path, date, &["merge", "-q", "-m", "merge", "side"])?;
+        git(path, date, &["checkout", "-q", "master"])?;
+        Ok(())
+    })
+}
+
+fn git_show(dir: &Path, args: &[&str]) -> eyre::Result<String> {
+    let out = Command::new("git")
+        .arg("show")
+        .args(args)
+        .current_dir(dir)
+        .env_remove("COLUMNS")
+        .output()?;
+    eyre::ensure!(out.status.success(), "git show {:?} failed", args);
+    Ok(String::from_utf8(out.stdout)?)
+}
+
+fn writ_show(
+    repo: &mut Repo,
+    names: &[&str],
+    format: &Format,
+    formats: &Formats,
+) -> eyre::Result<String> {
+    let mut objects = Vec::new();
+    for name in names {
+        let oid = repo.resolve_object(name)?;
+        for object in show::objects(&mut repo.db, oid)? {
+            objects.push((*name, object));
+        }
+    }
+
+    let mut out = Vec::new();
+    show::write(
+        &mut out,
+        objects.iter().map(|(name, object)| (*name, object)),
+        format,
+        false,
+        formats.dashes(),
+        |commit| -> eyre::Result<Vec<u8>> {
+            let diffs = repo.diff_first_parent(commit, &Pathspec::all())?;
+            let mut diff = Vec::new();
+            diff_format::write(&mut diff, &diffs, formats, &PatchOptions::default())?;
+            Ok(diff)
+        },
+    )?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[test]
+fn shows_objects_like_git() -> Result {
+    init();
+    let (dir, mut repo) = tags_fixture()?;
+
+    let patch = Formats {
+        patch: true,
+        ..Formats::default()
+    };
+    let stat = Formats {
+        stat: true,
+        ..Formats::default()
+    };
+    for (args, format, formats) in &[
+        (vec!["HEAD"], Format::Medium, patch),
+        (vec!["v1"], Format::Medium, patch),
+        (vec!["vv", "light"], Format::Medium, stat),
+        (vec!["vt", "HEAD:", "HEAD:d"], Format::Medium, patch),
+        (vec!["HEAD:a", "v1:a", "vt"], Format::Medium, patch),
+        (vec!["--oneline", "vv", "v1"], Format::Oneline, patch),
+        (vec!["--format=%h %s", "vt", "v2"], "%h %s".parse()?, stat),
+        (
+            vec!["--format=format:%s", "HEAD~1", "HEAD", "v1"],
+            "format:%s".parse()?,
+            Formats::default(),
+        ),
+    ] {
+        let names = args
+            .iter()
+            .filter(|arg| !arg.starts_with('-'))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut git_args = args.clone();
+        if *formats == stat {
+            git_args.push("--stat");
+        } else if *formats == Formats::default() {
+            git_args.push("-s");
+        }
+        assert_eq!(
+            git_show(dir.path(), &git_args)?,
+            writ_show(&mut repo, &names, format, formats)?,
+            "{:?}",
+            args
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn shows_merges_against_their_first_parent() -> Result {
+    init();
+    let (dir, mut repo) = tags_fixture()?;
+
+    let formats = Formats {
+        patch: true,
+        ..Formats::default()
+    };
+    let shown = writ_show(&mut repo, &["merged"], &Format::Medium, &formats)?;
+    assert!(shown.contains("+side"), "{}", shown);
+    // Git shows merges as combined diffs unless asked for the first parent
+    assert_eq!(git_show(dir.path(), &["--first-parent", "merged"])?, shown);
+    Ok(())
+}
+
+#[test]
+fn resolves_tags_and_paths() -> Result {
+    init();
+    let (_dir, mut repo) = tags_fixture()?;
+
+    let second = repo.resolve("HEAD")?;
+    assert_eq!(second, repo.resolve("vv")?);
+    assert_eq!(repo.resolve("HEAD~1")?, repo.resolve("v1")?);
+    assert_eq!(second.into_untyped(), repo.resolve_object("light")?);
+    assert_ne!(second.into_untyped(), repo.resolve_object("v2")?);
+
+    let tree = repo.db.load(second)?.tree;
+    assert_eq!(tree.into_untyped(), repo.resolve_object("v2:")?);
+    assert_eq!(
+        repo.resolve_object("HEAD:d")?,
+        repo.resolve_object("HEAD:d/")?
+    );
+
+    assert!(matches!(
+        repo.resolve("vt"),
+        Err(ResolveError::NotACommit(name)) if name == "vt"
+    ));
+    assert!(matches!(
+        repo.resolve_object("HEAD:d/c"),
+        Err(ResolveError::NotInTree { path, .. }) if path == "d/c"
+    ));
+    assert!(matches!(
+        repo.resolve_object("HEAD:a/b"),
+        Err(ResolveError::NotInTree { .. })
+    ));
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use cmd_lib::run_cmd;
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{repo::WorktreeAddOptions, Status, WsPath};
+
+/// A committed repository with files at the top, in `a`, `a/b` and `c`.
+fn fixture() -> eyre::Result<(TempDir, Repo)> {
+    committed_fixture(|path| {
+        for file in &[
+            "top.txt",
+            "a/a.txt",
+            "a/b/b.txt",
+            "a/other/o.txt",
+            "c/c.txt",
+        ] {
+            write_to(path.join(file), format!("{}\n", file))?;
+        }
+        Ok(())
+    })
+}
+
+fn skipped(repo: &Repo) -> eyre::Result<Vec<String>> {
+    Ok(repo
+        .index()?
+        .entries()
+        .filter(|e| e.skip_worktree())
+        .map(|e| e.path.to_string())
+        .collect())
+}
+
+fn path(path: &str) -> WsPath {
+    WsPath::new_unchecked(path)
+}
+
+#[test]
+fn set_removes_files_outside_the_cone() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+
+    let kept = repo.sparse_checkout_set(vec![path("a/b")])?;
+    assert!(kept.is_empty());
+    for included in &["top.txt", "a/a.txt", "a/b/b.txt"] {
+        assert!(dir.join(included).exists(), "{}", included);
+    }
+    assert!(!dir.join("a/other").exists());
+    assert!(!dir.join("c").exists());
+    assert_eq!(vec!["a/other/o.txt", "c/c.txt"], skipped(&repo)?);
+    assert!(is_clean(&mut repo)?);
+
+    // Opened again, the patterns are read back
+    let repo = Repo::new(dir)?;
+    assert_eq!(
+        vec![&path("a/b")],
+        repo.workspace()?.sparse().dirs().collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn status_and_add_skip_paths_outside_the_cone() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+    repo.sparse_checkout_set(vec![path("a")])?;
+
+    // Left out, so not reported
+    write_to(dir.join("c/new.txt"), "")?;
+    write_to(dir.join("a/new.txt"), "")?;
+    let status = repo.status()?;
+    assert_eq!(Status::Untracked, status[&path("a/new.txt")].workspace);
+    assert!(!status.contains_key(&path("c/new.txt")));
+    assert!(!status.contains_key(&path("c")));
+
+    // Skipped entries aren't taken to be deleted
+    repo.add(vec!["."])?;
+    assert_eq!(vec!["c/c.txt"], skipped(&repo)?);
+    assert!(repo.index()?.entry(&path("a/new.txt")).is_some());
+    assert!(repo.index()?.entry(&path("c/new.txt")).is_none());
+    Ok(())
+}
+
+#[test]
+fn changed_files_are_kept_until_disabled() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+    write_to(dir.join("c/c.txt"), "changed\n")?;
+
+    let kept = repo.sparse_checkout_set(vec![path("a")])?;
+    assert_eq!(vec![path("c/c.txt")], kept);
+    assert_eq!("changed\n", fs::read_to_string(dir.join("c/c.txt"))?);
+    assert!(skipped(&repo)?.is_empty());
+
+    assert!(repo.sparse_checkout_disable()?.is_empty());
+    assert!(!repo.workspace()?.sparse().is_sparse());
+    write_to(dir.join("c/c.txt"), "c/c.txt\n")?;
+    repo.sparse_checkout_set(vec![path("a/b")])?;
+    assert!(!dir.join("c").exists());
+
+    repo.sparse_checkout_disable()?;
+    assert_eq!("c/c.txt\n", fs::read_to_string(dir.join("c/c.txt"))?);
+    assert_eq!(
+        "a/other/o.txt\n",
+        fs::read_to_string(dir.join("a/other/o.txt"))?
+    );
+    assert!(skipped(&repo)?.is_empty());
+    assert!(is_clean(&mut repo)?);
+    Ok(())
+}
+
+#[test]
+fn new_worktrees_check_out_only_the_cone() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    repo.sparse_checkout_set(vec![path("c")])?;
+
+    let wt_path = dir.path().join("wt");
+    let mut wt = repo.worktree_add(&wt_path, WorktreeAddOptions::default())?;
+    assert!(wt_path.join("top.txt").exists());
+    assert!(wt_path.join("c/c.txt").exists());
+    assert!(!wt_path.join("a").exists());
+    assert_eq!(vec!["a/a.txt", "a/b/b.txt", "a/other/o.txt"], skipped(&wt)?);
+    assert!(is_clean(&mut wt)?);
+    Ok(())
+}
+
+#[test]
+fn toggling_does_not_grow_config() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let git_dir = dir.path().join(".git");
+
+    repo.sparse_checkout_set(vec![path("a")])?;
+    let config = fs::read_to_string(git_dir.join("config"))?;
+    let worktree_config = fs::read_to_string(git_dir.join("config.worktree"))?;
+    for _ in 0..3 {
+        repo.sparse_checkout_disable()?;
+        repo.sparse_checkout_set(vec![path("a")])?;
+    }
+
+    assert_eq!(config, fs::read_to_string(git_dir.join("config"))?);
+    assert_eq!(
+        worktree_config,
+        fs::read_to_string(git_dir.join("config.worktree"))?
+    );
+    assert!(!config.contains("sparseCheckout"));
+    Ok(())
+}
+
+#[test]
+fn linked_worktrees_are_sparse_on_their_own() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    // Outside the main workspace, so it isn't untracked there
+    let wt_dir = tempdir()?;
+    let wt_path = wt_dir.path().join("wt");
+    let mut wt = repo.worktree_add(&wt_path, WorktreeAddOptions::default())?;
+
+    wt.sparse_checkout_set(vec![path("c")])?;
+    assert!(!wt_path.join("a").exists());
+
+    let mut repo = Repo::new(dir.path())?;
+    assert!(!repo.workspace()?.sparse().is_sparse());
+    assert!(dir.path().join("a/a.txt").exists());
+    assert!(skipped(&repo)?.is_empty());
+    assert!(is_clean(&mut repo)?);
+
+    // And the other way round
+    repo.sparse_checkout_set(vec![path("a")])?;
+    repo.sparse_checkout_disable()?;
+    let wt = Repo::new(&wt_path)?;
+    assert!(wt.workspace()?.sparse().is_sparse());
+    Ok(())
+}
+
+#[test]
+fn git_understands_our_sparse_checkout() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+    let dir_s = dir.to_str().unwrap();
+    repo.sparse_checkout_set(vec![path("a/b")])?;
+
+    let listed = run_fun! {
+        cd $dir_s;
+        git sparse-checkout list;
+    }?;
+    assert_eq!("a/b", listed);
+    let status = run_fun! {
+        cd $dir_s;
+        git status --porcelain;
+    }?;
+    assert_eq!("", status);
+
+    run_cmd! {
+        cd $dir_s;
+        git sparse-checkout set c;
+    }?;
+    let mut repo = Repo::new(dir)?;
+    assert_eq!(
+        vec![&path("c")],
+        repo.workspace()?.sparse().dirs().collect::<Vec<_>>()
+    );
+    assert!(!dir.join("a").exists());
+    assert!(is_clean(&mut repo)?);
+    Ok(())
+}
@@ -1,10 +1,20 @@
-use std::{thread, time::Duration};
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
 use cmd_lib::run_cmd;
+use filetime::FileTime;
 use test_support::assert_eq;
 use test_support::*;
 
-use writ::core::{FileStatus, Status};
+use writ::core::{
+    index::{entry::Stage, ConflictKind},
+    repo::{AddOptions, RmOptions, UpdateIndexOptions},
+    status::format::{self, Format, FormatOptions},
+    FileStatus, Pathspec, Renames, Stat, Status, StatusOptions, UntrackedFiles, WsPath,
+};
 
 #[test]
 fn lists_untracked() -> Result {
@@ -67,7 +77,7 @@ fn lists_untracked_dirs_with_contents() -> Result {
     assert_contains_unordered(
         status,
         [
-            |s: &FileStatus| s.workspace == Status::Untracked && s.path == "dir/another.txt",
+            |s: &FileStatus| s.workspace == Status::Untracked && s.path == "dir" && s.is_dir,
             |s: &FileStatus| s.workspace == Status::Untracked && s.path == "file.txt",
         ],
     );
@@ -93,7 +103,7 @@ fn lists_untracked_files_inside_tracked_dirs() -> Result {
         status,
         [
             |s: &FileStatus| s.workspace == Status::Unmodified,
-            |s: &FileStatus| s.workspace == Status::Untracked && s.path == "a/b/c/file.txt",
+            |s: &FileStatus| s.workspace == Status::Untracked && s.path == "a/b/c" && s.is_dir,
             |s: &FileStatus| s.workspace == Status::Untracked && s.path == "a/outer.txt",
         ],
     );
@@ -101,35 +111,118 @@ fn lists_untracked_files_inside_tracked_dirs() -> Result {
     Ok(())
 }
 
+fn untracked_and_ignored(
+    repo: &mut Repo,
+    untracked: UntrackedFiles,
+    ignored: bool,
+) -> eyre::Result<Vec<String>> {
+    let opts = StatusOptions {
+        untracked,
+        ignored,
+        ..StatusOptions::default()
+    };
+    let statuses = repo
+        .status_with_options(Pathspec::all(), opts)?
+        .into_values()
+        .filter(|s| matches!(s.workspace, Status::Untracked | Status::Ignored))
+        .map(|s| {
+            let slash = if s.is_dir { "/" } else { "" };
+            format!("{} {}{}", s.workspace.name(), s.path, slash)
+        })
+        .collect();
+    Ok(statuses)
+}
+
 #[test]
-fn doesnt_list_empty_untracked_dir() -> Result {
+fn untracked_files_modes() -> Result {
     init();
     let (dir, mut repo) = repo_fixture()?;
     let dir = dir.path();
-    fs::create_dir(dir.join("the_dir"))?;
 
-    let status = repo.status()?;
-    assert_eq!(0, status.len());
+    write_to(dir.join("tracked/a.txt"), b"")?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    write_to(dir.join("new.txt"), b"")?;
+    write_to(dir.join("tracked/b.txt"), b"")?;
+    write_to(dir.join("untracked/c.txt"), b"")?;
+    write_to(dir.join("untracked/deeper/d.txt"), b"")?;
+
+    assert_eq!(
+        vec![
+            "untracked new.txt",
+            "untracked tracked/b.txt",
+            "untracked untracked/"
+        ],
+        untracked_and_ignored(&mut repo, UntrackedFiles::Normal, false)?
+    );
+    assert_eq!(
+        vec![
+            "untracked new.txt",
+            "untracked tracked/b.txt",
+            "untracked untracked/c.txt",
+            "untracked untracked/deeper/d.txt"
+        ],
+        untracked_and_ignored(&mut repo, UntrackedFiles::All, false)?
+    );
+    assert_eq!(
+        Vec::<String>::new(),
+        untracked_and_ignored(&mut repo, UntrackedFiles::No, false)?
+    );
 
     Ok(())
 }
 
-fn init_with_commit() -> eyre::Result<(TempDir, Repo)> {
+#[test]
+fn lists_ignored_files_and_dirs() -> Result {
     init();
-    let (dir_h, mut repo) = repo_fixture()?;
-    let dir = dir_h.path();
-
-    write_to(dir.join("1.txt"), "one")?;
-    write_to(dir.join("a/2.txt"), "two")?;
-    write_to(dir.join("a/b/3.txt"), "three")?;
-
-    // make sure timestamp comparison detects changes
-    thread::sleep(Duration::from_nanos(10));
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
 
+    write_to(dir.join(".gitignore"), b"*.log\ntarget/\n")?;
+    write_to(dir.join("src/main.rs"), b"")?;
     repo.add(vec!["."])?;
     repo.commit(NAME, EMAIL, MSG)?;
 
-    Ok((dir_h, repo))
+    write_to(dir.join("src/debug.log"), b"")?;
+    write_to(dir.join("target/debug/writ"), b"")?;
+    write_to(dir.join("new/a.txt"), b"")?;
+    write_to(dir.join("new/b.log"), b"")?;
+
+    let mut repo = Repo::new(dir)?;
+    assert_eq!(
+        vec![
+            "untracked new/",
+            "ignored new/b.log",
+            "ignored src/debug.log",
+            "ignored target/"
+        ],
+        untracked_and_ignored(&mut repo, UntrackedFiles::Normal, true)?
+    );
+    assert_eq!(
+        vec![
+            "untracked new/a.txt",
+            "ignored new/b.log",
+            "ignored src/debug.log",
+            "ignored target/debug/writ"
+        ],
+        untracked_and_ignored(&mut repo, UntrackedFiles::All, true)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn doesnt_list_empty_untracked_dir() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+    fs::create_dir(dir.join("the_dir"))?;
+
+    let status = repo.status()?;
+    assert_eq!(0, status.len());
+
+    Ok(())
 }
 
 fn not_unmodified_statuses(mut repo: Repo) -> eyre::Result<Vec<FileStatus>> {
@@ -343,3 +436,312 @@ fn reports_all_deleted_files_in_dir() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn reports_unmerged_paths() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("a/2.txt"), "<<<<<<< ours")?;
+    record_conflict(
+        &mut repo,
+        "a/2.txt",
+        &[Stage::Base, Stage::Ours, Stage::Theirs],
+    )?;
+    record_conflict(&mut repo, "both_added.txt", &[Stage::Ours, Stage::Theirs])?;
+
+    assert_contains_unordered(
+        not_unmodified_statuses(repo)?,
+        [
+            |s: &FileStatus| {
+                s.index == Status::Unmerged(ConflictKind::BothModified)
+                    && s.workspace == Status::Unmerged(ConflictKind::BothModified)
+                    && s.path == "a/2.txt"
+            },
+            |s: &FileStatus| {
+                s.index == Status::Unmerged(ConflictKind::BothAdded)
+                    && s.workspace == Status::Unmerged(ConflictKind::BothAdded)
+                    && s.path == "both_added.txt"
+            },
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn ignores_workspace_changes_to_assume_unchanged() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("1.txt"), "changed")?;
+    fs::remove_file(dir.path().join("a/2.txt"))?;
+
+    let opts = UpdateIndexOptions {
+        assume_unchanged: Some(true),
+        ..UpdateIndexOptions::default()
+    };
+    repo.update_index(vec!["1.txt", "a/2.txt"], opts)?;
+    assert_eq!(0, not_unmodified_statuses(repo.clone())?.len());
+
+    let opts = UpdateIndexOptions {
+        assume_unchanged: Some(false),
+        ..UpdateIndexOptions::default()
+    };
+    repo.update_index(vec!["1.txt", "a/2.txt"], opts)?;
+    assert_contains_unordered(
+        not_unmodified_statuses(repo)?,
+        [
+            |s: &FileStatus| s.workspace == Status::Modified && s.path == "1.txt",
+            |s: &FileStatus| s.workspace == Status::Deleted && s.path == "a/2.txt",
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn doesnt_report_skip_worktree_as_deleted() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    fs::remove_dir_all(dir.path().join("a"))?;
+
+    let opts = UpdateIndexOptions {
+        skip_worktree: Some(true),
+        ..UpdateIndexOptions::default()
+    };
+    repo.update_index(vec!["a/2.txt", "a/b/3.txt"], opts)?;
+
+    assert_eq!(Vec::<FileStatus>::new(), not_unmodified_statuses(repo)?);
+
+    Ok(())
+}
+
+#[test]
+fn reports_intent_to_add_as_added_in_workspace() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    write_to(dir.path().join("new.txt"), "new")?;
+
+    let opts = AddOptions {
+        intent_to_add: true,
+    };
+    repo.add_with_options(vec!["new.txt"], opts)?;
+
+    assert_contains_unordered(
+        not_unmodified_statuses(repo)?,
+        [|s: &FileStatus| {
+            s.index == Status::Unmodified && s.workspace == Status::Added && s.path == "new.txt"
+        }],
+    );
+
+    Ok(())
+}
+
+/// Records the stat of `1.txt` after it's been modified without updating its
+/// oid, and backdates the index to the file's mtime, as if the file had
+/// changed in the same instant the index was written.
+fn make_racily_clean(dir: &Path, repo: &mut Repo) -> eyre::Result<SystemTime> {
+    write_to(dir.join("1.txt"), "won")?;
+    let stat = Stat::from(&fs::metadata(dir.join("1.txt"))?);
+
+    let mut index = repo.index_mut()?.modify()?;
+    index.update_stat(&WsPath::new_unchecked("1.txt"), stat)?;
+    index.commit()?;
+
+    set_index_mtime(dir, repo, stat.mtime)?;
+    Ok(stat.mtime)
+}
+
+fn set_index_mtime(dir: &Path, repo: &mut Repo, mtime: SystemTime) -> Result {
+    filetime::set_file_mtime(dir.join(".git/index"), FileTime::from_system_time(mtime))?;
+    repo.index_mut()?.reload()?;
+    Ok(())
+}
+
+#[test]
+fn rehashes_racily_clean_entries() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    make_racily_clean(dir.path(), &mut repo)?;
+
+    assert_contains_unordered(
+        not_unmodified_statuses(repo)?,
+        [|s: &FileStatus| s.workspace == Status::Modified && s.path == "1.txt"],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn smudges_racily_clean_entries_when_rewriting_index() -> Result {
+    let (dir, mut repo) = init_with_commit()?;
+    let dir = dir.path();
+    let mtime = make_racily_clean(dir, &mut repo)?;
+
+    write_to(dir.join("new.txt"), "new")?;
+    repo.add(vec!["new.txt"])?;
+    let entry = repo
+        .index()?
+        .entry(&WsPath::new_unchecked("1.txt"))
+        .unwrap();
+    assert_eq!(0, entry.stat.size);
+
+    // Once the index is much newer than the file, only the smudge can reveal
+    // the change
+    set_index_mtime(dir, &mut repo, mtime + Duration::from_secs(60))?;
+
+    assert_contains_unordered(
+        not_unmodified_statuses(repo)?,
+        [
+            |s: &FileStatus| s.workspace == Status::Modified && s.path == "1.txt",
+            |s: &FileStatus| s.index == Status::Added && s.path == "new.txt",
+        ],
+    );
+
+    Ok(())
+}
+
+#[test]
+fn porcelain_matches_git() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+    let dir_s = dir.to_str().unwrap();
+
+    write_to(dir.join("unchanged.txt"), "unchanged")?;
+    write_to(dir.join("modified.txt"), "before")?;
+    write_to(dir.join("staged.txt"), "before")?;
+    write_to(dir.join("deleted.txt"), "deleted")?;
+    write_to(dir.join("removed.txt"), "removed")?;
+    write_to(dir.join("run.sh"), "#!/bin/sh")?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    write_to(dir.join("modified.txt"), "after")?;
+    write_to(dir.join("staged.txt"), "after")?;
+    write_to(dir.join("added.txt"), "added")?;
+    repo.add(vec!["staged.txt", "added.txt"])?;
+    write_to(dir.join("added.txt"), "added, then changed")?;
+    fs::remove_file(dir.join("deleted.txt"))?;
+    repo.rm(vec!["removed.txt"], RmOptions::default())?;
+    fs::set_permissions(dir.join("run.sh"), fs::Permissions::from_mode(0o755))?;
+    write_to(dir.join("untracked.txt"), "")?;
+    write_to(dir.join("new/a.txt"), "")?;
+
+    let head = repo.refs.head()?;
+    let status = repo.status()?;
+    for (format, flag) in [
+        (Format::PorcelainV1, "--porcelain=v1"),
+        (Format::PorcelainV2, "--porcelain=v2"),
+    ] {
+        let opts = FormatOptions {
+            format,
+            branch: true,
+            nul_terminated: false,
+        };
+        let mut actual = Vec::new();
+        format::write(&mut actual, status.values(), head, opts, |path| {
+            path.to_bstring()
+        })?;
+
+        let expected = run_fun! {
+            cd $dir_s;
+            git status $flag --branch;
+        }?;
+        assert_eq!(expected, String::from_utf8(actual)?.trim_end());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn detects_renames_like_git() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+    let dir_s = dir.to_str().unwrap();
+
+    let lines = |n: usize, last: &str| {
+        let mut contents: String = (0..n).map(|i| format!("line {i:02}\n")).collect();
+        contents.push_str(last);
+        contents
+    };
+    write_to(dir.join("exact.txt"), lines(3, "exact\n"))?;
+    write_to(dir.join("similar.txt"), lines(9, "similar\n"))?;
+    write_to(dir.join("different.txt"), "different\n")?;
+    write_to(dir.join("copied.txt"), lines(9, "copied\n"))?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    fs::create_dir(dir.join("dir"))?;
+    repo.mv(vec!["exact.txt"], "dir/exact.txt")?;
+    repo.rm(vec!["similar.txt", "different.txt"], RmOptions::default())?;
+    write_to(dir.join("moved.txt"), lines(9, "changed\n"))?;
+    write_to(dir.join("unrelated.txt"), "unrelated\n")?;
+    write_to(dir.join("copied.txt"), lines(9, "copied and changed\n"))?;
+    write_to(dir.join("copy.txt"), lines(9, "copied\n"))?;
+    repo.add(vec!["."])?;
+
+    let head = repo.refs.head()?;
+    for (renames, config) in [
+        (Renames::Renames, "status.renames=true"),
+        (Renames::Copies, "status.renames=copies"),
+    ] {
+        let opts = StatusOptions {
+            renames,
+            ..StatusOptions::default()
+        };
+        let status = repo.status_with_options(Pathspec::all(), opts)?;
+        for (format, flag) in [
+            (Format::PorcelainV1, "--porcelain=v1"),
+            (Format::PorcelainV2, "--porcelain=v2"),
+        ] {
+            let opts = FormatOptions {
+                format,
+                branch: false,
+                nul_terminated: false,
+            };
+            let mut actual = Vec::new();
+            format::write(&mut actual, status.values(), head, opts, |path| {
+                path.to_bstring()
+            })?;
+
+            let expected = run_fun! {
+                cd $dir_s;
+                git -c $config status $flag;
+            }?;
+            assert_eq!(expected, String::from_utf8(actual)?.trim_end());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn reports_renames_in_untracked_place() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join("old.txt"), "contents\n")?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    repo.mv(vec!["old.txt"], "new.txt")?;
+    write_to(dir.join("old.txt"), "something else\n")?;
+
+    assert_contains_unordered(
+        not_unmodified_statuses(repo)?,
+        [
+            |s: &FileStatus| {
+                s.index
+                    == Status::Renamed {
+                        from: WsPath::new_unchecked("old.txt"),
+                        score: 100,
+                    }
+                    && s.path == "new.txt"
+            },
+            |s: &FileStatus| {
+                s.index == Status::Untracked
+                    && s.workspace == Status::Untracked
+                    && s.path == "old.txt"
+            },
+        ],
+    );
+
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use cmd_lib::run_cmd;
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    repo::SubmoduleError, stat::Mode, submodule, Oid, Status, StatusOptions, UntrackedFiles, WsPath,
+};
+
+/// A repository committing `files` at `dir`, with its head.
+fn upstream(
+    dir: &Path,
+    files: &[(&str, &str)],
+) -> eyre::Result<(Repo, Oid<writ::core::db::Commit>)> {
+    let mut repo = if dir.exists() {
+        Repo::new(dir)?
+    } else {
+        Repo::init(dir)?
+    };
+    for (path, contents) in files {
+        write_to(dir.join(path), contents)?;
+    }
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+    let head = repo.refs.head()?.unwrap();
+    Ok((repo, head))
+}
+
+/// A superproject at `super` recording the head of `lib` as a submodule at
+/// `lib`, which is left uninitialized.
+fn super_fixture() -> eyre::Result<(TempDir, Repo, Oid<writ::core::db::Commit>)> {
+    let dir = tempdir()?;
+    let (up, sup) = (dir.path().join("lib"), dir.path().join("super"));
+    let (_, head) = upstream(&up, &[("a.txt", "a\n")])?;
+
+    let mut repo = Repo::init(&sup)?;
+    let (up_s, sub_s) = (up.to_str().unwrap(), sup.join("lib"));
+    let sub_s = sub_s.to_str().unwrap();
+    run_cmd!(git clone -q $up_s $sub_s)?;
+    write_to(
+        sup.join(".gitmodules"),
+        "[submodule \"lib\"]\n\tpath = lib\n\turl = ../lib\n",
+    )?;
+    repo.add(vec!["."])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+    fs::remove_dir_all(sup.join("lib"))?;
+    fs::create_dir(sup.join("lib"))?;
+
+    Ok((dir, repo, head))
+}
+
+fn statuses(repo: &mut Repo) -> eyre::Result<Vec<(char, Option<Oid<writ::core::db::Commit>>)>> {
+    Ok(repo
+        .submodule_status()?
+        .into_iter()
+        .map(|status| (status.prefix(), status.commit()))
+        .collect())
+}
+
+fn workspace_status(repo: &mut Repo, path: &str) -> eyre::Result<Option<Status>> {
+    let opts = StatusOptions {
+        untracked: UntrackedFiles::All,
+        ..StatusOptions::default()
+    };
+    Ok(repo
+        .status_with_options(vec![path], opts)?
+        .remove(&WsPath::new_unchecked(path))
+        .map(|status| status.workspace))
+}
+
+#[test]
+fn add_records_gitlink_and_status_compares_commits() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    let dir = dir.path();
+    let (mut lib, first) = upstream(&dir.join("lib"), &[("a.txt", "a\n")])?;
+
+    repo.add(vec!["."])?;
+    let entry = repo.index()?.entry(&WsPath::new_unchecked("lib")).unwrap();
+    assert_eq!(Mode::Gitlink, entry.mode());
+    assert_eq!(first, submodule::commit_of(entry.oid));
+    // Nothing inside the submodule is tracked by the superproject
+    assert!(repo
+        .index()?
+        .entry(&WsPath::new_unchecked("lib/a.txt"))
+        .is_none());
+    repo.commit(NAME, EMAIL, MSG)?;
+    assert_eq!(
+        Some(Status::Unmodified),
+        workspace_status(&mut repo, "lib")?
+    );
+
+    write_to(dir.join("lib/b.txt"), "b\n")?;
+    lib.add(vec!["b.txt"])?;
+    lib.commit(NAME, EMAIL, MSG)?;
+    assert_eq!(Some(Status::Modified), workspace_status(&mut repo, "lib")?);
+
+    let dir_s = dir.to_str().unwrap();
+    let tree = run_fun! {
+        cd $dir_s;
+        git ls-tree HEAD lib;
+    }?;
+    assert_eq!(format!("160000 commit {}\tlib", first.to_hex()), tree);
+    let status = run_fun! {
+        cd $dir_s;
+        git status --porcelain;
+    }?;
+    assert_eq!(" M lib", status);
+    Ok(())
+}
+
+#[test]
+fn nested_repo_without_commit_cannot_be_added() -> Result {
+    init();
+    let (dir, mut repo) = repo_fixture()?;
+    Repo::init(dir.path().join("empty"))?;
+
+    let err = repo.add(vec!["empty"]).unwrap_err();
+    assert_eq!(
+        "'empty' does not have a commit checked out",
+        err.to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn init_and_update_clone_from_local_path() -> Result {
+    init();
+    let (dir, mut repo, head) = super_fixture()?;
+    let (up, sup) = (dir.path().join("lib"), dir.path().join("super"));
+
+    // Not checked out, which isn't a change
+    assert_eq!(vec![('-', Some(head))], statuses(&mut repo)?);
+    assert_eq!(
+        Some(Status::Unmodified),
+        workspace_status(&mut repo, "lib")?
+    );
+
+    // Nothing to update before init
+    assert!(repo.submodule_update()?.is_empty());
+
+    let registered = repo.submodule_init()?;
+    assert_eq!(1, registered.len());
+    assert_eq!(Some(up.to_str().unwrap().to_owned()), registered[0].url);
+    assert!(repo.submodule_init()?.is_empty());
+
+    let updated = repo.submodule_update()?;
+    assert_eq!(1, updated.len());
+    assert_eq!("a\n", fs::read_to_string(sup.join("lib/a.txt"))?);
+    assert!(sup.join(".git/modules/lib/objects").is_dir());
+    assert!(sup.join("lib/.git").is_file());
+    assert_eq!(vec![(' ', Some(head))], statuses(&mut repo)?);
+    assert_eq!(
+        Some(Status::Unmodified),
+        workspace_status(&mut repo, "lib")?
+    );
+    assert!(repo.submodule_update()?.is_empty());
+
+    let sub = Repo::new(sup.join("lib"))?;
+    assert_eq!(Some(head), sub.refs.head()?);
+
+    // The git directory left behind is used again
+    fs::remove_dir_all(sup.join("lib"))?;
+    assert_eq!(1, repo.submodule_update()?.len());
+    assert_eq!("a\n", fs::read_to_string(sup.join("lib/a.txt"))?);
+    assert_eq!(vec![(' ', Some(head))], statuses(&mut repo)?);
+    Ok(())
+}
+
+#[test]
+fn update_switches_commits_unless_there_are_changes() -> Result {
+    init();
+    let (dir, mut repo, first) = super_fixture()?;
+    let (up, sup) = (dir.path().join("lib"), dir.path().join("super"));
+    repo.submodule_init()?;
+    repo.submodule_update()?;
+
+    // Record a later commit from upstream
+    let (_, second) = upstream(&up, &[("b.txt", "b\n")])?;
+    let sub_dir = sup.join("lib");
+    let sub_s = sub_dir.to_str().unwrap();
+    let (first_s, second_s) = (first.to_hex(), second.to_hex());
+    run_cmd! {
+        cd $sub_s;
+        git fetch -q origin;
+        git checkout -q $second_s;
+    }?;
+    assert_eq!(vec![('+', Some(second))], statuses(&mut repo)?);
+    repo.add(vec!["lib"])?;
+    repo.commit(NAME, EMAIL, MSG)?;
+
+    // Back to the first, then updated to the recorded second
+    run_cmd! {
+        cd $sub_s;
+        git checkout -q $first_s;
+    }?;
+    assert!(!sub_dir.join("b.txt").exists());
+    assert_eq!(1, repo.submodule_update()?.len());
+    assert_eq!("b\n", fs::read_to_string(sub_dir.join("b.txt"))?);
+    assert_eq!(vec![(' ', Some(second))], statuses(&mut repo)?);
+
+    run_cmd! {
+        cd $sub_s;
+        git checkout -q $first_s;
+    }?;
+    write_to(sub_dir.join("a.txt"), "changed\n")?;
+    let err = repo.submodule_update().unwrap_err();
+    assert!(matches!(err, SubmoduleError::LocalChanges(_)), "{:?}", err);
+    assert_eq!("changed\n", fs::read_to_string(sub_dir.join("a.txt"))?);
+    Ok(())
+}
+
+#[test]
+fn git_understands_our_submodules() -> Result {
+    init();
+    let (dir, mut repo, head) = super_fixture()?;
+    repo.submodule_init()?;
+    repo.submodule_update()?;
+
+    let sup = dir.path().join("super");
+    let sup_s = sup.to_str().unwrap();
+    let status = run_fun! {
+        cd $sup_s;
+        git submodule status;
+    }?;
+    // Followed by what git describes the commit as
+    assert!(
+        status.starts_with(&format!(" {} lib ", head.to_hex())),
+        "{}",
+        status
+    );
+    let status = run_fun! {
+        cd $sup_s;
+        git status --porcelain;
+    }?;
+    assert_eq!("", status);
+    Ok(())
+}
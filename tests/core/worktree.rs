@@ -0,0 +1,210 @@
+use std::{os::unix::fs::PermissionsExt, path::Path};
+
+use cmd_lib::run_cmd;
+use test_support::assert_eq;
+use test_support::*;
+
+use writ::core::{
+    repo::{WorktreeAddOptions, WorktreeRemoveError},
+    worktree,
+};
+
+/// A committed repository with a few files, including an executable one in
+/// a subdirectory.
+fn fixture() -> eyre::Result<(TempDir, Repo)> {
+    committed_fixture(|path| {
+        write_to(path.join("a.txt"), "a\n")?;
+        write_to(path.join("dir/run.sh"), "#!/bin/sh\n")?;
+        fs::set_permissions(path.join("dir/run.sh"), fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    })
+}
+
+fn names(dir: &Path) -> eyre::Result<Vec<Option<String>>> {
+    Ok(worktree::list(&dir.join(".git"))?
+        .into_iter()
+        .map(|w| w.name)
+        .collect())
+}
+
+#[test]
+fn add_checks_out_head_and_shares_objects() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+    let main_head = repo.refs.head()?;
+
+    let wt_dir = tempdir()?;
+    let wt_path = wt_dir.path().join("wt");
+    let mut wt = repo.worktree_add(&wt_path, WorktreeAddOptions::default())?;
+    let wt_path = wt_path.canonicalize()?;
+    assert_eq!(wt_path, wt.workspace()?.path());
+    assert_eq!(main_head, wt.refs.head()?);
+    assert_eq!("a\n", fs::read_to_string(wt_path.join("a.txt"))?);
+    let mode = fs::metadata(wt_path.join("dir/run.sh"))?.mode();
+    assert!(mode & 0o111 != 0, "{:o}", mode);
+    assert!(is_clean(&mut wt)?);
+
+    // Committing in the new worktree only moves its own HEAD, but the commit
+    // is in the shared objects
+    write_to(wt_path.join("b.txt"), "b\n")?;
+    wt.add(vec!["b.txt"])?;
+    wt.commit(NAME, EMAIL, MSG)?;
+    let wt_head = wt.refs.head()?.unwrap();
+    assert_ne!(main_head, Some(wt_head));
+    let mut repo = Repo::new(dir)?;
+    assert_eq!(main_head, repo.refs.head()?);
+    repo.db.load(wt_head)?;
+    assert!(is_clean(&mut repo)?);
+
+    // Found from inside the worktree through its .git file
+    let found = Repo::discover(wt_path.join("dir"))?;
+    assert_eq!(wt_path, found.workspace()?.path());
+    assert_eq!(repo.common_dir(), found.common_dir());
+    assert_eq!(Some(wt_head), found.refs.head()?);
+    Ok(())
+}
+
+#[test]
+fn add_refuses_existing_paths_and_picks_unique_names() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+
+    write_to(dir.join("taken/file"), "")?;
+    let err = repo
+        .worktree_add(dir.join("taken"), WorktreeAddOptions::default())
+        .unwrap_err();
+    assert!(
+        matches!(err, writ::core::repo::WorktreeAddError::Exists(_)),
+        "{:?}",
+        err
+    );
+
+    fs::create_dir(dir.join("empty"))?;
+    repo.worktree_add(dir.join("empty"), WorktreeAddOptions::default())?;
+    repo.worktree_add(dir.join("other/empty"), WorktreeAddOptions::default())?;
+    assert_eq!(
+        vec![None, Some("empty".to_owned()), Some("empty1".to_owned())],
+        names(dir)?
+    );
+    Ok(())
+}
+
+#[test]
+fn remove_refuses_changes_unless_forced() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+    let wt_path = dir.join("wt");
+    repo.worktree_add(&wt_path, WorktreeAddOptions::default())?;
+
+    write_to(wt_path.join("untracked.txt"), "")?;
+    let err = repo.worktree_remove(&wt_path, false).unwrap_err();
+    assert!(matches!(err, WorktreeRemoveError::Dirty(_)), "{:?}", err);
+
+    fs::remove_file(wt_path.join("untracked.txt"))?;
+    write_to(dir.join(".git/worktrees/wt/locked"), "on a stick\n")?;
+    let err = repo.worktree_remove(&wt_path, false).unwrap_err();
+    assert_eq!(
+        format!(
+            "Cannot remove a locked working tree {:?}, lock reason: on a stick",
+            wt_path
+        ),
+        err.to_string()
+    );
+
+    let err = repo.worktree_remove(dir, true).unwrap_err();
+    assert!(matches!(err, WorktreeRemoveError::Main), "{:?}", err);
+
+    repo.worktree_remove(&wt_path, true)?;
+    assert!(!wt_path.exists());
+    assert_eq!(vec![None], names(dir)?);
+    Ok(())
+}
+
+#[test]
+fn prune_removes_missing_unlocked_worktrees() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+    for name in &["gone", "kept", "locked"] {
+        repo.worktree_add(dir.join(name), WorktreeAddOptions::default())?;
+    }
+    write_to(dir.join(".git/worktrees/locked/locked"), "")?;
+    fs::remove_dir_all(dir.join("gone"))?;
+    fs::remove_dir_all(dir.join("locked"))?;
+
+    let listed = worktree::list(repo.common_dir())?;
+    let prunable = listed
+        .iter()
+        .map(|w| (w.name.clone(), w.prunable.clone(), w.locked.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            (None, None, None),
+            (
+                Some("gone".to_owned()),
+                Some("gitdir file points to non-existent location".to_owned()),
+                None
+            ),
+            (Some("kept".to_owned()), None, None),
+            (Some("locked".to_owned()), None, Some("".to_owned())),
+        ],
+        prunable
+    );
+
+    let pruned = worktree::prune(repo.common_dir(), true)?;
+    assert_eq!(1, pruned.len());
+    assert!(dir.join(".git/worktrees/gone").exists());
+
+    worktree::prune(repo.common_dir(), false)?;
+    assert_eq!(
+        vec![None, Some("kept".to_owned()), Some("locked".to_owned())],
+        names(dir)?
+    );
+    Ok(())
+}
+
+#[test]
+fn git_understands_worktrees() -> Result {
+    init();
+    let (dir, mut repo) = fixture()?;
+    let dir = dir.path();
+    let dir_s = dir.to_str().unwrap();
+
+    // Ours, as seen by git
+    let ours = dir.join("ours");
+    let ours_s = ours.to_str().unwrap();
+    repo.worktree_add(&ours, WorktreeAddOptions::default())?;
+    let listed = run_fun! {
+        cd $dir_s;
+        git worktree list --porcelain;
+    }?;
+    assert!(
+        listed.contains(&format!("worktree {}\n", ours_s)),
+        "{}",
+        listed
+    );
+    let status = run_fun! {
+        cd $ours_s;
+        git status --porcelain;
+    }?;
+    assert_eq!("", status);
+
+    // Git's, as seen by us
+    let theirs = dir.join("theirs");
+    let theirs_s = theirs.to_str().unwrap();
+    run_cmd! {
+        cd $dir_s;
+        git worktree add -q --detach $theirs_s;
+    }?;
+    let mut wt = Repo::new(&theirs)?;
+    assert_eq!(repo.refs.head()?, wt.refs.head()?);
+    assert!(is_clean(&mut wt)?);
+    assert_eq!(
+        vec![None, Some("ours".to_owned()), Some("theirs".to_owned())],
+        names(dir)?
+    );
+    Ok(())
+}